@@ -1,14 +1,23 @@
 use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
 use bevy::render::texture::ImagePlugin;
 use bevy::window::{PrimaryWindow, WindowResolution};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use sim_core::{
-    movement_step_with_obstacles, InputState as CoreInputState, StructureObstacle,
-    PLAYER_COLLIDER_RADIUS, STRUCTURE_COLLIDER_HALF_EXTENT,
+    movement_step_with_obstacles_fixed, movement_step_with_terrain_fixed, power_demand_kw,
+    projectile_step_with_hits_fixed, recipe_by_id, step_reach, structure_has_behavior,
+    structure_is_directional, structure_is_solid, structure_prototype, structure_prototypes,
+    technology_by_id, tile_to_chunk, weapon_by_id, world_to_tile, Direction, Footprint,
+    InputState as CoreInputState, MovementStep, ObstacleIndex, OreKind, PlayerCollider,
+    ResearchState, Stamina, StructureBehavior, Terrain, TerrainTile, Weapon, WeaponCooldowns,
+    ANALOG_AXIS_MAX, BELT_LANE_LENGTH, BELT_LEFT_LANE, ENEMY_COLLIDER_RADIUS, INSERTER_SWING_TICKS,
+    PLAYER_COLLIDER_RADIUS, PROJECTILE_COLLIDER_RADIUS, RECIPES, STAMINA_MAX_SECONDS, TECHNOLOGIES,
+    TERRAIN_CHUNK_TILES, WEAPONS,
 };
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Mutex;
 use uuid::Uuid;
 use wasm_bindgen::prelude::*;
@@ -26,14 +35,20 @@ const FOOTSTEP_VOLUME_VARIATION: [f32; 6] = [0.88, 1.0, 0.94, 1.06, 0.9, 1.02];
 const FOOTSTEP_SPEED_VARIATION: [f32; 6] = [0.96, 1.03, 0.99, 1.05, 0.97, 1.01];
 const STRUCTURE_SIZE: f32 = 18.0;
 const PROJECTILE_SIZE: f32 = 8.0;
+const ENEMY_SIZE: f32 = ENEMY_COLLIDER_RADIUS * 2.0;
 const MAP_LIMIT: f32 = 5000.0;
 const BUILD_GRID_SIZE: f32 = 32.0;
+const BUILD_CHUNK_CELLS: i32 = 32;
+/// Structure kinds selectable with the number keys, in key order.
+const BELT_ITEM_SIZE: f32 = 6.0;
+const BELT_LANE_OFFSET: f32 = 8.0;
+const DIRECTION_NOTCH_THICKNESS: f32 = 4.0;
 const BUILD_PREVIEW_SEND_INTERVAL_SECONDS: f32 = 0.08;
 const MOVE_SPEED: f32 = 220.0;
-const PROJECTILE_SPEED: f32 = 760.0;
-const PROJECTILE_TTL_SECONDS: f32 = 1.8;
 const CLIENT_SIM_HZ: f32 = 60.0;
 const CLIENT_SIM_DT: f32 = 1.0 / CLIENT_SIM_HZ;
+/// Tick rate of the room simulation, which machine timings are counted in.
+const SERVER_SIM_HZ: f32 = 30.0;
 const MAX_SIM_STEPS_PER_FRAME: usize = 8;
 const MAX_INPUT_HISTORY: usize = 512;
 const MAX_OUTBOUND_INPUTS: usize = 256;
@@ -44,9 +59,29 @@ const PROJECTILE_RECONCILE_HARD_SNAP_DISTANCE: f32 = 140.0;
 const CHARACTER_DIRECTION_EPSILON: f32 = 0.001;
 const SNAPSHOT_Z: f32 = 4.0;
 const STRUCTURE_Z: f32 = 3.0;
+const BELT_ITEM_Z: f32 = 3.2;
 const PROJECTILE_Z: f32 = 5.0;
 const FLOOR_Z: f32 = 0.0;
 const BUILD_PREVIEW_Z: f32 = 3.6;
+const HEALTH_BAR_Z: f32 = 6.0;
+const HEALTH_BAR_WIDTH: f32 = 26.0;
+const HEALTH_BAR_HEIGHT: f32 = 4.0;
+const PLAYER_HEALTH_BAR_OFFSET: f32 = 36.0;
+const PLAYER_STAMINA_BAR_OFFSET: f32 = -30.0;
+const GAMEPAD_DEADZONE: f32 = 0.15;
+/// Gap between the top of a structure and its health bar.
+const STRUCTURE_HEALTH_BAR_GAP: f32 = 7.0;
+const INVULNERABLE_ALPHA: f32 = 0.5;
+const TERRAIN_VIEW_RADIUS_CHUNKS: i32 = 2;
+const BLOCKED_GHOST_COLOR: Color = Color::srgba(0.9, 0.25, 0.25, 0.55);
+const GHOST_NOTCH_COLOR: Color = Color::srgba(1.0, 1.0, 1.0, 0.7);
+const MACHINE_GAUGE_HEIGHT: f32 = 3.0;
+/// Gap between the bottom of a machine and its gauge.
+const MACHINE_GAUGE_GAP: f32 = 4.0;
+const ASSEMBLER_RECIPE_ICON_SIZE: f32 = 8.0;
+const INSERTER_HAND_SIZE: f32 = 8.0;
+/// How far from the inserter's centre the hand reaches at either end of a swing.
+const INSERTER_REACH: f32 = BUILD_GRID_SIZE * 0.5;
 
 static INBOUND_SNAPSHOTS: Lazy<Mutex<Vec<SnapshotPayload>>> = Lazy::new(|| Mutex::new(Vec::new()));
 static OUTBOUND_INPUTS: Lazy<Mutex<Vec<InputCommand>>> = Lazy::new(|| Mutex::new(Vec::new()));
@@ -55,6 +90,12 @@ static OUTBOUND_FEATURE_COMMANDS: Lazy<Mutex<Vec<OutboundFeatureCommand>>> =
 static NEXT_PLAYER_ID: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
 static STARTED: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
 static PENDING_SESSION_RESET: Lazy<Mutex<bool>> = Lazy::new(|| Mutex::new(false));
+static PENDING_TERRAIN_SEED: Lazy<Mutex<Option<u32>>> = Lazy::new(|| Mutex::new(None));
+static GAMEPAD_INPUT: Lazy<Mutex<GamepadInput>> = Lazy::new(|| Mutex::new(GamepadInput::default()));
+static RENDER_STRUCTURES: Lazy<Mutex<RenderStructureStore>> =
+    Lazy::new(|| Mutex::new(RenderStructureStore::default()));
+static RENDER_BELTS: Lazy<Mutex<RenderBeltStore>> =
+    Lazy::new(|| Mutex::new(RenderBeltStore::default()));
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct PlayerState {
@@ -64,6 +105,13 @@ struct PlayerState {
     vx: f32,
     vy: f32,
     connected: bool,
+    #[serde(default)]
+    weapon: Option<String>,
+    /// Sprint budget; only sent for the local player.
+    #[serde(default)]
+    stamina: Option<f32>,
+    #[serde(default)]
+    exhausted: Option<bool>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -74,6 +122,64 @@ struct StructureState {
     kind: String,
     #[serde(rename = "ownerId")]
     owner_id: String,
+    #[serde(default)]
+    direction: Option<String>,
+    #[serde(default)]
+    miner: Option<MinerView>,
+    #[serde(default)]
+    assembler: Option<AssemblerView>,
+    #[serde(default)]
+    inserter: Option<InserterView>,
+    #[serde(default)]
+    power: Option<StructurePower>,
+}
+
+/// Power network of a pole, generator or consumer; `None` when no pole reaches it.
+#[derive(Debug, Clone, Serialize, Deserialize, Component, PartialEq)]
+struct StructurePower {
+    network: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Component, PartialEq)]
+struct MinerView {
+    item: Option<String>,
+    output: u32,
+    capacity: u32,
+    working: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct ItemCountView {
+    item: String,
+    count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Component, PartialEq)]
+struct AssemblerView {
+    recipe: Option<String>,
+    inputs: Vec<ItemCountView>,
+    outputs: Vec<ItemCountView>,
+    progress: f32,
+    crafting: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Component, PartialEq)]
+struct InserterView {
+    held: Option<String>,
+    phase: String,
+}
+
+/// Belt items of one build chunk; `items` holds `[cell, lane, position,
+/// paletteIndex]` quadruples.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BeltChunkState {
+    id: String,
+    #[serde(rename = "chunkX")]
+    chunk_x: i32,
+    #[serde(rename = "chunkY")]
+    chunk_y: i32,
+    palette: Vec<String>,
+    items: Vec<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,6 +189,8 @@ struct BuildPreviewState {
     x: f32,
     y: f32,
     kind: String,
+    #[serde(default)]
+    direction: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -98,6 +206,129 @@ struct ProjectileState {
     client_projectile_id: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PowerNetworkState {
+    id: String,
+    satisfaction: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ResearchPayload {
+    current: Option<String>,
+    #[serde(default)]
+    progress: HashMap<String, u32>,
+    #[serde(default)]
+    unlocked: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EnemyState {
+    id: String,
+    x: f32,
+    y: f32,
+    hp: i32,
+    #[serde(rename = "maxHp")]
+    max_hp: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PlayerHealthState {
+    id: String,
+    hp: i32,
+    #[serde(rename = "maxHp")]
+    max_hp: i32,
+    dead: bool,
+    #[serde(rename = "respawnInMs", default)]
+    respawn_in_ms: i64,
+    #[serde(default)]
+    invulnerable: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StructureHealthState {
+    id: String,
+    hp: i32,
+    #[serde(rename = "maxHp")]
+    max_hp: i32,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum BuildMode {
+    #[default]
+    Full,
+    Delta,
+}
+
+/// Structure set as of the latest pushed snapshot. Build deltas are applied in
+/// `push_snapshot` because the render systems only consume the newest queued
+/// snapshot and would otherwise drop intermediate changes.
+#[derive(Debug, Default)]
+struct RenderStructureStore {
+    revision: u64,
+    structures: HashMap<String, StructureState>,
+    /// Colliders of the solid structures above, used for prediction.
+    obstacles: ObstacleIndex<String>,
+}
+
+impl RenderStructureStore {
+    fn apply(&mut self, mode: BuildMode, upserts: Vec<StructureState>, removes: Vec<String>) {
+        if mode == BuildMode::Delta && upserts.is_empty() && removes.is_empty() {
+            return;
+        }
+        if mode == BuildMode::Full {
+            self.structures.clear();
+            self.obstacles.clear();
+        }
+        for id in removes {
+            self.structures.remove(&id);
+            self.obstacles.remove(&id);
+        }
+        for structure in upserts {
+            if structure_is_solid(&structure.kind) {
+                let obstacle = structure_footprint(&structure).obstacle(structure.x, structure.y);
+                self.obstacles.insert(structure.id.clone(), obstacle);
+            }
+            self.structures.insert(structure.id.clone(), structure);
+        }
+        self.revision += 1;
+    }
+
+    fn clear(&mut self) {
+        self.structures.clear();
+        self.obstacles.clear();
+        self.revision += 1;
+    }
+}
+
+/// Belt chunks as of the latest pushed snapshot, plus the ids whose item
+/// sprites need rebuilding.
+#[derive(Debug, Default)]
+struct RenderBeltStore {
+    chunks: HashMap<String, BeltChunkState>,
+    changed: HashSet<String>,
+}
+
+impl RenderBeltStore {
+    fn apply(&mut self, mode: BuildMode, upserts: Vec<BeltChunkState>, removes: Vec<String>) {
+        if mode == BuildMode::Full {
+            self.clear();
+        }
+        for id in removes {
+            self.chunks.remove(&id);
+            self.changed.insert(id);
+        }
+        for chunk in upserts {
+            self.changed.insert(chunk.id.clone());
+            self.chunks.insert(chunk.id.clone(), chunk);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.changed.extend(self.chunks.drain().map(|(id, _)| id));
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct SnapshotPayload {
     #[serde(rename = "serverTick")]
@@ -109,20 +340,46 @@ struct SnapshotPayload {
     #[serde(rename = "renderDelayMs", default)]
     render_delay_ms: f32,
     players: Vec<PlayerState>,
+    #[serde(rename = "buildMode", default)]
+    build_mode: BuildMode,
     #[serde(default)]
     structures: Vec<StructureState>,
+    #[serde(rename = "structureRemoves", default)]
+    structure_removes: Vec<String>,
+    #[serde(rename = "beltMode", default)]
+    belt_mode: BuildMode,
+    #[serde(rename = "beltChunks", default)]
+    belt_chunks: Vec<BeltChunkState>,
+    #[serde(rename = "beltChunkRemoves", default)]
+    belt_chunk_removes: Vec<String>,
     #[serde(default)]
     previews: Vec<BuildPreviewState>,
     #[serde(default)]
     projectiles: Vec<ProjectileState>,
+    #[serde(default)]
+    enemies: Vec<EnemyState>,
+    #[serde(rename = "powerNetworks", default)]
+    power_networks: Vec<PowerNetworkState>,
+    #[serde(default)]
+    research: Option<ResearchPayload>,
+    #[serde(rename = "playerHealth", default)]
+    player_health: Vec<PlayerHealthState>,
+    #[serde(rename = "structureHealth", default)]
+    structure_health: Vec<StructureHealthState>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 struct InputState {
     up: bool,
     down: bool,
     left: bool,
     right: bool,
+    #[serde(rename = "moveX", default)]
+    move_x: i8,
+    #[serde(rename = "moveY", default)]
+    move_y: i8,
+    #[serde(default)]
+    sprint: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -132,6 +389,18 @@ struct InputCommand {
     down: bool,
     left: bool,
     right: bool,
+    #[serde(rename = "moveX")]
+    move_x: i8,
+    #[serde(rename = "moveY")]
+    move_y: i8,
+    sprint: bool,
+}
+
+/// Latest gamepad state pushed from JS; the stick uses +y up.
+#[derive(Debug, Clone, Copy, Default)]
+struct GamepadInput {
+    stick: Vec2,
+    sprint: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -155,6 +424,33 @@ struct Actor {
 #[derive(Component, Default)]
 struct ActorVelocity(Vec2);
 
+/// Predicted sprint budget of the local player.
+#[derive(Component, Default)]
+struct LocalStamina(Stamina);
+
+/// Weapon the server last reported for the local player, and when the client
+/// last fired each weapon (in `Time` milliseconds) so it does not shoot faster
+/// than allowed.
+#[derive(Component)]
+struct LocalWeapon {
+    weapon: &'static Weapon,
+    cooldowns: WeaponCooldowns,
+}
+
+impl Default for LocalWeapon {
+    fn default() -> Self {
+        Self {
+            weapon: Weapon::default_weapon(),
+            cooldowns: WeaponCooldowns::default(),
+        }
+    }
+}
+
+#[derive(Component)]
+struct StaminaBarSprite {
+    fill: bool,
+}
+
 #[derive(Component)]
 struct LocalActor;
 
@@ -167,6 +463,58 @@ struct RemoteTarget(Vec2);
 #[derive(Component)]
 struct StructureActor {
     id: String,
+    /// Half the footprint in world units, for hit tests and bar placement.
+    half_size: Vec2,
+}
+
+/// Parent of the item sprites on the belts of one build chunk.
+#[derive(Component)]
+struct BeltChunkActor {
+    id: String,
+}
+
+/// Edge marker showing which way a belt or inserter points; a child of its
+/// structure.
+#[derive(Component)]
+struct DirectionNotch;
+
+/// Direction marker on the local build ghost, shown while placing directional
+/// kinds.
+#[derive(Component)]
+struct LocalBuildGhostNotch;
+
+/// Output fill bar drawn under a miner; a child of its structure.
+#[derive(Component)]
+struct MinerGauge;
+
+/// Marks chests so E can open the one under the cursor.
+#[derive(Component)]
+struct ChestActor;
+
+/// Shade over a consumer that gets less power than it asks for; a child of its
+/// structure.
+#[derive(Component)]
+struct UnpoweredOverlay;
+
+/// Craft progress bar drawn under an assembler; a child of its structure.
+#[derive(Component)]
+struct AssemblerGauge;
+
+/// Square in the middle of an assembler tinted with its recipe's product.
+#[derive(Component)]
+struct AssemblerRecipeIcon;
+
+/// Hand of an inserter arm, tinted with the held item; a child of its structure.
+#[derive(Component)]
+struct InserterHand;
+
+/// Client-side swing timing. Snapshots only carry the phase, so the hand is
+/// animated from the moment a new phase arrives.
+#[derive(Component)]
+struct InserterSwing {
+    forward: Vec2,
+    phase: String,
+    started_at: f32,
 }
 
 #[derive(Component)]
@@ -182,6 +530,20 @@ struct ProjectileActor {
     id: String,
 }
 
+#[derive(Component)]
+struct EnemyActor {
+    id: String,
+}
+
+/// Session resets clear enemies along with the server's projectiles.
+type ProjectileOrEnemy = Or<(With<ProjectileActor>, With<EnemyActor>)>;
+
+#[derive(Component)]
+struct HealthBarSprite {
+    target_id: String,
+    fill: bool,
+}
+
 #[derive(Component)]
 struct PredictedProjectileActor {
     client_projectile_id: String,
@@ -285,10 +647,48 @@ impl Default for NextInputSeq {
 #[derive(Resource, Default)]
 struct InputHistory(VecDeque<InputHistoryEntry>);
 
+/// Satisfaction of the power networks in the latest snapshot, by network id.
+#[derive(Resource, Default)]
+struct PowerView(HashMap<String, f32>);
+
+/// Room research as of the latest snapshot that carried it.
+#[derive(Resource, Default)]
+struct ResearchView(ResearchState);
+
+/// Research screen, toggled with T.
+#[derive(Component)]
+struct ResearchScreen;
+
+/// One technology on the research screen; its first child is the label.
+#[derive(Component)]
+struct ResearchButton(&'static str);
+
+#[derive(Resource, Default)]
+struct HealthView {
+    players: HashMap<String, PlayerHealthState>,
+    structures: HashMap<String, StructureHealthState>,
+}
+
+impl HealthView {
+    fn player_is_dead(&self, player_id: Option<&str>) -> bool {
+        player_id
+            .and_then(|id| self.players.get(id))
+            .is_some_and(|health| health.dead)
+    }
+}
+
+/// Terrain chunk sprites currently spawned around the camera.
+#[derive(Resource, Default)]
+struct TerrainView {
+    terrain: Option<Terrain>,
+    chunks: HashMap<IVec2, Entity>,
+}
+
 #[derive(Resource)]
 struct BuildPlacementState {
     active: bool,
     kind: &'static str,
+    direction: Direction,
     last_sent_cell: Option<IVec2>,
     send_cooldown: f32,
 }
@@ -298,6 +698,7 @@ impl Default for BuildPlacementState {
         Self {
             active: false,
             kind: "beacon",
+            direction: Direction::default(),
             last_sent_cell: None,
             send_cooldown: 0.0,
         }
@@ -314,6 +715,12 @@ fn clear_protocol_queues() {
     if let Ok(mut queue) = OUTBOUND_FEATURE_COMMANDS.lock() {
         queue.clear();
     }
+    if let Ok(mut store) = RENDER_STRUCTURES.lock() {
+        store.clear();
+    }
+    if let Ok(mut store) = RENDER_BELTS.lock() {
+        store.clear();
+    }
 }
 
 fn take_pending_session_reset() -> bool {
@@ -355,6 +762,10 @@ pub fn boot_game(canvas_id: String) -> Result<(), JsValue> {
         .insert_resource(SimAccumulator::default())
         .insert_resource(NextInputSeq::default())
         .insert_resource(InputHistory::default())
+        .insert_resource(HealthView::default())
+        .insert_resource(PowerView::default())
+        .insert_resource(ResearchView::default())
+        .insert_resource(TerrainView::default())
         .insert_resource(BuildPlacementState::default())
         .insert_resource(FootstepState::default())
         .add_plugins(
@@ -369,21 +780,43 @@ pub fn boot_game(canvas_id: String) -> Result<(), JsValue> {
                 })
                 .set(ImagePlugin::default_nearest()),
         )
-        .add_systems(Startup, setup_world)
+        .add_systems(Startup, (setup_world, setup_research_screen))
         .add_systems(
             Update,
             (
-                apply_pending_session_reset,
-                sync_player_id,
-                simulate_local_player,
-                emit_footstep_audio,
-                handle_build_placement_controls,
-                emit_projectile_fire_command,
-                simulate_predicted_projectiles,
-                apply_latest_snapshot,
-                smooth_remote_motion,
-                animate_character_sprites,
-                follow_camera,
+                // Input and simulation, then snapshot application and
+                // presentation; split only because a tuple holds at most 20.
+                (
+                    apply_pending_session_reset,
+                    sync_player_id,
+                    sync_terrain_chunks,
+                    simulate_local_player,
+                    emit_footstep_audio,
+                    handle_build_placement_controls,
+                    handle_assembler_recipe_controls,
+                    handle_chest_controls,
+                    handle_research_controls,
+                    handle_weapon_controls,
+                    emit_projectile_fire_command,
+                    simulate_predicted_projectiles,
+                    apply_latest_snapshot,
+                )
+                    .chain(),
+                (
+                    sync_power_overlays,
+                    sync_research_screen,
+                    sync_miner_gauges,
+                    sync_assembler_views,
+                    sync_belt_items,
+                    animate_inserter_hands,
+                    smooth_remote_motion,
+                    apply_player_life_state,
+                    sync_health_bars,
+                    sync_stamina_bar,
+                    animate_character_sprites,
+                    follow_camera,
+                )
+                    .chain(),
             )
                 .chain(),
         );
@@ -407,11 +840,46 @@ pub fn set_player_id(player_id: String) {
     }
 }
 
+/// Left stick in `[-1, 1]` per axis with +y up, plus the sprint button.
+#[wasm_bindgen]
+pub fn set_gamepad_input(x: f32, y: f32, sprint: bool) {
+    if let Ok(mut gamepad) = GAMEPAD_INPUT.lock() {
+        *gamepad = GamepadInput {
+            stick: Vec2::new(x, y),
+            sprint,
+        };
+    }
+}
+
+#[wasm_bindgen]
+pub fn set_terrain_seed(seed: u32) {
+    if let Ok(mut pending_seed) = PENDING_TERRAIN_SEED.lock() {
+        *pending_seed = Some(seed);
+    }
+}
+
 #[wasm_bindgen]
 pub fn push_snapshot(snapshot_json: String) -> Result<(), JsValue> {
-    let snapshot = serde_json::from_str::<SnapshotPayload>(&snapshot_json)
+    let mut snapshot = serde_json::from_str::<SnapshotPayload>(&snapshot_json)
         .map_err(|error| JsValue::from_str(&format!("invalid snapshot payload: {error}")))?;
 
+    RENDER_STRUCTURES
+        .lock()
+        .map_err(|_| JsValue::from_str("structure store mutex poisoned"))?
+        .apply(
+            snapshot.build_mode,
+            std::mem::take(&mut snapshot.structures),
+            std::mem::take(&mut snapshot.structure_removes),
+        );
+    RENDER_BELTS
+        .lock()
+        .map_err(|_| JsValue::from_str("belt store mutex poisoned"))?
+        .apply(
+            snapshot.belt_mode,
+            std::mem::take(&mut snapshot.belt_chunks),
+            std::mem::take(&mut snapshot.belt_chunk_removes),
+        );
+
     let mut queue = INBOUND_SNAPSHOTS
         .lock()
         .map_err(|_| JsValue::from_str("snapshot queue mutex poisoned"))?;
@@ -469,26 +937,6 @@ fn setup_world(
 
     commands.spawn(Camera2dBundle::default());
 
-    for x in -25..=25 {
-        for y in -25..=25 {
-            let tint = if (x + y) % 2 == 0 {
-                Color::srgb_u8(24, 44, 33)
-            } else {
-                Color::srgb_u8(21, 35, 27)
-            };
-
-            commands.spawn(SpriteBundle {
-                sprite: Sprite {
-                    color: tint,
-                    custom_size: Some(Vec2::splat(TILE_SIZE - 1.0)),
-                    ..default()
-                },
-                transform: Transform::from_xyz(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE, FLOOR_Z),
-                ..default()
-            });
-        }
-    }
-
     let texture = asset_server.load("sprites/factorio-character-sheet.png");
     let layout = atlas_layouts.add(TextureAtlasLayout::from_grid(
         UVec2::splat(CHARACTER_FRAME_SIZE as u32),
@@ -516,22 +964,65 @@ fn setup_world(
         },
         ActorVelocity::default(),
         CharacterAnimator::default(),
+        LocalStamina::default(),
+        LocalWeapon::default(),
         LocalActor,
     ));
 
-    commands.spawn((
-        SpriteBundle {
-            sprite: Sprite {
-                color: structure_preview_color("beacon", true),
-                custom_size: Some(Vec2::splat(STRUCTURE_SIZE)),
+    for fill in [false, true] {
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: if fill {
+                        Color::srgb_u8(250, 204, 21)
+                    } else {
+                        Color::srgba_u8(12, 16, 24, 200)
+                    },
+                    custom_size: Some(Vec2::new(HEALTH_BAR_WIDTH, HEALTH_BAR_HEIGHT)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(
+                    0.0,
+                    0.0,
+                    HEALTH_BAR_Z + if fill { 0.1 } else { 0.0 },
+                ),
+                visibility: Visibility::Hidden,
                 ..default()
             },
-            transform: Transform::from_xyz(0.0, 0.0, BUILD_PREVIEW_Z),
-            visibility: Visibility::Hidden,
-            ..default()
-        },
-        LocalBuildGhost,
-    ));
+            StaminaBarSprite { fill },
+        ));
+    }
+
+    let (ghost_notch_size, ghost_notch_offset) =
+        direction_notch(Direction::East, structure_size("beacon", Direction::East));
+    commands
+        .spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: structure_preview_color("beacon", true),
+                    custom_size: Some(Vec2::splat(STRUCTURE_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(0.0, 0.0, BUILD_PREVIEW_Z),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            LocalBuildGhost,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::NONE,
+                        custom_size: Some(ghost_notch_size),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(ghost_notch_offset),
+                    ..default()
+                },
+                LocalBuildGhostNotch,
+            ));
+        });
 }
 
 fn apply_pending_session_reset(
@@ -540,6 +1031,7 @@ fn apply_pending_session_reset(
     mut accumulator: ResMut<SimAccumulator>,
     mut next_input_seq: ResMut<NextInputSeq>,
     mut input_history: ResMut<InputHistory>,
+    mut health_view: ResMut<HealthView>,
     mut placement: ResMut<BuildPlacementState>,
     mut footstep_state: ResMut<FootstepState>,
     mut local_query: Query<
@@ -549,6 +1041,8 @@ fn apply_pending_session_reset(
             &mut ActorVelocity,
             &mut CharacterAnimator,
             &mut TextureAtlas,
+            &mut LocalStamina,
+            &mut LocalWeapon,
         ),
         (With<LocalActor>, Without<LocalBuildGhost>),
     >,
@@ -558,8 +1052,9 @@ fn apply_pending_session_reset(
     >,
     remote_query: Query<Entity, With<RemoteActor>>,
     structure_query: Query<Entity, With<StructureActor>>,
+    belt_chunk_query: Query<Entity, With<BeltChunkActor>>,
     preview_query: Query<Entity, With<BuildPreviewActor>>,
-    projectile_query: Query<Entity, With<ProjectileActor>>,
+    projectile_query: Query<Entity, ProjectileOrEnemy>,
     predicted_projectile_query: Query<Entity, With<PredictedProjectileActor>>,
 ) {
     if !take_pending_session_reset() {
@@ -572,12 +1067,22 @@ fn apply_pending_session_reset(
     accumulator.0 = 0.0;
     next_input_seq.0 = 1;
     input_history.0.clear();
+    *health_view = HealthView::default();
     *placement = BuildPlacementState::default();
     *footstep_state = FootstepState::default();
 
-    if let Ok((mut transform, mut actor, mut velocity, mut animator, mut atlas)) =
-        local_query.get_single_mut()
+    if let Ok((
+        mut transform,
+        mut actor,
+        mut velocity,
+        mut animator,
+        mut atlas,
+        mut stamina,
+        mut weapon,
+    )) = local_query.get_single_mut()
     {
+        stamina.0 = Stamina::full();
+        *weapon = LocalWeapon::default();
         transform.translation.x = 0.0;
         transform.translation.y = 0.0;
         velocity.0 = Vec2::ZERO;
@@ -600,6 +1105,9 @@ fn apply_pending_session_reset(
     for entity in &structure_query {
         commands.entity(entity).despawn_recursive();
     }
+    for entity in &belt_chunk_query {
+        commands.entity(entity).despawn_recursive();
+    }
     for entity in &preview_query {
         commands.entity(entity).despawn_recursive();
     }
@@ -629,13 +1137,198 @@ fn sync_player_id(
     }
 }
 
+const NEST_RGBA: [u8; 4] = [74, 30, 38, 255];
+
+fn terrain_tile_rgba(tile: TerrainTile, tile_x: i32, tile_y: i32) -> [u8; 4] {
+    let (light, dark) = match tile {
+        TerrainTile::Ground => ([24, 44, 33], [21, 35, 27]),
+        TerrainTile::Water => ([24, 62, 104], [21, 55, 94]),
+        TerrainTile::Ore(OreKind::Iron) => ([92, 104, 118], [82, 93, 106]),
+        TerrainTile::Ore(OreKind::Copper) => ([156, 92, 54], [140, 82, 47]),
+        TerrainTile::Ore(OreKind::Stone) => ([134, 118, 90], [120, 105, 80]),
+        TerrainTile::Ore(OreKind::Coal) => ([40, 40, 44], [31, 31, 35]),
+    };
+    let [r, g, b] = if (tile_x + tile_y).rem_euclid(2) == 0 {
+        light
+    } else {
+        dark
+    };
+    [r, g, b, 255]
+}
+
+/// One texel per tile; nearest sampling scales it up to tile size.
+fn terrain_chunk_image(terrain: &Terrain, chunk: IVec2) -> Image {
+    let size = TERRAIN_CHUNK_TILES as usize;
+    let tiles = terrain.chunk_tiles(chunk.x, chunk.y);
+    let mut data = vec![0u8; size * size * 4];
+    for (index, tile) in tiles.into_iter().enumerate() {
+        let local_x = index % size;
+        let local_y = index / size;
+        // Texture rows run top-down while world y points up.
+        let texel = ((size - 1 - local_y) * size + local_x) * 4;
+        data[texel..texel + 4].copy_from_slice(&terrain_tile_rgba(
+            tile,
+            chunk.x * TERRAIN_CHUNK_TILES + local_x as i32,
+            chunk.y * TERRAIN_CHUNK_TILES + local_y as i32,
+        ));
+    }
+    // Nests cover the tiles around their center, all inside the chunk.
+    if let Some((nest_x, nest_y)) = terrain.nest_in_chunk(chunk.x, chunk.y) {
+        let local_x = (nest_x - chunk.x * TERRAIN_CHUNK_TILES) as usize;
+        let local_y = (nest_y - chunk.y * TERRAIN_CHUNK_TILES) as usize;
+        for y in local_y - 1..=local_y + 1 {
+            for x in local_x - 1..=local_x + 1 {
+                let texel = ((size - 1 - y) * size + x) * 4;
+                data[texel..texel + 4].copy_from_slice(&NEST_RGBA);
+            }
+        }
+    }
+
+    Image::new(
+        Extent3d {
+            width: size as u32,
+            height: size as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+}
+
+fn sync_terrain_chunks(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut terrain_view: ResMut<TerrainView>,
+    camera_query: Query<&Transform, With<Camera2d>>,
+) {
+    let pending_seed = PENDING_TERRAIN_SEED
+        .lock()
+        .ok()
+        .and_then(|mut pending| pending.take());
+    if let Some(seed) = pending_seed {
+        if terrain_view.terrain.map(|terrain| terrain.seed()) != Some(seed) {
+            for (_, entity) in terrain_view.chunks.drain() {
+                commands.entity(entity).despawn();
+            }
+            terrain_view.terrain = Some(Terrain::new(seed));
+        }
+    }
+
+    let Some(terrain) = terrain_view.terrain else {
+        return;
+    };
+    let Ok(camera) = camera_query.get_single() else {
+        return;
+    };
+
+    let center = IVec2::new(
+        tile_to_chunk(world_to_tile(camera.translation.x)),
+        tile_to_chunk(world_to_tile(camera.translation.y)),
+    );
+    // Keep one extra ring alive so chunks do not flicker at the boundary.
+    terrain_view.chunks.retain(|chunk, entity| {
+        let keep = (*chunk - center).abs().max_element() <= TERRAIN_VIEW_RADIUS_CHUNKS + 1;
+        if !keep {
+            commands.entity(*entity).despawn();
+        }
+        keep
+    });
+
+    let chunk_world_size = TERRAIN_CHUNK_TILES as f32 * TILE_SIZE;
+    for chunk_y in -TERRAIN_VIEW_RADIUS_CHUNKS..=TERRAIN_VIEW_RADIUS_CHUNKS {
+        for chunk_x in -TERRAIN_VIEW_RADIUS_CHUNKS..=TERRAIN_VIEW_RADIUS_CHUNKS {
+            let chunk = center + IVec2::new(chunk_x, chunk_y);
+            if terrain_view.chunks.contains_key(&chunk) {
+                continue;
+            }
+
+            // Tile centers sit on multiples of TILE_SIZE, so chunks start half a tile early.
+            let origin = chunk.as_vec2() * chunk_world_size - Vec2::splat(TILE_SIZE * 0.5);
+            let entity = commands
+                .spawn(SpriteBundle {
+                    texture: images.add(terrain_chunk_image(&terrain, chunk)),
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::splat(chunk_world_size)),
+                        anchor: bevy::sprite::Anchor::BottomLeft,
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(origin.x, origin.y, FLOOR_Z),
+                    ..default()
+                })
+                .id();
+            terrain_view.chunks.insert(chunk, entity);
+        }
+    }
+}
+
+/// Advances the local player one step, draining or refilling `stamina`.
+fn predict_movement_step(
+    position: Vec2,
+    state: &InputState,
+    stamina: &mut Stamina,
+    obstacle_index: &ObstacleIndex<String>,
+    terrain: Option<&Terrain>,
+) -> MovementStep {
+    let speed = stamina.step(to_core_input(state), MOVE_SPEED, CLIENT_SIM_DT);
+    let structure_obstacles = &obstacle_index.obstacles_near(
+        position.x,
+        position.y,
+        step_reach(speed, CLIENT_SIM_DT, PLAYER_COLLIDER_RADIUS),
+    );
+    match terrain {
+        Some(terrain) => movement_step_with_terrain_fixed(
+            position.x,
+            position.y,
+            to_core_input(state),
+            CLIENT_SIM_DT,
+            speed,
+            MAP_LIMIT,
+            structure_obstacles,
+            PLAYER_COLLIDER_RADIUS,
+            terrain,
+        ),
+        None => movement_step_with_obstacles_fixed(
+            position.x,
+            position.y,
+            to_core_input(state),
+            CLIENT_SIM_DT,
+            speed,
+            MAP_LIMIT,
+            structure_obstacles,
+            PLAYER_COLLIDER_RADIUS,
+        ),
+    }
+}
+
 fn sample_input_state(input: &ButtonInput<KeyCode>) -> InputState {
+    let gamepad = GAMEPAD_INPUT
+        .lock()
+        .map(|gamepad| *gamepad)
+        .unwrap_or_default();
+    let (move_x, move_y) = quantize_stick(gamepad.stick);
     InputState {
         up: input.pressed(KeyCode::KeyW) || input.pressed(KeyCode::ArrowUp),
         down: input.pressed(KeyCode::KeyS) || input.pressed(KeyCode::ArrowDown),
         left: input.pressed(KeyCode::KeyA) || input.pressed(KeyCode::ArrowLeft),
         right: input.pressed(KeyCode::KeyD) || input.pressed(KeyCode::ArrowRight),
+        move_x,
+        move_y,
+        sprint: input.pressed(KeyCode::ShiftLeft)
+            || input.pressed(KeyCode::ShiftRight)
+            || gamepad.sprint,
+    }
+}
+
+/// Stick position as the wire's `-ANALOG_AXIS_MAX..=ANALOG_AXIS_MAX` axes;
+/// zero inside the deadzone so the keyboard keeps working.
+fn quantize_stick(stick: Vec2) -> (i8, i8) {
+    if !stick.is_finite() || stick.length() < GAMEPAD_DEADZONE {
+        return (0, 0);
     }
+    let scaled = stick.clamp_length_max(1.0) * ANALOG_AXIS_MAX as f32;
+    (scaled.x.round() as i8, scaled.y.round() as i8)
 }
 
 fn to_core_input(input: &InputState) -> CoreInputState {
@@ -644,6 +1337,9 @@ fn to_core_input(input: &InputState) -> CoreInputState {
         down: input.down,
         left: input.left,
         right: input.right,
+        move_x: input.move_x,
+        move_y: input.move_y,
+        sprint: input.sprint,
     }
 }
 
@@ -712,6 +1408,7 @@ fn set_local_build_ghost_visible(
             Visibility::Hidden
         };
         sprite.color = structure_preview_color(kind, true);
+        sprite.custom_size = Some(structure_size(kind, Direction::East));
     }
 }
 
@@ -734,11 +1431,55 @@ fn handle_build_placement_controls(
     input: Res<ButtonInput<KeyCode>>,
     mouse_buttons: Res<ButtonInput<MouseButton>>,
     sfx_handles: Res<SfxAudioHandles>,
+    terrain_view: Res<TerrainView>,
     mut placement: ResMut<BuildPlacementState>,
     window_query: Query<&Window, With<PrimaryWindow>>,
     camera_query: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
     mut ghost_query: Query<(&mut Transform, &mut Visibility, &mut Sprite), With<LocalBuildGhost>>,
+    mut ghost_notch_query: Query<
+        (&LocalBuildGhostNotch, &mut Sprite, &mut Transform),
+        Without<LocalBuildGhost>,
+    >,
 ) {
+    let kind_keys = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+    // Number keys follow the prototype file's order.
+    let kinds = structure_prototypes()
+        .iter()
+        .map(|prototype| prototype.kind.as_str());
+    for (key, kind) in kind_keys.into_iter().zip(kinds) {
+        if input.just_pressed(key) {
+            placement.kind = kind;
+        }
+    }
+    if input.just_pressed(KeyCode::KeyR) {
+        placement.direction = placement.direction.rotate_cw();
+    }
+    // The ghost is sized facing east with its notch on the east edge;
+    // rotating the whole ghost turns both the footprint and the notch.
+    if let Ok((_, mut sprite, mut transform)) = ghost_notch_query.get_single_mut() {
+        let (notch_size, offset) = direction_notch(
+            Direction::East,
+            structure_size(placement.kind, Direction::East),
+        );
+        sprite.custom_size = Some(notch_size);
+        sprite.color = GHOST_NOTCH_COLOR;
+        transform.translation = offset;
+    }
+    if let Ok((mut transform, _, _)) = ghost_query.get_single_mut() {
+        let forward = direction_vec(placement.direction);
+        transform.rotation = Quat::from_rotation_z(forward.y.atan2(forward.x));
+    }
+
     if input.just_pressed(KeyCode::KeyQ) {
         if placement.active {
             disable_build_mode(&mut placement);
@@ -780,9 +1521,21 @@ fn handle_build_placement_controls(
     };
 
     let (cell, snapped) = snap_world_to_build_grid(world_pos);
-    set_local_build_ghost_visible(&mut ghost_query, true, snapped, placement.kind);
-
-    placement.send_cooldown += time.delta_seconds();
+    let footprint = Footprint::for_structure(placement.kind, placement.direction);
+    let center = Vec2::from(footprint.center((cell.x, cell.y)));
+    set_local_build_ghost_visible(&mut ghost_query, true, center, placement.kind);
+    let buildable = terrain_view.terrain.is_none_or(|terrain| {
+        footprint
+            .cells((cell.x, cell.y))
+            .all(|(x, y)| terrain.tile_at(x, y).is_buildable())
+    });
+    if !buildable {
+        if let Ok((_, _, mut sprite)) = ghost_query.get_single_mut() {
+            sprite.color = BLOCKED_GHOST_COLOR;
+        }
+    }
+
+    placement.send_cooldown += time.delta_seconds();
     let cell_changed = placement.last_sent_cell != Some(cell);
     if cell_changed || placement.send_cooldown >= BUILD_PREVIEW_SEND_INTERVAL_SECONDS {
         placement.last_sent_cell = Some(cell);
@@ -795,11 +1548,12 @@ fn handle_build_placement_controls(
                 "x": snapped.x,
                 "y": snapped.y,
                 "kind": placement.kind,
+                "direction": placement.direction.as_str(),
             }),
         );
     }
 
-    if mouse_buttons.just_pressed(MouseButton::Left) {
+    if mouse_buttons.just_pressed(MouseButton::Left) && buildable {
         commands.spawn(AudioBundle {
             source: sfx_handles.placement_clip.clone(),
             settings: bevy::audio::PlaybackSettings::DESPAWN
@@ -814,6 +1568,7 @@ fn handle_build_placement_controls(
                 "x": snapped.x,
                 "y": snapped.y,
                 "kind": placement.kind,
+            "direction": placement.direction.as_str(),
                 "clientBuildId": format!("build_{}", Uuid::new_v4()),
             }),
         );
@@ -823,44 +1578,46 @@ fn handle_build_placement_controls(
 fn simulate_local_player(
     input: Res<ButtonInput<KeyCode>>,
     time: Res<Time>,
+    current_player_id: Res<CurrentPlayerId>,
+    health_view: Res<HealthView>,
+    terrain_view: Res<TerrainView>,
     mut accumulator: ResMut<SimAccumulator>,
     mut next_input_seq: ResMut<NextInputSeq>,
     mut input_history: ResMut<InputHistory>,
-    structure_query: Query<&Transform, (With<StructureActor>, Without<LocalActor>)>,
     mut local_transform_query: Query<
-        (&mut Transform, &mut ActorVelocity),
-        (With<LocalActor>, Without<StructureActor>),
+        (&mut Transform, &mut ActorVelocity, &mut LocalStamina),
+        Without<StructureActor>,
     >,
 ) {
-    let Ok((mut transform, mut velocity)) = local_transform_query.get_single_mut() else {
+    let Ok((mut transform, mut velocity, mut stamina)) = local_transform_query.get_single_mut()
+    else {
         return;
     };
 
     accumulator.0 += time.delta_seconds();
     let mut steps = 0;
-    let structure_obstacles: Vec<StructureObstacle> = structure_query
-        .iter()
-        .map(|structure| StructureObstacle {
-            x: structure.translation.x,
-            y: structure.translation.y,
-            half_extent: STRUCTURE_COLLIDER_HALF_EXTENT,
-        })
-        .collect();
+    let Ok(structures) = RENDER_STRUCTURES.lock() else {
+        return;
+    };
+    // The server ignores movement while dead; predicting neutral input keeps
+    // the replayed history consistent with that.
+    let local_dead = health_view.player_is_dead(current_player_id.0.as_deref());
 
     while accumulator.0 >= CLIENT_SIM_DT && steps < MAX_SIM_STEPS_PER_FRAME {
         accumulator.0 -= CLIENT_SIM_DT;
         steps += 1;
 
-        let state = sample_input_state(&input);
-        let step = movement_step_with_obstacles(
-            transform.translation.x,
-            transform.translation.y,
-            to_core_input(&state),
-            CLIENT_SIM_DT,
-            MOVE_SPEED,
-            MAP_LIMIT,
-            &structure_obstacles,
-            PLAYER_COLLIDER_RADIUS,
+        let state = if local_dead {
+            InputState::default()
+        } else {
+            sample_input_state(&input)
+        };
+        let step = predict_movement_step(
+            transform.translation.truncate(),
+            &state,
+            &mut stamina.0,
+            &structures.obstacles,
+            terrain_view.terrain.as_ref(),
         );
         transform.translation.x = step.x;
         transform.translation.y = step.y;
@@ -888,6 +1645,9 @@ fn simulate_local_player(
                 down: state.down,
                 left: state.left,
                 right: state.right,
+                move_x: state.move_x,
+                move_y: state.move_y,
+                sprint: state.sprint,
             });
 
             if outbound.len() > MAX_OUTBOUND_INPUTS {
@@ -959,21 +1719,66 @@ fn emit_footstep_audio(
     }
 }
 
+/// Weapon after `current` in `WEAPONS`, wrapping around.
+fn next_weapon(current: &Weapon) -> &'static Weapon {
+    let index = WEAPONS
+        .iter()
+        .position(|weapon| weapon.id == current.id)
+        .map_or(0, |index| (index + 1) % WEAPONS.len());
+    &WEAPONS[index]
+}
+
+fn handle_weapon_controls(
+    input: Res<ButtonInput<KeyCode>>,
+    local_query: Query<&LocalWeapon, With<LocalActor>>,
+) {
+    if !input.just_pressed(KeyCode::KeyF) {
+        return;
+    }
+    let Ok(local_weapon) = local_query.get_single() else {
+        return;
+    };
+
+    // The switch shows up once the server confirms it in a snapshot.
+    queue_feature_command(
+        "projectile",
+        "equip",
+        json!({ "weaponId": next_weapon(local_weapon.weapon).id }),
+    );
+}
+
 fn emit_projectile_fire_command(
     mut commands: Commands,
     input: Res<ButtonInput<KeyCode>>,
-    local_transform_query: Query<&Transform, With<LocalActor>>,
+    time: Res<Time>,
+    current_player_id: Res<CurrentPlayerId>,
+    health_view: Res<HealthView>,
+    mut local_query: Query<(&Transform, &mut LocalWeapon), With<LocalActor>>,
 ) {
-    if !input.just_pressed(KeyCode::Space) {
+    if !input.pressed(KeyCode::Space) {
+        return;
+    }
+    if health_view.player_is_dead(current_player_id.0.as_deref()) {
         return;
     }
 
-    let Ok(local_transform) = local_transform_query.get_single() else {
+    let Ok((local_transform, mut local_weapon)) = local_query.get_single_mut() else {
         return;
     };
 
+    let weapon = local_weapon.weapon;
+    let now = time.elapsed().as_millis() as i64;
+    if !local_weapon.cooldowns.ready(weapon, now) {
+        return;
+    }
+
+    // Spread is rolled by the server; the predicted shot flies dead on.
     let direction = movement_direction_from_input(&input);
-    let velocity = direction * PROJECTILE_SPEED;
+    let Some((vx, vy)) = weapon.shot_velocity(direction.x, direction.y, 0.0) else {
+        return;
+    };
+    let velocity = Vec2::new(vx, vy);
+    local_weapon.cooldowns.fired(weapon, now);
     let client_projectile_id = format!("proj_{}", Uuid::new_v4());
 
     queue_feature_command(
@@ -985,6 +1790,7 @@ fn emit_projectile_fire_command(
             "vx": velocity.x,
             "vy": velocity.y,
             "clientProjectileId": client_projectile_id,
+            "weaponId": weapon.id,
         }),
     );
 
@@ -1006,7 +1812,7 @@ fn emit_projectile_fire_command(
             client_projectile_id,
         },
         PredictedProjectileVelocity(velocity),
-        PredictedProjectileLifetime(PROJECTILE_TTL_SECONDS),
+        PredictedProjectileLifetime(weapon.projectile_ttl_ms as f32 / 1000.0),
         PredictedProjectileTarget {
             has_target: false,
             position: Vec2::ZERO,
@@ -1017,6 +1823,7 @@ fn emit_projectile_fire_command(
 fn simulate_predicted_projectiles(
     time: Res<Time>,
     mut commands: Commands,
+    remote_query: Query<&Transform, (With<RemoteActor>, Without<PredictedProjectileActor>)>,
     mut predicted_query: Query<
         (
             Entity,
@@ -1029,11 +1836,42 @@ fn simulate_predicted_projectiles(
     >,
 ) {
     let dt = time.delta_seconds();
+    let Ok(structures) = RENDER_STRUCTURES.lock() else {
+        return;
+    };
+    let remote_colliders: Vec<PlayerCollider> = remote_query
+        .iter()
+        .map(|remote| PlayerCollider {
+            x: remote.translation.x,
+            y: remote.translation.y,
+            radius: PLAYER_COLLIDER_RADIUS,
+        })
+        .collect();
+
     for (entity, mut transform, velocity, mut ttl, target) in &mut predicted_query {
-        transform.translation.x =
-            (transform.translation.x + velocity.0.x * dt).clamp(-MAP_LIMIT, MAP_LIMIT);
-        transform.translation.y =
-            (transform.translation.y + velocity.0.y * dt).clamp(-MAP_LIMIT, MAP_LIMIT);
+        let structure_obstacles = structures.obstacles.obstacles_near(
+            transform.translation.x,
+            transform.translation.y,
+            step_reach(velocity.0.length(), dt, PROJECTILE_COLLIDER_RADIUS),
+        );
+        let step = projectile_step_with_hits_fixed(
+            transform.translation.x,
+            transform.translation.y,
+            velocity.0.x,
+            velocity.0.y,
+            dt,
+            MAP_LIMIT,
+            &structure_obstacles,
+            &remote_colliders,
+            PROJECTILE_COLLIDER_RADIUS,
+        );
+        transform.translation.x = step.x;
+        transform.translation.y = step.y;
+
+        if step.hit.is_some() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
 
         let current = transform.translation.truncate();
         let error = target.position - current;
@@ -1061,13 +1899,26 @@ fn apply_latest_snapshot(
     character_atlas: Res<CharacterAtlasHandles>,
     current_player_id: Res<CurrentPlayerId>,
     mut input_history: ResMut<InputHistory>,
-    mut local_query: Query<(&mut Transform, &mut Actor), (With<LocalActor>, Without<RemoteActor>)>,
+    mut health_view: ResMut<HealthView>,
+    (mut power_view, mut research_view): (ResMut<PowerView>, ResMut<ResearchView>),
+    terrain_view: Res<TerrainView>,
+    mut local_query: Query<
+        (
+            &mut Transform,
+            &mut Actor,
+            &mut LocalStamina,
+            &mut LocalWeapon,
+        ),
+        Without<RemoteActor>,
+    >,
     remote_query: Query<(Entity, &Actor), (With<RemoteActor>, Without<LocalActor>)>,
     structure_query: Query<(Entity, &StructureActor)>,
     preview_query: Query<(Entity, &BuildPreviewActor)>,
     projectile_query: Query<(Entity, &ProjectileActor)>,
     predicted_projectile_query: Query<(Entity, &PredictedProjectileActor)>,
+    enemy_query: Query<(Entity, &EnemyActor)>,
     mut predicted_target_query: Query<&mut PredictedProjectileTarget>,
+    mut synced_structure_revision: Local<u64>,
 ) {
     let latest_snapshot = {
         let mut queue = match INBOUND_SNAPSHOTS.lock() {
@@ -1089,19 +1940,52 @@ fn apply_latest_snapshot(
         local_ack_seq,
         render_delay_ms,
         players,
-        structures,
         previews,
         projectiles,
+        enemies,
+        power_networks,
+        research,
+        player_health,
+        structure_health,
         ..
     } = snapshot;
-    let structure_obstacles: Vec<StructureObstacle> = structures
-        .iter()
-        .map(|structure| StructureObstacle {
-            x: structure.x,
-            y: structure.y,
-            half_extent: STRUCTURE_COLLIDER_HALF_EXTENT,
-        })
+    power_view.0 = power_networks
+        .into_iter()
+        .map(|network| (network.id, network.satisfaction))
         .collect();
+    if let Some(research) = research {
+        let next = ResearchState::restore(
+            research.unlocked.iter().map(String::as_str),
+            research.current.as_deref(),
+            research
+                .progress
+                .iter()
+                .map(|(id, packs)| (id.as_str(), *packs)),
+        );
+        // Only a real change should re-render the research screen.
+        if research_view.0 != next {
+            research_view.0 = next;
+        }
+    }
+    health_view.players = player_health
+        .into_iter()
+        .map(|health| (health.id.clone(), health))
+        .collect();
+    health_view.structures = structure_health
+        .into_iter()
+        .map(|health| (health.id.clone(), health))
+        .collect();
+    let Ok(structure_store) = RENDER_STRUCTURES.lock() else {
+        return;
+    };
+    let changed_structures = (structure_store.revision != *synced_structure_revision).then(|| {
+        *synced_structure_revision = structure_store.revision;
+        structure_store
+            .structures
+            .values()
+            .cloned()
+            .collect::<Vec<_>>()
+    });
     let local_player_id = current_player_id.0.clone();
 
     let mut remote_entities: HashMap<String, Entity> = remote_query
@@ -1116,14 +2000,27 @@ fn apply_latest_snapshot(
             .is_some_and(|player_id| player_id == player.id);
 
         if is_local {
-            if let Ok((mut local_transform, mut local_actor)) = local_query.get_single_mut() {
+            if let Ok((mut local_transform, mut local_actor, mut local_stamina, mut local_weapon)) =
+                local_query.get_single_mut()
+            {
                 local_actor.id = player.id.clone();
+                if let Some(weapon) = player.weapon.as_deref().and_then(weapon_by_id) {
+                    local_weapon.weapon = weapon;
+                }
+                if let Some(seconds) = player.stamina {
+                    local_stamina.0 = Stamina {
+                        seconds,
+                        exhausted: player.exhausted.unwrap_or(false),
+                    };
+                }
                 reconcile_local_transform(
                     &mut local_transform,
                     Vec2::new(player.x, player.y),
                     local_ack_seq,
                     &mut input_history,
-                    &structure_obstacles,
+                    &mut local_stamina.0,
+                    &structure_store.obstacles,
+                    terrain_view.terrain.as_ref(),
                 );
             }
 
@@ -1144,29 +2041,45 @@ fn apply_latest_snapshot(
         }
     }
 
+    drop(structure_store);
+
     for entity in remote_entities.values() {
         commands.entity(*entity).despawn_recursive();
     }
 
-    let mut structure_entities: HashMap<String, Entity> = structure_query
-        .iter()
-        .map(|(entity, structure)| (structure.id.clone(), entity))
-        .collect();
-
-    for structure in structures {
-        if let Some(entity) = structure_entities.remove(&structure.id) {
-            commands.entity(entity).insert(Transform::from_xyz(
-                structure.x,
-                structure.y,
-                STRUCTURE_Z,
-            ));
-        } else {
-            spawn_structure_actor(&mut commands, &structure);
+    if let Some(structures) = changed_structures {
+        let mut structure_entities: HashMap<String, Entity> = structure_query
+            .iter()
+            .map(|(entity, structure)| (structure.id.clone(), entity))
+            .collect();
+
+        for structure in structures {
+            if let Some(entity) = structure_entities.remove(&structure.id) {
+                commands.entity(entity).insert(Transform::from_xyz(
+                    structure.x,
+                    structure.y,
+                    STRUCTURE_Z,
+                ));
+                if let Some(miner) = structure.miner {
+                    commands.entity(entity).insert(miner);
+                }
+                if let Some(assembler) = structure.assembler {
+                    commands.entity(entity).insert(assembler);
+                }
+                if let Some(inserter) = structure.inserter {
+                    commands.entity(entity).insert(inserter);
+                }
+                if let Some(power) = structure.power {
+                    commands.entity(entity).insert(power);
+                }
+            } else {
+                spawn_structure_actor(&mut commands, &structure);
+            }
         }
-    }
 
-    for entity in structure_entities.values() {
-        commands.entity(*entity).despawn_recursive();
+        for entity in structure_entities.values() {
+            commands.entity(*entity).despawn_recursive();
+        }
     }
 
     let mut preview_entities: HashMap<String, Entity> = preview_query
@@ -1186,10 +2099,11 @@ fn apply_latest_snapshot(
         }
 
         if let Some(entity) = preview_entities.remove(&preview.player_id) {
-            commands.entity(entity).insert(Transform::from_xyz(
-                preview.x,
-                preview.y,
-                BUILD_PREVIEW_Z,
+            // Kind and rotation can change between previews, so the sprite is
+            // replaced along with the position.
+            commands.entity(entity).insert((
+                Transform::from_xyz(preview.x, preview.y, BUILD_PREVIEW_Z),
+                build_preview_sprite(&preview),
             ));
         } else {
             spawn_build_preview_actor(&mut commands, &preview);
@@ -1246,6 +2160,25 @@ fn apply_latest_snapshot(
     for entity in projectile_entities.values() {
         commands.entity(*entity).despawn_recursive();
     }
+
+    let mut enemy_entities: HashMap<String, Entity> = enemy_query
+        .iter()
+        .map(|(entity, enemy)| (enemy.id.clone(), entity))
+        .collect();
+    for enemy in enemies {
+        if let Some(entity) = enemy_entities.remove(&enemy.id) {
+            commands.entity(entity).insert((
+                Transform::from_xyz(enemy.x, enemy.y, SNAPSHOT_Z),
+                enemy_sprite(&enemy),
+            ));
+        } else {
+            spawn_enemy_actor(&mut commands, &enemy);
+        }
+    }
+
+    for entity in enemy_entities.values() {
+        commands.entity(*entity).despawn_recursive();
+    }
 }
 
 fn reconcile_local_transform(
@@ -1253,7 +2186,9 @@ fn reconcile_local_transform(
     authoritative_position: Vec2,
     local_ack_seq: u32,
     input_history: &mut InputHistory,
-    structure_obstacles: &[StructureObstacle],
+    stamina: &mut Stamina,
+    obstacle_index: &ObstacleIndex<String>,
+    terrain: Option<&Terrain>,
 ) {
     while input_history
         .0
@@ -1265,15 +2200,12 @@ fn reconcile_local_transform(
 
     let mut replay_position = authoritative_position;
     for entry in input_history.0.iter() {
-        let step = movement_step_with_obstacles(
-            replay_position.x,
-            replay_position.y,
-            to_core_input(&entry.state),
-            CLIENT_SIM_DT,
-            MOVE_SPEED,
-            MAP_LIMIT,
-            structure_obstacles,
-            PLAYER_COLLIDER_RADIUS,
+        let step = predict_movement_step(
+            replay_position,
+            &entry.state,
+            stamina,
+            obstacle_index,
+            terrain,
         );
         replay_position.x = step.x;
         replay_position.y = step.y;
@@ -1298,6 +2230,174 @@ fn smooth_remote_motion(
     }
 }
 
+fn apply_player_life_state(
+    health_view: Res<HealthView>,
+    mut actor_query: Query<(&Actor, &mut Visibility, &mut Sprite)>,
+) {
+    for (actor, mut visibility, mut sprite) in &mut actor_query {
+        let health = health_view.players.get(&actor.id);
+        let dead = health.is_some_and(|health| health.dead);
+        let invulnerable = health.is_some_and(|health| health.invulnerable);
+
+        let next_visibility = if dead {
+            Visibility::Hidden
+        } else {
+            Visibility::Inherited
+        };
+        if *visibility != next_visibility {
+            *visibility = next_visibility;
+        }
+        let alpha = if invulnerable {
+            INVULNERABLE_ALPHA
+        } else {
+            1.0
+        };
+        let base = sprite.color.to_srgba();
+        if (base.alpha - alpha).abs() > f32::EPSILON {
+            sprite.color = Color::srgba(base.red, base.green, base.blue, alpha);
+        }
+    }
+}
+
+fn sync_health_bars(
+    mut commands: Commands,
+    health_view: Res<HealthView>,
+    actor_query: Query<(&Actor, &Transform), Without<HealthBarSprite>>,
+    structure_query: Query<(&StructureActor, &Transform), Without<HealthBarSprite>>,
+    mut bar_query: Query<(Entity, &HealthBarSprite, &mut Transform, &mut Sprite)>,
+) {
+    // (target id, health ratio, bar anchor) for everything currently below max health.
+    let mut wanted: HashMap<String, (f32, Vec2)> = HashMap::new();
+    for (actor, transform) in &actor_query {
+        let Some(health) = health_view.players.get(&actor.id) else {
+            continue;
+        };
+        if health.dead || health.hp >= health.max_hp {
+            continue;
+        }
+        wanted.insert(
+            actor.id.clone(),
+            (
+                health.hp as f32 / health.max_hp.max(1) as f32,
+                transform.translation.truncate() + Vec2::Y * PLAYER_HEALTH_BAR_OFFSET,
+            ),
+        );
+    }
+    for (structure, transform) in &structure_query {
+        let Some(health) = health_view.structures.get(&structure.id) else {
+            continue;
+        };
+        wanted.insert(
+            structure.id.clone(),
+            (
+                health.hp as f32 / health.max_hp.max(1) as f32,
+                transform.translation.truncate()
+                    + Vec2::Y * (structure.half_size.y + STRUCTURE_HEALTH_BAR_GAP),
+            ),
+        );
+    }
+
+    let mut existing: HashSet<String> = HashSet::new();
+    for (entity, bar, mut transform, mut sprite) in &mut bar_query {
+        let Some((ratio, anchor)) = wanted.get(&bar.target_id) else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+
+        existing.insert(bar.target_id.clone());
+        if bar.fill {
+            let width = HEALTH_BAR_WIDTH * ratio.clamp(0.0, 1.0);
+            sprite.custom_size = Some(Vec2::new(width, HEALTH_BAR_HEIGHT));
+            transform.translation.x = anchor.x - (HEALTH_BAR_WIDTH - width) * 0.5;
+        } else {
+            transform.translation.x = anchor.x;
+        }
+        transform.translation.y = anchor.y;
+    }
+
+    for (target_id, (ratio, anchor)) in wanted {
+        if existing.contains(&target_id) {
+            continue;
+        }
+        spawn_health_bar(&mut commands, target_id, ratio, anchor);
+    }
+}
+
+/// Shows the local sprint budget under the player while it is not full.
+fn sync_stamina_bar(
+    local_query: Query<(&Transform, &LocalStamina), Without<StaminaBarSprite>>,
+    mut bar_query: Query<(
+        &StaminaBarSprite,
+        &mut Transform,
+        &mut Sprite,
+        &mut Visibility,
+    )>,
+) {
+    let Ok((local_transform, stamina)) = local_query.get_single() else {
+        return;
+    };
+    let anchor = local_transform.translation.truncate() + Vec2::Y * PLAYER_STAMINA_BAR_OFFSET;
+    let ratio = (stamina.0.seconds / STAMINA_MAX_SECONDS).clamp(0.0, 1.0);
+
+    for (bar, mut transform, mut sprite, mut visibility) in &mut bar_query {
+        *visibility = if stamina.0.is_full() {
+            Visibility::Hidden
+        } else {
+            Visibility::Visible
+        };
+        transform.translation.x = anchor.x;
+        transform.translation.y = anchor.y;
+        if bar.fill {
+            let width = HEALTH_BAR_WIDTH * ratio;
+            sprite.custom_size = Some(Vec2::new(width, HEALTH_BAR_HEIGHT));
+            sprite.color = if stamina.0.exhausted {
+                Color::srgb_u8(148, 163, 184)
+            } else {
+                Color::srgb_u8(250, 204, 21)
+            };
+            transform.translation.x -= (HEALTH_BAR_WIDTH - width) * 0.5;
+        }
+    }
+}
+
+fn spawn_health_bar(commands: &mut Commands, target_id: String, ratio: f32, anchor: Vec2) {
+    let width = HEALTH_BAR_WIDTH * ratio.clamp(0.0, 1.0);
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::srgba_u8(12, 16, 24, 200),
+                custom_size: Some(Vec2::new(HEALTH_BAR_WIDTH, HEALTH_BAR_HEIGHT)),
+                ..default()
+            },
+            transform: Transform::from_xyz(anchor.x, anchor.y, HEALTH_BAR_Z),
+            ..default()
+        },
+        HealthBarSprite {
+            target_id: target_id.clone(),
+            fill: false,
+        },
+    ));
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::srgb_u8(110, 231, 138),
+                custom_size: Some(Vec2::new(width, HEALTH_BAR_HEIGHT)),
+                ..default()
+            },
+            transform: Transform::from_xyz(
+                anchor.x - (HEALTH_BAR_WIDTH - width) * 0.5,
+                anchor.y,
+                HEALTH_BAR_Z + 0.1,
+            ),
+            ..default()
+        },
+        HealthBarSprite {
+            target_id,
+            fill: true,
+        },
+    ));
+}
+
 fn facing_from_velocity(velocity: Vec2, fallback: FacingDirection) -> FacingDirection {
     if velocity.length_squared() < CHARACTER_DIRECTION_EPSILON {
         return fallback;
@@ -1380,14 +2480,49 @@ fn spawn_remote_actor(
 }
 
 fn structure_color(kind: &str) -> Color {
-    match kind {
-        "beacon" => Color::srgb_u8(99, 210, 255),
-        "miner" => Color::srgb_u8(167, 139, 250),
-        "assembler" => Color::srgb_u8(74, 222, 128),
-        _ => Color::srgb_u8(255, 255, 255),
+    let [red, green, blue] =
+        structure_prototype(kind).map_or([255, 255, 255], |prototype| prototype.sprite.color);
+    Color::srgb_u8(red, green, blue)
+}
+
+fn structure_footprint(structure: &StructureState) -> Footprint {
+    let direction = structure
+        .direction
+        .as_deref()
+        .and_then(Direction::parse)
+        .unwrap_or_default();
+    Footprint::for_structure(structure.kind.as_str(), direction)
+}
+
+/// Sprite size covering the footprint, inset so neighbours stay apart. Belts
+/// fill their whole cell so adjacent belts read as one line.
+fn structure_size(kind: &str, direction: Direction) -> Vec2 {
+    let footprint = Footprint::for_structure(kind, direction);
+    let cells = Vec2::new(footprint.width as f32, footprint.height as f32) * BUILD_GRID_SIZE;
+    if structure_prototype(kind).is_some_and(|prototype| prototype.sprite.fills_footprint) {
+        cells
+    } else {
+        cells - Vec2::splat(BUILD_GRID_SIZE - STRUCTURE_SIZE)
     }
 }
 
+fn direction_vec(direction: Direction) -> Vec2 {
+    let (x, y) = direction.offset();
+    Vec2::new(x as f32, y as f32)
+}
+
+/// Size and local offset of a notch along the edge `direction` points at.
+fn direction_notch(direction: Direction, size: Vec2) -> (Vec2, Vec3) {
+    let forward = direction_vec(direction);
+    let offset = forward * (size - Vec2::splat(DIRECTION_NOTCH_THICKNESS)) * 0.5;
+    let notch = if forward.x != 0.0 {
+        Vec2::new(DIRECTION_NOTCH_THICKNESS, size.y * 0.6)
+    } else {
+        Vec2::new(size.x * 0.6, DIRECTION_NOTCH_THICKNESS)
+    };
+    (notch, offset.extend(0.1))
+}
+
 fn structure_preview_color(kind: &str, is_local: bool) -> Color {
     let base = structure_color(kind).to_srgba();
     let alpha = if is_local { 0.55 } else { 0.35 };
@@ -1395,11 +2530,19 @@ fn structure_preview_color(kind: &str, is_local: bool) -> Color {
 }
 
 fn spawn_structure_actor(commands: &mut Commands, structure: &StructureState) {
-    commands.spawn((
+    let direction = structure
+        .direction
+        .as_deref()
+        .and_then(Direction::parse)
+        .unwrap_or_default();
+    let footprint = Footprint::for_structure(structure.kind.as_str(), direction);
+    let size = structure_size(structure.kind.as_str(), direction);
+    let gauge_offset = size.y * 0.5 + MACHINE_GAUGE_GAP;
+    let mut entity = commands.spawn((
         SpriteBundle {
             sprite: Sprite {
                 color: structure_color(structure.kind.as_str()),
-                custom_size: Some(Vec2::splat(STRUCTURE_SIZE)),
+                custom_size: Some(size),
                 ..default()
             },
             transform: Transform::from_xyz(structure.x, structure.y, STRUCTURE_Z),
@@ -1407,18 +2550,576 @@ fn spawn_structure_actor(commands: &mut Commands, structure: &StructureState) {
         },
         StructureActor {
             id: structure.id.clone(),
+            half_size: Vec2::from(footprint.half_size()),
         },
     ));
+
+    if structure_has_behavior(structure.kind.as_str(), StructureBehavior::Chest) {
+        entity.insert(ChestActor);
+    }
+
+    if structure_is_directional(structure.kind.as_str()) {
+        let (notch_size, offset) = direction_notch(direction, size);
+        entity.with_children(|parent| {
+            parent.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::srgb_u8(250, 204, 21),
+                        custom_size: Some(notch_size),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(offset),
+                    ..default()
+                },
+                DirectionNotch,
+            ));
+        });
+    }
+
+    if let Some(power) = structure.power.clone() {
+        entity.insert(power);
+        if power_demand_kw(structure.kind.as_str()) > 0 {
+            entity.with_children(|parent| {
+                parent.spawn((
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: Color::srgba(0.05, 0.05, 0.1, 0.6),
+                            custom_size: Some(size),
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(0.0, 0.0, 0.2),
+                        visibility: Visibility::Hidden,
+                        ..default()
+                    },
+                    UnpoweredOverlay,
+                ));
+            });
+        }
+    }
+
+    if let Some(miner) = structure.miner.clone() {
+        entity.insert(miner).with_children(|parent| {
+            parent.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::new(0.0, MACHINE_GAUGE_HEIGHT)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, -gauge_offset, 0.1),
+                    ..default()
+                },
+                MinerGauge,
+            ));
+        });
+    }
+
+    if let Some(assembler) = structure.assembler.clone() {
+        entity.insert(assembler).with_children(|parent| {
+            parent.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        custom_size: Some(Vec2::new(0.0, MACHINE_GAUGE_HEIGHT)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, -gauge_offset, 0.1),
+                    ..default()
+                },
+                AssemblerGauge,
+            ));
+            parent.spawn((
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::NONE,
+                        custom_size: Some(Vec2::splat(ASSEMBLER_RECIPE_ICON_SIZE)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                    ..default()
+                },
+                AssemblerRecipeIcon,
+            ));
+        });
+    }
+
+    if let Some(inserter) = structure.inserter.clone() {
+        let forward = direction_vec(direction);
+        entity
+            .insert((
+                InserterSwing {
+                    forward,
+                    phase: inserter.phase.clone(),
+                    // Start as if the phase had just finished so a fresh
+                    // spawn does not replay half a swing.
+                    started_at: f32::NEG_INFINITY,
+                },
+                inserter,
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    SpriteBundle {
+                        sprite: Sprite {
+                            custom_size: Some(Vec2::splat(INSERTER_HAND_SIZE)),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(
+                            (-forward * INSERTER_REACH).extend(0.2),
+                        ),
+                        ..default()
+                    },
+                    InserterHand,
+                ));
+            });
+    }
+}
+
+fn item_color(item: Option<&str>) -> Color {
+    match item {
+        Some("iron_ore") => Color::srgb_u8(168, 184, 204),
+        Some("copper_ore") => Color::srgb_u8(222, 132, 76),
+        Some("stone") => Color::srgb_u8(196, 176, 136),
+        Some("coal") => Color::srgb_u8(96, 96, 104),
+        Some("iron_plate") => Color::srgb_u8(206, 214, 224),
+        Some("copper_plate") => Color::srgb_u8(240, 160, 104),
+        Some("stone_brick") => Color::srgb_u8(214, 196, 160),
+        Some("iron_gear") => Color::srgb_u8(148, 163, 184),
+        Some("copper_cable") => Color::srgb_u8(251, 191, 36),
+        Some("circuit") => Color::srgb_u8(52, 211, 153),
+        _ => Color::srgb_u8(120, 120, 120),
+    }
+}
+
+/// Consumers without a network are fully shaded; underpowered ones fade in
+/// proportion to the missing share.
+fn sync_power_overlays(
+    power_view: Res<PowerView>,
+    structure_query: Query<(&StructurePower, &Children)>,
+    mut overlay_query: Query<(&mut Sprite, &mut Visibility), With<UnpoweredOverlay>>,
+) {
+    for (power, children) in &structure_query {
+        let satisfaction = power
+            .network
+            .as_ref()
+            .and_then(|network| power_view.0.get(network))
+            .copied()
+            .unwrap_or(0.0);
+        for child in children.iter() {
+            let Ok((mut sprite, mut visibility)) = overlay_query.get_mut(*child) else {
+                continue;
+            };
+            if satisfaction >= 1.0 {
+                *visibility = Visibility::Hidden;
+                continue;
+            }
+            *visibility = Visibility::Inherited;
+            sprite.color.set_alpha(0.25 + 0.45 * (1.0 - satisfaction));
+        }
+    }
+}
+
+fn sync_miner_gauges(
+    miner_query: Query<(&MinerView, &Children), Changed<MinerView>>,
+    mut gauge_query: Query<(&mut Sprite, &mut Transform), With<MinerGauge>>,
+) {
+    for (miner, children) in &miner_query {
+        let ratio = miner.output as f32 / miner.capacity.max(1) as f32;
+        let width = STRUCTURE_SIZE * ratio.clamp(0.0, 1.0);
+        for child in children.iter() {
+            let Ok((mut sprite, mut transform)) = gauge_query.get_mut(*child) else {
+                continue;
+            };
+            sprite.custom_size = Some(Vec2::new(width, MACHINE_GAUGE_HEIGHT));
+            let base = item_color(miner.item.as_deref()).to_srgba();
+            // A stalled miner (full or off ore) dims its gauge.
+            let alpha = if miner.working { 1.0 } else { 0.5 };
+            sprite.color = Color::srgba(base.red, base.green, base.blue, alpha);
+            transform.translation.x = -(STRUCTURE_SIZE - width) * 0.5;
+        }
+    }
+}
+
+/// Rebuilds the item sprites of every belt chunk that changed since the last
+/// frame. Items are placed along their belt's direction, with the left lane
+/// offset to the left of travel.
+fn sync_belt_items(mut commands: Commands, chunk_query: Query<(Entity, &BeltChunkActor)>) {
+    let Ok(mut belts) = RENDER_BELTS.lock() else {
+        return;
+    };
+    if belts.changed.is_empty() {
+        return;
+    }
+    let directions: HashMap<IVec2, Direction> = match RENDER_STRUCTURES.lock() {
+        Ok(store) => store
+            .structures
+            .values()
+            .filter_map(|structure| {
+                let direction = Direction::parse(structure.direction.as_deref()?)?;
+                let (cell, _) = snap_world_to_build_grid(Vec2::new(structure.x, structure.y));
+                Some((cell, direction))
+            })
+            .collect(),
+        Err(_) => return,
+    };
+
+    let changed: HashSet<String> = belts.changed.drain().collect();
+    for (entity, chunk) in &chunk_query {
+        if changed.contains(&chunk.id) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for chunk in changed.iter().filter_map(|id| belts.chunks.get(id)) {
+        let origin = IVec2::new(chunk.chunk_x, chunk.chunk_y) * BUILD_CHUNK_CELLS;
+        commands
+            .spawn((
+                SpatialBundle::default(),
+                BeltChunkActor {
+                    id: chunk.id.clone(),
+                },
+            ))
+            .with_children(|parent| {
+                for item in chunk.items.chunks_exact(4) {
+                    let &[cell, lane, position, palette_index] = item else {
+                        continue;
+                    };
+                    let cell = origin
+                        + IVec2::new(
+                            cell as i32 % BUILD_CHUNK_CELLS,
+                            cell as i32 / BUILD_CHUNK_CELLS,
+                        );
+                    let Some(direction) = directions.get(&cell) else {
+                        continue;
+                    };
+                    let forward = direction_vec(*direction);
+                    let left = forward.perp();
+                    let along = position as f32 / BELT_LANE_LENGTH as f32 - 0.5;
+                    let side = if lane as usize == BELT_LEFT_LANE {
+                        BELT_LANE_OFFSET
+                    } else {
+                        -BELT_LANE_OFFSET
+                    };
+                    let world = cell.as_vec2() * BUILD_GRID_SIZE
+                        + forward * along * BUILD_GRID_SIZE
+                        + left * side;
+                    let item = chunk.palette.get(palette_index as usize);
+                    parent.spawn(SpriteBundle {
+                        sprite: Sprite {
+                            color: item_color(item.map(String::as_str)),
+                            custom_size: Some(Vec2::splat(BELT_ITEM_SIZE)),
+                            ..default()
+                        },
+                        transform: Transform::from_translation(world.extend(BELT_ITEM_Z)),
+                        ..default()
+                    });
+                }
+            });
+    }
+}
+
+/// The item an assembler's recipe produces first, used to tint its gauge and icon.
+fn recipe_product(recipe: Option<&str>) -> Option<&'static str> {
+    let recipe = recipe_by_id(recipe?)?;
+    recipe.outputs.first().map(|(item, _)| item.as_str())
+}
+
+fn sync_assembler_views(
+    assembler_query: Query<(&AssemblerView, &Children), Changed<AssemblerView>>,
+    mut gauge_query: Query<(&mut Sprite, &mut Transform), With<AssemblerGauge>>,
+    mut icon_query: Query<&mut Sprite, (With<AssemblerRecipeIcon>, Without<AssemblerGauge>)>,
+) {
+    for (assembler, children) in &assembler_query {
+        let product = recipe_product(assembler.recipe.as_deref());
+        let width = STRUCTURE_SIZE * assembler.progress.clamp(0.0, 1.0);
+        for child in children.iter() {
+            if let Ok((mut sprite, mut transform)) = gauge_query.get_mut(*child) {
+                sprite.custom_size = Some(Vec2::new(width, MACHINE_GAUGE_HEIGHT));
+                sprite.color = item_color(product);
+                transform.translation.x = -(STRUCTURE_SIZE - width) * 0.5;
+            }
+            if let Ok(mut sprite) = icon_query.get_mut(*child) {
+                sprite.color = product.map_or(Color::NONE, |product| item_color(Some(product)));
+            }
+        }
+    }
+}
+
+/// Moves each inserter's hand between the cell behind it and the cell in
+/// front: out while extending, back while retracting, parked behind when idle.
+fn animate_inserter_hands(
+    time: Res<Time>,
+    mut inserter_query: Query<(&InserterView, &mut InserterSwing, &Children)>,
+    mut hand_query: Query<(&mut Sprite, &mut Transform), With<InserterHand>>,
+) {
+    let now = time.elapsed_seconds();
+    let swing_seconds = INSERTER_SWING_TICKS as f32 / SERVER_SIM_HZ;
+    for (inserter, mut swing, children) in &mut inserter_query {
+        if swing.phase != inserter.phase {
+            swing.phase.clone_from(&inserter.phase);
+            swing.started_at = now;
+        }
+        let t = ((now - swing.started_at) / swing_seconds).clamp(0.0, 1.0);
+        let reach = match swing.phase.as_str() {
+            "extending" => -1.0 + 2.0 * t,
+            "retracting" => 1.0 - 2.0 * t,
+            _ => -1.0,
+        };
+        for child in children.iter() {
+            let Ok((mut sprite, mut transform)) = hand_query.get_mut(*child) else {
+                continue;
+            };
+            sprite.color = match inserter.held.as_deref() {
+                Some(item) => item_color(Some(item)),
+                None => Color::srgb_u8(64, 64, 64),
+            };
+            let offset = swing.forward * INSERTER_REACH * reach;
+            transform.translation.x = offset.x;
+            transform.translation.y = offset.y;
+        }
+    }
+}
+
+/// Unlocked recipe after `current` in `RECIPES`; cycling past the last one
+/// clears it.
+fn next_recipe_id(current: Option<&str>, research: &ResearchState) -> Option<&'static str> {
+    let start = current
+        .and_then(|id| RECIPES.iter().position(|recipe| recipe.id == id))
+        .map_or(0, |index| index + 1);
+    RECIPES[start..]
+        .iter()
+        .find(|recipe| research.recipe_unlocked(recipe.id))
+        .map(|recipe| recipe.id)
+}
+
+/// C cycles the recipe of the assembler under the cursor.
+fn handle_assembler_recipe_controls(
+    input: Res<ButtonInput<KeyCode>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    assembler_query: Query<(&StructureActor, &Transform, &AssemblerView)>,
+    research_view: Res<ResearchView>,
+) {
+    if !input.just_pressed(KeyCode::KeyC) {
+        return;
+    }
+
+    let Ok(window) = window_query.get_single() else {
+        return;
+    };
+    let Some(cursor_pos) = window.cursor_position() else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    let Some(world_pos) = camera.viewport_to_world_2d(camera_transform, cursor_pos) else {
+        return;
+    };
+
+    let Some((structure, _, assembler)) =
+        assembler_query.iter().find(|(structure, transform, _)| {
+            let offset = (world_pos - transform.translation.truncate()).abs();
+            offset.x < structure.half_size.x && offset.y < structure.half_size.y
+        })
+    else {
+        return;
+    };
+
+    queue_feature_command(
+        "build",
+        "set_recipe",
+        json!({
+            "id": structure.id,
+            "recipe": next_recipe_id(assembler.recipe.as_deref(), &research_view.0),
+        }),
+    );
+}
+
+fn research_text_style() -> TextStyle {
+    TextStyle {
+        font_size: 16.0,
+        color: Color::srgb_u8(226, 232, 240),
+        ..default()
+    }
+}
+
+fn setup_research_screen(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            ResearchScreen,
+        ))
+        .with_children(|root| {
+            root.spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(6.0),
+                    padding: UiRect::all(Val::Px(12.0)),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::srgba(0.02, 0.05, 0.1, 0.9)),
+                ..default()
+            })
+            .with_children(|panel| {
+                panel.spawn(TextBundle::from_section(
+                    "Research (T to close)",
+                    research_text_style(),
+                ));
+                for technology in TECHNOLOGIES {
+                    panel
+                        .spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    padding: UiRect::axes(Val::Px(8.0), Val::Px(4.0)),
+                                    ..default()
+                                },
+                                ..default()
+                            },
+                            ResearchButton(technology.id),
+                        ))
+                        .with_children(|button| {
+                            button.spawn(TextBundle::from_section(
+                                technology.name,
+                                research_text_style(),
+                            ));
+                        });
+                }
+            });
+        });
+}
+
+/// T toggles the research screen. Clicking a technology makes the room
+/// research it; clicking the one in progress stops research.
+fn handle_research_controls(
+    input: Res<ButtonInput<KeyCode>>,
+    research_view: Res<ResearchView>,
+    mut screen_query: Query<&mut Visibility, With<ResearchScreen>>,
+    button_query: Query<(&Interaction, &ResearchButton), Changed<Interaction>>,
+) {
+    if input.just_pressed(KeyCode::KeyT) {
+        for mut visibility in &mut screen_query {
+            *visibility = match *visibility {
+                Visibility::Hidden => Visibility::Inherited,
+                _ => Visibility::Hidden,
+            };
+        }
+    }
+
+    let current = research_view.0.current().map(|technology| technology.id);
+    for (interaction, button) in &button_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let tech_id = (current != Some(button.0)).then_some(button.0);
+        queue_feature_command("research", "select", json!({ "techId": tech_id }));
+    }
+}
+
+/// Labels each technology with what it unlocks and where it stands.
+fn sync_research_screen(
+    research_view: Res<ResearchView>,
+    mut button_query: Query<(&ResearchButton, &mut BackgroundColor, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !research_view.is_changed() {
+        return;
+    }
+    let research = &research_view.0;
+    for (button, mut background, children) in &mut button_query {
+        let Some(technology) = technology_by_id(button.0) else {
+            continue;
+        };
+        let packs = format!("{}/{}", research.progress(technology.id), technology.cost);
+        let (status, color) = if research.is_unlocked(technology.id) {
+            ("done".to_string(), Color::srgb_u8(22, 101, 52))
+        } else if research.current() == Some(technology) {
+            (format!("researching {packs}"), Color::srgb_u8(30, 64, 175))
+        } else if research.is_available(technology) {
+            (packs, Color::srgb_u8(51, 65, 85))
+        } else {
+            (
+                format!("needs {}", technology.prerequisites.join(", ")),
+                Color::srgb_u8(30, 30, 36),
+            )
+        };
+        let unlocks: Vec<&str> = technology
+            .structures
+            .iter()
+            .chain(technology.recipes)
+            .copied()
+            .collect();
+        *background = BackgroundColor(color);
+        if let Some(mut text) = children
+            .first()
+            .and_then(|child| text_query.get_mut(*child).ok())
+        {
+            text.sections[0].value =
+                format!("{} ({}): {status}", technology.name, unlocks.join(", "));
+        }
+    }
+}
+
+/// E opens the chest under the cursor, or closes the open one anywhere else.
+/// The server decides whether the chest is in reach.
+fn handle_chest_controls(
+    input: Res<ButtonInput<KeyCode>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    chest_query: Query<(&StructureActor, &Transform), With<ChestActor>>,
+) {
+    if !input.just_pressed(KeyCode::KeyE) {
+        return;
+    }
+
+    let world_pos = window_query
+        .get_single()
+        .ok()
+        .and_then(|window| window.cursor_position())
+        .zip(camera_query.get_single().ok())
+        .and_then(|(cursor_pos, (camera, camera_transform))| {
+            camera.viewport_to_world_2d(camera_transform, cursor_pos)
+        });
+    let chest = world_pos.and_then(|world_pos| {
+        chest_query.iter().find(|(structure, transform)| {
+            let offset = (world_pos - transform.translation.truncate()).abs();
+            offset.x < structure.half_size.x && offset.y < structure.half_size.y
+        })
+    });
+
+    match chest {
+        Some((structure, _)) => {
+            queue_feature_command("build", "open", json!({ "id": structure.id }));
+        }
+        None => queue_feature_command("build", "close", json!({})),
+    }
+}
+
+fn build_preview_sprite(preview: &BuildPreviewState) -> Sprite {
+    let direction = preview
+        .direction
+        .as_deref()
+        .and_then(Direction::parse)
+        .unwrap_or_default();
+    Sprite {
+        color: structure_preview_color(preview.kind.as_str(), false),
+        custom_size: Some(structure_size(preview.kind.as_str(), direction)),
+        ..default()
+    }
 }
 
 fn spawn_build_preview_actor(commands: &mut Commands, preview: &BuildPreviewState) {
     commands.spawn((
         SpriteBundle {
-            sprite: Sprite {
-                color: structure_preview_color(preview.kind.as_str(), false),
-                custom_size: Some(Vec2::splat(STRUCTURE_SIZE)),
-                ..default()
-            },
+            sprite: build_preview_sprite(preview),
             transform: Transform::from_xyz(preview.x, preview.y, BUILD_PREVIEW_Z),
             ..default()
         },
@@ -1428,6 +3129,29 @@ fn spawn_build_preview_actor(commands: &mut Commands, preview: &BuildPreviewStat
     ));
 }
 
+/// Enemies fade towards a dark red as they lose health.
+fn enemy_sprite(enemy: &EnemyState) -> Sprite {
+    let health = (enemy.hp as f32 / enemy.max_hp.max(1) as f32).clamp(0.0, 1.0);
+    Sprite {
+        color: Color::srgb(0.35 + 0.55 * health, 0.12 + 0.1 * health, 0.14),
+        custom_size: Some(Vec2::splat(ENEMY_SIZE)),
+        ..default()
+    }
+}
+
+fn spawn_enemy_actor(commands: &mut Commands, enemy: &EnemyState) {
+    commands.spawn((
+        SpriteBundle {
+            sprite: enemy_sprite(enemy),
+            transform: Transform::from_xyz(enemy.x, enemy.y, SNAPSHOT_Z),
+            ..default()
+        },
+        EnemyActor {
+            id: enemy.id.clone(),
+        },
+    ));
+}
+
 fn spawn_projectile_actor(commands: &mut Commands, projectile: &ProjectileState) {
     commands.spawn((
         SpriteBundle {
@@ -0,0 +1,480 @@
+//! Conveyor belts: two item lanes per tile, advanced in fixed-point steps so
+//! the worker and clients agree on every item position.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{ItemKind, ItemStack};
+
+/// Length of one belt lane in fixed-point units.
+pub const BELT_LANE_LENGTH: u16 = 256;
+/// Minimum gap between two items on the same lane.
+pub const BELT_ITEM_SPACING: u16 = 64;
+/// How far items advance per simulation tick.
+pub const BELT_SPEED_PER_TICK: u16 = 8;
+/// Lane on the left of a belt's direction of travel.
+pub const BELT_LEFT_LANE: usize = 0;
+/// Lane on the right of a belt's direction of travel.
+pub const BELT_RIGHT_LANE: usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub enum Direction {
+    North,
+    #[default]
+    East,
+    South,
+    West,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::North => "north",
+            Direction::East => "east",
+            Direction::South => "south",
+            Direction::West => "west",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|direction| direction.as_str() == value)
+    }
+
+    /// Grid step in this direction; north is +y.
+    pub fn offset(self) -> (i32, i32) {
+        match self {
+            Direction::North => (0, 1),
+            Direction::East => (1, 0),
+            Direction::South => (0, -1),
+            Direction::West => (-1, 0),
+        }
+    }
+
+    pub fn rotate_cw(self) -> Self {
+        match self {
+            Direction::North => Direction::East,
+            Direction::East => Direction::South,
+            Direction::South => Direction::West,
+            Direction::West => Direction::North,
+        }
+    }
+
+    pub fn rotate_ccw(self) -> Self {
+        match self {
+            Direction::North => Direction::West,
+            Direction::East => Direction::North,
+            Direction::South => Direction::East,
+            Direction::West => Direction::South,
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        self.rotate_cw().rotate_cw()
+    }
+}
+
+fn step_cell(cell: (i32, i32), direction: Direction) -> (i32, i32) {
+    let (dx, dy) = direction.offset();
+    (cell.0 + dx, cell.1 + dy)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BeltItem {
+    pub item: ItemKind,
+    /// Distance along the lane in `[0, BELT_LANE_LENGTH]`; items wait for the
+    /// next belt at `BELT_LANE_LENGTH`.
+    pub position: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BeltState {
+    pub direction: Direction,
+    /// Indexed by `BELT_LEFT_LANE`/`BELT_RIGHT_LANE`; each lane is ordered
+    /// front (highest position) first.
+    pub lanes: [Vec<BeltItem>; 2],
+}
+
+impl BeltState {
+    pub fn new(direction: Direction) -> Self {
+        Self {
+            direction,
+            lanes: [Vec::new(), Vec::new()],
+        }
+    }
+
+    /// Rebuilds a belt from saved lanes, dropping items that are out of range
+    /// or closer than `BELT_ITEM_SPACING` to the item ahead.
+    pub fn restore(direction: Direction, lanes: [Vec<BeltItem>; 2]) -> Self {
+        let mut belt = Self::new(direction);
+        for (lane, mut items) in lanes.into_iter().enumerate() {
+            items.retain(|item| item.position <= BELT_LANE_LENGTH);
+            items.sort_by_key(|item| std::cmp::Reverse(item.position));
+            for item in items {
+                if belt.can_accept(lane, item.position) {
+                    belt.lanes[lane].push(item);
+                }
+            }
+        }
+        belt
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lanes.iter().all(Vec::is_empty)
+    }
+
+    /// Every item on the belt, one stack per item.
+    pub fn items(&self) -> impl Iterator<Item = ItemStack> + '_ {
+        self.lanes.iter().flatten().map(|item| ItemStack {
+            item: item.item,
+            count: 1,
+        })
+    }
+
+    pub fn can_accept(&self, lane: usize, position: u16) -> bool {
+        self.lanes[lane]
+            .iter()
+            .all(|item| item.position.abs_diff(position) >= BELT_ITEM_SPACING)
+    }
+
+    fn insert_sorted(&mut self, lane: usize, item: BeltItem) {
+        let index = self.lanes[lane]
+            .iter()
+            .position(|existing| existing.position < item.position)
+            .unwrap_or(self.lanes[lane].len());
+        self.lanes[lane].insert(index, item);
+    }
+}
+
+/// All belts of a room keyed by grid cell. Iteration is in cell order, which
+/// keeps hand-offs between belts deterministic.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BeltGrid {
+    belts: BTreeMap<(i32, i32), BeltState>,
+}
+
+impl BeltGrid {
+    pub fn get(&self, cell: (i32, i32)) -> Option<&BeltState> {
+        self.belts.get(&cell)
+    }
+
+    pub fn insert(&mut self, cell: (i32, i32), belt: BeltState) {
+        self.belts.insert(cell, belt);
+    }
+
+    pub fn remove(&mut self, cell: (i32, i32)) -> Option<BeltState> {
+        self.belts.remove(&cell)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&(i32, i32), &BeltState)> {
+        self.belts.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.belts.is_empty()
+    }
+
+    /// Puts `item` on a lane of the belt at `cell` if there is room at `position`.
+    pub fn insert_item(
+        &mut self,
+        cell: (i32, i32),
+        lane: usize,
+        item: ItemKind,
+        position: u16,
+    ) -> bool {
+        let Some(belt) = self.belts.get_mut(&cell) else {
+            return false;
+        };
+        let position = position.min(BELT_LANE_LENGTH);
+        if !belt.can_accept(lane, position) {
+            return false;
+        }
+        belt.insert_sorted(lane, BeltItem { item, position });
+        true
+    }
+
+    /// Kinds an inserter could pick from the belt at `cell`, front-most first.
+    pub fn pickable_items(&self, cell: (i32, i32)) -> Vec<ItemKind> {
+        let Some(belt) = self.belts.get(&cell) else {
+            return Vec::new();
+        };
+        let mut fronts: Vec<BeltItem> = belt
+            .lanes
+            .iter()
+            .filter_map(|lane| lane.first())
+            .copied()
+            .collect();
+        fronts.sort_by_key(|item| std::cmp::Reverse(item.position));
+        fronts.into_iter().map(|item| item.item).collect()
+    }
+
+    /// Removes the front-most `item` at the head of either lane.
+    pub fn take_item(&mut self, cell: (i32, i32), item: ItemKind) -> bool {
+        let Some(belt) = self.belts.get_mut(&cell) else {
+            return false;
+        };
+        let lane = [BELT_LEFT_LANE, BELT_RIGHT_LANE]
+            .into_iter()
+            .filter(|&lane| {
+                belt.lanes[lane]
+                    .first()
+                    .is_some_and(|front| front.item == item)
+            })
+            .max_by_key(|&lane| (belt.lanes[lane][0].position, std::cmp::Reverse(lane)));
+        let Some(lane) = lane else {
+            return false;
+        };
+        belt.lanes[lane].remove(0);
+        true
+    }
+
+    /// Inserters drop onto the lane farther from them, halfway along the belt.
+    /// Dropping from behind or ahead uses the right lane.
+    fn drop_point(&self, cell: (i32, i32), direction: Direction) -> Option<(usize, u16)> {
+        let belt = self.belts.get(&cell)?;
+        let lane = if belt.direction.rotate_ccw() == direction {
+            BELT_LEFT_LANE
+        } else {
+            BELT_RIGHT_LANE
+        };
+        Some((lane, BELT_LANE_LENGTH / 2))
+    }
+
+    /// Whether an inserter moving items `direction` can drop onto the belt at `cell`.
+    pub fn can_drop(&self, cell: (i32, i32), direction: Direction) -> bool {
+        self.drop_point(cell, direction)
+            .is_some_and(|(lane, position)| self.belts[&cell].can_accept(lane, position))
+    }
+
+    pub fn drop_item(&mut self, cell: (i32, i32), item: ItemKind, direction: Direction) -> bool {
+        match self.drop_point(cell, direction) {
+            Some((lane, position)) => self.insert_item(cell, lane, item, position),
+            None => false,
+        }
+    }
+
+    fn feeds(&self, from: (i32, i32), to: (i32, i32)) -> bool {
+        self.belts
+            .get(&from)
+            .is_some_and(|belt| step_cell(from, belt.direction) == to)
+    }
+
+    /// Where an item leaving `lane` of a belt moving `direction` lands on the
+    /// belt at `target_cell`, as `(lane, position)`.
+    ///
+    /// Straight runs and curves keep the lane. A belt fed from behind, or from
+    /// both sides, is side-loaded instead: items join the near lane halfway along.
+    fn entry_point(
+        &self,
+        direction: Direction,
+        lane: usize,
+        target_cell: (i32, i32),
+    ) -> Option<(usize, u16)> {
+        let target = self.belts.get(&target_cell)?;
+        if target.direction == direction {
+            return Some((lane, 0));
+        }
+        if target.direction == direction.opposite() {
+            return None;
+        }
+
+        let behind = step_cell(target_cell, target.direction.opposite());
+        let left = step_cell(target_cell, target.direction.rotate_ccw());
+        let right = step_cell(target_cell, target.direction.rotate_cw());
+        let side_feeders = [left, right]
+            .into_iter()
+            .filter(|side| self.feeds(*side, target_cell))
+            .count();
+        if !self.feeds(behind, target_cell) && side_feeders == 1 {
+            return Some((lane, 0));
+        }
+
+        let near_lane = if direction.opposite() == target.direction.rotate_ccw() {
+            BELT_LEFT_LANE
+        } else {
+            BELT_RIGHT_LANE
+        };
+        Some((near_lane, BELT_LANE_LENGTH / 2))
+    }
+
+    /// Advances every belt by one simulation tick and returns the cells whose
+    /// contents changed.
+    pub fn step(&mut self) -> Vec<(i32, i32)> {
+        let mut changed = BTreeSet::new();
+
+        // Items move along their own lane and queue behind the item ahead.
+        for (cell, belt) in self.belts.iter_mut() {
+            for lane in belt.lanes.iter_mut() {
+                let mut limit = BELT_LANE_LENGTH;
+                for item in lane.iter_mut() {
+                    let next = item.position.saturating_add(BELT_SPEED_PER_TICK).min(limit);
+                    if next > item.position {
+                        item.position = next;
+                        changed.insert(*cell);
+                    }
+                    limit = item.position.saturating_sub(BELT_ITEM_SPACING);
+                }
+            }
+        }
+
+        // Items waiting at a lane end move on to the next belt if it has room.
+        let cells: Vec<(i32, i32)> = self.belts.keys().copied().collect();
+        for cell in cells {
+            let direction = self.belts[&cell].direction;
+            let target_cell = step_cell(cell, direction);
+            for lane in [BELT_LEFT_LANE, BELT_RIGHT_LANE] {
+                let waiting = self.belts[&cell].lanes[lane]
+                    .first()
+                    .is_some_and(|item| item.position >= BELT_LANE_LENGTH);
+                if !waiting {
+                    continue;
+                }
+                let Some((target_lane, position)) = self.entry_point(direction, lane, target_cell)
+                else {
+                    continue;
+                };
+                if !self.belts[&target_cell].can_accept(target_lane, position) {
+                    continue;
+                }
+
+                let Some(source) = self.belts.get_mut(&cell) else {
+                    continue;
+                };
+                let item = source.lanes[lane].remove(0).item;
+                if let Some(target) = self.belts.get_mut(&target_cell) {
+                    target.insert_sorted(target_lane, BeltItem { item, position });
+                }
+                changed.insert(cell);
+                changed.insert(target_cell);
+            }
+        }
+
+        changed.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(grid: &mut BeltGrid, cells: &[(i32, i32)], direction: Direction) {
+        for cell in cells {
+            grid.insert(*cell, BeltState::new(direction));
+        }
+    }
+
+    fn run(grid: &mut BeltGrid, ticks: u32) {
+        for _ in 0..ticks {
+            grid.step();
+        }
+    }
+
+    fn ticks_per_tile() -> u32 {
+        (BELT_LANE_LENGTH / BELT_SPEED_PER_TICK) as u32
+    }
+
+    #[test]
+    fn items_travel_and_back_up_at_the_end() {
+        let mut grid = BeltGrid::default();
+        line(&mut grid, &[(0, 0), (1, 0), (2, 0)], Direction::East);
+
+        let mut inserted = 0;
+        for _ in 0..ticks_per_tile() * 20 {
+            if grid.insert_item((0, 0), BELT_RIGHT_LANE, ItemKind::IronOre, 0) {
+                inserted += 1;
+            }
+            grid.step();
+        }
+
+        let last = grid.get((2, 0)).unwrap();
+        assert_eq!(last.lanes[BELT_RIGHT_LANE][0].position, BELT_LANE_LENGTH);
+        assert!(last.lanes[BELT_LEFT_LANE].is_empty());
+        for belt in [(0, 0), (1, 0), (2, 0)].map(|cell| grid.get(cell).unwrap()) {
+            for pair in belt.lanes[BELT_RIGHT_LANE].windows(2) {
+                assert_eq!(pair[0].position - pair[1].position, BELT_ITEM_SPACING);
+            }
+        }
+        let held: usize = grid.iter().map(|(_, belt)| belt.items().count()).sum();
+        assert_eq!(held, inserted);
+    }
+
+    #[test]
+    fn curves_keep_lanes_and_opposing_belts_block() {
+        let mut grid = BeltGrid::default();
+        line(&mut grid, &[(0, 0)], Direction::East);
+        line(&mut grid, &[(1, 0), (1, 1)], Direction::North);
+        line(&mut grid, &[(5, 0)], Direction::East);
+        line(&mut grid, &[(6, 0)], Direction::West);
+        assert!(grid.insert_item((0, 0), BELT_LEFT_LANE, ItemKind::Coal, 0));
+        assert!(grid.insert_item((5, 0), BELT_LEFT_LANE, ItemKind::Coal, 0));
+
+        run(&mut grid, ticks_per_tile() * 2 + 4);
+
+        let top = grid.get((1, 1)).unwrap();
+        assert_eq!(top.lanes[BELT_LEFT_LANE].len(), 1);
+        assert!(top.lanes[BELT_RIGHT_LANE].is_empty());
+        let blocked = grid.get((5, 0)).unwrap();
+        assert_eq!(blocked.lanes[BELT_LEFT_LANE][0].position, BELT_LANE_LENGTH);
+        assert!(grid.get((6, 0)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn side_loading_joins_the_near_lane() {
+        let mut grid = BeltGrid::default();
+        line(&mut grid, &[(1, -1), (1, 0), (1, 1)], Direction::North);
+        line(&mut grid, &[(0, 0)], Direction::East);
+        line(&mut grid, &[(2, 0)], Direction::West);
+        assert!(grid.insert_item((0, 0), BELT_RIGHT_LANE, ItemKind::IronPlate, 0));
+        assert!(grid.insert_item((2, 0), BELT_RIGHT_LANE, ItemKind::CopperPlate, 0));
+
+        run(&mut grid, ticks_per_tile() + 1);
+
+        let joined = grid.get((1, 0)).unwrap();
+        assert_eq!(joined.lanes[BELT_LEFT_LANE].len(), 1);
+        assert_eq!(joined.lanes[BELT_LEFT_LANE][0].item, ItemKind::IronPlate);
+        assert_eq!(joined.lanes[BELT_RIGHT_LANE].len(), 1);
+        assert_eq!(joined.lanes[BELT_RIGHT_LANE][0].item, ItemKind::CopperPlate);
+    }
+
+    #[test]
+    fn restore_drops_overlapping_items() {
+        let belt = BeltState::restore(
+            Direction::South,
+            [
+                vec![
+                    BeltItem {
+                        item: ItemKind::Stone,
+                        position: 10,
+                    },
+                    BeltItem {
+                        item: ItemKind::Stone,
+                        position: 200,
+                    },
+                    BeltItem {
+                        item: ItemKind::Stone,
+                        position: 180,
+                    },
+                ],
+                vec![BeltItem {
+                    item: ItemKind::Stone,
+                    position: BELT_LANE_LENGTH + 1,
+                }],
+            ],
+        );
+
+        let positions: Vec<u16> = belt.lanes[BELT_LEFT_LANE]
+            .iter()
+            .map(|item| item.position)
+            .collect();
+        assert_eq!(positions, vec![200, 10]);
+        assert!(belt.lanes[BELT_RIGHT_LANE].is_empty());
+        assert_eq!(Direction::North.rotate_ccw(), Direction::West);
+    }
+}
@@ -0,0 +1,255 @@
+//! Hostile creatures. Nests (see [`crate::Terrain::nest_in_chunk`]) spawn them
+//! while players are around; they chase players that come close and otherwise
+//! follow a flow field towards the nearest solid structure, attacking whatever
+//! they touch.
+
+use std::collections::VecDeque;
+
+use crate::{
+    clamp_axis, tile_center, world_to_tile, MovementStep, PlayerCollider, StructureObstacle,
+};
+
+pub const ENEMY_MAX_HP: i32 = 60;
+pub const ENEMY_SPEED: f32 = 120.0;
+pub const ENEMY_COLLIDER_RADIUS: f32 = 9.0;
+pub const ENEMY_CONTACT_DAMAGE: i32 = 8;
+pub const ENEMY_ATTACK_INTERVAL_MS: i64 = 1000;
+/// How far past touching an enemy can still land a hit.
+pub const ENEMY_ATTACK_REACH: f32 = 4.0;
+/// Players closer than this are chased directly instead of following the field.
+pub const ENEMY_AGGRO_RADIUS: f32 = 320.0;
+/// Nests only spawn while a player is within this distance.
+pub const NEST_ACTIVATION_RADIUS: f32 = 1024.0;
+/// Enemies with no player this close are despawned.
+pub const ENEMY_LEASH_RADIUS: f32 = 1536.0;
+pub const NEST_SPAWN_INTERVAL_MS: i64 = 8000;
+pub const NEST_MAX_ENEMIES: usize = 4;
+/// Cells the flow field extends past the outermost target.
+pub const FLOW_FIELD_MARGIN_TILES: i32 = 64;
+
+const UNREACHED: u32 = u32::MAX;
+const NEIGHBOURS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// Breadth-first distances, in cells, from every cell around a set of targets
+/// to the nearest target. Enemies walk it downhill.
+#[derive(Debug, Clone, Default)]
+pub struct FlowField {
+    origin: (i32, i32),
+    width: i32,
+    height: i32,
+    distances: Vec<u32>,
+}
+
+impl FlowField {
+    /// Targets are seeded at distance zero even though structures sit on
+    /// them; every other cell is only entered when `passable`.
+    pub fn build(targets: &[(i32, i32)], passable: impl Fn(i32, i32) -> bool) -> Self {
+        let Some(min_x) = targets.iter().map(|cell| cell.0).min() else {
+            return Self::default();
+        };
+        let max_x = targets.iter().map(|cell| cell.0).max().unwrap_or(min_x);
+        let min_y = targets.iter().map(|cell| cell.1).min().unwrap_or(0);
+        let max_y = targets.iter().map(|cell| cell.1).max().unwrap_or(min_y);
+
+        let mut field = Self {
+            origin: (
+                min_x - FLOW_FIELD_MARGIN_TILES,
+                min_y - FLOW_FIELD_MARGIN_TILES,
+            ),
+            width: max_x - min_x + 1 + 2 * FLOW_FIELD_MARGIN_TILES,
+            height: max_y - min_y + 1 + 2 * FLOW_FIELD_MARGIN_TILES,
+            distances: Vec::new(),
+        };
+        field.distances = vec![UNREACHED; (field.width * field.height) as usize];
+
+        let mut queue = VecDeque::new();
+        for &cell in targets {
+            if let Some(index) = field.index(cell) {
+                field.distances[index] = 0;
+                queue.push_back(cell);
+            }
+        }
+        while let Some(cell) = queue.pop_front() {
+            let next = field.distances[field.index(cell).unwrap_or_default()] + 1;
+            for (dx, dy) in NEIGHBOURS {
+                let neighbour = (cell.0 + dx, cell.1 + dy);
+                let Some(index) = field.index(neighbour) else {
+                    continue;
+                };
+                if field.distances[index] != UNREACHED || !passable(neighbour.0, neighbour.1) {
+                    continue;
+                }
+                field.distances[index] = next;
+                queue.push_back(neighbour);
+            }
+        }
+        field
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.distances.is_empty()
+    }
+
+    fn index(&self, cell: (i32, i32)) -> Option<usize> {
+        let (x, y) = (cell.0 - self.origin.0, cell.1 - self.origin.1);
+        ((0..self.width).contains(&x) && (0..self.height).contains(&y))
+            .then(|| (y * self.width + x) as usize)
+    }
+
+    /// Steps from `cell` to the nearest target, if one can be reached.
+    pub fn distance(&self, cell: (i32, i32)) -> Option<u32> {
+        self.index(cell)
+            .map(|index| self.distances[index])
+            .filter(|distance| *distance != UNREACHED)
+    }
+
+    /// The neighbouring cell one step closer to a target. `None` on a target,
+    /// outside the field or where no target can be reached.
+    pub fn next_cell(&self, cell: (i32, i32)) -> Option<(i32, i32)> {
+        let distance = self.distance(cell)?;
+        NEIGHBOURS
+            .iter()
+            .map(|(dx, dy)| (cell.0 + dx, cell.1 + dy))
+            .filter_map(|neighbour| Some((self.distance(neighbour)?, neighbour)))
+            .filter(|(neighbour_distance, _)| *neighbour_distance < distance)
+            .min_by_key(|(neighbour_distance, _)| *neighbour_distance)
+            .map(|(_, neighbour)| neighbour)
+    }
+}
+
+/// Where an enemy at `(x, y)` heads next: the closest player within
+/// [`ENEMY_AGGRO_RADIUS`], else the center of the next flow field cell.
+pub fn enemy_waypoint(
+    x: f32,
+    y: f32,
+    players: &[PlayerCollider],
+    flow_field: &FlowField,
+) -> Option<(f32, f32)> {
+    let closest_player = players
+        .iter()
+        .map(|player| ((player.x - x).hypot(player.y - y), player))
+        .filter(|(distance, _)| *distance <= ENEMY_AGGRO_RADIUS)
+        .min_by(|a, b| a.0.total_cmp(&b.0));
+    if let Some((_, player)) = closest_player {
+        return Some((player.x, player.y));
+    }
+
+    flow_field
+        .next_cell((world_to_tile(x), world_to_tile(y)))
+        .map(|(tile_x, tile_y)| (tile_center(tile_x), tile_center(tile_y)))
+}
+
+/// Moves an enemy towards `waypoint` for one step, sliding along whatever
+/// `blocked` reports like player movement does.
+pub fn enemy_step(
+    x: f32,
+    y: f32,
+    waypoint: Option<(f32, f32)>,
+    dt_seconds: f32,
+    map_limit: f32,
+    blocked: impl Fn(f32, f32) -> bool,
+) -> MovementStep {
+    let (mut vx, mut vy) = (0.0, 0.0);
+    if let Some((target_x, target_y)) = waypoint {
+        let (dx, dy) = (target_x - x, target_y - y);
+        let distance = dx.hypot(dy);
+        if distance > f32::EPSILON {
+            // Slow down rather than overshoot a waypoint that is closer than a step.
+            let speed = ENEMY_SPEED.min(distance / dt_seconds.max(f32::EPSILON));
+            (vx, vy) = (dx / distance * speed, dy / distance * speed);
+        }
+    }
+
+    let mut resolved_x = clamp_axis(x + vx * dt_seconds, map_limit);
+    let mut resolved_y = clamp_axis(y + vy * dt_seconds, map_limit);
+    if blocked(resolved_x, y) {
+        resolved_x = x;
+        vx = 0.0;
+    }
+    if blocked(resolved_x, resolved_y) {
+        resolved_y = y;
+        vy = 0.0;
+    }
+
+    MovementStep {
+        x: resolved_x,
+        y: resolved_y,
+        vx,
+        vy,
+    }
+}
+
+pub fn enemy_touches_player(x: f32, y: f32, player: &PlayerCollider) -> bool {
+    (player.x - x).hypot(player.y - y) <= ENEMY_COLLIDER_RADIUS + player.radius + ENEMY_ATTACK_REACH
+}
+
+pub fn enemy_touches_structure(x: f32, y: f32, obstacle: &StructureObstacle) -> bool {
+    obstacle.blocks(x, y, ENEMY_COLLIDER_RADIUS + ENEMY_ATTACK_REACH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Footprint, PLAYER_COLLIDER_RADIUS, TERRAIN_TILE_SIZE};
+
+    #[test]
+    fn flow_field_routes_around_walls() {
+        // A wall at x = 2 from y = -3 to 3 between the enemy and the target.
+        let wall = |x: i32, y: i32| x == 2 && (-3..=3).contains(&y);
+        let field = FlowField::build(&[(0, 0)], |x, y| !wall(x, y));
+
+        assert_eq!(field.distance((0, 0)), Some(0));
+        assert_eq!(field.distance((1, 0)), Some(1));
+        assert_eq!(field.distance((2, 0)), None);
+        // Around the end of the wall: 4 up, 2 across, 4 back down and 1 more.
+        assert_eq!(field.distance((3, 0)), Some(11));
+        assert_eq!(field.next_cell((0, 0)), None);
+        assert_eq!(field.distance((FLOW_FIELD_MARGIN_TILES + 1, 0)), None);
+
+        let mut cell = (3, 0);
+        let mut steps = 0;
+        while let Some(next) = field.next_cell(cell) {
+            assert!(!wall(next.0, next.1));
+            cell = next;
+            steps += 1;
+        }
+        assert_eq!((cell, steps), ((0, 0), 11));
+        assert!(FlowField::build(&[], |_, _| true).is_empty());
+    }
+
+    #[test]
+    fn enemies_walk_to_structures_and_chase_nearby_players() {
+        let field = FlowField::build(&[(0, 0)], |_, _| true);
+        let obstacle = Footprint::SINGLE.obstacle(0.0, 0.0);
+        let (mut x, mut y) = (10.0 * TERRAIN_TILE_SIZE, 3.0 * TERRAIN_TILE_SIZE);
+        for _ in 0..300 {
+            let waypoint = enemy_waypoint(x, y, &[], &field);
+            let step = enemy_step(x, y, waypoint, 1.0 / 30.0, 5000.0, |x, y| {
+                obstacle.blocks(x, y, ENEMY_COLLIDER_RADIUS)
+            });
+            (x, y) = (step.x, step.y);
+        }
+        assert!(enemy_touches_structure(x, y, &obstacle));
+        assert!(!obstacle.blocks(x, y, ENEMY_COLLIDER_RADIUS));
+
+        let player = PlayerCollider {
+            x: 200.0,
+            y: 0.0,
+            radius: PLAYER_COLLIDER_RADIUS,
+        };
+        assert_eq!(
+            enemy_waypoint(100.0, 0.0, &[player], &field),
+            Some((200.0, 0.0))
+        );
+        let far = PlayerCollider {
+            x: 1000.0,
+            ..player
+        };
+        assert_eq!(
+            enemy_waypoint(100.0, 0.0, &[far], &field),
+            Some((2.0 * TERRAIN_TILE_SIZE, 0.0))
+        );
+        assert!(enemy_touches_player(180.0, 0.0, &player));
+        assert!(!enemy_touches_player(170.0, 0.0, &player));
+    }
+}
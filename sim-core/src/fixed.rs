@@ -0,0 +1,626 @@
+//! Fixed-point movement and projectile steps.
+//!
+//! The worker and the browser client run the same `f32` code, which only
+//! agrees as long as every build evaluates the intermediate float math the same
+//! way; a single differing ulp is enough for prediction to snap on
+//! reconciliation. The `*_fixed` functions below do the whole step on 16.16
+//! integers instead. They take and return `f32` so they
+//! drop in for their float counterparts: inputs are quantized on entry and the
+//! result is converted back once, both of which are exact or correctly rounded.
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::{
+    InputState, MovementStep, PlayerCollider, ProjectileHit, ProjectileStep, StructureObstacle,
+    Terrain, ANALOG_AXIS_MAX,
+};
+
+pub const FIXED_FRACTION_BITS: u32 = 16;
+
+/// A signed 16.16 fixed-point number, stored in an `i64` for headroom.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed(pub i64);
+
+/// `1 / sqrt(2)`, used for diagonal movement instead of a runtime `sqrt`.
+const FRAC_1_SQRT_2: Fixed = Fixed(46_341);
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(1 << FIXED_FRACTION_BITS);
+
+    pub const fn from_int(value: i32) -> Self {
+        Fixed((value as i64) << FIXED_FRACTION_BITS)
+    }
+
+    /// Nearest fixed-point value; scaling by a power of two is exact in `f32`.
+    pub fn from_f32(value: f32) -> Self {
+        Fixed((value * Self::ONE.0 as f32).round() as i64)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / Self::ONE.0 as f32
+    }
+
+    pub fn abs(self) -> Self {
+        Fixed(self.0.abs())
+    }
+
+    pub fn clamp_axis(self, map_limit: Fixed) -> Self {
+        self.max(-map_limit).min(map_limit)
+    }
+
+    /// Largest value whose square does not exceed `self`; zero for negatives.
+    pub fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Fixed::ZERO;
+        }
+        Fixed((((self.0 as u128) << FIXED_FRACTION_BITS).isqrt()) as i64)
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed(self.0 + other.0)
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+
+    fn sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0 - other.0)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+
+    fn neg(self) -> Fixed {
+        Fixed(-self.0)
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+
+    /// Rounds towards negative infinity.
+    fn mul(self, other: Fixed) -> Fixed {
+        Fixed(((self.0 as i128 * other.0 as i128) >> FIXED_FRACTION_BITS) as i64)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+
+    /// Rounds towards zero.
+    fn div(self, other: Fixed) -> Fixed {
+        Fixed((((self.0 as i128) << FIXED_FRACTION_BITS) / other.0 as i128) as i64)
+    }
+}
+
+pub fn movement_velocity_fixed(input: InputState, speed: Fixed) -> (Fixed, Fixed) {
+    let input = input.clamped();
+    if input.has_analog() {
+        let axis = |value: i8| Fixed(speed.0 * value as i64 / ANALOG_AXIS_MAX as i64);
+        return (axis(input.move_x), axis(input.move_y));
+    }
+
+    let dx = i64::from(input.right) - i64::from(input.left);
+    let dy = i64::from(input.up) - i64::from(input.down);
+    let axis_speed = if dx != 0 && dy != 0 {
+        speed * FRAC_1_SQRT_2
+    } else {
+        speed
+    };
+    (Fixed(axis_speed.0 * dx), Fixed(axis_speed.0 * dy))
+}
+
+fn obstacle_blocks_fixed(obstacle: &StructureObstacle, x: Fixed, y: Fixed, radius: Fixed) -> bool {
+    (x - Fixed::from_f32(obstacle.x)).abs() < Fixed::from_f32(obstacle.half_width) + radius
+        && (y - Fixed::from_f32(obstacle.y)).abs() < Fixed::from_f32(obstacle.half_height) + radius
+}
+
+/// Fixed-point [`crate::movement_step_with_obstacles`].
+#[allow(clippy::too_many_arguments)]
+pub fn movement_step_with_obstacles_fixed(
+    x: f32,
+    y: f32,
+    input: InputState,
+    dt_seconds: f32,
+    speed: f32,
+    map_limit: f32,
+    obstacles: &[StructureObstacle],
+    player_radius: f32,
+) -> MovementStep {
+    let radius = Fixed::from_f32(player_radius);
+    resolve_movement_fixed(x, y, input, dt_seconds, speed, map_limit, |x, y| {
+        obstacles
+            .iter()
+            .any(|obstacle| obstacle_blocks_fixed(obstacle, x, y, radius))
+    })
+}
+
+/// Fixed-point [`crate::movement_step_with_terrain`]. Terrain tiles are still
+/// tested in `f32`, but only with subtractions and comparisons on values that
+/// every target rounds identically.
+#[allow(clippy::too_many_arguments)]
+pub fn movement_step_with_terrain_fixed(
+    x: f32,
+    y: f32,
+    input: InputState,
+    dt_seconds: f32,
+    speed: f32,
+    map_limit: f32,
+    obstacles: &[StructureObstacle],
+    player_radius: f32,
+    terrain: &Terrain,
+) -> MovementStep {
+    let radius = Fixed::from_f32(player_radius);
+    resolve_movement_fixed(x, y, input, dt_seconds, speed, map_limit, |x, y| {
+        terrain.blocks_area(x.to_f32(), y.to_f32(), player_radius)
+            || obstacles
+                .iter()
+                .any(|obstacle| obstacle_blocks_fixed(obstacle, x, y, radius))
+    })
+}
+
+fn resolve_movement_fixed(
+    x: f32,
+    y: f32,
+    input: InputState,
+    dt_seconds: f32,
+    speed: f32,
+    map_limit: f32,
+    blocked: impl Fn(Fixed, Fixed) -> bool,
+) -> MovementStep {
+    let (x, y) = (Fixed::from_f32(x), Fixed::from_f32(y));
+    let dt = Fixed::from_f32(dt_seconds);
+    let map_limit = Fixed::from_f32(map_limit);
+    let (mut vx, mut vy) = movement_velocity_fixed(input, Fixed::from_f32(speed));
+
+    let mut resolved_x = (x + vx * dt).clamp_axis(map_limit);
+    let mut resolved_y = (y + vy * dt).clamp_axis(map_limit);
+
+    if blocked(resolved_x, y) {
+        resolved_x = x;
+        vx = Fixed::ZERO;
+    }
+
+    if blocked(resolved_x, resolved_y) {
+        resolved_y = y;
+        vy = Fixed::ZERO;
+    }
+
+    MovementStep {
+        x: resolved_x.to_f32(),
+        y: resolved_y.to_f32(),
+        vx: vx.to_f32(),
+        vy: vy.to_f32(),
+    }
+}
+
+/// Fixed-point [`crate::projectile_step`].
+pub fn projectile_step_fixed(
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+    dt_seconds: f32,
+    map_limit: f32,
+) -> (f32, f32) {
+    let dt = Fixed::from_f32(dt_seconds);
+    let map_limit = Fixed::from_f32(map_limit);
+    let advance = |position: f32, velocity: f32| {
+        (Fixed::from_f32(position) + Fixed::from_f32(velocity) * dt)
+            .clamp_axis(map_limit)
+            .to_f32()
+    };
+    (advance(x, vx), advance(y, vy))
+}
+
+/// Fixed-point [`crate::segment_hits_obstacle`]: the slab test, with entry
+/// and exit times as 16.16 fractions of the segment.
+fn segment_hits_obstacle_fixed(
+    start: (Fixed, Fixed),
+    delta: (Fixed, Fixed),
+    obstacle: &StructureObstacle,
+    radius: Fixed,
+) -> Option<Fixed> {
+    let mut t_enter = Fixed::ZERO;
+    let mut t_exit = Fixed::ONE;
+
+    for (start, delta, center, extent) in [
+        (
+            start.0,
+            delta.0,
+            Fixed::from_f32(obstacle.x),
+            Fixed::from_f32(obstacle.half_width) + radius,
+        ),
+        (
+            start.1,
+            delta.1,
+            Fixed::from_f32(obstacle.y),
+            Fixed::from_f32(obstacle.half_height) + radius,
+        ),
+    ] {
+        let min = center - extent;
+        let max = center + extent;
+        if delta == Fixed::ZERO {
+            if start < min || start > max {
+                return None;
+            }
+            continue;
+        }
+
+        let mut t0 = (min - start) / delta;
+        let mut t1 = (max - start) / delta;
+        if t0 > t1 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+        t_enter = t_enter.max(t0);
+        t_exit = t_exit.min(t1);
+        if t_enter > t_exit {
+            return None;
+        }
+    }
+
+    Some(t_enter)
+}
+
+/// Fixed-point [`crate::segment_hits_player`], solving the same quadratic
+/// with an integer square root.
+fn segment_hits_player_fixed(
+    start: (Fixed, Fixed),
+    delta: (Fixed, Fixed),
+    player: &PlayerCollider,
+    radius: Fixed,
+) -> Option<Fixed> {
+    let combined = Fixed::from_f32(player.radius) + radius;
+    let offset_x = start.0 - Fixed::from_f32(player.x);
+    let offset_y = start.1 - Fixed::from_f32(player.y);
+    let c = offset_x * offset_x + offset_y * offset_y - combined * combined;
+    if c <= Fixed::ZERO {
+        return Some(Fixed::ZERO);
+    }
+
+    let a = delta.0 * delta.0 + delta.1 * delta.1;
+    if a == Fixed::ZERO {
+        return None;
+    }
+
+    let half_b = offset_x * delta.0 + offset_y * delta.1;
+    let b = half_b + half_b;
+    let discriminant = b * b - Fixed::from_int(4) * a * c;
+    if discriminant < Fixed::ZERO {
+        return None;
+    }
+
+    let t = (-b - discriminant.sqrt()) / (a + a);
+    (Fixed::ZERO..=Fixed::ONE).contains(&t).then_some(t)
+}
+
+/// Fixed-point [`crate::projectile_step_with_hits`]. The segment comes from
+/// [`projectile_step_fixed`] and is swept against structures and players in
+/// 16.16 as well, so a hit lands on the same point on every target.
+#[allow(clippy::too_many_arguments)]
+pub fn projectile_step_with_hits_fixed(
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+    dt_seconds: f32,
+    map_limit: f32,
+    obstacles: &[StructureObstacle],
+    players: &[PlayerCollider],
+    projectile_radius: f32,
+) -> ProjectileStep {
+    let (next_x, next_y) = projectile_step_fixed(x, y, vx, vy, dt_seconds, map_limit);
+    let start = (Fixed::from_f32(x), Fixed::from_f32(y));
+    let delta = (
+        Fixed::from_f32(next_x) - start.0,
+        Fixed::from_f32(next_y) - start.1,
+    );
+    let radius = Fixed::from_f32(projectile_radius);
+
+    let mut earliest: Option<(Fixed, ProjectileHit)> = None;
+    let mut consider = |t: Option<Fixed>, hit: ProjectileHit| {
+        if let Some(t) = t {
+            if earliest.is_none_or(|(best, _)| t < best) {
+                earliest = Some((t, hit));
+            }
+        }
+    };
+
+    for (index, obstacle) in obstacles.iter().enumerate() {
+        consider(
+            segment_hits_obstacle_fixed(start, delta, obstacle, radius),
+            ProjectileHit::Structure(index),
+        );
+    }
+    for (index, player) in players.iter().enumerate() {
+        consider(
+            segment_hits_player_fixed(start, delta, player, radius),
+            ProjectileHit::Player(index),
+        );
+    }
+
+    match earliest {
+        Some((t, hit)) => ProjectileStep {
+            x: (start.0 + delta.0 * t).to_f32(),
+            y: (start.1 + delta.1 * t).to_f32(),
+            hit: Some(hit),
+        },
+        None => ProjectileStep {
+            x: next_x,
+            y: next_y,
+            hit: None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fnv1a_hash, movement_step_with_obstacles, projectile_step_with_hits, Direction, Footprint,
+        PLAYER_COLLIDER_RADIUS, PROJECTILE_COLLIDER_RADIUS, STRUCTURE_COLLIDER_HALF_EXTENT,
+    };
+
+    #[test]
+    fn fixed_arithmetic_round_trips_world_values() {
+        for value in [0.0, 1.5, -1.5, 4999.0, -5000.0, 0.25, 16.0] {
+            assert_eq!(Fixed::from_f32(value).to_f32(), value);
+        }
+        assert_eq!(
+            Fixed::from_int(3) * Fixed::from_f32(0.5),
+            Fixed::from_f32(1.5)
+        );
+        assert_eq!(
+            Fixed::from_int(-7).clamp_axis(Fixed::from_int(5)),
+            Fixed::from_int(-5)
+        );
+    }
+
+    #[test]
+    fn fixed_diagonal_velocity_is_normalized() {
+        let (vx, vy) = movement_velocity_fixed(
+            InputState {
+                up: true,
+                down: false,
+                left: true,
+                right: false,
+                ..Default::default()
+            },
+            Fixed::from_int(220),
+        );
+        assert_eq!(vx, -vy);
+        let magnitude = (vx.to_f32() * vx.to_f32() + vy.to_f32() * vy.to_f32()).sqrt();
+        assert!((magnitude - 220.0).abs() < 0.01);
+
+        let (vx, vy) = movement_velocity_fixed(
+            InputState {
+                up: true,
+                down: true,
+                left: false,
+                right: true,
+                ..Default::default()
+            },
+            Fixed::from_int(220),
+        );
+        assert_eq!((vx, vy), (Fixed::from_int(220), Fixed::ZERO));
+    }
+
+    #[test]
+    fn fixed_movement_tracks_the_float_step() {
+        let obstacle = Footprint::SINGLE.obstacle(0.0, 0.0);
+        let input = InputState {
+            up: false,
+            down: false,
+            left: false,
+            right: true,
+            ..Default::default()
+        };
+        let (mut fixed, mut float) = ((-40.0f32, 3.0f32), (-40.0f32, 3.0f32));
+        for _ in 0..30 {
+            let step = movement_step_with_obstacles_fixed(
+                fixed.0,
+                fixed.1,
+                input,
+                1.0 / 30.0,
+                220.0,
+                5000.0,
+                &[obstacle],
+                PLAYER_COLLIDER_RADIUS,
+            );
+            fixed = (step.x, step.y);
+            let step = movement_step_with_obstacles(
+                float.0,
+                float.1,
+                input,
+                1.0 / 30.0,
+                220.0,
+                5000.0,
+                &[obstacle],
+                PLAYER_COLLIDER_RADIUS,
+            );
+            float = (step.x, step.y);
+        }
+        // Both stop against the same face of the structure, after two steps.
+        // The fixed dt is 1/30 rounded to 2^-16, so each step may drift from
+        // the float one by speed * 2^-17 plus one rounding of the product.
+        assert!(fixed.0 < -STRUCTURE_COLLIDER_HALF_EXTENT - PLAYER_COLLIDER_RADIUS);
+        assert_eq!(fixed, (-25.3302, 3.0));
+        assert!((fixed.0 - float.0).abs() <= 2.0 * (220.0 / 131_072.0 + 1.0 / 65_536.0));
+
+        let (x, y) = projectile_step_fixed(0.0, 0.0, 10000.0, -900.0, 1.0, 5500.0);
+        assert_eq!((x, y), (5500.0, -900.0));
+    }
+
+    #[test]
+    fn fixed_projectile_sweep_hits_what_the_float_one_hits() {
+        let obstacles = [Footprint::SINGLE.obstacle(100.0, 0.0)];
+        let players = [PlayerCollider {
+            x: 60.0,
+            y: 40.0,
+            radius: PLAYER_COLLIDER_RADIUS,
+        }];
+        type Sweep = fn(
+            f32,
+            f32,
+            f32,
+            f32,
+            f32,
+            f32,
+            &[StructureObstacle],
+            &[PlayerCollider],
+            f32,
+        ) -> ProjectileStep;
+        for (vx, vy, hit) in [
+            (900.0, 0.0, Some(ProjectileHit::Structure(0))),
+            (300.0, 200.0, Some(ProjectileHit::Player(0))),
+            (-900.0, 0.0, None),
+        ] {
+            let step = |sweep: Sweep| {
+                sweep(
+                    0.1,
+                    0.0,
+                    vx,
+                    vy,
+                    1.0 / 3.0,
+                    5500.0,
+                    &obstacles,
+                    &players,
+                    PROJECTILE_COLLIDER_RADIUS,
+                )
+            };
+            let fixed = step(projectile_step_with_hits_fixed);
+            let float = step(projectile_step_with_hits);
+
+            assert_eq!(fixed.hit, hit);
+            assert_eq!(float.hit, hit);
+            for (fixed, float) in [(fixed.x, float.x), (fixed.y, float.y)] {
+                assert_eq!(Fixed::from_f32(fixed).to_f32(), fixed);
+                // One step of 1/3 s at up to 900 u/s, against a dt rounded to 2^-16.
+                assert!((fixed - float).abs() <= 900.0 / 131_072.0 + 1.0 / 65_536.0);
+            }
+        }
+    }
+
+    /// Pinned here and checked again under `cargo test --target wasm32-wasip1`,
+    /// so the native and wasm builds of the fixed-point steps must agree bit
+    /// for bit.
+    const GOLDEN: &str = include_str!("../fixtures/fixed-golden.json");
+
+    fn golden_f32(value: &serde_json::Value) -> f32 {
+        value.as_str().unwrap().parse().unwrap()
+    }
+
+    fn golden_hash(value: &serde_json::Value) -> u64 {
+        u64::from_str_radix(value.as_str().unwrap(), 16).unwrap()
+    }
+
+    #[test]
+    fn fixed_steps_match_the_golden_fixture() {
+        let golden: serde_json::Value = serde_json::from_str(GOLDEN).unwrap();
+        let inputs = [(1, 1), (0, 1), (-1, 0), (0, -1)].map(|(x, y)| InputState {
+            up: y > 0,
+            down: y < 0,
+            left: x < 0,
+            right: x > 0,
+            ..Default::default()
+        });
+        let obstacles: Vec<StructureObstacle> = (0..6)
+            .map(|i| {
+                let kind = if i % 2 == 0 { "assembler" } else { "beacon" };
+                Footprint::for_structure(kind, Direction::East)
+                    .obstacle(i as f32 * 96.0 - 240.0, (i % 3) as f32 * 96.0 - 96.0)
+            })
+            .collect();
+        let players: Vec<PlayerCollider> = (0..4)
+            .map(|i| PlayerCollider {
+                x: i as f32 * 110.0 - 150.0,
+                y: 40.0 - i as f32 * 60.0,
+                radius: PLAYER_COLLIDER_RADIUS,
+            })
+            .collect();
+
+        // A long walk through a cluster of structures.
+        let mut position = (0.0f32, 0.0f32);
+        let mut trace = Vec::new();
+        for step_index in 0..3600u32 {
+            let input = inputs[(step_index / 45) as usize % inputs.len()];
+            let step = movement_step_with_obstacles_fixed(
+                position.0,
+                position.1,
+                input,
+                1.0 / 60.0,
+                220.0,
+                5000.0,
+                &obstacles,
+                PLAYER_COLLIDER_RADIUS,
+            );
+            position = (step.x, step.y);
+            trace.extend_from_slice(&step.x.to_bits().to_le_bytes());
+            trace.extend_from_slice(&step.y.to_bits().to_le_bytes());
+        }
+        let movement = &golden["movement"];
+        assert_eq!(
+            position,
+            (golden_f32(&movement["x"]), golden_f32(&movement["y"]))
+        );
+        assert_eq!(fnv1a_hash(&trace), golden_hash(&movement["traceHash"]));
+
+        let projectile = (0..90).fold(position, |(x, y), _| {
+            projectile_step_fixed(x, y, 700.0, -350.0, 1.0 / 30.0, 5500.0)
+        });
+        assert_eq!(
+            projectile,
+            (
+                golden_f32(&golden["projectile"]["x"]),
+                golden_f32(&golden["projectile"]["y"])
+            )
+        );
+
+        // A fan of shots swept against the same structures and some players.
+        let mut hits = Vec::new();
+        let mut trace = Vec::new();
+        for shot in 0..24 {
+            let (mut x, mut y) = ((shot % 6 * 150 - 410) as f32, (shot / 6 * 130 - 250) as f32);
+            let (vx, vy) = (
+                (shot * 37 % 11 - 5) as f32 * 143.0,
+                (shot * 53 % 9 - 4) as f32 * 151.0,
+            );
+            let mut hit = None;
+            for _ in 0..60 {
+                let step = projectile_step_with_hits_fixed(
+                    x,
+                    y,
+                    vx,
+                    vy,
+                    1.0 / 30.0,
+                    5500.0,
+                    &obstacles,
+                    &players,
+                    PROJECTILE_COLLIDER_RADIUS,
+                );
+                (x, y) = (step.x, step.y);
+                trace.extend_from_slice(&x.to_bits().to_le_bytes());
+                trace.extend_from_slice(&y.to_bits().to_le_bytes());
+                if step.hit.is_some() {
+                    hit = step.hit;
+                    break;
+                }
+            }
+            hits.push(match hit {
+                Some(ProjectileHit::Structure(index)) => format!("structure:{index}"),
+                Some(ProjectileHit::Player(index)) => format!("player:{index}"),
+                None => "none".to_string(),
+            });
+        }
+        let sweep = &golden["sweep"];
+        assert_eq!(hits, sweep["hits"].as_array().unwrap().to_vec());
+        assert_eq!(fnv1a_hash(&trace), golden_hash(&sweep["traceHash"]));
+    }
+}
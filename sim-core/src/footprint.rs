@@ -0,0 +1,142 @@
+//! Grid cells a structure covers. A structure is anchored on the cell it was
+//! placed on and its footprint extends around that cell; rotating it swaps
+//! the footprint's width and height.
+
+use crate::{
+    structure_prototype, Direction, StructureObstacle, STRUCTURE_COLLIDER_HALF_EXTENT,
+    TERRAIN_TILE_SIZE,
+};
+
+/// Gap between a solid structure's collider and the edge of its footprint.
+const COLLIDER_INSET: f32 = TERRAIN_TILE_SIZE * 0.5 - STRUCTURE_COLLIDER_HALF_EXTENT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Footprint {
+    /// Cells along x.
+    pub width: i32,
+    /// Cells along y.
+    pub height: i32,
+}
+
+impl Footprint {
+    pub const SINGLE: Footprint = Footprint {
+        width: 1,
+        height: 1,
+    };
+
+    /// Footprint of `kind` facing `direction`. Prototypes list sizes facing
+    /// east; unknown kinds cover a single cell.
+    pub fn for_structure(kind: &str, direction: Direction) -> Self {
+        structure_prototype(kind)
+            .map_or(Self::SINGLE, |prototype| prototype.footprint)
+            .rotated(direction)
+    }
+
+    /// This east-facing footprint turned to face `direction`.
+    pub fn rotated(self, direction: Direction) -> Self {
+        match direction {
+            Direction::East | Direction::West => self,
+            Direction::North | Direction::South => Footprint {
+                width: self.height,
+                height: self.width,
+            },
+        }
+    }
+
+    /// Lowest cell covered when anchored on `anchor`. Odd sizes are centered on
+    /// the anchor; even sizes extend one more cell towards +x/+y.
+    pub fn origin(self, anchor: (i32, i32)) -> (i32, i32) {
+        (
+            anchor.0 - (self.width - 1) / 2,
+            anchor.1 - (self.height - 1) / 2,
+        )
+    }
+
+    pub fn cells(self, anchor: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+        let (min_x, min_y) = self.origin(anchor);
+        (min_y..min_y + self.height)
+            .flat_map(move |y| (min_x..min_x + self.width).map(move |x| (x, y)))
+    }
+
+    pub fn contains(self, anchor: (i32, i32), cell: (i32, i32)) -> bool {
+        let (min_x, min_y) = self.origin(anchor);
+        (min_x..min_x + self.width).contains(&cell.0)
+            && (min_y..min_y + self.height).contains(&cell.1)
+    }
+
+    /// World position of the footprint's center.
+    pub fn center(self, anchor: (i32, i32)) -> (f32, f32) {
+        let (min_x, min_y) = self.origin(anchor);
+        (
+            (min_x as f32 + (self.width - 1) as f32 * 0.5) * TERRAIN_TILE_SIZE,
+            (min_y as f32 + (self.height - 1) as f32 * 0.5) * TERRAIN_TILE_SIZE,
+        )
+    }
+
+    /// Half size of the footprint in world units.
+    pub fn half_size(self) -> (f32, f32) {
+        (
+            self.width as f32 * TERRAIN_TILE_SIZE * 0.5,
+            self.height as f32 * TERRAIN_TILE_SIZE * 0.5,
+        )
+    }
+
+    /// Collider around a structure centered at `(x, y)`, inset from the
+    /// footprint so players can squeeze between neighbours.
+    pub fn obstacle(self, x: f32, y: f32) -> StructureObstacle {
+        let (half_width, half_height) = self.half_size();
+        StructureObstacle {
+            x,
+            y,
+            half_width: half_width - COLLIDER_INSET,
+            half_height: half_height - COLLIDER_INSET,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn odd_footprints_center_on_the_anchor() {
+        let assembler = Footprint::for_structure("assembler", Direction::East);
+        let cells: Vec<_> = assembler.cells((4, -2)).collect();
+        assert_eq!(cells.len(), 9);
+        assert_eq!(cells.first(), Some(&(3, -3)));
+        assert_eq!(cells.last(), Some(&(5, -1)));
+        assert_eq!(assembler.center((4, -2)), (128.0, -64.0));
+
+        let single = Footprint::for_structure("belt", Direction::North);
+        assert_eq!(single.cells((0, 0)).collect::<Vec<_>>(), vec![(0, 0)]);
+        assert_eq!(
+            single.obstacle(0.0, 0.0).half_width,
+            STRUCTURE_COLLIDER_HALF_EXTENT
+        );
+    }
+
+    #[test]
+    fn even_footprints_extend_towards_positive_axes() {
+        let miner = Footprint::for_structure("miner", Direction::South);
+        assert!(miner.contains((0, 0), (1, 1)));
+        assert!(!miner.contains((0, 0), (-1, 0)));
+        assert_eq!(miner.center((0, 0)), (16.0, 16.0));
+        let obstacle = miner.obstacle(16.0, 16.0);
+        assert_eq!(obstacle.half_width, 32.0 - COLLIDER_INSET);
+    }
+
+    #[test]
+    fn rotation_swaps_width_and_height() {
+        let wide = Footprint {
+            width: 3,
+            height: 1,
+        };
+        assert_eq!(wide.rotated(Direction::West), wide);
+        let tall = wide.rotated(Direction::North);
+        assert_eq!((tall.width, tall.height), (1, 3));
+        assert!(tall.contains((0, 0), (0, 1)));
+        assert!(!tall.contains((0, 0), (1, 0)));
+        let obstacle = tall.obstacle(0.0, 0.0);
+        assert!(obstacle.half_height > obstacle.half_width);
+    }
+}
@@ -0,0 +1,127 @@
+//! Short trail of where a player has been, so the server can judge requests
+//! the client made while looking at an older position.
+
+use std::collections::VecDeque;
+
+/// How much history a player keeps.
+pub const POSITION_HISTORY_MS: i64 = 1000;
+
+#[derive(Debug, Clone, Default)]
+pub struct PositionHistory {
+    /// `(time_ms, x, y)`, oldest first.
+    samples: VecDeque<(i64, f32, f32)>,
+}
+
+/// Distance from `(x, y)` to the segment `a`..`b`.
+fn segment_distance(x: f32, y: f32, a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq > 0.0 {
+        (((x - a.0) * dx + (y - a.1) * dy) / length_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    ((a.0 + dx * t - x).powi(2) + (a.1 + dy * t - y).powi(2)).sqrt()
+}
+
+impl PositionHistory {
+    /// Appends a sample and drops the ones older than [`POSITION_HISTORY_MS`].
+    /// Samples must arrive in time order; an older one is ignored.
+    pub fn record(&mut self, time_ms: i64, x: f32, y: f32) {
+        if let Some(last) = self.samples.back_mut() {
+            if time_ms < last.0 {
+                return;
+            }
+            if time_ms == last.0 {
+                *last = (time_ms, x, y);
+                return;
+            }
+        }
+        self.samples.push_back((time_ms, x, y));
+        // Keep one sample at or before the cutoff so lookups right at the
+        // edge of the window can still interpolate.
+        while self.samples.len() > 2 && self.samples[1].0 <= time_ms - POSITION_HISTORY_MS {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Interpolated position at `time_ms`, clamped to the recorded range.
+    pub fn position_at(&self, time_ms: i64) -> Option<(f32, f32)> {
+        let first = self.samples.front()?;
+        if time_ms <= first.0 {
+            return Some((first.1, first.2));
+        }
+        for (a, b) in self.samples.iter().zip(self.samples.iter().skip(1)) {
+            if time_ms <= b.0 {
+                let t = (time_ms - a.0) as f32 / (b.0 - a.0) as f32;
+                return Some((a.1 + (b.1 - a.1) * t, a.2 + (b.2 - a.2) * t));
+            }
+        }
+        self.samples.back().map(|last| (last.1, last.2))
+    }
+
+    /// Closest the recorded path from `since_ms` up to the latest sample came
+    /// to `(x, y)`. `None` without any history.
+    pub fn distance_since(&self, since_ms: i64, x: f32, y: f32) -> Option<f32> {
+        let mut previous = self.position_at(since_ms)?;
+        let mut closest = segment_distance(x, y, previous, previous);
+        for &(time_ms, sample_x, sample_y) in &self.samples {
+            if time_ms <= since_ms {
+                continue;
+            }
+            closest = closest.min(segment_distance(x, y, previous, (sample_x, sample_y)));
+            previous = (sample_x, sample_y);
+        }
+        Some(closest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_interpolate_and_clamp_to_the_window() {
+        let mut history = PositionHistory::default();
+        assert_eq!(history.position_at(0), None);
+        for i in 0..=60 {
+            history.record(i * 50, i as f32 * 10.0, 0.0);
+        }
+        // Only the last second (plus one sample at the cutoff) is kept.
+        assert_eq!(history.samples.len(), 21);
+        assert_eq!(history.position_at(2525), Some((505.0, 0.0)));
+        assert_eq!(history.position_at(0), Some((400.0, 0.0)));
+        assert_eq!(history.position_at(9000), Some((600.0, 0.0)));
+
+        history.record(1000, 0.0, 0.0);
+        assert_eq!(history.position_at(9000), Some((600.0, 0.0)));
+    }
+
+    #[test]
+    fn distance_follows_the_path_since_the_given_time() {
+        let mut history = PositionHistory::default();
+        history.record(0, 0.0, 0.0);
+        history.record(100, 100.0, 0.0);
+        history.record(200, 100.0, 100.0);
+
+        assert_eq!(history.distance_since(0, 50.0, 10.0), Some(10.0));
+        // From t=150 on the path starts at (100, 50).
+        assert_eq!(
+            history.distance_since(150, 50.0, 0.0),
+            Some(50.0 * 2f32.sqrt())
+        );
+        assert_eq!(history.distance_since(150, 100.0, 80.0), Some(0.0));
+
+        history.clear();
+        assert!(history.is_empty());
+        assert_eq!(history.distance_since(0, 0.0, 0.0), None);
+    }
+}
@@ -0,0 +1,375 @@
+//! Inserters: arms that move one item at a time from the cell behind them to
+//! the cell in front of them. Timing is counted in simulation ticks so every
+//! peer sees the same swings.
+
+use std::collections::BTreeMap;
+
+use crate::{Direction, ItemKind};
+
+/// Ticks for one swing from pickup to drop; the return swing takes as long.
+pub const INSERTER_SWING_TICKS: u16 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InserterPhase {
+    /// Waiting at the pickup side for an item the drop side accepts.
+    #[default]
+    Idle,
+    /// Carrying an item towards the drop side; waits there until it is accepted.
+    Extending,
+    /// Swinging back empty-handed.
+    Retracting,
+}
+
+impl InserterPhase {
+    pub const ALL: [InserterPhase; 3] = [
+        InserterPhase::Idle,
+        InserterPhase::Extending,
+        InserterPhase::Retracting,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            InserterPhase::Idle => "idle",
+            InserterPhase::Extending => "extending",
+            InserterPhase::Retracting => "retracting",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|phase| phase.as_str() == value)
+    }
+}
+
+/// What inserters can see of the entities around them. Implemented by
+/// whoever owns the room state; cells without an entity give and take nothing.
+pub trait InserterWorld {
+    /// Item kinds the entity at `cell` can hand out, most preferred first.
+    fn pickable_items(&self, cell: (i32, i32)) -> Vec<ItemKind>;
+    /// Removes one `item` from the entity at `cell`.
+    fn take_item(&mut self, cell: (i32, i32), item: ItemKind) -> bool;
+    /// Whether the entity at `cell` accepts `item` from an inserter moving
+    /// items `direction`.
+    fn can_accept(&self, cell: (i32, i32), item: ItemKind, direction: Direction) -> bool;
+    /// Hands `item` to the entity at `cell`; returns whether it was accepted.
+    fn drop_item(&mut self, cell: (i32, i32), item: ItemKind, direction: Direction) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InserterState {
+    /// The way items move: picked up behind, dropped in front.
+    pub direction: Direction,
+    pub held: Option<ItemKind>,
+    pub phase: InserterPhase,
+    /// Ticks spent in the current swing, up to `INSERTER_SWING_TICKS`.
+    pub ticks: u16,
+}
+
+impl InserterState {
+    pub fn new(direction: Direction) -> Self {
+        Self {
+            direction,
+            ..Self::default()
+        }
+    }
+
+    /// Rebuilds an inserter from saved state; a held item forces the
+    /// extending phase so it is never lost.
+    pub fn restore(
+        direction: Direction,
+        held: Option<ItemKind>,
+        phase: InserterPhase,
+        ticks: u16,
+    ) -> Self {
+        let phase = match (held, phase) {
+            (Some(_), _) => InserterPhase::Extending,
+            (None, InserterPhase::Extending) => InserterPhase::Idle,
+            (None, phase) => phase,
+        };
+        Self {
+            direction,
+            held,
+            phase,
+            ticks: ticks.min(INSERTER_SWING_TICKS),
+        }
+    }
+
+    /// Advances one tick and returns whether the phase or held item changed.
+    pub fn step(&mut self, cell: (i32, i32), world: &mut impl InserterWorld) -> bool {
+        let (dx, dy) = self.direction.offset();
+        let source = (cell.0 - dx, cell.1 - dy);
+        let target = (cell.0 + dx, cell.1 + dy);
+
+        match self.phase {
+            InserterPhase::Idle => {
+                let item = world
+                    .pickable_items(source)
+                    .into_iter()
+                    .find(|item| world.can_accept(target, *item, self.direction));
+                match item {
+                    Some(item) if world.take_item(source, item) => {
+                        self.held = Some(item);
+                        self.phase = InserterPhase::Extending;
+                        self.ticks = 0;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            InserterPhase::Extending => {
+                self.ticks = (self.ticks + 1).min(INSERTER_SWING_TICKS);
+                let Some(item) = self.held else {
+                    self.phase = InserterPhase::Retracting;
+                    self.ticks = 0;
+                    return true;
+                };
+                if self.ticks < INSERTER_SWING_TICKS
+                    || !world.drop_item(target, item, self.direction)
+                {
+                    return false;
+                }
+                self.held = None;
+                self.phase = InserterPhase::Retracting;
+                self.ticks = 0;
+                true
+            }
+            InserterPhase::Retracting => {
+                self.ticks += 1;
+                if self.ticks < INSERTER_SWING_TICKS {
+                    return false;
+                }
+                self.phase = InserterPhase::Idle;
+                self.ticks = 0;
+                true
+            }
+        }
+    }
+}
+
+/// All inserters of a room keyed by grid cell, stepped in cell order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InserterGrid {
+    inserters: BTreeMap<(i32, i32), InserterState>,
+}
+
+impl InserterGrid {
+    pub fn get(&self, cell: (i32, i32)) -> Option<&InserterState> {
+        self.inserters.get(&cell)
+    }
+
+    pub fn insert(&mut self, cell: (i32, i32), inserter: InserterState) {
+        self.inserters.insert(cell, inserter);
+    }
+
+    pub fn remove(&mut self, cell: (i32, i32)) -> Option<InserterState> {
+        self.inserters.remove(&cell)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inserters.is_empty()
+    }
+
+    /// Advances every inserter by one tick and returns the cells of those
+    /// whose phase or held item changed.
+    pub fn step(&mut self, world: &mut impl InserterWorld) -> Vec<(i32, i32)> {
+        let mut changed = Vec::new();
+        for (cell, inserter) in self.inserters.iter_mut() {
+            if inserter.step(*cell, world) {
+                changed.push(*cell);
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        recipe_by_id, AssemblerState, BeltGrid, BeltState, MinerState, OreKind, TerrainTile,
+    };
+
+    #[derive(Default)]
+    struct TestWorld {
+        belts: BeltGrid,
+        miners: BTreeMap<(i32, i32), MinerState>,
+        assemblers: BTreeMap<(i32, i32), AssemblerState>,
+    }
+
+    impl InserterWorld for TestWorld {
+        fn pickable_items(&self, cell: (i32, i32)) -> Vec<ItemKind> {
+            if let Some(miner) = self.miners.get(&cell) {
+                return miner
+                    .output_item
+                    .filter(|_| miner.output_count > 0)
+                    .into_iter()
+                    .collect();
+            }
+            if let Some(assembler) = self.assemblers.get(&cell) {
+                return assembler
+                    .outputs
+                    .iter()
+                    .filter(|slot| slot.count > 0)
+                    .map(|slot| slot.item)
+                    .collect();
+            }
+            self.belts.pickable_items(cell)
+        }
+
+        fn take_item(&mut self, cell: (i32, i32), item: ItemKind) -> bool {
+            if let Some(miner) = self.miners.get_mut(&cell) {
+                return miner.take_output(1).is_some();
+            }
+            if let Some(assembler) = self.assemblers.get_mut(&cell) {
+                return assembler.take_item(item);
+            }
+            self.belts.take_item(cell, item)
+        }
+
+        fn can_accept(&self, cell: (i32, i32), item: ItemKind, direction: Direction) -> bool {
+            if let Some(assembler) = self.assemblers.get(&cell) {
+                return assembler.input_room(item) > 0;
+            }
+            self.belts.can_drop(cell, direction)
+        }
+
+        fn drop_item(&mut self, cell: (i32, i32), item: ItemKind, direction: Direction) -> bool {
+            if let Some(assembler) = self.assemblers.get_mut(&cell) {
+                return assembler.insert_input(item, 1) == 1;
+            }
+            self.belts.drop_item(cell, item, direction)
+        }
+    }
+
+    fn stocked_miner(ore: OreKind, count: u32) -> MinerState {
+        let mut miner = MinerState::on_tile(TerrainTile::Ore(ore));
+        miner.output_count = count;
+        miner
+    }
+
+    fn run(world: &mut TestWorld, inserters: &mut InserterGrid, ticks: usize) {
+        for _ in 0..ticks {
+            world.belts.step();
+            inserters.step(world);
+        }
+    }
+
+    #[test]
+    fn inserter_moves_one_item_per_cycle_onto_a_belt() {
+        let mut world = TestWorld::default();
+        world
+            .miners
+            .insert((0, 0), stocked_miner(OreKind::Iron, 40));
+        for x in 2..14 {
+            world.belts.insert((x, 0), BeltState::new(Direction::East));
+        }
+        let mut inserters = InserterGrid::default();
+        inserters.insert((1, 0), InserterState::new(Direction::East));
+
+        // Pickup, a full swing out and a full swing back.
+        let cycle = 1 + 2 * INSERTER_SWING_TICKS as usize;
+        run(&mut world, &mut inserters, cycle * 10);
+
+        let on_belts: usize = world
+            .belts
+            .iter()
+            .map(|(_, belt)| belt.items().count())
+            .sum();
+        assert_eq!(on_belts, 10);
+        assert_eq!(world.miners[&(0, 0)].output_count, 30);
+        assert!(world
+            .belts
+            .iter()
+            .all(|(_, belt)| belt.lanes[crate::BELT_LEFT_LANE].is_empty()));
+    }
+
+    #[test]
+    fn inserter_only_picks_what_the_target_accepts_and_waits_when_full() {
+        let mut world = TestWorld::default();
+        world
+            .miners
+            .insert((0, 0), stocked_miner(OreKind::Copper, 10));
+        world.assemblers.insert(
+            (2, 0),
+            AssemblerState::with_recipe(recipe_by_id("iron_plate")),
+        );
+        let mut inserters = InserterGrid::default();
+        inserters.insert((1, 0), InserterState::new(Direction::East));
+
+        run(&mut world, &mut inserters, 100);
+        assert_eq!(world.miners[&(0, 0)].output_count, 10);
+        assert_eq!(inserters.get((1, 0)).unwrap().phase, InserterPhase::Idle);
+
+        // The assembler fills up while iron ore is in hand: the arm waits at
+        // the drop side until there is room again.
+        world
+            .miners
+            .insert((0, 0), stocked_miner(OreKind::Iron, 10));
+        run(&mut world, &mut inserters, 1);
+        let assembler = world.assemblers.get_mut(&(2, 0)).unwrap();
+        assembler.insert_input(ItemKind::IronOre, u32::MAX);
+        run(&mut world, &mut inserters, 60);
+        let inserter = inserters.get((1, 0)).unwrap();
+        assert_eq!(inserter.phase, InserterPhase::Extending);
+        assert_eq!(inserter.held, Some(ItemKind::IronOre));
+        assert_eq!(inserter.ticks, INSERTER_SWING_TICKS);
+
+        world.assemblers.get_mut(&(2, 0)).unwrap().inputs[0].count -= 1;
+        run(&mut world, &mut inserters, 1);
+        let inserter = inserters.get((1, 0)).unwrap();
+        assert_eq!(inserter.phase, InserterPhase::Retracting);
+        assert_eq!(inserter.held, None);
+        assert_eq!(world.miners[&(0, 0)].output_count, 9);
+    }
+
+    #[test]
+    fn miner_assembler_belt_chain_produces_plates() {
+        let mut world = TestWorld::default();
+        world
+            .miners
+            .insert((0, 0), stocked_miner(OreKind::Iron, 50));
+        world.assemblers.insert(
+            (2, 0),
+            AssemblerState::with_recipe(recipe_by_id("iron_plate")),
+        );
+        for x in 4..10 {
+            world.belts.insert((x, 0), BeltState::new(Direction::East));
+        }
+        let mut inserters = InserterGrid::default();
+        inserters.insert((1, 0), InserterState::new(Direction::East));
+        inserters.insert((3, 0), InserterState::new(Direction::East));
+
+        let recipe = recipe_by_id("iron_plate").unwrap();
+        let craft_ticks = (recipe.craft_seconds * 30.0) as usize;
+        for _ in 0..craft_ticks * 5 {
+            world.belts.step();
+            inserters.step(&mut world);
+            if let Some(assembler) = world.assemblers.get_mut(&(2, 0)) {
+                assembler.step(1.0 / 30.0);
+            }
+        }
+
+        let plates: usize = world
+            .belts
+            .iter()
+            .flat_map(|(_, belt)| belt.items())
+            .filter(|stack| stack.item == ItemKind::IronPlate)
+            .count();
+        // The first craft waits for ore, so the last plate has not reached the belt yet.
+        assert_eq!(plates, 4);
+    }
+
+    #[test]
+    fn restore_keeps_held_items() {
+        let restored = InserterState::restore(
+            Direction::North,
+            Some(ItemKind::Coal),
+            InserterPhase::Idle,
+            40,
+        );
+        assert_eq!(restored.phase, InserterPhase::Extending);
+        assert_eq!(restored.ticks, INSERTER_SWING_TICKS);
+
+        let empty = InserterState::restore(Direction::North, None, InserterPhase::Extending, 3);
+        assert_eq!(empty.phase, InserterPhase::Idle);
+    }
+}
@@ -0,0 +1,344 @@
+//! Player and chest inventories: a fixed number of slots holding typed item
+//! stacks.
+
+use crate::{ItemKind, ItemStack};
+
+pub const INVENTORY_SLOTS: usize = 24;
+pub const CHEST_SLOTS: usize = 16;
+
+/// What a player without a saved inventory starts with.
+pub const STARTER_ITEMS: &[(ItemKind, u32)] = &[
+    (ItemKind::Beacon, 10),
+    (ItemKind::Miner, 10),
+    (ItemKind::Assembler, 5),
+    (ItemKind::Belt, 100),
+    (ItemKind::Inserter, 20),
+    (ItemKind::Ammo, 100),
+    (ItemKind::Generator, 2),
+    (ItemKind::Chest, 5),
+    (ItemKind::Lab, 2),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Self::empty(INVENTORY_SLOTS)
+    }
+}
+
+impl Inventory {
+    pub fn empty(slot_count: usize) -> Self {
+        Self {
+            slots: vec![None; slot_count],
+        }
+    }
+
+    pub fn starter() -> Self {
+        let mut inventory = Self::default();
+        for &(item, count) in STARTER_ITEMS {
+            inventory.insert(item, count);
+        }
+        inventory
+    }
+
+    /// Rebuilds a player inventory from saved slots, padding or truncating to
+    /// `INVENTORY_SLOTS` and clamping each stack to its stack size.
+    pub fn from_slots(slots: impl IntoIterator<Item = Option<ItemStack>>) -> Self {
+        Self::restore(INVENTORY_SLOTS, slots)
+    }
+
+    /// Like [`Inventory::from_slots`] for an inventory of `slot_count` slots.
+    pub fn restore(slot_count: usize, slots: impl IntoIterator<Item = Option<ItemStack>>) -> Self {
+        let mut slots: Vec<Option<ItemStack>> = slots
+            .into_iter()
+            .take(slot_count)
+            .map(|slot| {
+                slot.filter(|stack| stack.count > 0).map(|stack| ItemStack {
+                    item: stack.item,
+                    count: stack.count.min(stack.item.stack_size()),
+                })
+            })
+            .collect();
+        slots.resize(slot_count, None);
+        Self { slots }
+    }
+
+    pub fn slots(&self) -> &[Option<ItemStack>] {
+        &self.slots
+    }
+
+    pub fn count(&self, item: ItemKind) -> u32 {
+        self.slots
+            .iter()
+            .flatten()
+            .filter(|stack| stack.item == item)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    /// Adds up to `count` items, topping up existing stacks before using empty
+    /// slots. Returns how many were added.
+    pub fn insert(&mut self, item: ItemKind, count: u32) -> u32 {
+        let stack_size = item.stack_size();
+        let mut remaining = count;
+
+        for stack in self.slots.iter_mut().flatten() {
+            if remaining == 0 {
+                break;
+            }
+            if stack.item == item {
+                let added = remaining.min(stack_size.saturating_sub(stack.count));
+                stack.count += added;
+                remaining -= added;
+            }
+        }
+
+        for slot in self.slots.iter_mut().filter(|slot| slot.is_none()) {
+            if remaining == 0 {
+                break;
+            }
+            let added = remaining.min(stack_size);
+            *slot = Some(ItemStack { item, count: added });
+            remaining -= added;
+        }
+
+        count - remaining
+    }
+
+    /// How many more `item`s would fit.
+    pub fn room_for(&self, item: ItemKind) -> u32 {
+        let stack_size = item.stack_size();
+        self.slots
+            .iter()
+            .map(|slot| match slot {
+                None => stack_size,
+                Some(stack) if stack.item == item => stack_size.saturating_sub(stack.count),
+                Some(_) => 0,
+            })
+            .sum()
+    }
+
+    /// Moves up to `max` `item`s into `other`, as many as this inventory
+    /// holds and `other` has room for. Returns how many moved.
+    pub fn move_to(&mut self, other: &mut Inventory, item: ItemKind, max: u32) -> u32 {
+        let moved = max.min(self.count(item)).min(other.room_for(item));
+        if moved > 0 && self.remove(item, moved) {
+            other.insert(item, moved);
+        }
+        moved
+    }
+
+    /// Removes exactly `count` items, or nothing if the inventory holds fewer.
+    pub fn remove(&mut self, item: ItemKind, count: u32) -> bool {
+        if self.count(item) < count {
+            return false;
+        }
+
+        let mut remaining = count;
+        for slot in self.slots.iter_mut().rev() {
+            if remaining == 0 {
+                break;
+            }
+            let Some(stack) = slot.as_mut().filter(|stack| stack.item == item) else {
+                continue;
+            };
+            let taken = remaining.min(stack.count);
+            stack.count -= taken;
+            remaining -= taken;
+            if stack.count == 0 {
+                *slot = None;
+            }
+        }
+        true
+    }
+
+    /// Removes every stack in `stacks`, or nothing if any of them is short.
+    pub fn remove_all(&mut self, stacks: &[ItemStack]) -> bool {
+        let short = stacks.iter().any(|stack| {
+            let needed: u32 = stacks
+                .iter()
+                .filter(|other| other.item == stack.item)
+                .map(|other| other.count)
+                .sum();
+            self.count(stack.item) < needed
+        });
+        if short {
+            return false;
+        }
+        for stack in stacks {
+            self.remove(stack.item, stack.count);
+        }
+        true
+    }
+
+    /// Adds every stack in `stacks`, or nothing if they do not all fit.
+    pub fn insert_all(&mut self, stacks: &[ItemStack]) -> bool {
+        let mut filled = self.clone();
+        if stacks
+            .iter()
+            .all(|stack| filled.insert(stack.item, stack.count) == stack.count)
+        {
+            *self = filled;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_fills_stacks_then_empty_slots() {
+        let mut inventory = Inventory::default();
+        let stack_size = ItemKind::IronOre.stack_size();
+
+        assert_eq!(
+            inventory.insert(ItemKind::IronOre, stack_size - 5),
+            stack_size - 5
+        );
+        assert_eq!(inventory.insert(ItemKind::Coal, 3), 3);
+        assert_eq!(inventory.insert(ItemKind::IronOre, 10), 10);
+
+        assert_eq!(
+            inventory.slots()[0],
+            Some(ItemStack {
+                item: ItemKind::IronOre,
+                count: stack_size
+            })
+        );
+        assert_eq!(
+            inventory.slots()[2],
+            Some(ItemStack {
+                item: ItemKind::IronOre,
+                count: 5
+            })
+        );
+        assert_eq!(inventory.count(ItemKind::IronOre), stack_size + 5);
+    }
+
+    #[test]
+    fn insert_stops_when_full_and_remove_is_all_or_nothing() {
+        let mut inventory = Inventory::default();
+        let capacity = ItemKind::Stone.stack_size() * INVENTORY_SLOTS as u32;
+        assert_eq!(inventory.insert(ItemKind::Stone, capacity + 7), capacity);
+        assert_eq!(inventory.insert(ItemKind::Coal, 1), 0);
+
+        assert!(!inventory.remove(ItemKind::Coal, 1));
+        assert!(!inventory.remove(ItemKind::Stone, capacity + 1));
+        assert!(inventory.remove(ItemKind::Stone, ItemKind::Stone.stack_size() + 1));
+        assert_eq!(inventory.slots()[INVENTORY_SLOTS - 1], None);
+        assert_eq!(
+            inventory.count(ItemKind::Stone),
+            capacity - ItemKind::Stone.stack_size() - 1
+        );
+        assert_eq!(inventory.insert(ItemKind::Coal, 1), 1);
+    }
+
+    #[test]
+    fn from_slots_normalizes_saved_state() {
+        let stack_size = ItemKind::Miner.stack_size();
+        let inventory = Inventory::from_slots(vec![
+            Some(ItemStack {
+                item: ItemKind::Miner,
+                count: stack_size + 10,
+            }),
+            Some(ItemStack {
+                item: ItemKind::Coal,
+                count: 0,
+            }),
+        ]);
+
+        assert_eq!(inventory.slots().len(), INVENTORY_SLOTS);
+        assert_eq!(inventory.count(ItemKind::Miner), stack_size);
+        assert_eq!(inventory.slots()[1], None);
+        assert_eq!(Inventory::starter().count(ItemKind::Beacon), 10);
+        assert_eq!(ItemKind::for_structure("miner"), Some(ItemKind::Miner));
+        assert_eq!(ItemKind::for_structure("iron_plate"), None);
+
+        let chest = Inventory::restore(CHEST_SLOTS, inventory.slots().iter().copied());
+        assert_eq!(chest.slots().len(), CHEST_SLOTS);
+        assert_eq!(chest.count(ItemKind::Miner), stack_size);
+    }
+
+    #[test]
+    fn move_to_stops_at_the_smaller_of_stock_and_room() {
+        let mut player = Inventory::default();
+        let mut chest = Inventory::empty(2);
+        let stack_size = ItemKind::Coal.stack_size();
+        player.insert(ItemKind::Coal, stack_size * 3);
+        chest.insert(ItemKind::Stone, 1);
+
+        assert_eq!(chest.room_for(ItemKind::Coal), stack_size);
+        assert_eq!(
+            player.move_to(&mut chest, ItemKind::Coal, u32::MAX),
+            stack_size
+        );
+        assert_eq!(player.count(ItemKind::Coal), stack_size * 2);
+        assert_eq!(chest.room_for(ItemKind::Coal), 0);
+        assert_eq!(player.move_to(&mut chest, ItemKind::Coal, 5), 0);
+
+        assert_eq!(chest.move_to(&mut player, ItemKind::Coal, 7), 7);
+        assert_eq!(chest.count(ItemKind::Coal), stack_size - 7);
+        assert_eq!(chest.move_to(&mut player, ItemKind::Circuit, 1), 0);
+    }
+
+    #[test]
+    fn remove_all_takes_every_stack_or_none() {
+        let mut inventory = Inventory::default();
+        inventory.insert(ItemKind::IronPlate, 5);
+        inventory.insert(ItemKind::IronGear, 2);
+        let cost = |plates, gears| {
+            [
+                ItemStack {
+                    item: ItemKind::IronPlate,
+                    count: plates,
+                },
+                ItemStack {
+                    item: ItemKind::IronGear,
+                    count: gears,
+                },
+            ]
+        };
+
+        assert!(!inventory.remove_all(&cost(4, 3)));
+        assert_eq!(inventory.count(ItemKind::IronPlate), 5);
+        // The same item listed twice has to be covered in total.
+        assert!(!inventory.remove_all(&[cost(3, 0)[0], cost(3, 0)[0]]));
+        assert!(inventory.remove_all(&cost(4, 2)));
+        assert_eq!(inventory.count(ItemKind::IronPlate), 1);
+        assert_eq!(inventory.count(ItemKind::IronGear), 0);
+    }
+
+    #[test]
+    fn insert_all_adds_every_stack_or_none() {
+        let mut inventory = Inventory::empty(2);
+        let stack_size = ItemKind::Coal.stack_size();
+        inventory.insert(ItemKind::Coal, stack_size - 1);
+        let stacks = |coal, ore| {
+            [
+                ItemStack {
+                    item: ItemKind::Coal,
+                    count: coal,
+                },
+                ItemStack {
+                    item: ItemKind::IronOre,
+                    count: ore,
+                },
+            ]
+        };
+
+        assert!(!inventory.insert_all(&stacks(2, 1)));
+        assert_eq!(inventory.count(ItemKind::Coal), stack_size - 1);
+        assert_eq!(inventory.count(ItemKind::IronOre), 0);
+        assert!(inventory.insert_all(&stacks(1, 1)));
+        assert_eq!(inventory.count(ItemKind::Coal), stack_size);
+        assert_eq!(inventory.count(ItemKind::IronOre), 1);
+    }
+}
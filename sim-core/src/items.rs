@@ -0,0 +1,193 @@
+//! Item kinds moved around by machines, belts and inventories.
+
+use crate::{structure_prototype, OreKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ItemKind {
+    IronOre,
+    CopperOre,
+    Stone,
+    Coal,
+    IronPlate,
+    CopperPlate,
+    StoneBrick,
+    IronGear,
+    CopperCable,
+    Circuit,
+    Beacon,
+    Miner,
+    Assembler,
+    Belt,
+    Inserter,
+    Ammo,
+    Generator,
+    Chest,
+    SciencePack,
+    Lab,
+}
+
+impl ItemKind {
+    pub const ALL: [ItemKind; 20] = [
+        ItemKind::IronOre,
+        ItemKind::CopperOre,
+        ItemKind::Stone,
+        ItemKind::Coal,
+        ItemKind::IronPlate,
+        ItemKind::CopperPlate,
+        ItemKind::StoneBrick,
+        ItemKind::IronGear,
+        ItemKind::CopperCable,
+        ItemKind::Circuit,
+        ItemKind::Beacon,
+        ItemKind::Miner,
+        ItemKind::Assembler,
+        ItemKind::Belt,
+        ItemKind::Inserter,
+        ItemKind::Ammo,
+        ItemKind::Generator,
+        ItemKind::Chest,
+        ItemKind::SciencePack,
+        ItemKind::Lab,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ItemKind::IronOre => "iron_ore",
+            ItemKind::CopperOre => "copper_ore",
+            ItemKind::Stone => "stone",
+            ItemKind::Coal => "coal",
+            ItemKind::IronPlate => "iron_plate",
+            ItemKind::CopperPlate => "copper_plate",
+            ItemKind::StoneBrick => "stone_brick",
+            ItemKind::IronGear => "iron_gear",
+            ItemKind::CopperCable => "copper_cable",
+            ItemKind::Circuit => "circuit",
+            ItemKind::Beacon => "beacon",
+            ItemKind::Miner => "miner",
+            ItemKind::Assembler => "assembler",
+            ItemKind::Belt => "belt",
+            ItemKind::Inserter => "inserter",
+            ItemKind::Ammo => "ammo",
+            ItemKind::Generator => "generator",
+            ItemKind::Chest => "chest",
+            ItemKind::SciencePack => "science_pack",
+            ItemKind::Lab => "lab",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == value)
+    }
+
+    /// Most items of this kind one inventory slot can hold.
+    pub fn stack_size(self) -> u32 {
+        match self {
+            ItemKind::IronOre | ItemKind::CopperOre | ItemKind::Stone | ItemKind::Coal => 50,
+            ItemKind::IronPlate
+            | ItemKind::CopperPlate
+            | ItemKind::StoneBrick
+            | ItemKind::IronGear => 100,
+            ItemKind::CopperCable | ItemKind::Circuit | ItemKind::Ammo | ItemKind::SciencePack => {
+                200
+            }
+            ItemKind::Beacon
+            | ItemKind::Miner
+            | ItemKind::Assembler
+            | ItemKind::Inserter
+            | ItemKind::Generator
+            | ItemKind::Chest
+            | ItemKind::Lab => 50,
+            ItemKind::Belt => 100,
+        }
+    }
+
+    /// The item that places a structure of `kind`.
+    pub fn for_structure(kind: &str) -> Option<Self> {
+        Self::parse(kind).filter(|item| item.structure_kind().is_some())
+    }
+
+    /// The structure kind this item places, if it is placeable: the one whose
+    /// prototype shares its name.
+    pub fn structure_kind(self) -> Option<&'static str> {
+        structure_prototype(self.as_str()).map(|prototype| prototype.kind.as_str())
+    }
+
+    /// What a miner produces from an ore tile.
+    pub fn from_ore(ore: OreKind) -> Self {
+        match ore {
+            OreKind::Iron => ItemKind::IronOre,
+            OreKind::Copper => ItemKind::CopperOre,
+            OreKind::Stone => ItemKind::Stone,
+            OreKind::Coal => ItemKind::Coal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemStack {
+    pub item: ItemKind,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Recipe {
+    pub id: &'static str,
+    pub inputs: &'static [(ItemKind, u32)],
+    pub outputs: &'static [(ItemKind, u32)],
+    pub craft_seconds: f32,
+}
+
+pub const RECIPES: &[Recipe] = &[
+    Recipe {
+        id: "iron_plate",
+        inputs: &[(ItemKind::IronOre, 1)],
+        outputs: &[(ItemKind::IronPlate, 1)],
+        craft_seconds: 2.0,
+    },
+    Recipe {
+        id: "copper_plate",
+        inputs: &[(ItemKind::CopperOre, 1)],
+        outputs: &[(ItemKind::CopperPlate, 1)],
+        craft_seconds: 2.0,
+    },
+    Recipe {
+        id: "stone_brick",
+        inputs: &[(ItemKind::Stone, 2)],
+        outputs: &[(ItemKind::StoneBrick, 1)],
+        craft_seconds: 2.0,
+    },
+    Recipe {
+        id: "iron_gear",
+        inputs: &[(ItemKind::IronPlate, 2)],
+        outputs: &[(ItemKind::IronGear, 1)],
+        craft_seconds: 1.0,
+    },
+    Recipe {
+        id: "copper_cable",
+        inputs: &[(ItemKind::CopperPlate, 1)],
+        outputs: &[(ItemKind::CopperCable, 2)],
+        craft_seconds: 0.5,
+    },
+    Recipe {
+        id: "circuit",
+        inputs: &[(ItemKind::IronPlate, 1), (ItemKind::CopperCable, 3)],
+        outputs: &[(ItemKind::Circuit, 1)],
+        craft_seconds: 1.0,
+    },
+    Recipe {
+        id: "science_pack",
+        inputs: &[(ItemKind::IronGear, 1), (ItemKind::CopperPlate, 1)],
+        outputs: &[(ItemKind::SciencePack, 1)],
+        craft_seconds: 5.0,
+    },
+    Recipe {
+        id: "ammo",
+        inputs: &[(ItemKind::IronPlate, 1)],
+        outputs: &[(ItemKind::Ammo, 10)],
+        craft_seconds: 1.0,
+    },
+];
+
+pub fn recipe_by_id(id: &str) -> Option<&'static Recipe> {
+    RECIPES.iter().find(|recipe| recipe.id == id)
+}
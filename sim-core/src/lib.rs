@@ -1,9 +1,49 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+mod belts;
+mod enemies;
+mod fixed;
+mod footprint;
+mod history;
+mod inserters;
+mod inventory;
+mod items;
+mod machines;
+mod power;
+mod prototypes;
+mod research;
+mod spatial;
+mod stamina;
+mod terrain;
+mod weapons;
+
+pub use belts::*;
+pub use enemies::*;
+pub use fixed::*;
+pub use footprint::*;
+pub use history::*;
+pub use inserters::*;
+pub use inventory::*;
+pub use items::*;
+pub use machines::*;
+pub use power::*;
+pub use prototypes::*;
+pub use research::*;
+pub use spatial::*;
+pub use stamina::*;
+pub use terrain::*;
+pub use weapons::*;
+
+/// Movement input for one step. A non-zero analog vector (`move_x`,
+/// `move_y`) takes precedence over the four direction flags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct InputState {
     pub up: bool,
     pub down: bool,
     pub left: bool,
     pub right: bool,
+    /// Quantized stick position, `-ANALOG_AXIS_MAX..=ANALOG_AXIS_MAX` per axis.
+    pub move_x: i8,
+    pub move_y: i8,
+    pub sprint: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -24,17 +64,173 @@ pub struct MovementStep {
 pub struct StructureObstacle {
     pub x: f32,
     pub y: f32,
-    pub half_extent: f32,
+    pub half_width: f32,
+    pub half_height: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerCollider {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectileHit {
+    Structure(usize),
+    Player(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProjectileStep {
+    pub x: f32,
+    pub y: f32,
+    pub hit: Option<ProjectileHit>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Health {
+    pub current: i32,
+    pub max: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DamageOutcome {
+    Ignored,
+    Damaged,
+    Killed,
 }
 
+/// Full deflection of one analog axis.
+pub const ANALOG_AXIS_MAX: i8 = 127;
+
 pub const PLAYER_COLLIDER_RADIUS: f32 = 10.0;
 pub const STRUCTURE_COLLIDER_HALF_EXTENT: f32 = 11.0;
+pub const PROJECTILE_COLLIDER_RADIUS: f32 = 4.0;
+
+pub const PLAYER_MAX_HP: i32 = 100;
+pub const PROJECTILE_DAMAGE: i32 = 20;
+pub const RESPAWN_DELAY_MS: i64 = 3000;
+pub const RESPAWN_INVULNERABILITY_MS: i64 = 2000;
+pub const RESPAWN_RING_RADIUS: f32 = 96.0;
+
+impl InputState {
+    pub fn has_analog(&self) -> bool {
+        self.move_x != 0 || self.move_y != 0
+    }
+
+    /// Whether this input moves the player at all.
+    pub fn is_moving(&self) -> bool {
+        self.has_analog() || self.up != self.down || self.left != self.right
+    }
+
+    /// The same input with its analog vector scaled down to at most unit
+    /// length. Integer-only, so every target clamps identically.
+    pub fn clamped(self) -> Self {
+        let max = ANALOG_AXIS_MAX as i32;
+        let x = (self.move_x as i32).clamp(-max, max);
+        let y = (self.move_y as i32).clamp(-max, max);
+        let squared = x * x + y * y;
+        let mut magnitude = max;
+        while magnitude * magnitude < squared {
+            magnitude += 1;
+        }
+        Self {
+            move_x: (x * max / magnitude) as i8,
+            move_y: (y * max / magnitude) as i8,
+            ..self
+        }
+    }
+}
+
+impl StructureObstacle {
+    /// Whether a collider of `radius` at `(x, y)` overlaps this box. Players
+    /// are tested as squares here, matching movement.
+    pub fn blocks(&self, x: f32, y: f32, radius: f32) -> bool {
+        (x - self.x).abs() < self.half_width + radius
+            && (y - self.y).abs() < self.half_height + radius
+    }
+}
+
+impl Health {
+    pub fn full(max: i32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0
+    }
+
+    pub fn apply_damage(&mut self, amount: i32) -> DamageOutcome {
+        if amount <= 0 || self.is_dead() {
+            return DamageOutcome::Ignored;
+        }
+
+        self.current = (self.current - amount).max(0);
+        if self.is_dead() {
+            DamageOutcome::Killed
+        } else {
+            DamageOutcome::Damaged
+        }
+    }
+}
+
+/// Belts lie flat: players walk over them and projectiles pass above them.
+/// Unknown kinds are treated as solid.
+pub fn structure_is_solid(kind: &str) -> bool {
+    structure_prototype(kind).is_none_or(|prototype| prototype.collider == StructureCollider::Box)
+}
+
+/// Kinds placed with a direction: belts move items that way, inserters pick
+/// up behind and drop in front.
+pub fn structure_is_directional(kind: &str) -> bool {
+    structure_prototype(kind).is_some_and(|prototype| prototype.directional)
+}
+
+pub fn structure_max_hp(kind: &str) -> i32 {
+    structure_prototype(kind).map_or(200, |prototype| prototype.max_hp)
+}
+
+/// Players are only damageable while alive and outside their post-respawn
+/// invulnerability window.
+pub fn player_can_take_damage(health: Health, invulnerable_until_ms: i64, now_ms: i64) -> bool {
+    !health.is_dead() && now_ms >= invulnerable_until_ms
+}
+
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// Deterministic respawn point on a ring around the world origin. Mixing in
+/// the respawn count spreads repeated deaths of the same player around.
+pub fn respawn_position(player_id: &str, respawn_count: u32) -> (f32, f32) {
+    let hash = fnv1a_hash(player_id.as_bytes()) ^ (respawn_count as u64).wrapping_mul(0x9e37_79b9);
+    let angle = (hash % 3600) as f32 / 3600.0 * std::f32::consts::TAU;
+    (
+        angle.cos() * RESPAWN_RING_RADIUS,
+        angle.sin() * RESPAWN_RING_RADIUS,
+    )
+}
 
 pub fn clamp_axis(value: f32, map_limit: f32) -> f32 {
     value.max(-map_limit).min(map_limit)
 }
 
 pub fn movement_velocity(input: InputState, speed: f32) -> Velocity {
+    let input = input.clamped();
+    if input.has_analog() {
+        let scale = speed / ANALOG_AXIS_MAX as f32;
+        return Velocity {
+            x: input.move_x as f32 * scale,
+            y: input.move_y as f32 * scale,
+        };
+    }
+
     let mut dx = 0.0f32;
     let mut dy = 0.0f32;
 
@@ -82,17 +278,27 @@ pub fn movement_step(
     )
 }
 
-fn collides_with_obstacle(
+#[allow(clippy::too_many_arguments)]
+pub fn movement_step_with_obstacles(
     x: f32,
     y: f32,
-    obstacle: &StructureObstacle,
+    input: InputState,
+    dt_seconds: f32,
+    speed: f32,
+    map_limit: f32,
+    obstacles: &[StructureObstacle],
     player_radius: f32,
-) -> bool {
-    let blocked = obstacle.half_extent + player_radius;
-    (x - obstacle.x).abs() < blocked && (y - obstacle.y).abs() < blocked
+) -> MovementStep {
+    resolve_movement(x, y, input, dt_seconds, speed, map_limit, |x, y| {
+        obstacles
+            .iter()
+            .any(|obstacle| obstacle.blocks(x, y, player_radius))
+    })
 }
 
-pub fn movement_step_with_obstacles(
+/// Like `movement_step_with_obstacles`, but unwalkable terrain tiles block too.
+#[allow(clippy::too_many_arguments)]
+pub fn movement_step_with_terrain(
     x: f32,
     y: f32,
     input: InputState,
@@ -101,6 +307,24 @@ pub fn movement_step_with_obstacles(
     map_limit: f32,
     obstacles: &[StructureObstacle],
     player_radius: f32,
+    terrain: &Terrain,
+) -> MovementStep {
+    resolve_movement(x, y, input, dt_seconds, speed, map_limit, |x, y| {
+        terrain.blocks_area(x, y, player_radius)
+            || obstacles
+                .iter()
+                .any(|obstacle| obstacle.blocks(x, y, player_radius))
+    })
+}
+
+fn resolve_movement(
+    x: f32,
+    y: f32,
+    input: InputState,
+    dt_seconds: f32,
+    speed: f32,
+    map_limit: f32,
+    blocked: impl Fn(f32, f32) -> bool,
 ) -> MovementStep {
     let velocity = movement_velocity(input, speed);
 
@@ -112,18 +336,12 @@ pub fn movement_step_with_obstacles(
     let mut resolved_vx = velocity.x;
     let mut resolved_vy = velocity.y;
 
-    if obstacles
-        .iter()
-        .any(|obstacle| collides_with_obstacle(resolved_x, y, obstacle, player_radius))
-    {
+    if blocked(resolved_x, y) {
         resolved_x = x;
         resolved_vx = 0.0;
     }
 
-    if obstacles
-        .iter()
-        .any(|obstacle| collides_with_obstacle(resolved_x, resolved_y, obstacle, player_radius))
-    {
+    if blocked(resolved_x, resolved_y) {
         resolved_y = y;
         resolved_vy = 0.0;
     }
@@ -150,6 +368,135 @@ pub fn projectile_step(
     )
 }
 
+/// Earliest entry time in `[0, 1]` of the segment `start + t * delta` into the
+/// box around `obstacle`, inflated by `radius`.
+fn segment_hits_obstacle(
+    start_x: f32,
+    start_y: f32,
+    delta_x: f32,
+    delta_y: f32,
+    obstacle: &StructureObstacle,
+    radius: f32,
+) -> Option<f32> {
+    let mut t_enter = 0.0f32;
+    let mut t_exit = 1.0f32;
+
+    for (start, delta, center, extent) in [
+        (start_x, delta_x, obstacle.x, obstacle.half_width + radius),
+        (start_y, delta_y, obstacle.y, obstacle.half_height + radius),
+    ] {
+        let min = center - extent;
+        let max = center + extent;
+        if delta.abs() <= f32::EPSILON {
+            if start < min || start > max {
+                return None;
+            }
+            continue;
+        }
+
+        let mut t0 = (min - start) / delta;
+        let mut t1 = (max - start) / delta;
+        if t0 > t1 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+        t_enter = t_enter.max(t0);
+        t_exit = t_exit.min(t1);
+        if t_enter > t_exit {
+            return None;
+        }
+    }
+
+    Some(t_enter)
+}
+
+/// Earliest entry time in `[0, 1]` of the segment `start + t * delta` into the
+/// circle around `player`, inflated by `radius`.
+fn segment_hits_player(
+    start_x: f32,
+    start_y: f32,
+    delta_x: f32,
+    delta_y: f32,
+    player: &PlayerCollider,
+    radius: f32,
+) -> Option<f32> {
+    let combined = player.radius + radius;
+    let offset_x = start_x - player.x;
+    let offset_y = start_y - player.y;
+    let c = offset_x * offset_x + offset_y * offset_y - combined * combined;
+    if c <= 0.0 {
+        return Some(0.0);
+    }
+
+    let a = delta_x * delta_x + delta_y * delta_y;
+    if a <= f32::EPSILON {
+        return None;
+    }
+
+    let b = 2.0 * (offset_x * delta_x + offset_y * delta_y);
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let t = (-b - discriminant.sqrt()) / (2.0 * a);
+    (0.0..=1.0).contains(&t).then_some(t)
+}
+
+/// Advances a projectile like [`projectile_step`], but sweeps the travelled
+/// segment against structures and players and stops at the earliest contact.
+/// Callers are expected to leave the shooter out of `players`.
+#[allow(clippy::too_many_arguments)]
+pub fn projectile_step_with_hits(
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+    dt_seconds: f32,
+    map_limit: f32,
+    obstacles: &[StructureObstacle],
+    players: &[PlayerCollider],
+    projectile_radius: f32,
+) -> ProjectileStep {
+    let (next_x, next_y) = projectile_step(x, y, vx, vy, dt_seconds, map_limit);
+    let delta_x = next_x - x;
+    let delta_y = next_y - y;
+
+    let mut earliest: Option<(f32, ProjectileHit)> = None;
+    let mut consider = |t: Option<f32>, hit: ProjectileHit| {
+        if let Some(t) = t {
+            if earliest.is_none_or(|(best, _)| t < best) {
+                earliest = Some((t, hit));
+            }
+        }
+    };
+
+    for (index, obstacle) in obstacles.iter().enumerate() {
+        consider(
+            segment_hits_obstacle(x, y, delta_x, delta_y, obstacle, projectile_radius),
+            ProjectileHit::Structure(index),
+        );
+    }
+    for (index, player) in players.iter().enumerate() {
+        consider(
+            segment_hits_player(x, y, delta_x, delta_y, player, projectile_radius),
+            ProjectileHit::Player(index),
+        );
+    }
+
+    match earliest {
+        Some((t, hit)) => ProjectileStep {
+            x: x + delta_x * t,
+            y: y + delta_y * t,
+            hit: Some(hit),
+        },
+        None => ProjectileStep {
+            x: next_x,
+            y: next_y,
+            hit: None,
+        },
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn sim_compute_velocity_x(
     up: u32,
@@ -164,6 +511,7 @@ pub extern "C" fn sim_compute_velocity_x(
             down: down != 0,
             left: left != 0,
             right: right != 0,
+            ..Default::default()
         },
         speed,
     )
@@ -184,6 +532,7 @@ pub extern "C" fn sim_compute_velocity_y(
             down: down != 0,
             left: left != 0,
             right: right != 0,
+            ..Default::default()
         },
         speed,
     )
@@ -212,6 +561,7 @@ mod tests {
                 down: false,
                 left: false,
                 right: true,
+                ..Default::default()
             },
             220.0,
         );
@@ -220,6 +570,39 @@ mod tests {
         assert!((magnitude - 220.0).abs() < 0.001);
     }
 
+    #[test]
+    fn analog_input_is_clamped_to_unit_length() {
+        let full_diagonal = InputState {
+            move_x: ANALOG_AXIS_MAX,
+            move_y: -ANALOG_AXIS_MAX,
+            ..Default::default()
+        }
+        .clamped();
+        let (x, y) = (full_diagonal.move_x as i32, full_diagonal.move_y as i32);
+        assert!(x * x + y * y <= 127 * 127);
+        assert_eq!(x, -y);
+        assert!(x >= 89);
+
+        let half = InputState {
+            move_x: 0,
+            move_y: 64,
+            ..Default::default()
+        };
+        assert_eq!(half.clamped(), half);
+        let velocity = movement_velocity(half, 220.0);
+        assert_eq!(velocity.x, 0.0);
+        assert!((velocity.y - 110.9).abs() < 0.1);
+
+        // The stick wins over the direction flags.
+        let mixed = InputState {
+            left: true,
+            move_x: i8::MIN,
+            ..Default::default()
+        };
+        let (vx, vy) = movement_velocity_fixed(mixed, Fixed::from_int(220));
+        assert_eq!((vx, vy), (Fixed::from_int(-220), Fixed::ZERO));
+    }
+
     #[test]
     fn movement_step_clamps_at_bounds() {
         let result = movement_step(
@@ -230,6 +613,7 @@ mod tests {
                 down: false,
                 left: false,
                 right: true,
+                ..Default::default()
             },
             1.0,
             220.0,
@@ -246,12 +630,138 @@ mod tests {
         assert_eq!(y, -5500.0);
     }
 
+    #[test]
+    fn projectile_sweep_hits_structure_between_steps() {
+        let obstacle = StructureObstacle {
+            x: 100.0,
+            y: 0.0,
+            half_width: STRUCTURE_COLLIDER_HALF_EXTENT,
+            half_height: STRUCTURE_COLLIDER_HALF_EXTENT,
+        };
+
+        // One step covers 0 -> 300, so a point sample would tunnel through.
+        let result = projectile_step_with_hits(
+            0.0,
+            0.0,
+            900.0,
+            0.0,
+            1.0 / 3.0,
+            5500.0,
+            &[obstacle],
+            &[],
+            PROJECTILE_COLLIDER_RADIUS,
+        );
+
+        assert_eq!(result.hit, Some(ProjectileHit::Structure(0)));
+        let expected_x = 100.0 - STRUCTURE_COLLIDER_HALF_EXTENT - PROJECTILE_COLLIDER_RADIUS;
+        assert!((result.x - expected_x).abs() < 0.01);
+    }
+
+    #[test]
+    fn projectile_sweep_reports_nearest_target() {
+        let players = [
+            PlayerCollider {
+                x: 200.0,
+                y: 0.0,
+                radius: PLAYER_COLLIDER_RADIUS,
+            },
+            PlayerCollider {
+                x: 80.0,
+                y: 5.0,
+                radius: PLAYER_COLLIDER_RADIUS,
+            },
+        ];
+        let far_obstacle = StructureObstacle {
+            x: 150.0,
+            y: 0.0,
+            half_width: STRUCTURE_COLLIDER_HALF_EXTENT,
+            half_height: STRUCTURE_COLLIDER_HALF_EXTENT,
+        };
+
+        let result = projectile_step_with_hits(
+            0.0,
+            0.0,
+            760.0,
+            0.0,
+            0.5,
+            5500.0,
+            &[far_obstacle],
+            &players,
+            PROJECTILE_COLLIDER_RADIUS,
+        );
+
+        assert_eq!(result.hit, Some(ProjectileHit::Player(1)));
+        assert!(result.x < 80.0);
+    }
+
+    #[test]
+    fn projectile_sweep_misses_offset_targets() {
+        let player = PlayerCollider {
+            x: 100.0,
+            y: 40.0,
+            radius: PLAYER_COLLIDER_RADIUS,
+        };
+
+        let result = projectile_step_with_hits(
+            0.0,
+            0.0,
+            760.0,
+            0.0,
+            0.5,
+            5500.0,
+            &[],
+            &[player],
+            PROJECTILE_COLLIDER_RADIUS,
+        );
+
+        assert_eq!(result.hit, None);
+        assert_eq!(result.x, 380.0);
+    }
+
+    #[test]
+    fn damage_kills_once_and_ignores_corpses() {
+        let mut health = Health::full(PLAYER_MAX_HP);
+        for _ in 0..4 {
+            assert_eq!(
+                health.apply_damage(PROJECTILE_DAMAGE),
+                DamageOutcome::Damaged
+            );
+        }
+        assert_eq!(
+            health.apply_damage(PROJECTILE_DAMAGE),
+            DamageOutcome::Killed
+        );
+        assert_eq!(health.current, 0);
+        assert_eq!(
+            health.apply_damage(PROJECTILE_DAMAGE),
+            DamageOutcome::Ignored
+        );
+    }
+
+    #[test]
+    fn invulnerability_window_blocks_damage() {
+        let health = Health::full(PLAYER_MAX_HP);
+        assert!(!player_can_take_damage(health, 5000, 4999));
+        assert!(player_can_take_damage(health, 5000, 5000));
+    }
+
+    #[test]
+    fn respawn_position_is_deterministic_and_on_ring() {
+        let a = respawn_position("user_abc", 3);
+        let b = respawn_position("user_abc", 3);
+        assert_eq!(a, b);
+
+        let radius = (a.0 * a.0 + a.1 * a.1).sqrt();
+        assert!((radius - RESPAWN_RING_RADIUS).abs() < 0.01);
+    }
+
     #[test]
     fn movement_step_respects_obstacles() {
         let obstacle = StructureObstacle {
             x: 0.0,
             y: 0.0,
-            half_extent: STRUCTURE_COLLIDER_HALF_EXTENT,
+            half_width: STRUCTURE_COLLIDER_HALF_EXTENT,
+            half_height: STRUCTURE_COLLIDER_HALF_EXTENT,
         };
 
         let result = movement_step_with_obstacles(
@@ -262,6 +772,7 @@ mod tests {
                 down: false,
                 left: false,
                 right: true,
+                ..Default::default()
             },
             0.25,
             220.0,
@@ -274,6 +785,52 @@ mod tests {
         assert_eq!(result.vx, 0.0);
     }
 
+    #[test]
+    fn rectangular_obstacles_block_along_their_long_side() {
+        // A 3x1 footprint rotated north: tall and narrow.
+        let obstacle = Footprint {
+            width: 3,
+            height: 1,
+        }
+        .rotated(Direction::North)
+        .obstacle(0.0, 0.0);
+        let right = InputState {
+            up: false,
+            down: false,
+            left: false,
+            right: true,
+            ..Default::default()
+        };
+
+        let blocked = movement_step_with_obstacles(
+            -30.0,
+            40.0,
+            right,
+            0.05,
+            220.0,
+            5000.0,
+            &[obstacle],
+            PLAYER_COLLIDER_RADIUS,
+        );
+        assert_eq!(blocked.x, -30.0);
+
+        let above = movement_step_with_obstacles(
+            -30.0,
+            70.0,
+            right,
+            0.05,
+            220.0,
+            5000.0,
+            &[obstacle],
+            PLAYER_COLLIDER_RADIUS,
+        );
+        assert!(above.x > -30.0);
+
+        let hit =
+            projectile_step_with_hits(-60.0, 40.0, 600.0, 0.0, 0.25, 5000.0, &[obstacle], &[], 4.0);
+        assert_eq!(hit.hit, Some(ProjectileHit::Structure(0)));
+    }
+
     #[test]
     fn deterministic_movement_sequence_matches() {
         let inputs = [
@@ -282,24 +839,28 @@ mod tests {
                 down: false,
                 left: false,
                 right: true,
+                ..Default::default()
             },
             InputState {
                 up: true,
                 down: false,
                 left: false,
                 right: false,
+                ..Default::default()
             },
             InputState {
                 up: false,
                 down: false,
                 left: true,
                 right: false,
+                ..Default::default()
             },
             InputState {
                 up: false,
                 down: true,
                 left: false,
                 right: false,
+                ..Default::default()
             },
         ];
 
@@ -0,0 +1,340 @@
+//! Tick-driven production machines.
+
+use crate::{ItemKind, ItemStack, Recipe, TerrainTile};
+
+pub const MINER_CYCLE_SECONDS: f32 = 2.0;
+pub const MINER_OUTPUT_CAPACITY: u32 = 50;
+/// Input slots hold ingredients for this many crafts.
+pub const ASSEMBLER_INPUT_BUFFER_CRAFTS: u32 = 2;
+/// Output slots hold this many crafts' worth of products before crafting stalls.
+pub const ASSEMBLER_OUTPUT_BUFFER_CRAFTS: u32 = 10;
+
+/// A miner extracts the ore under it into an output buffer and stalls once
+/// that buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MinerState {
+    /// `None` when the miner does not sit on ore; it then never produces.
+    pub output_item: Option<ItemKind>,
+    pub output_count: u32,
+    /// Fraction of the current cycle in `[0, 1)`.
+    pub progress: f32,
+}
+
+impl MinerState {
+    pub fn on_tile(tile: TerrainTile) -> Self {
+        Self {
+            output_item: tile.ore().map(ItemKind::from_ore),
+            output_count: 0,
+            progress: 0.0,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.output_count >= MINER_OUTPUT_CAPACITY
+    }
+
+    pub fn is_working(&self) -> bool {
+        self.output_item.is_some() && !self.is_full()
+    }
+
+    /// Advances mining by `dt_seconds` and returns how many items were produced.
+    pub fn step(&mut self, dt_seconds: f32) -> u32 {
+        if !self.is_working() || dt_seconds <= 0.0 {
+            return 0;
+        }
+
+        self.progress += dt_seconds / MINER_CYCLE_SECONDS;
+        let mut produced = 0;
+        while self.progress >= 1.0 && !self.is_full() {
+            self.progress -= 1.0;
+            self.output_count += 1;
+            produced += 1;
+        }
+        if self.is_full() {
+            self.progress = 0.0;
+        }
+        produced
+    }
+
+    /// Removes up to `max` items from the output buffer.
+    pub fn take_output(&mut self, max: u32) -> Option<(ItemKind, u32)> {
+        let item = self.output_item?;
+        let taken = self.output_count.min(max);
+        if taken == 0 {
+            return None;
+        }
+        self.output_count -= taken;
+        Some((item, taken))
+    }
+}
+
+/// An assembler crafts its selected recipe whenever every input slot holds a
+/// full set of ingredients and the outputs have room. Ingredients are consumed
+/// when a craft starts.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssemblerState {
+    pub recipe: Option<&'static Recipe>,
+    /// One slot per recipe input, in recipe order.
+    pub inputs: Vec<ItemStack>,
+    /// One slot per recipe output, in recipe order.
+    pub outputs: Vec<ItemStack>,
+    /// Fraction of the current craft in `[0, 1)`.
+    pub progress: f32,
+    pub crafting: bool,
+}
+
+fn empty_slots(items: &[(ItemKind, u32)]) -> Vec<ItemStack> {
+    items
+        .iter()
+        .map(|&(item, _)| ItemStack { item, count: 0 })
+        .collect()
+}
+
+impl AssemblerState {
+    pub fn with_recipe(recipe: Option<&'static Recipe>) -> Self {
+        Self {
+            recipe,
+            inputs: recipe.map_or_else(Vec::new, |recipe| empty_slots(recipe.inputs)),
+            outputs: recipe.map_or_else(Vec::new, |recipe| empty_slots(recipe.outputs)),
+            progress: 0.0,
+            crafting: false,
+        }
+    }
+
+    /// Switches recipe and returns everything the assembler was holding,
+    /// including the ingredients of an unfinished craft.
+    pub fn set_recipe(&mut self, recipe: Option<&'static Recipe>) -> Vec<ItemStack> {
+        let mut returned: Vec<ItemStack> = self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .copied()
+            .filter(|slot| slot.count > 0)
+            .collect();
+        if let (true, Some(current)) = (self.crafting, self.recipe) {
+            returned.extend(
+                current
+                    .inputs
+                    .iter()
+                    .map(|&(item, count)| ItemStack { item, count }),
+            );
+        }
+
+        *self = Self::with_recipe(recipe);
+        returned
+    }
+
+    pub fn input_capacity(&self, item: ItemKind) -> u32 {
+        self.recipe
+            .and_then(|recipe| recipe.inputs.iter().find(|(input, _)| *input == item))
+            .map_or(0, |(_, amount)| amount * ASSEMBLER_INPUT_BUFFER_CRAFTS)
+    }
+
+    pub fn output_capacity(&self, item: ItemKind) -> u32 {
+        self.recipe
+            .and_then(|recipe| recipe.outputs.iter().find(|(output, _)| *output == item))
+            .map_or(0, |(_, amount)| amount * ASSEMBLER_OUTPUT_BUFFER_CRAFTS)
+    }
+
+    /// How many more of `item` the input slots can take.
+    pub fn input_room(&self, item: ItemKind) -> u32 {
+        let stored = self
+            .inputs
+            .iter()
+            .find(|slot| slot.item == item)
+            .map_or(0, |slot| slot.count);
+        self.input_capacity(item).saturating_sub(stored)
+    }
+
+    /// Adds up to `count` ingredients and returns how many were accepted.
+    pub fn insert_input(&mut self, item: ItemKind, count: u32) -> u32 {
+        let capacity = self.input_capacity(item);
+        let Some(slot) = self.inputs.iter_mut().find(|slot| slot.item == item) else {
+            return 0;
+        };
+        let accepted = count.min(capacity.saturating_sub(slot.count));
+        slot.count += accepted;
+        accepted
+    }
+
+    /// Removes up to `max` items from the first non-empty output slot.
+    pub fn take_output(&mut self, max: u32) -> Option<(ItemKind, u32)> {
+        let slot = self.outputs.iter_mut().find(|slot| slot.count > 0)?;
+        let taken = slot.count.min(max);
+        if taken == 0 {
+            return None;
+        }
+        slot.count -= taken;
+        Some((slot.item, taken))
+    }
+
+    /// Removes one `item` from the output slots.
+    pub fn take_item(&mut self, item: ItemKind) -> bool {
+        match self
+            .outputs
+            .iter_mut()
+            .find(|slot| slot.item == item && slot.count > 0)
+        {
+            Some(slot) => {
+                slot.count -= 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_working(&self) -> bool {
+        self.crafting
+    }
+
+    fn can_start(&self, recipe: &Recipe) -> bool {
+        let inputs_ready = recipe
+            .inputs
+            .iter()
+            .zip(self.inputs.iter())
+            .all(|(&(_, amount), slot)| slot.count >= amount);
+        let outputs_free = recipe
+            .outputs
+            .iter()
+            .zip(self.outputs.iter())
+            .all(|(&(_, amount), slot)| slot.count + amount <= self.output_capacity(slot.item));
+        inputs_ready && outputs_free
+    }
+
+    /// Advances crafting by `dt_seconds` and returns how many crafts finished.
+    pub fn step(&mut self, dt_seconds: f32) -> u32 {
+        let Some(recipe) = self.recipe else {
+            return 0;
+        };
+        if dt_seconds <= 0.0 {
+            return 0;
+        }
+
+        let mut remaining = dt_seconds / recipe.craft_seconds;
+        let mut completed = 0;
+        loop {
+            if !self.crafting {
+                if !self.can_start(recipe) {
+                    self.progress = 0.0;
+                    return completed;
+                }
+                for (&(_, amount), slot) in recipe.inputs.iter().zip(self.inputs.iter_mut()) {
+                    slot.count -= amount;
+                }
+                self.crafting = true;
+            }
+
+            let needed = 1.0 - self.progress;
+            if remaining < needed {
+                self.progress += remaining;
+                return completed;
+            }
+
+            remaining -= needed;
+            self.progress = 0.0;
+            self.crafting = false;
+            for (&(_, amount), slot) in recipe.outputs.iter().zip(self.outputs.iter_mut()) {
+                slot.count += amount;
+            }
+            completed += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{recipe_by_id, OreKind};
+
+    #[test]
+    fn miner_produces_at_cycle_rate() {
+        let mut miner = MinerState::on_tile(TerrainTile::Ore(OreKind::Copper));
+        let dt = 1.0 / 30.0;
+        let produced: u32 = (0..(30.0 * MINER_CYCLE_SECONDS * 3.0) as usize + 1)
+            .map(|_| miner.step(dt))
+            .sum();
+
+        assert_eq!(produced, 3);
+        assert_eq!(miner.output_item, Some(ItemKind::CopperOre));
+        assert_eq!(miner.take_output(2), Some((ItemKind::CopperOre, 2)));
+        assert_eq!(miner.output_count, 1);
+    }
+
+    #[test]
+    fn miner_stalls_when_full_or_off_ore() {
+        let mut miner = MinerState::on_tile(TerrainTile::Ore(OreKind::Iron));
+        miner.step(MINER_CYCLE_SECONDS * (MINER_OUTPUT_CAPACITY + 10) as f32);
+        assert_eq!(miner.output_count, MINER_OUTPUT_CAPACITY);
+        assert!(!miner.is_working());
+        assert_eq!(miner.step(MINER_CYCLE_SECONDS), 0);
+
+        miner.take_output(1);
+        assert!(miner.is_working());
+
+        let mut idle = MinerState::on_tile(TerrainTile::Ground);
+        assert_eq!(idle.step(MINER_CYCLE_SECONDS * 4.0), 0);
+        assert_eq!(idle.take_output(1), None);
+    }
+
+    #[test]
+    fn assembler_crafts_when_inputs_are_ready() {
+        let recipe = recipe_by_id("circuit").unwrap();
+        let mut assembler = AssemblerState::with_recipe(Some(recipe));
+
+        assert_eq!(assembler.insert_input(ItemKind::IronPlate, 5), 2);
+        assert_eq!(assembler.insert_input(ItemKind::CopperCable, 2), 2);
+        assert_eq!(assembler.insert_input(ItemKind::Coal, 1), 0);
+        assert_eq!(assembler.step(recipe.craft_seconds * 2.0), 0);
+        assert!(!assembler.is_working());
+
+        assembler.insert_input(ItemKind::CopperCable, 4);
+        assert_eq!(assembler.step(recipe.craft_seconds * 0.5), 0);
+        assert!(assembler.is_working());
+        assert_eq!(assembler.inputs[0].count, 1);
+        assert_eq!(assembler.inputs[1].count, 3);
+
+        assert_eq!(assembler.step(recipe.craft_seconds * 1.5), 2);
+        assert_eq!(assembler.take_output(5), Some((ItemKind::Circuit, 2)));
+        assert_eq!(assembler.inputs[0].count, 0);
+    }
+
+    #[test]
+    fn assembler_stalls_on_full_output_and_refunds_on_recipe_change() {
+        let recipe = recipe_by_id("copper_cable").unwrap();
+        let mut assembler = AssemblerState::with_recipe(Some(recipe));
+        for _ in 0..ASSEMBLER_OUTPUT_BUFFER_CRAFTS + 1 {
+            assembler.insert_input(ItemKind::CopperPlate, 1);
+            assembler.step(recipe.craft_seconds);
+        }
+        assert_eq!(
+            assembler.outputs[0].count,
+            2 * ASSEMBLER_OUTPUT_BUFFER_CRAFTS
+        );
+        assert_eq!(assembler.inputs[0].count, 1);
+
+        assembler.take_output(2);
+        assembler.step(recipe.craft_seconds * 0.5);
+        assert!(assembler.is_working());
+
+        let returned = assembler.set_recipe(recipe_by_id("iron_gear"));
+        let total = |item| -> u32 {
+            returned
+                .iter()
+                .filter(|slot| slot.item == item)
+                .map(|slot| slot.count)
+                .sum()
+        };
+        assert_eq!(
+            total(ItemKind::CopperCable),
+            2 * ASSEMBLER_OUTPUT_BUFFER_CRAFTS - 2
+        );
+        assert_eq!(total(ItemKind::CopperPlate), 1);
+        assert_eq!(
+            assembler.inputs,
+            vec![ItemStack {
+                item: ItemKind::IronPlate,
+                count: 0
+            }]
+        );
+    }
+}
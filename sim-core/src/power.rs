@@ -0,0 +1,363 @@
+//! Electric networks. Poles (the `beacon` structure) wire up to every other
+//! pole within reach, and each connected group of poles is one network.
+//! Generators and consumers attach to a pole whose supply area covers part of
+//! their footprint; consumers then run at the share of their demand that the
+//! network's generators can meet.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::Hash;
+
+use serde::Deserialize;
+
+use crate::{structure_prototype, Footprint, StructurePower};
+
+/// Two poles connect when their cells are at most this far apart.
+pub const POLE_WIRE_REACH_CELLS: i32 = 7;
+/// Cells a pole powers in each direction around its own.
+pub const POLE_SUPPLY_RADIUS_CELLS: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerRole {
+    Pole,
+    Generator,
+    Consumer,
+}
+
+/// How a structure kind takes part in a power network, if at all.
+pub fn power_role(kind: &str) -> Option<PowerRole> {
+    structure_power(kind).map(|power| power.role)
+}
+
+pub fn power_output_kw(kind: &str) -> u32 {
+    structure_power(kind).map_or(0, |power| power.output_kw)
+}
+
+pub fn power_demand_kw(kind: &str) -> u32 {
+    structure_power(kind).map_or(0, |power| power.demand_kw)
+}
+
+fn structure_power(kind: &str) -> Option<StructurePower> {
+    structure_prototype(kind)?.power
+}
+
+/// Supply and demand of one network.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkBalance {
+    pub supply_kw: u32,
+    pub demand_kw: u32,
+}
+
+impl NetworkBalance {
+    /// Share of demand that is met, in `[0, 1]`. A network without demand is
+    /// fully satisfied.
+    pub fn satisfaction(&self) -> f32 {
+        if self.demand_kw == 0 {
+            return 1.0;
+        }
+        (self.supply_kw as f32 / self.demand_kw as f32).min(1.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PowerMember {
+    role: PowerRole,
+    output_kw: u32,
+    demand_kw: u32,
+    /// Lowest and highest covered cell.
+    min: (i32, i32),
+    max: (i32, i32),
+    /// The pole a generator or consumer draws through; a pole's own cell.
+    pole: Option<(i32, i32)>,
+}
+
+impl PowerMember {
+    fn covered_by(&self, pole: (i32, i32)) -> bool {
+        pole.0 >= self.min.0 - POLE_SUPPLY_RADIUS_CELLS
+            && pole.0 <= self.max.0 + POLE_SUPPLY_RADIUS_CELLS
+            && pole.1 >= self.min.1 - POLE_SUPPLY_RADIUS_CELLS
+            && pole.1 <= self.max.1 + POLE_SUPPLY_RADIUS_CELLS
+    }
+}
+
+fn poles_connect(a: (i32, i32), b: (i32, i32)) -> bool {
+    let (dx, dy) = ((a.0 - b.0) as i64, (a.1 - b.1) as i64);
+    dx * dx + dy * dy <= (POLE_WIRE_REACH_CELLS as i64).pow(2)
+}
+
+/// Power networks of a room, updated as structures are placed and removed
+/// instead of being rebuilt: a new pole joins or merges the networks in
+/// reach, and a removed pole only re-walks its own network to find a split.
+#[derive(Debug, Clone)]
+pub struct PowerGrid<K> {
+    /// Network id by pole cell.
+    poles: HashMap<(i32, i32), u32>,
+    members: HashMap<K, PowerMember>,
+    next_network_id: u32,
+}
+
+impl<K> Default for PowerGrid<K> {
+    fn default() -> Self {
+        Self {
+            poles: HashMap::new(),
+            members: HashMap::new(),
+            next_network_id: 1,
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone> PowerGrid<K> {
+    /// Adds a structure anchored on `anchor`. Kinds without a [`PowerRole`]
+    /// are ignored.
+    pub fn insert(&mut self, key: K, kind: &str, anchor: (i32, i32), footprint: Footprint) {
+        let Some(role) = power_role(kind) else {
+            return;
+        };
+        self.remove(&key);
+
+        let min = footprint.origin(anchor);
+        let mut member = PowerMember {
+            role,
+            output_kw: power_output_kw(kind),
+            demand_kw: power_demand_kw(kind),
+            min,
+            max: (min.0 + footprint.width - 1, min.1 + footprint.height - 1),
+            pole: None,
+        };
+        if role == PowerRole::Pole {
+            member.pole = Some(anchor);
+            self.members.insert(key, member);
+            self.connect_pole(anchor);
+        } else {
+            member.pole = self.covering_pole(&member);
+            self.members.insert(key, member);
+        }
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        let Some(member) = self.members.remove(key) else {
+            return;
+        };
+        if let (PowerRole::Pole, Some(cell)) = (member.role, member.pole) {
+            self.disconnect_pole(cell);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.poles.clear();
+        self.members.clear();
+    }
+
+    /// Network a structure belongs to; `None` when it is not reached by any pole.
+    pub fn network_of(&self, key: &K) -> Option<u32> {
+        self.poles.get(&self.members.get(key)?.pole?).copied()
+    }
+
+    /// Supply and demand of every network, including ones with neither.
+    pub fn balances(&self) -> BTreeMap<u32, NetworkBalance> {
+        let mut balances: BTreeMap<u32, NetworkBalance> = self
+            .poles
+            .values()
+            .map(|network| (*network, NetworkBalance::default()))
+            .collect();
+        for member in self.members.values() {
+            let Some(network) = member.pole.and_then(|pole| self.poles.get(&pole)) else {
+                continue;
+            };
+            let balance = balances.entry(*network).or_default();
+            balance.supply_kw += member.output_kw;
+            balance.demand_kw += member.demand_kw;
+        }
+        balances
+    }
+
+    /// How fast a structure works given `balances`: its network's satisfaction,
+    /// `0` without a network, and `1` for kinds that need no power.
+    pub fn satisfaction_of(&self, key: &K, balances: &BTreeMap<u32, NetworkBalance>) -> f32 {
+        match self.members.get(key) {
+            Some(member) if member.demand_kw > 0 => self
+                .network_of(key)
+                .and_then(|network| balances.get(&network))
+                .map_or(0.0, NetworkBalance::satisfaction),
+            _ => 1.0,
+        }
+    }
+
+    fn covering_pole(&self, member: &PowerMember) -> Option<(i32, i32)> {
+        let reach = POLE_SUPPLY_RADIUS_CELLS;
+        (member.min.1 - reach..=member.max.1 + reach)
+            .flat_map(|y| (member.min.0 - reach..=member.max.0 + reach).map(move |x| (x, y)))
+            .find(|cell| self.poles.contains_key(cell))
+    }
+
+    fn poles_in_reach(&self, cell: (i32, i32)) -> impl Iterator<Item = (i32, i32)> + '_ {
+        let reach = POLE_WIRE_REACH_CELLS;
+        (cell.1 - reach..=cell.1 + reach)
+            .flat_map(move |y| (cell.0 - reach..=cell.0 + reach).map(move |x| (x, y)))
+            .filter(move |other| *other != cell && poles_connect(cell, *other))
+            .filter(|other| self.poles.contains_key(other))
+    }
+
+    /// Joins a new pole to the networks in reach, merging them into the
+    /// lowest id, and hands it the members nothing else powers.
+    fn connect_pole(&mut self, cell: (i32, i32)) {
+        let joined: BTreeSet<u32> = self
+            .poles_in_reach(cell)
+            .filter_map(|other| self.poles.get(&other).copied())
+            .collect();
+        let network = match joined.first() {
+            Some(network) => *network,
+            None => {
+                self.next_network_id += 1;
+                self.next_network_id - 1
+            }
+        };
+        if joined.len() > 1 {
+            for id in self.poles.values_mut() {
+                if joined.contains(id) {
+                    *id = network;
+                }
+            }
+        }
+        self.poles.insert(cell, network);
+
+        for member in self.members.values_mut() {
+            if member.pole.is_none() && member.covered_by(cell) {
+                member.pole = Some(cell);
+            }
+        }
+    }
+
+    /// Moves the members a removed pole powered to another pole, then splits
+    /// its network if the pole was the only link between two parts. The part
+    /// found first keeps the id.
+    fn disconnect_pole(&mut self, cell: (i32, i32)) {
+        let Some(network) = self.poles.remove(&cell) else {
+            return;
+        };
+
+        let orphans: Vec<K> = self
+            .members
+            .iter()
+            .filter(|(_, member)| member.role != PowerRole::Pole && member.pole == Some(cell))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in orphans {
+            let pole = self
+                .members
+                .get(&key)
+                .and_then(|member| self.covering_pole(member));
+            if let Some(member) = self.members.get_mut(&key) {
+                member.pole = pole;
+            }
+        }
+
+        let mut remaining: BTreeSet<(i32, i32)> = self
+            .poles
+            .iter()
+            .filter(|(_, id)| **id == network)
+            .map(|(cell, _)| *cell)
+            .collect();
+        let mut first = true;
+        while let Some(start) = remaining.pop_first() {
+            let id = if first {
+                first = false;
+                network
+            } else {
+                self.next_network_id += 1;
+                self.next_network_id - 1
+            };
+            let mut stack = vec![start];
+            while let Some(pole) = stack.pop() {
+                self.poles.insert(pole, id);
+                let linked: Vec<(i32, i32)> = self
+                    .poles_in_reach(pole)
+                    .filter(|other| remaining.contains(other))
+                    .collect();
+                for other in linked {
+                    remaining.remove(&other);
+                    stack.push(other);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn place(grid: &mut PowerGrid<&'static str>, key: &'static str, kind: &str, cell: (i32, i32)) {
+        grid.insert(
+            key,
+            kind,
+            cell,
+            Footprint::for_structure(kind, crate::Direction::East),
+        );
+    }
+
+    #[test]
+    fn poles_merge_and_split_networks() {
+        let mut grid = PowerGrid::default();
+        place(&mut grid, "a", "beacon", (0, 0));
+        place(&mut grid, "c", "beacon", (14, 0));
+        assert_ne!(grid.network_of(&"a"), grid.network_of(&"c"));
+
+        place(&mut grid, "b", "beacon", (7, 0));
+        let merged = grid.network_of(&"a");
+        assert!(merged.is_some());
+        assert_eq!(grid.network_of(&"b"), merged);
+        assert_eq!(grid.network_of(&"c"), merged);
+        assert_eq!(grid.balances().len(), 1);
+
+        grid.remove(&"b");
+        assert_eq!(grid.network_of(&"a"), merged);
+        assert!(grid.network_of(&"c").is_some());
+        assert_ne!(grid.network_of(&"c"), merged);
+        assert_eq!(grid.balances().len(), 2);
+    }
+
+    #[test]
+    fn consumers_slow_down_when_demand_exceeds_supply() {
+        let mut grid = PowerGrid::default();
+        place(&mut grid, "miner", "miner", (2, -1));
+        assert_eq!(grid.network_of(&"miner"), None);
+        let balances = grid.balances();
+        assert_eq!(grid.satisfaction_of(&"miner", &balances), 0.0);
+        assert_eq!(grid.satisfaction_of(&"nothing", &balances), 1.0);
+
+        // The miner covers (2, -1)..=(3, 0), inside the pole's supply area.
+        place(&mut grid, "pole", "beacon", (0, 0));
+        place(&mut grid, "generator", "generator", (-2, 0));
+        let network = grid.network_of(&"pole");
+        assert!(network.is_some());
+        assert_eq!(grid.network_of(&"miner"), network);
+        assert_eq!(grid.network_of(&"generator"), network);
+        assert_eq!(grid.satisfaction_of(&"miner", &grid.balances()), 1.0);
+
+        let assemblers = [(-3, -3), (0, -3), (3, -3), (-3, 3), (0, 3), (3, 3)];
+        for (key, cell) in ["a1", "a2", "a3", "a4", "a5", "a6"]
+            .into_iter()
+            .zip(assemblers)
+        {
+            place(&mut grid, key, "assembler", cell);
+        }
+        let balance = grid.balances()[&network.unwrap()];
+        assert_eq!(balance.supply_kw, power_output_kw("generator"));
+        assert_eq!(
+            balance.demand_kw,
+            power_demand_kw("miner") + 6 * power_demand_kw("assembler")
+        );
+        let satisfaction = grid.satisfaction_of(&"a1", &grid.balances());
+        assert!((satisfaction - 900.0 / 990.0).abs() < 1e-6);
+        assert_eq!(grid.satisfaction_of(&"generator", &grid.balances()), 1.0);
+
+        // Another pole still reaches the miner once the first one is gone,
+        // but not the generator.
+        place(&mut grid, "pole2", "beacon", (5, 1));
+        grid.remove(&"pole");
+        assert_eq!(grid.network_of(&"miner"), grid.network_of(&"pole2"));
+        assert_eq!(grid.network_of(&"generator"), None);
+        assert_eq!(grid.satisfaction_of(&"miner", &grid.balances()), 0.0);
+    }
+}
@@ -0,0 +1,241 @@
+//! Structure prototypes. Every buildable kind is described once in
+//! `data/structures.json`, which is compiled into the crate: footprint, hit
+//! points, collider, build cost, how it is drawn and what it does. The
+//! server's validation and the client's rendering both read it from here.
+
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::{Footprint, ItemKind, ItemStack, PowerRole};
+
+const STRUCTURES_JSON: &str = include_str!("../data/structures.json");
+
+/// Simulation a structure runs besides existing, keyed off by the worker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StructureBehavior {
+    Miner,
+    Assembler,
+    Belt,
+    Inserter,
+    Chest,
+    Lab,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StructureCollider {
+    /// Blocks players and projectiles across its footprint.
+    Box,
+    /// Lies flat: walked over and shot over.
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructurePower {
+    pub role: PowerRole,
+    #[serde(default)]
+    pub output_kw: u32,
+    #[serde(default)]
+    pub demand_kw: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StructureSprite {
+    /// sRGB fill color.
+    pub color: [u8; 3],
+    /// Covers the whole footprint instead of leaving a gap to neighbours.
+    #[serde(default)]
+    pub fills_footprint: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructurePrototype {
+    pub kind: String,
+    /// Facing east.
+    pub footprint: Footprint,
+    pub max_hp: i32,
+    pub collider: StructureCollider,
+    /// Placed with a direction, see [`crate::structure_is_directional`].
+    pub directional: bool,
+    /// Taken from the builder's inventory on placement, refunded on removal.
+    pub cost: Vec<ItemStack>,
+    pub sprite: StructureSprite,
+    pub behaviors: Vec<StructureBehavior>,
+    pub power: Option<StructurePower>,
+}
+
+impl StructurePrototype {
+    pub fn has_behavior(&self, behavior: StructureBehavior) -> bool {
+        self.behaviors.contains(&behavior)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct StructurePrototypeRecord {
+    kind: String,
+    footprint: [i32; 2],
+    max_hp: i32,
+    collider: StructureCollider,
+    #[serde(default)]
+    directional: bool,
+    cost: Vec<CostRecord>,
+    sprite: StructureSprite,
+    #[serde(default)]
+    behaviors: Vec<StructureBehavior>,
+    power: Option<StructurePower>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CostRecord {
+    item: String,
+    count: u32,
+}
+
+impl StructurePrototypeRecord {
+    fn into_prototype(self) -> Result<StructurePrototype, String> {
+        let kind = self.kind;
+        if ItemKind::parse(&kind).is_none() {
+            return Err(format!("{kind}: no item is named after it"));
+        }
+        let [width, height] = self.footprint;
+        if width < 1 || height < 1 {
+            return Err(format!("{kind}: footprint must cover at least one cell"));
+        }
+        if self.max_hp < 1 {
+            return Err(format!("{kind}: maxHp must be positive"));
+        }
+        if self.cost.is_empty() {
+            return Err(format!("{kind}: cost is empty"));
+        }
+        let cost = self
+            .cost
+            .into_iter()
+            .map(|entry| match ItemKind::parse(&entry.item) {
+                Some(item) if entry.count > 0 => Ok(ItemStack {
+                    item,
+                    count: entry.count,
+                }),
+                Some(_) => Err(format!("{kind}: cost of {} must be positive", entry.item)),
+                None => Err(format!("{kind}: unknown cost item {}", entry.item)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if let Some(power) = self.power {
+            let valid = match power.role {
+                PowerRole::Pole => power.output_kw == 0 && power.demand_kw == 0,
+                PowerRole::Generator => power.output_kw > 0 && power.demand_kw == 0,
+                PowerRole::Consumer => power.output_kw == 0 && power.demand_kw > 0,
+            };
+            if !valid {
+                return Err(format!("{kind}: power kW does not match its role"));
+            }
+        }
+        Ok(StructurePrototype {
+            kind,
+            footprint: Footprint { width, height },
+            max_hp: self.max_hp,
+            collider: self.collider,
+            directional: self.directional,
+            cost,
+            sprite: self.sprite,
+            behaviors: self.behaviors,
+            power: self.power,
+        })
+    }
+}
+
+fn parse_prototypes(json: &str) -> Result<Vec<StructurePrototype>, String> {
+    let records: Vec<StructurePrototypeRecord> =
+        serde_json::from_str(json).map_err(|error| error.to_string())?;
+    let mut kinds = BTreeSet::new();
+    records
+        .into_iter()
+        .map(|record| {
+            let prototype = record.into_prototype()?;
+            if !kinds.insert(prototype.kind.clone()) {
+                return Err(format!("{}: defined twice", prototype.kind));
+            }
+            Ok(prototype)
+        })
+        .collect()
+}
+
+/// Every buildable structure, in the order the build menu lists them.
+pub fn structure_prototypes() -> &'static [StructurePrototype] {
+    static PROTOTYPES: OnceLock<Vec<StructurePrototype>> = OnceLock::new();
+    PROTOTYPES.get_or_init(|| {
+        parse_prototypes(STRUCTURES_JSON)
+            .unwrap_or_else(|error| panic!("invalid data/structures.json: {error}"))
+    })
+}
+
+pub fn structure_prototype(kind: &str) -> Option<&'static StructurePrototype> {
+    structure_prototypes()
+        .iter()
+        .find(|prototype| prototype.kind == kind)
+}
+
+pub fn structure_has_behavior(kind: &str, behavior: StructureBehavior) -> bool {
+    structure_prototype(kind).is_some_and(|prototype| prototype.has_behavior(behavior))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_prototypes_load_and_bad_ones_are_rejected() {
+        let prototypes = structure_prototypes();
+        assert_eq!(prototypes[0].kind, "beacon");
+        let assembler = structure_prototype("assembler").unwrap();
+        assert_eq!(
+            assembler.footprint,
+            Footprint {
+                width: 3,
+                height: 3
+            }
+        );
+        assert!(assembler.has_behavior(StructureBehavior::Assembler));
+        assert_eq!(
+            assembler.cost,
+            vec![ItemStack {
+                item: ItemKind::Assembler,
+                count: 1
+            }]
+        );
+        assert!(structure_has_behavior("belt", StructureBehavior::Belt));
+        assert!(!structure_has_behavior(
+            "warp_gate",
+            StructureBehavior::Belt
+        ));
+
+        let valid = r#"{"kind": "chest", "footprint": [1, 1], "maxHp": 10, "collider": "box",
+            "cost": [{"item": "chest", "count": 1}], "sprite": {"color": [0, 0, 0]}}"#;
+        assert_eq!(parse_prototypes(&format!("[{valid}]")).unwrap().len(), 1);
+        assert!(parse_prototypes(&format!("[{valid}, {valid}]"))
+            .unwrap_err()
+            .contains("defined twice"));
+        for (from, to) in [
+            (r#""kind": "chest""#, r#""kind": "warp_gate""#),
+            ("[1, 1]", "[0, 1]"),
+            (r#""count": 1"#, r#""count": 0"#),
+            (r#""item": "chest""#, r#""item": "unobtainium""#),
+            (
+                r#""collider": "box""#,
+                r#""collider": "box", "power": {"role": "generator"}"#,
+            ),
+        ] {
+            let invalid = valid.replace(from, to);
+            assert!(
+                parse_prototypes(&format!("[{invalid}]")).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+}
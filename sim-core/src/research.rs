@@ -0,0 +1,308 @@
+//! Room-wide research. Labs turn science packs into progress on the selected
+//! technology; finished technologies unlock structures and recipes for every
+//! player in the room.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ItemKind;
+
+pub const LAB_PACK_CAPACITY: u32 = 10;
+pub const LAB_SECONDS_PER_PACK: f32 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Technology {
+    pub id: &'static str,
+    pub name: &'static str,
+    /// Science packs the room's labs have to work through.
+    pub cost: u32,
+    pub prerequisites: &'static [&'static str],
+    pub structures: &'static [&'static str],
+    pub recipes: &'static [&'static str],
+}
+
+/// Available without any research.
+pub const STARTING_STRUCTURES: &[&str] = &[
+    "beacon",
+    "miner",
+    "assembler",
+    "belt",
+    "inserter",
+    "generator",
+    "lab",
+];
+pub const STARTING_RECIPES: &[&str] = &[
+    "iron_plate",
+    "copper_plate",
+    "stone_brick",
+    "iron_gear",
+    "copper_cable",
+    "science_pack",
+];
+
+pub const TECHNOLOGIES: &[Technology] = &[
+    Technology {
+        id: "electronics",
+        name: "Electronics",
+        cost: 10,
+        prerequisites: &[],
+        structures: &[],
+        recipes: &["circuit"],
+    },
+    Technology {
+        id: "military",
+        name: "Military",
+        cost: 10,
+        prerequisites: &[],
+        structures: &[],
+        recipes: &["ammo"],
+    },
+    Technology {
+        id: "storage",
+        name: "Storage",
+        cost: 15,
+        prerequisites: &["electronics"],
+        structures: &["chest"],
+        recipes: &[],
+    },
+];
+
+pub fn technology_by_id(id: &str) -> Option<&'static Technology> {
+    TECHNOLOGIES.iter().find(|technology| technology.id == id)
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResearchState {
+    unlocked: BTreeSet<&'static str>,
+    current: Option<&'static Technology>,
+    /// Packs spent per unfinished technology; kept when switching away.
+    progress: BTreeMap<&'static str, u32>,
+}
+
+impl ResearchState {
+    /// Rebuilds saved state. Unknown ids are dropped, as is a current
+    /// technology that is no longer available.
+    pub fn restore<'a>(
+        unlocked: impl IntoIterator<Item = &'a str>,
+        current: Option<&str>,
+        progress: impl IntoIterator<Item = (&'a str, u32)>,
+    ) -> Self {
+        let mut state = Self {
+            unlocked: unlocked
+                .into_iter()
+                .filter_map(technology_by_id)
+                .map(|technology| technology.id)
+                .collect(),
+            ..Self::default()
+        };
+        state.progress = progress
+            .into_iter()
+            .filter_map(|(id, packs)| {
+                let technology = technology_by_id(id)?;
+                (!state.is_unlocked(id) && packs > 0)
+                    .then(|| (technology.id, packs.min(technology.cost - 1)))
+            })
+            .collect();
+        if let Some(id) = current {
+            state.select(id);
+        }
+        state
+    }
+
+    pub fn current(&self) -> Option<&'static Technology> {
+        self.current
+    }
+
+    pub fn progress(&self, id: &str) -> u32 {
+        self.progress.get(id).copied().unwrap_or(0)
+    }
+
+    /// Progress of every technology that has some, by id.
+    pub fn progress_entries(&self) -> impl Iterator<Item = (&'static str, u32)> + '_ {
+        self.progress.iter().map(|(id, packs)| (*id, *packs))
+    }
+
+    pub fn unlocked(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.unlocked.iter().copied()
+    }
+
+    pub fn is_unlocked(&self, id: &str) -> bool {
+        self.unlocked.contains(id)
+    }
+
+    /// Not yet researched, with every prerequisite done.
+    pub fn is_available(&self, technology: &Technology) -> bool {
+        !self.is_unlocked(technology.id)
+            && technology
+                .prerequisites
+                .iter()
+                .all(|prerequisite| self.is_unlocked(prerequisite))
+    }
+
+    /// Makes `id` the technology labs work on. `None` if it is unknown or
+    /// not available.
+    pub fn select(&mut self, id: &str) -> Option<&'static Technology> {
+        let technology = technology_by_id(id).filter(|technology| self.is_available(technology))?;
+        self.current = Some(technology);
+        Some(technology)
+    }
+
+    /// Stops research; progress so far is kept.
+    pub fn cancel(&mut self) -> bool {
+        self.current.take().is_some()
+    }
+
+    /// Adds finished packs to the current technology. Returns it once its
+    /// cost is reached, after unlocking it; packs past the cost are lost.
+    pub fn add_progress(&mut self, packs: u32) -> Option<&'static Technology> {
+        let technology = self.current.filter(|_| packs > 0)?;
+        let progress = self.progress.entry(technology.id).or_default();
+        *progress += packs;
+        if *progress < technology.cost {
+            return None;
+        }
+        self.progress.remove(technology.id);
+        self.unlocked.insert(technology.id);
+        self.current = None;
+        Some(technology)
+    }
+
+    pub fn structure_unlocked(&self, kind: &str) -> bool {
+        STARTING_STRUCTURES.contains(&kind)
+            || self
+                .unlocked_technologies()
+                .any(|technology| technology.structures.contains(&kind))
+    }
+
+    pub fn recipe_unlocked(&self, id: &str) -> bool {
+        STARTING_RECIPES.contains(&id)
+            || self
+                .unlocked_technologies()
+                .any(|technology| technology.recipes.contains(&id))
+    }
+
+    fn unlocked_technologies(&self) -> impl Iterator<Item = &'static Technology> + '_ {
+        self.unlocked.iter().filter_map(|id| technology_by_id(id))
+    }
+}
+
+/// A lab's pack buffer and the pack it is working through.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LabState {
+    pub packs: u32,
+    /// Share of the current pack done, in `[0, 1)`.
+    pub progress: f32,
+    /// Whether a pack has been taken and is being worked on.
+    pub researching: bool,
+}
+
+impl LabState {
+    pub fn input_room(&self, item: ItemKind) -> u32 {
+        if item == ItemKind::SciencePack {
+            LAB_PACK_CAPACITY.saturating_sub(self.packs)
+        } else {
+            0
+        }
+    }
+
+    /// Adds up to `count` packs; returns how many fit.
+    pub fn insert(&mut self, item: ItemKind, count: u32) -> u32 {
+        let added = count.min(self.input_room(item));
+        self.packs += added;
+        added
+    }
+
+    /// Advances by `dt_seconds` while the room has research selected, taking
+    /// a pack when work on one starts. Returns whether a pack was finished.
+    pub fn step(&mut self, dt_seconds: f32, research_selected: bool) -> bool {
+        if !research_selected {
+            return false;
+        }
+        if !self.researching {
+            if self.packs == 0 {
+                return false;
+            }
+            self.packs -= 1;
+            self.researching = true;
+            self.progress = 0.0;
+        }
+        self.progress += dt_seconds / LAB_SECONDS_PER_PACK;
+        if self.progress < 1.0 {
+            return false;
+        }
+        self.progress = 0.0;
+        self.researching = false;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn technologies_unlock_in_prerequisite_order() {
+        let mut research = ResearchState::default();
+        assert!(research.structure_unlocked("lab"));
+        assert!(!research.structure_unlocked("chest"));
+        assert!(!research.recipe_unlocked("circuit"));
+        assert_eq!(research.select("storage"), None);
+        assert_eq!(research.select("warp_drive"), None);
+        assert_eq!(research.add_progress(5), None);
+
+        research.select("electronics").unwrap();
+        assert_eq!(research.add_progress(4), None);
+        research.select("military").unwrap();
+        assert_eq!(
+            research.add_progress(10).map(|tech| tech.id),
+            Some("military")
+        );
+        assert!(research.recipe_unlocked("ammo"));
+        assert_eq!(research.current(), None);
+        assert_eq!(research.select("military"), None);
+
+        // Switching back keeps the packs already spent.
+        research.select("electronics").unwrap();
+        assert_eq!(research.progress("electronics"), 4);
+        assert_eq!(
+            research.add_progress(20).map(|tech| tech.id),
+            Some("electronics")
+        );
+        assert!(research.recipe_unlocked("circuit"));
+        research.select("storage").unwrap();
+        assert_eq!(
+            research.add_progress(15).map(|tech| tech.id),
+            Some("storage")
+        );
+        assert!(research.structure_unlocked("chest"));
+
+        let restored = ResearchState::restore(
+            ["electronics", "antigravity"],
+            Some("storage"),
+            [("storage", 40), ("military", 3)],
+        );
+        assert_eq!(restored.unlocked().collect::<Vec<_>>(), vec!["electronics"]);
+        assert_eq!(restored.current().map(|tech| tech.id), Some("storage"));
+        assert_eq!(restored.progress("storage"), 14);
+        assert_eq!(restored.progress("military"), 3);
+    }
+
+    #[test]
+    fn labs_take_a_pack_at_a_time_while_research_is_selected() {
+        let mut lab = LabState::default();
+        assert_eq!(lab.input_room(ItemKind::IronPlate), 0);
+        assert_eq!(lab.insert(ItemKind::SciencePack, 12), LAB_PACK_CAPACITY);
+        assert!(!lab.step(1.0, false));
+        assert_eq!(lab.packs, LAB_PACK_CAPACITY);
+
+        let dt = 1.25;
+        let steps_per_pack = (LAB_SECONDS_PER_PACK / dt) as usize;
+        let finished = (0..steps_per_pack).filter(|_| lab.step(dt, true)).count();
+        assert_eq!(finished, 1);
+        assert_eq!(lab.packs, LAB_PACK_CAPACITY - 1);
+        assert!(!lab.researching);
+
+        lab.packs = 0;
+        assert!(!lab.step(dt, true));
+        assert!(!lab.researching);
+    }
+}
@@ -0,0 +1,238 @@
+//! Chunked index of structure colliders so movement and projectiles only test
+//! the obstacles around them instead of every structure in the room.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{StructureObstacle, TERRAIN_TILE_SIZE};
+
+/// Side of one index chunk in world units.
+pub const OBSTACLE_CHUNK_SIZE: f32 = TERRAIN_TILE_SIZE * 8.0;
+
+/// Obstacles keyed by structure id. Each obstacle is listed in every chunk
+/// its box overlaps, so a query only has to look at the chunks it covers.
+#[derive(Debug, Clone)]
+pub struct ObstacleIndex<K> {
+    obstacles: BTreeMap<K, StructureObstacle>,
+    chunks: BTreeMap<(i32, i32), Vec<K>>,
+}
+
+impl<K> Default for ObstacleIndex<K> {
+    fn default() -> Self {
+        Self {
+            obstacles: BTreeMap::new(),
+            chunks: BTreeMap::new(),
+        }
+    }
+}
+
+fn chunk_axis(value: f32) -> i32 {
+    (value / OBSTACLE_CHUNK_SIZE).floor() as i32
+}
+
+/// Chunks overlapping the box `[min, max]`.
+fn chunks_covering(min: (f32, f32), max: (f32, f32)) -> impl Iterator<Item = (i32, i32)> {
+    let (min_x, max_x) = (chunk_axis(min.0), chunk_axis(max.0));
+    (chunk_axis(min.1)..=chunk_axis(max.1)).flat_map(move |y| (min_x..=max_x).map(move |x| (x, y)))
+}
+
+fn obstacle_chunks(obstacle: &StructureObstacle) -> impl Iterator<Item = (i32, i32)> {
+    chunks_covering(
+        (
+            obstacle.x - obstacle.half_width,
+            obstacle.y - obstacle.half_height,
+        ),
+        (
+            obstacle.x + obstacle.half_width,
+            obstacle.y + obstacle.half_height,
+        ),
+    )
+}
+
+impl<K: Clone + Ord> ObstacleIndex<K> {
+    /// Adds or replaces the obstacle stored under `key`.
+    pub fn insert(&mut self, key: K, obstacle: StructureObstacle) {
+        self.remove(&key);
+        for chunk in obstacle_chunks(&obstacle) {
+            self.chunks.entry(chunk).or_default().push(key.clone());
+        }
+        self.obstacles.insert(key, obstacle);
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<StructureObstacle> {
+        let obstacle = self.obstacles.remove(key)?;
+        for chunk in obstacle_chunks(&obstacle) {
+            if let Some(keys) = self.chunks.get_mut(&chunk) {
+                keys.retain(|other| other != key);
+                if keys.is_empty() {
+                    self.chunks.remove(&chunk);
+                }
+            }
+        }
+        Some(obstacle)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&StructureObstacle> {
+        self.obstacles.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.obstacles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.obstacles.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.obstacles.clear();
+        self.chunks.clear();
+    }
+
+    /// Obstacles whose boxes come within `reach` of `(x, y)` on both axes,
+    /// each listed once, ordered by key.
+    pub fn near(&self, x: f32, y: f32, reach: f32) -> Vec<(&K, StructureObstacle)> {
+        let mut keys = BTreeSet::new();
+        for chunk in chunks_covering((x - reach, y - reach), (x + reach, y + reach)) {
+            if let Some(chunk_keys) = self.chunks.get(&chunk) {
+                keys.extend(chunk_keys);
+            }
+        }
+        keys.into_iter()
+            .filter_map(|key| Some((key, *self.obstacles.get(key)?)))
+            .filter(|(_, obstacle)| obstacle.blocks(x, y, reach))
+            .collect()
+    }
+
+    /// Like [`ObstacleIndex::near`] without the keys, ready for
+    /// `movement_step_with_obstacles`.
+    pub fn obstacles_near(&self, x: f32, y: f32, reach: f32) -> Vec<StructureObstacle> {
+        self.near(x, y, reach)
+            .into_iter()
+            .map(|(_, obstacle)| obstacle)
+            .collect()
+    }
+}
+
+/// How far from its start a mover of `radius` can touch anything in one step
+/// of `dt_seconds` at `speed`; pass it as the `reach` of a query.
+pub fn step_reach(speed: f32, dt_seconds: f32, radius: f32) -> f32 {
+    speed.abs() * dt_seconds.max(0.0) + radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        movement_step_with_obstacles, Footprint, InputState, PLAYER_COLLIDER_RADIUS,
+        STRUCTURE_COLLIDER_HALF_EXTENT,
+    };
+
+    fn square(x: f32, y: f32) -> StructureObstacle {
+        StructureObstacle {
+            x,
+            y,
+            half_width: STRUCTURE_COLLIDER_HALF_EXTENT,
+            half_height: STRUCTURE_COLLIDER_HALF_EXTENT,
+        }
+    }
+
+    #[test]
+    fn queries_only_return_nearby_obstacles() {
+        let mut index = ObstacleIndex::default();
+        for i in 0..100 {
+            index.insert(i, square(i as f32 * 64.0, 0.0));
+        }
+
+        let near: Vec<i32> = index
+            .near(640.0, 0.0, 40.0)
+            .iter()
+            .map(|(k, _)| **k)
+            .collect();
+        assert_eq!(near, vec![10]);
+        let wider: Vec<i32> = index
+            .near(640.0, 0.0, 60.0)
+            .iter()
+            .map(|(k, _)| **k)
+            .collect();
+        assert_eq!(wider, vec![9, 10, 11]);
+        assert!(index.near(640.0, 500.0, 40.0).is_empty());
+    }
+
+    #[test]
+    fn obstacles_spanning_chunks_are_listed_once_and_removed_everywhere() {
+        let mut index = ObstacleIndex::default();
+        // A 3x3 footprint centered on a chunk corner covers four chunks.
+        let big = Footprint {
+            width: 3,
+            height: 3,
+        }
+        .obstacle(OBSTACLE_CHUNK_SIZE, OBSTACLE_CHUNK_SIZE);
+        index.insert("assembler", big);
+        index.insert("beacon", square(100.0, 100.0));
+
+        let near = index.near(OBSTACLE_CHUNK_SIZE, OBSTACLE_CHUNK_SIZE, 40.0);
+        assert_eq!(near.len(), 1);
+        assert_eq!(*near[0].0, "assembler");
+
+        index.insert("assembler", square(-500.0, -500.0));
+        assert!(index
+            .near(OBSTACLE_CHUNK_SIZE, OBSTACLE_CHUNK_SIZE, 40.0)
+            .is_empty());
+        assert_eq!(index.len(), 2);
+
+        assert!(index.remove(&"assembler").is_some());
+        assert!(index.near(-500.0, -500.0, 40.0).is_empty());
+        // Only the beacon's chunk is left.
+        assert_eq!(index.chunks.len(), 1);
+    }
+
+    #[test]
+    fn indexed_movement_matches_a_full_scan() {
+        let mut index = ObstacleIndex::default();
+        let mut all = Vec::new();
+        for i in 0..40 {
+            let obstacle = square((i % 8) as f32 * 90.0, (i / 8) as f32 * 90.0);
+            index.insert(i, obstacle);
+            all.push(obstacle);
+        }
+
+        let input = InputState {
+            up: true,
+            down: false,
+            left: false,
+            right: true,
+            ..Default::default()
+        };
+        let dt = 1.0 / 30.0;
+        let (mut x, mut y) = (-40.0, -40.0);
+        let (mut ix, mut iy) = (x, y);
+        for _ in 0..300 {
+            let step = movement_step_with_obstacles(
+                x,
+                y,
+                input,
+                dt,
+                220.0,
+                5000.0,
+                &all,
+                PLAYER_COLLIDER_RADIUS,
+            );
+            (x, y) = (step.x, step.y);
+
+            let nearby =
+                index.obstacles_near(ix, iy, step_reach(220.0, dt, PLAYER_COLLIDER_RADIUS));
+            let step = movement_step_with_obstacles(
+                ix,
+                iy,
+                input,
+                dt,
+                220.0,
+                5000.0,
+                &nearby,
+                PLAYER_COLLIDER_RADIUS,
+            );
+            (ix, iy) = (step.x, step.y);
+        }
+        assert_eq!((x, y), (ix, iy));
+    }
+}
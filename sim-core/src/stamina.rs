@@ -0,0 +1,120 @@
+//! Sprint budget. Sprinting drains stamina while the player moves; anything
+//! else refills it. A fully drained player has to recover part of the budget
+//! before sprinting again, so holding sprint does not stutter at zero.
+
+use crate::{Fixed, InputState};
+
+pub const SPRINT_SPEED_MULTIPLIER: f32 = 1.5;
+/// Seconds of sprinting on a full budget.
+pub const STAMINA_MAX_SECONDS: f32 = 4.0;
+/// Seconds of sprint regained per second of not sprinting.
+pub const STAMINA_REGEN_PER_SECOND: f32 = 0.5;
+/// Budget an exhausted player needs back before sprint works again.
+pub const STAMINA_RECOVERY_SECONDS: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stamina {
+    pub seconds: f32,
+    pub exhausted: bool,
+}
+
+impl Default for Stamina {
+    fn default() -> Self {
+        Self::full()
+    }
+}
+
+impl Stamina {
+    pub fn full() -> Self {
+        Self {
+            seconds: STAMINA_MAX_SECONDS,
+            exhausted: false,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.seconds >= STAMINA_MAX_SECONDS
+    }
+
+    /// Advances the budget over one movement step of `dt_seconds` and returns
+    /// the speed to move at. Done in fixed point like the movement step itself.
+    pub fn step(&mut self, input: InputState, speed: f32, dt_seconds: f32) -> f32 {
+        let dt = Fixed::from_f32(dt_seconds);
+        let mut seconds = Fixed::from_f32(self.seconds);
+        let sprinting = input.sprint && input.is_moving() && !self.exhausted;
+
+        if sprinting {
+            seconds = (seconds - dt).max(Fixed::ZERO);
+            self.exhausted = seconds == Fixed::ZERO;
+        } else {
+            seconds = (seconds + dt * Fixed::from_f32(STAMINA_REGEN_PER_SECOND))
+                .min(Fixed::from_f32(STAMINA_MAX_SECONDS));
+            if seconds >= Fixed::from_f32(STAMINA_RECOVERY_SECONDS) {
+                self.exhausted = false;
+            }
+        }
+        self.seconds = seconds.to_f32();
+
+        if sprinting {
+            (Fixed::from_f32(speed) * Fixed::from_f32(SPRINT_SPEED_MULTIPLIER)).to_f32()
+        } else {
+            speed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprint_right() -> InputState {
+        InputState {
+            right: true,
+            sprint: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sprinting_drains_until_exhausted_then_recovers() {
+        let dt = 1.0 / 30.0;
+        let mut stamina = Stamina::full();
+        assert_eq!(stamina.step(sprint_right(), 200.0, dt), 300.0);
+
+        let mut sprint_ticks = 1;
+        while stamina.step(sprint_right(), 200.0, dt) > 200.0 {
+            sprint_ticks += 1;
+        }
+        assert!((sprint_ticks as f32 * dt - STAMINA_MAX_SECONDS).abs() < 0.1);
+        assert!(stamina.exhausted);
+
+        // Still holding sprint: walks while the budget refills to the
+        // recovery threshold, then sprints again.
+        let mut walk_ticks = 0;
+        while stamina.step(sprint_right(), 200.0, dt) == 200.0 {
+            walk_ticks += 1;
+        }
+        let expected = STAMINA_RECOVERY_SECONDS / STAMINA_REGEN_PER_SECOND;
+        assert!((walk_ticks as f32 * dt - expected).abs() < 0.1);
+    }
+
+    #[test]
+    fn sprint_needs_movement() {
+        let mut stamina = Stamina::full();
+        let idle = InputState {
+            sprint: true,
+            ..Default::default()
+        };
+        assert_eq!(stamina.step(idle, 200.0, 1.0), 200.0);
+        assert!(stamina.is_full());
+
+        // Opposing keys cancel out and do not count as moving either.
+        let opposed = InputState {
+            left: true,
+            right: true,
+            sprint: true,
+            ..Default::default()
+        };
+        assert_eq!(stamina.step(opposed, 200.0, 1.0), 200.0);
+    }
+}
@@ -0,0 +1,425 @@
+//! Seeded terrain: ground, water, ore patches and enemy nests laid out on the
+//! build grid.
+//!
+//! Tile `(tx, ty)` is centered on `(tx * TERRAIN_TILE_SIZE, ty * TERRAIN_TILE_SIZE)`,
+//! so tiles and build cells line up one to one. Generation only uses integer
+//! hashing and basic float arithmetic, which keeps the worker and the WASM
+//! client in agreement without replicating tiles.
+
+pub const TERRAIN_TILE_SIZE: f32 = 32.0;
+pub const TERRAIN_CHUNK_TILES: i32 = 32;
+
+const WATER_SALT: u32 = 0x5741_5445;
+const WATER_DETAIL_SALT: u32 = 0x5744_544c;
+const ORE_SALT: u32 = 0x4f52_4553;
+const ORE_EDGE_SALT: u32 = 0x4544_4745;
+const NEST_SALT: u32 = 0x4e45_5354;
+
+const WATER_NOISE_SCALE_TILES: i32 = 24;
+const WATER_DETAIL_SCALE_TILES: i32 = 7;
+const WATER_THRESHOLD: f32 = 0.66;
+// Keeps the respawn ring and the starter ore patches on dry land.
+const SPAWN_CLEAR_RADIUS_TILES: i32 = 20;
+
+const ORE_REGION_TILES: i32 = 16;
+const ORE_PATCH_CHANCE_PERCENT: u32 = 35;
+const ORE_PATCH_MIN_RADIUS: i32 = 2;
+const ORE_PATCH_MAX_RADIUS: i32 = 5;
+
+const NEST_CHANCE_PERCENT: u32 = 25;
+// Leaves new players room to build before anything attacks them.
+const NEST_MIN_ORIGIN_DISTANCE_TILES: i32 = 48;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OreKind {
+    Iron,
+    Copper,
+    Stone,
+    Coal,
+}
+
+impl OreKind {
+    pub const ALL: [OreKind; 4] = [
+        OreKind::Iron,
+        OreKind::Copper,
+        OreKind::Stone,
+        OreKind::Coal,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OreKind::Iron => "iron",
+            OreKind::Copper => "copper",
+            OreKind::Stone => "stone",
+            OreKind::Coal => "coal",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TerrainTile {
+    Ground,
+    Water,
+    Ore(OreKind),
+}
+
+impl TerrainTile {
+    pub fn is_walkable(self) -> bool {
+        self != TerrainTile::Water
+    }
+
+    pub fn is_buildable(self) -> bool {
+        self != TerrainTile::Water
+    }
+
+    pub fn ore(self) -> Option<OreKind> {
+        match self {
+            TerrainTile::Ore(kind) => Some(kind),
+            _ => None,
+        }
+    }
+}
+
+pub fn world_to_tile(value: f32) -> i32 {
+    (value / TERRAIN_TILE_SIZE).round() as i32
+}
+
+pub fn tile_center(tile: i32) -> f32 {
+    tile as f32 * TERRAIN_TILE_SIZE
+}
+
+pub fn tile_to_chunk(tile: i32) -> i32 {
+    tile.div_euclid(TERRAIN_CHUNK_TILES)
+}
+
+fn hash_tile(seed: u32, salt: u32, x: i32, y: i32) -> u32 {
+    let mut hash = seed ^ salt.wrapping_mul(0x9e37_79b9);
+    hash ^= (x as u32).wrapping_mul(0x85eb_ca6b);
+    hash = hash.rotate_left(13);
+    hash ^= (y as u32).wrapping_mul(0xc2b2_ae35);
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
+
+fn unit_from_hash(hash: u32) -> f32 {
+    (hash >> 8) as f32 / (1u32 << 24) as f32
+}
+
+/// Bilinear value noise over a lattice `scale` tiles wide, in `[0, 1)`.
+fn value_noise(seed: u32, salt: u32, x: i32, y: i32, scale: i32) -> f32 {
+    let lattice_x = x.div_euclid(scale);
+    let lattice_y = y.div_euclid(scale);
+    let fx = x.rem_euclid(scale) as f32 / scale as f32;
+    let fy = y.rem_euclid(scale) as f32 / scale as f32;
+    let sx = fx * fx * (3.0 - 2.0 * fx);
+    let sy = fy * fy * (3.0 - 2.0 * fy);
+
+    let corner =
+        |dx: i32, dy: i32| unit_from_hash(hash_tile(seed, salt, lattice_x + dx, lattice_y + dy));
+    let top = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * sx;
+    let bottom = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * sx;
+    top + (bottom - top) * sy
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct OrePatch {
+    kind: OreKind,
+    center_x: i32,
+    center_y: i32,
+    radius: i32,
+}
+
+/// A room's terrain. Cheap to copy; tiles are computed on demand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Terrain {
+    seed: u32,
+}
+
+impl Terrain {
+    pub fn new(seed: u32) -> Self {
+        Self { seed }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn tile_at(&self, tile_x: i32, tile_y: i32) -> TerrainTile {
+        if self.is_water(tile_x, tile_y) {
+            return TerrainTile::Water;
+        }
+
+        match self.ore_patch(
+            tile_x.div_euclid(ORE_REGION_TILES),
+            tile_y.div_euclid(ORE_REGION_TILES),
+        ) {
+            Some(patch) if self.patch_covers(patch, tile_x, tile_y) => TerrainTile::Ore(patch.kind),
+            _ => TerrainTile::Ground,
+        }
+    }
+
+    pub fn tile_at_world(&self, x: f32, y: f32) -> TerrainTile {
+        self.tile_at(world_to_tile(x), world_to_tile(y))
+    }
+
+    /// Row-major tiles of one chunk, starting at tile
+    /// `(chunk_x * TERRAIN_CHUNK_TILES, chunk_y * TERRAIN_CHUNK_TILES)`.
+    pub fn chunk_tiles(&self, chunk_x: i32, chunk_y: i32) -> Vec<TerrainTile> {
+        let origin_x = chunk_x * TERRAIN_CHUNK_TILES;
+        let origin_y = chunk_y * TERRAIN_CHUNK_TILES;
+        let mut tiles = Vec::with_capacity((TERRAIN_CHUNK_TILES * TERRAIN_CHUNK_TILES) as usize);
+        for local_y in 0..TERRAIN_CHUNK_TILES {
+            for local_x in 0..TERRAIN_CHUNK_TILES {
+                tiles.push(self.tile_at(origin_x + local_x, origin_y + local_y));
+            }
+        }
+        tiles
+    }
+
+    /// Whether a square collider of `half_extent` around `(x, y)` overlaps
+    /// an unwalkable tile. Uses the same open-interval overlap as structures.
+    pub fn blocks_area(&self, x: f32, y: f32, half_extent: f32) -> bool {
+        let blocked = TERRAIN_TILE_SIZE * 0.5 + half_extent;
+        for tile_y in world_to_tile(y - half_extent)..=world_to_tile(y + half_extent) {
+            for tile_x in world_to_tile(x - half_extent)..=world_to_tile(x + half_extent) {
+                if self.tile_at(tile_x, tile_y).is_walkable() {
+                    continue;
+                }
+                if (x - tile_center(tile_x)).abs() < blocked
+                    && (y - tile_center(tile_y)).abs() < blocked
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Tile of the enemy nest in a terrain chunk, if it has one. A chunk holds
+    /// at most one, on plain ground at least one tile in from the chunk edge.
+    pub fn nest_in_chunk(&self, chunk_x: i32, chunk_y: i32) -> Option<(i32, i32)> {
+        let hash = hash_tile(self.seed, NEST_SALT, chunk_x, chunk_y);
+        if hash % 100 >= NEST_CHANCE_PERCENT {
+            return None;
+        }
+
+        let span = (TERRAIN_CHUNK_TILES - 2) as u32;
+        let tile_x = chunk_x * TERRAIN_CHUNK_TILES + 1 + ((hash >> 8) % span) as i32;
+        let tile_y = chunk_y * TERRAIN_CHUNK_TILES + 1 + ((hash >> 20) % span) as i32;
+        let origin_distance_sq = tile_x as i64 * tile_x as i64 + tile_y as i64 * tile_y as i64;
+        if origin_distance_sq < (NEST_MIN_ORIGIN_DISTANCE_TILES as i64).pow(2) {
+            return None;
+        }
+        (self.tile_at(tile_x, tile_y) == TerrainTile::Ground).then_some((tile_x, tile_y))
+    }
+
+    /// Nest tiles whose centers lie within `radius` world units of `(x, y)`,
+    /// ordered by chunk.
+    pub fn nests_near(&self, x: f32, y: f32, radius: f32) -> Vec<(i32, i32)> {
+        let chunk_range = |center: f32| {
+            tile_to_chunk(world_to_tile(center - radius))
+                ..=tile_to_chunk(world_to_tile(center + radius))
+        };
+        let mut nests = Vec::new();
+        for chunk_y in chunk_range(y) {
+            for chunk_x in chunk_range(x) {
+                let Some((tile_x, tile_y)) = self.nest_in_chunk(chunk_x, chunk_y) else {
+                    continue;
+                };
+                let (dx, dy) = (tile_center(tile_x) - x, tile_center(tile_y) - y);
+                if dx * dx + dy * dy <= radius * radius {
+                    nests.push((tile_x, tile_y));
+                }
+            }
+        }
+        nests
+    }
+
+    fn is_water(&self, tile_x: i32, tile_y: i32) -> bool {
+        let spawn_distance_sq = tile_x as i64 * tile_x as i64 + tile_y as i64 * tile_y as i64;
+        if spawn_distance_sq < (SPAWN_CLEAR_RADIUS_TILES as i64).pow(2) {
+            return false;
+        }
+
+        let broad = value_noise(
+            self.seed,
+            WATER_SALT,
+            tile_x,
+            tile_y,
+            WATER_NOISE_SCALE_TILES,
+        );
+        let detail = value_noise(
+            self.seed,
+            WATER_DETAIL_SALT,
+            tile_x,
+            tile_y,
+            WATER_DETAIL_SCALE_TILES,
+        );
+        broad * 0.75 + detail * 0.25 > WATER_THRESHOLD
+    }
+
+    fn ore_patch(&self, region_x: i32, region_y: i32) -> Option<OrePatch> {
+        let hash = hash_tile(self.seed, ORE_SALT, region_x, region_y);
+        let starter = (-1..=0).contains(&region_x) && (-1..=0).contains(&region_y);
+
+        let kind = if starter {
+            // The four regions touching the origin always hold one patch of each ore.
+            let slot = ((region_x + 1) + (region_y + 1) * 2) as u32;
+            OreKind::ALL[((slot + self.seed % 4) % 4) as usize]
+        } else if hash % 100 < ORE_PATCH_CHANCE_PERCENT {
+            OreKind::ALL[((hash >> 8) % 4) as usize]
+        } else {
+            return None;
+        };
+
+        let radius_span = (ORE_PATCH_MAX_RADIUS - ORE_PATCH_MIN_RADIUS + 1) as u32;
+        let radius = ORE_PATCH_MIN_RADIUS + ((hash >> 12) % radius_span) as i32;
+        // Patches stay inside their region so a tile only ever checks one.
+        let placement_span = (ORE_REGION_TILES - 2 * radius) as u32;
+        Some(OrePatch {
+            kind,
+            center_x: region_x * ORE_REGION_TILES + radius + ((hash >> 16) % placement_span) as i32,
+            center_y: region_y * ORE_REGION_TILES + radius + ((hash >> 24) % placement_span) as i32,
+            radius,
+        })
+    }
+
+    fn patch_covers(&self, patch: OrePatch, tile_x: i32, tile_y: i32) -> bool {
+        let dx = (tile_x - patch.center_x) as f32;
+        let dy = (tile_y - patch.center_y) as f32;
+        let jitter = unit_from_hash(hash_tile(self.seed, ORE_EDGE_SALT, tile_x, tile_y));
+        let reach = patch.radius as f32 + jitter - 0.5;
+        dx * dx + dy * dy <= reach * reach
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count_tiles(
+        terrain: &Terrain,
+        range: i32,
+        mut predicate: impl FnMut(TerrainTile) -> bool,
+    ) -> usize {
+        let mut count = 0;
+        for tile_y in -range..range {
+            for tile_x in -range..range {
+                if predicate(terrain.tile_at(tile_x, tile_y)) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn terrain_is_deterministic_per_seed() {
+        let first = Terrain::new(1234);
+        let second = Terrain::new(1234);
+        let other = Terrain::new(98_765);
+
+        assert_eq!(first.chunk_tiles(2, -3), second.chunk_tiles(2, -3));
+        assert_ne!(first.chunk_tiles(2, -3), other.chunk_tiles(2, -3));
+    }
+
+    #[test]
+    fn chunk_tiles_match_point_queries() {
+        let terrain = Terrain::new(7);
+        let tiles = terrain.chunk_tiles(-1, 1);
+
+        for (index, tile) in tiles.iter().enumerate() {
+            let local_x = index as i32 % TERRAIN_CHUNK_TILES;
+            let local_y = index as i32 / TERRAIN_CHUNK_TILES;
+            let tile_x = -TERRAIN_CHUNK_TILES + local_x;
+            let tile_y = TERRAIN_CHUNK_TILES + local_y;
+            assert_eq!(*tile, terrain.tile_at(tile_x, tile_y));
+            assert_eq!(tile_to_chunk(tile_x), -1);
+            assert_eq!(tile_to_chunk(tile_y), 1);
+        }
+    }
+
+    #[test]
+    fn spawn_area_is_dry_and_has_every_ore() {
+        for seed in [0, 1, 42, 0xdead_beef] {
+            let terrain = Terrain::new(seed);
+            let radius = SPAWN_CLEAR_RADIUS_TILES;
+            for tile_y in -radius..radius {
+                for tile_x in -radius..radius {
+                    if tile_x * tile_x + tile_y * tile_y < radius * radius {
+                        assert_ne!(terrain.tile_at(tile_x, tile_y), TerrainTile::Water);
+                    }
+                }
+            }
+            for kind in OreKind::ALL {
+                assert!(
+                    count_tiles(&terrain, ORE_REGION_TILES, |tile| tile.ore() == Some(kind)) > 0,
+                    "seed {seed} has no starter {}",
+                    kind.as_str()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn world_has_some_water_and_ore() {
+        let terrain = Terrain::new(42);
+        let total = (160 * 2) * (160 * 2);
+        let water = count_tiles(&terrain, 160, |tile| tile == TerrainTile::Water);
+        let ore = count_tiles(&terrain, 160, |tile| tile.ore().is_some());
+
+        assert!(water * 100 > total * 3, "water {water}/{total}");
+        assert!(water * 100 < total * 30, "water {water}/{total}");
+        assert!(ore > 0);
+    }
+
+    #[test]
+    fn water_blocks_overlapping_areas() {
+        let terrain = Terrain::new(42);
+        let (tile_x, tile_y) = (-160..160)
+            .flat_map(|tile_y| (-160..160).map(move |tile_x| (tile_x, tile_y)))
+            .find(|&(tile_x, tile_y)| terrain.tile_at(tile_x, tile_y) == TerrainTile::Water)
+            .expect("seed 42 has water");
+
+        assert!(terrain.blocks_area(tile_center(tile_x), tile_center(tile_y), 1.0));
+        assert!(!terrain.blocks_area(0.0, 0.0, 10.0));
+    }
+
+    #[test]
+    fn nests_sit_on_ground_away_from_spawn() {
+        let terrain = Terrain::new(42);
+        let mut nests = 0;
+        for chunk_y in -6..6 {
+            for chunk_x in -6..6 {
+                let Some((tile_x, tile_y)) = terrain.nest_in_chunk(chunk_x, chunk_y) else {
+                    continue;
+                };
+                nests += 1;
+                assert_eq!(terrain.tile_at(tile_x, tile_y), TerrainTile::Ground);
+                assert_eq!(
+                    (tile_to_chunk(tile_x), tile_to_chunk(tile_y)),
+                    (chunk_x, chunk_y)
+                );
+                assert!(tile_x * tile_x + tile_y * tile_y >= NEST_MIN_ORIGIN_DISTANCE_TILES.pow(2));
+            }
+        }
+        assert!(nests > 0);
+        assert!(terrain
+            .nests_near(0.0, 0.0, 40.0 * TERRAIN_TILE_SIZE)
+            .is_empty());
+
+        let (tile_x, tile_y) = (-6..6)
+            .flat_map(|chunk_y| (-6..6).map(move |chunk_x| (chunk_x, chunk_y)))
+            .find_map(|(chunk_x, chunk_y)| terrain.nest_in_chunk(chunk_x, chunk_y))
+            .unwrap();
+        let near = terrain.nests_near(tile_center(tile_x) + 10.0, tile_center(tile_y), 64.0);
+        assert_eq!(near, vec![(tile_x, tile_y)]);
+    }
+}
@@ -0,0 +1,138 @@
+//! Weapons a player can fire. The server picks the equipped weapon's rules for
+//! every shot; clients only choose the aim.
+
+use std::collections::BTreeMap;
+
+use crate::{ItemKind, PROJECTILE_DAMAGE};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weapon {
+    pub id: &'static str,
+    /// Shortest time between two shots.
+    pub fire_interval_ms: i64,
+    pub projectile_speed: f32,
+    /// Widest deviation from the aimed direction, in radians either way.
+    pub spread_radians: f32,
+    pub damage: i32,
+    /// Item consumed per shot; `None` fires for free.
+    pub ammo: Option<ItemKind>,
+    pub projectile_ttl_ms: i64,
+}
+
+/// What players hold until they equip something else.
+pub const DEFAULT_WEAPON_ID: &str = "pistol";
+
+pub const WEAPONS: &[Weapon] = &[
+    Weapon {
+        id: "pistol",
+        fire_interval_ms: 250,
+        projectile_speed: 760.0,
+        spread_radians: 0.0,
+        damage: PROJECTILE_DAMAGE,
+        ammo: None,
+        projectile_ttl_ms: 1800,
+    },
+    Weapon {
+        id: "rifle",
+        fire_interval_ms: 100,
+        projectile_speed: 900.0,
+        spread_radians: 0.06,
+        damage: 12,
+        ammo: Some(ItemKind::Ammo),
+        projectile_ttl_ms: 1400,
+    },
+];
+
+pub fn weapon_by_id(id: &str) -> Option<&'static Weapon> {
+    WEAPONS.iter().find(|weapon| weapon.id == id)
+}
+
+impl Weapon {
+    pub fn default_weapon() -> &'static Weapon {
+        weapon_by_id(DEFAULT_WEAPON_ID).unwrap_or(&WEAPONS[0])
+    }
+
+    /// Whether a shot at `now` respects the cooldown after one at `last_fired_at`.
+    pub fn ready(&self, last_fired_at: i64, now: i64) -> bool {
+        now - last_fired_at >= self.fire_interval_ms
+    }
+
+    /// Velocity of a shot aimed along `(dx, dy)`. `roll` in `[-1, 1]` picks
+    /// where in the spread cone it lands; `0` is dead on. `None` for an aim
+    /// without a direction.
+    pub fn shot_velocity(&self, dx: f32, dy: f32, roll: f32) -> Option<(f32, f32)> {
+        let length = (dx * dx + dy * dy).sqrt();
+        if !length.is_finite() || length <= f32::EPSILON {
+            return None;
+        }
+        let angle = dy.atan2(dx) + self.spread_radians * roll.clamp(-1.0, 1.0);
+        Some((
+            angle.cos() * self.projectile_speed,
+            angle.sin() * self.projectile_speed,
+        ))
+    }
+}
+
+/// When each weapon was last fired. Cooldowns are per weapon: switching to
+/// another one neither waits out nor resets the first one's interval.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WeaponCooldowns {
+    last_fired_at: BTreeMap<&'static str, i64>,
+}
+
+impl WeaponCooldowns {
+    pub fn ready(&self, weapon: &Weapon, now: i64) -> bool {
+        self.last_fired_at
+            .get(weapon.id)
+            .is_none_or(|&last_fired_at| weapon.ready(last_fired_at, now))
+    }
+
+    pub fn fired(&mut self, weapon: &'static Weapon, now: i64) {
+        self.last_fired_at.insert(weapon.id, now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shots_use_the_weapon_speed_and_stay_inside_the_spread() {
+        let pistol = Weapon::default_weapon();
+        assert_eq!(pistol.id, DEFAULT_WEAPON_ID);
+        let (vx, vy) = pistol.shot_velocity(3.0, 4.0, 1.0).unwrap();
+        assert!((vx - 456.0).abs() < 0.01 && (vy - 608.0).abs() < 0.01);
+        assert_eq!(pistol.shot_velocity(0.0, 0.0, 0.0), None);
+        assert_eq!(pistol.shot_velocity(f32::NAN, 1.0, 0.0), None);
+
+        let rifle = weapon_by_id("rifle").unwrap();
+        assert_eq!(rifle.ammo, Some(ItemKind::Ammo));
+        for roll in [-5.0, -1.0, -0.3, 0.0, 0.7, 1.0, 5.0] {
+            let (vx, vy) = rifle.shot_velocity(1.0, 0.0, roll).unwrap();
+            assert!(((vx * vx + vy * vy).sqrt() - rifle.projectile_speed).abs() < 0.01);
+            assert!(vy.atan2(vx).abs() <= rifle.spread_radians + 1e-6);
+        }
+    }
+
+    #[test]
+    fn cooldown_is_per_weapon() {
+        let pistol = weapon_by_id("pistol").unwrap();
+        let rifle = weapon_by_id("rifle").unwrap();
+        assert!(!pistol.ready(1000, 1100));
+        assert!(rifle.ready(1000, 1100));
+        assert!(pistol.ready(1000, 1250));
+        assert!(weapon_by_id("railgun").is_none());
+
+        let mut cooldowns = WeaponCooldowns::default();
+        assert!(cooldowns.ready(pistol, 0));
+        cooldowns.fired(pistol, 1000);
+        assert!(!cooldowns.ready(pistol, 1100));
+        assert!(cooldowns.ready(rifle, 1100));
+        cooldowns.fired(rifle, 1100);
+        assert!(!cooldowns.ready(rifle, 1150));
+        assert!(cooldowns.ready(rifle, 1200));
+        // Firing the rifle did not touch the pistol's interval.
+        assert!(!cooldowns.ready(pistol, 1249));
+        assert!(cooldowns.ready(pistol, 1250));
+    }
+}
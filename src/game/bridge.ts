@@ -2,6 +2,8 @@ import type { InputCommand, OutboundFeatureCommand, RenderSnapshotPayload } from
 import init, {
   boot_game,
   set_player_id,
+  set_terrain_seed,
+  set_gamepad_input,
   push_snapshot,
   drain_input_events,
   drain_feature_commands,
@@ -49,6 +51,27 @@ export async function setPlayerId(playerId: string) {
   set_player_id(playerId);
 }
 
+export async function setTerrainSeed(seed: number) {
+  await initialize();
+  set_terrain_seed(seed);
+}
+
+// Standard mapping: left stick on axes 0/1 (+y down), sprint on the left
+// stick press or left bumper.
+export async function pollGamepad() {
+  await initialize();
+  const gamepad = navigator.getGamepads?.().find((candidate) => candidate?.connected) ?? null;
+  if (!gamepad) {
+    set_gamepad_input(0, 0, false);
+    return;
+  }
+  set_gamepad_input(
+    gamepad.axes[0] ?? 0,
+    -(gamepad.axes[1] ?? 0),
+    Boolean(gamepad.buttons[10]?.pressed || gamepad.buttons[4]?.pressed),
+  );
+}
+
 export async function resetSessionState() {
   await initialize();
   reset_session_state();
@@ -0,0 +1,69 @@
+import assert from 'node:assert/strict';
+import { readFileSync } from 'node:fs';
+import { test } from 'node:test';
+import type { ServerEnvelope } from '../types.ts';
+import { decodeServerEnvelope, encodeClientEnvelope } from './binary-protocol.ts';
+
+// Shared with worker/src/binary_protocol.rs, which checks the same bytes
+// against the Rust codec and both wire tables in full.
+type Fixture = {
+  server: (Omit<ServerEnvelope, 'v'> & { name: string; hex: string })[];
+  client: {
+    name: string;
+    hex: string;
+    seq: number;
+    clientTime: number;
+    feature: string;
+    action: string;
+    payload?: unknown;
+  }[];
+  routes: { feature: string; action: string; hex: string }[];
+};
+
+const fixture: Fixture = JSON.parse(
+  readFileSync(new URL('../../../worker/fixtures/binary-protocol.json', import.meta.url), 'utf8'),
+);
+
+function toHex(bytes: Uint8Array) {
+  return Array.from(bytes, (byte) => byte.toString(16).padStart(2, '0')).join('');
+}
+
+function fromHex(hex: string) {
+  const bytes = new Uint8Array(hex.length / 2);
+  for (let index = 0; index < bytes.length; index += 1) {
+    bytes[index] = parseInt(hex.slice(index * 2, index * 2 + 2), 16);
+  }
+  return bytes.buffer;
+}
+
+test('decodes server envelopes encoded by the worker', () => {
+  for (const { name, hex, ...envelope } of fixture.server) {
+    assert.deepEqual(
+      decodeServerEnvelope(fromHex(hex)),
+      { v: 3, seq: undefined, payload: undefined, ...envelope },
+      name,
+    );
+  }
+});
+
+test('encodes client commands the way the worker expects', () => {
+  for (const { name, hex, ...command } of fixture.client) {
+    assert.equal(toHex(encodeClientEnvelope({ v: 3, kind: 'command', ...command })), hex, name);
+  }
+});
+
+test('route table matches the worker', () => {
+  for (const { feature, action, hex } of fixture.routes) {
+    const bytes = encodeClientEnvelope({ v: 3, kind: 'command', seq: 0, clientTime: 0, feature, action });
+    assert.equal(toHex(bytes), hex, `${feature}.${action}`);
+  }
+});
+
+test('truncated server envelopes are rejected', () => {
+  for (const { name, hex } of fixture.server) {
+    const bytes = new Uint8Array(fromHex(hex));
+    for (let length = 0; length < bytes.length; length += 1) {
+      assert.equal(decodeServerEnvelope(bytes.slice(0, length).buffer), null, `${name} at ${length}`);
+    }
+  }
+});
@@ -0,0 +1,531 @@
+import type { ClientCommandEnvelope, InputCommand, ServerEnvelope } from '../types';
+// Explicit extension so the node test runner can load this module directly.
+import { BINARY_PROTOCOL_VERSION } from '../types.ts';
+
+// Protocol v3 codec. Mirrors worker/src/binary_protocol.rs; the tables below are
+// part of the wire format, so only ever append to them (in both places).
+
+const KNOWN_ROUTES: ReadonlyArray<readonly [string, string]> = [
+  ['core', 'connected'],
+  ['core', 'command'],
+  ['core', 'duplicate'],
+  ['core', 'state'],
+  ['core', 'pong'],
+  ['core', 'ping'],
+  ['core', 'snapshot_ack'],
+  ['core', 'invalid_message'],
+  ['core', 'command_rejected'],
+  ['movement', 'input_batch'],
+  ['build', 'place'],
+  ['build', 'remove'],
+  ['build', 'preview'],
+  ['projectile', 'fire'],
+  ['projectile', 'hit'],
+  ['health', 'death'],
+  ['interest', 'update'],
+  ['build', 'remove_rejected'],
+  ['team', 'join'],
+  ['team', 'leave'],
+  ['build', 'set_recipe'],
+  ['projectile', 'equip'],
+  ['build', 'open'],
+  ['build', 'close'],
+  ['build', 'transfer'],
+  ['research', 'select'],
+  ['build', 'place_rejected'],
+];
+
+const KNOWN_KEYS: readonly string[] = [
+  'id',
+  'x',
+  'y',
+  'vx',
+  'vy',
+  'connected',
+  'players',
+  'structures',
+  'previews',
+  'projectiles',
+  'inputAcks',
+  'speed',
+  'ownerId',
+  'kind',
+  'chunkX',
+  'chunkY',
+  'playerId',
+  'clientProjectileId',
+  'hp',
+  'maxHp',
+  'dead',
+  'respawnInMs',
+  'invulnerable',
+  'upserts',
+  'removes',
+  'online',
+  'onlineCount',
+  'structureCount',
+  'previewCount',
+  'projectileCount',
+  'presence',
+  'movement',
+  'build',
+  'projectile',
+  'health',
+  'roomCode',
+  'serverTick',
+  'simRateHz',
+  'snapshotRateHz',
+  'serverTime',
+  'mode',
+  'snapshotId',
+  'baselineId',
+  'features',
+  'inputs',
+  'seq',
+  'up',
+  'down',
+  'left',
+  'right',
+  'clientTime',
+  'clientBuildId',
+  'ok',
+  'resumeToken',
+  'interestRadiusChunks',
+  'chunkWorldSize',
+  'message',
+  'projectileId',
+  'targetKind',
+  'targetId',
+  'killerId',
+  'radius',
+  'entered',
+  'members',
+  'role',
+  'teamId',
+  'code',
+  'structureId',
+  'terrainSeed',
+  'miner',
+  'item',
+  'output',
+  'capacity',
+  'working',
+  'assembler',
+  'recipe',
+  'outputs',
+  'count',
+  'progress',
+  'crafting',
+  'inventory',
+  'slots',
+  'direction',
+  'belt',
+  'chunks',
+  'palette',
+  'items',
+  'inserter',
+  'held',
+  'phase',
+  'moveX',
+  'moveY',
+  'sprint',
+  'stamina',
+  'exhausted',
+  'weapon',
+  'weaponId',
+  'enemy',
+  'enemies',
+  'enemyCount',
+  'power',
+  'networks',
+  'network',
+  'supplyKw',
+  'demandKw',
+  'satisfaction',
+  'chest',
+  'open',
+  'to',
+  'research',
+  'current',
+  'unlocked',
+  'techId',
+  'lab',
+  'packs',
+  'researching',
+];
+
+const SERVER_KINDS: readonly ServerEnvelope['kind'][] = [
+  'welcome',
+  'ack',
+  'snapshot',
+  'event',
+  'error',
+  'pong',
+];
+const CLIENT_KIND_COMMAND = 0;
+const ROUTE_INLINE = 0xff;
+
+const FLAG_SEQ = 1 << 0;
+const FLAG_PAYLOAD = 1 << 1;
+const FLAG_PACKED_INPUTS = 1 << 2;
+
+const TAG_NULL = 0;
+const TAG_FALSE = 1;
+const TAG_TRUE = 2;
+const TAG_UINT = 3;
+const TAG_NEG_INT = 4;
+const TAG_F32 = 5;
+const TAG_F64 = 6;
+const TAG_STRING = 7;
+const TAG_ARRAY = 8;
+const TAG_OBJECT = 9;
+
+const INPUT_UP = 1 << 0;
+const INPUT_DOWN = 1 << 1;
+const INPUT_LEFT = 1 << 2;
+const INPUT_RIGHT = 1 << 3;
+const INPUT_SPRINT = 1 << 4;
+// Followed by two signed bytes: moveX, moveY.
+const INPUT_ANALOG = 1 << 5;
+
+const routeIndex = new Map(
+  KNOWN_ROUTES.map(([feature, action], index) => [`${feature}.${action}`, index]),
+);
+const keyIndex = new Map(KNOWN_KEYS.map((key, index) => [key, index + 1]));
+const textEncoder = new TextEncoder();
+const textDecoder = new TextDecoder();
+
+class Writer {
+  private buffer = new Uint8Array(256);
+  private view = new DataView(this.buffer.buffer);
+  private length = 0;
+
+  private reserve(extra: number) {
+    if (this.length + extra <= this.buffer.length) {
+      return;
+    }
+    let capacity = this.buffer.length * 2;
+    while (capacity < this.length + extra) {
+      capacity *= 2;
+    }
+    const next = new Uint8Array(capacity);
+    next.set(this.buffer.subarray(0, this.length));
+    this.buffer = next;
+    this.view = new DataView(next.buffer);
+  }
+
+  byte(value: number) {
+    this.reserve(1);
+    this.buffer[this.length] = value;
+    this.length += 1;
+  }
+
+  // Arithmetic instead of bitwise ops so values above 2^31 stay exact.
+  varint(value: number) {
+    let remaining = Math.max(0, Math.floor(value));
+    while (remaining >= 0x80) {
+      this.byte((remaining % 0x80) | 0x80);
+      remaining = Math.floor(remaining / 0x80);
+    }
+    this.byte(remaining);
+  }
+
+  f32(value: number) {
+    this.reserve(4);
+    this.view.setFloat32(this.length, value, true);
+    this.length += 4;
+  }
+
+  f64(value: number) {
+    this.reserve(8);
+    this.view.setFloat64(this.length, value, true);
+    this.length += 8;
+  }
+
+  string(value: string) {
+    const bytes = textEncoder.encode(value);
+    this.varint(bytes.length);
+    this.reserve(bytes.length);
+    this.buffer.set(bytes, this.length);
+    this.length += bytes.length;
+  }
+
+  finish() {
+    return this.buffer.slice(0, this.length);
+  }
+}
+
+class Reader {
+  private offset = 0;
+  private readonly bytes: Uint8Array;
+  private readonly view: DataView;
+
+  constructor(bytes: Uint8Array) {
+    this.bytes = bytes;
+    this.view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+  }
+
+  done() {
+    return this.offset === this.bytes.length;
+  }
+
+  byte() {
+    if (this.offset >= this.bytes.length) {
+      throw new Error('truncated');
+    }
+    const value = this.bytes[this.offset];
+    this.offset += 1;
+    return value;
+  }
+
+  varint() {
+    let value = 0;
+    let scale = 1;
+    for (;;) {
+      const byte = this.byte();
+      value += (byte & 0x7f) * scale;
+      if ((byte & 0x80) === 0) {
+        return value;
+      }
+      scale *= 0x80;
+    }
+  }
+
+  f32() {
+    const value = this.view.getFloat32(this.offset, true);
+    this.offset += 4;
+    return value;
+  }
+
+  f64() {
+    const value = this.view.getFloat64(this.offset, true);
+    this.offset += 8;
+    return value;
+  }
+
+  string() {
+    const length = this.varint();
+    if (this.offset + length > this.bytes.length) {
+      throw new Error('truncated');
+    }
+    const value = textDecoder.decode(this.bytes.subarray(this.offset, this.offset + length));
+    this.offset += length;
+    return value;
+  }
+}
+
+function writeRoute(writer: Writer, feature: string, action: string) {
+  const index = routeIndex.get(`${feature}.${action}`);
+  if (index !== undefined) {
+    writer.byte(index);
+    return;
+  }
+  writer.byte(ROUTE_INLINE);
+  writer.string(feature);
+  writer.string(action);
+}
+
+function readRoute(reader: Reader): [string, string] {
+  const index = reader.byte();
+  if (index === ROUTE_INLINE) {
+    return [reader.string(), reader.string()];
+  }
+  const route = KNOWN_ROUTES[index];
+  if (!route) {
+    throw new Error('unknown route');
+  }
+  return [route[0], route[1]];
+}
+
+function writeValue(writer: Writer, value: unknown) {
+  if (value === null || value === undefined) {
+    writer.byte(TAG_NULL);
+  } else if (typeof value === 'boolean') {
+    writer.byte(value ? TAG_TRUE : TAG_FALSE);
+  } else if (typeof value === 'number') {
+    if (Number.isSafeInteger(value)) {
+      if (value >= 0) {
+        writer.byte(TAG_UINT);
+        writer.varint(value);
+      } else {
+        writer.byte(TAG_NEG_INT);
+        writer.varint(-value - 1);
+      }
+    } else if (Math.fround(value) === value) {
+      writer.byte(TAG_F32);
+      writer.f32(value);
+    } else {
+      writer.byte(TAG_F64);
+      writer.f64(value);
+    }
+  } else if (typeof value === 'string') {
+    writer.byte(TAG_STRING);
+    writer.string(value);
+  } else if (Array.isArray(value)) {
+    writer.byte(TAG_ARRAY);
+    writer.varint(value.length);
+    for (const item of value) {
+      writeValue(writer, item);
+    }
+  } else if (typeof value === 'object') {
+    const entries = Object.entries(value).filter(([, field]) => field !== undefined);
+    writer.byte(TAG_OBJECT);
+    writer.varint(entries.length);
+    for (const [key, field] of entries) {
+      const index = keyIndex.get(key);
+      if (index !== undefined) {
+        writer.varint(index);
+      } else {
+        writer.varint(0);
+        writer.string(key);
+      }
+      writeValue(writer, field);
+    }
+  } else {
+    writer.byte(TAG_NULL);
+  }
+}
+
+function readValue(reader: Reader): unknown {
+  const tag = reader.byte();
+  switch (tag) {
+    case TAG_NULL:
+      return null;
+    case TAG_FALSE:
+      return false;
+    case TAG_TRUE:
+      return true;
+    case TAG_UINT:
+      return reader.varint();
+    case TAG_NEG_INT:
+      return -reader.varint() - 1;
+    case TAG_F32:
+      return reader.f32();
+    case TAG_F64:
+      return reader.f64();
+    case TAG_STRING:
+      return reader.string();
+    case TAG_ARRAY: {
+      const length = reader.varint();
+      const items: unknown[] = [];
+      for (let index = 0; index < length; index += 1) {
+        items.push(readValue(reader));
+      }
+      return items;
+    }
+    case TAG_OBJECT: {
+      const length = reader.varint();
+      const output: Record<string, unknown> = {};
+      for (let index = 0; index < length; index += 1) {
+        const keyId = reader.varint();
+        const key = keyId === 0 ? reader.string() : KNOWN_KEYS[keyId - 1];
+        if (key === undefined) {
+          throw new Error('unknown key');
+        }
+        output[key] = readValue(reader);
+      }
+      return output;
+    }
+    default:
+      throw new Error('unknown value tag');
+  }
+}
+
+function packedInputs(payload: unknown): InputCommand[] | null {
+  if (typeof payload !== 'object' || payload === null) {
+    return null;
+  }
+  const inputs = (payload as { inputs?: unknown }).inputs;
+  if (!Array.isArray(inputs)) {
+    return null;
+  }
+  let previousSeq = 0;
+  for (const input of inputs as InputCommand[]) {
+    if (!Number.isSafeInteger(input.seq) || input.seq < previousSeq) {
+      return null;
+    }
+    previousSeq = input.seq;
+  }
+  return inputs as InputCommand[];
+}
+
+export function encodeClientEnvelope(envelope: ClientCommandEnvelope): Uint8Array {
+  const writer = new Writer();
+  writer.byte(BINARY_PROTOCOL_VERSION);
+  writer.byte(CLIENT_KIND_COMMAND);
+  writer.varint(envelope.seq);
+  writer.f64(envelope.clientTime);
+  writeRoute(writer, envelope.feature, envelope.action);
+
+  // Input batches go out every frame, so they get a bit-packed layout.
+  const inputs =
+    envelope.feature === 'movement' && envelope.action === 'input_batch'
+      ? packedInputs(envelope.payload)
+      : null;
+  if (inputs) {
+    writer.byte(FLAG_PACKED_INPUTS);
+    writer.varint(inputs.length);
+    let previousSeq = 0;
+    for (const input of inputs) {
+      writer.varint(input.seq - previousSeq);
+      previousSeq = input.seq;
+      const moveX = input.moveX ?? 0;
+      const moveY = input.moveY ?? 0;
+      const analog = moveX !== 0 || moveY !== 0;
+      writer.byte(
+        (input.up ? INPUT_UP : 0) |
+          (input.down ? INPUT_DOWN : 0) |
+          (input.left ? INPUT_LEFT : 0) |
+          (input.right ? INPUT_RIGHT : 0) |
+          (input.sprint ? INPUT_SPRINT : 0) |
+          (analog ? INPUT_ANALOG : 0),
+      );
+      if (analog) {
+        writer.byte(moveX & 0xff);
+        writer.byte(moveY & 0xff);
+      }
+    }
+  } else if (envelope.payload !== undefined) {
+    writer.byte(FLAG_PAYLOAD);
+    writeValue(writer, envelope.payload);
+  } else {
+    writer.byte(0);
+  }
+
+  return writer.finish();
+}
+
+export function decodeServerEnvelope(data: ArrayBuffer): ServerEnvelope | null {
+  try {
+    const reader = new Reader(new Uint8Array(data));
+    if (reader.byte() !== BINARY_PROTOCOL_VERSION) {
+      return null;
+    }
+    const kind = SERVER_KINDS[reader.byte()];
+    if (!kind) {
+      return null;
+    }
+    const tick = reader.varint();
+    const serverTime = reader.varint();
+    const [feature, action] = readRoute(reader);
+    const flags = reader.byte();
+    const seq = flags & FLAG_SEQ ? reader.varint() : undefined;
+    const payload = flags & FLAG_PAYLOAD ? readValue(reader) : undefined;
+    if (!reader.done()) {
+      return null;
+    }
+
+    return {
+      v: BINARY_PROTOCOL_VERSION,
+      kind,
+      tick,
+      serverTime,
+      feature,
+      action,
+      seq,
+      payload,
+    };
+  } catch {
+    return null;
+  }
+}
@@ -1,14 +1,20 @@
 import type {
+  BeltChunk,
   BuildPreview,
   BuildStructure,
+  EnemyState,
+  EntityDelta,
   PlayerState,
   ProjectileState,
   RenderSnapshotPayload,
   RoomSnapshot,
+  WireRoomSnapshot,
 } from '../types';
 
 const DEFAULT_INTERPOLATION_DELAY_MS = 110;
 const MAX_BUFFERED_SNAPSHOTS = 90;
+// The server keeps at most 32 unacked snapshots, so older baselines are never referenced.
+const MAX_BASELINE_SNAPSHOTS = 64;
 
 function lerp(a: number, b: number, t: number) {
   return a + (b - a) * t;
@@ -61,6 +67,9 @@ function interpolatePlayers(
       vx: lerp(from.vx, to.vx, alpha),
       vy: lerp(from.vy, to.vy, alpha),
       connected: to.connected,
+      weapon: to.weapon,
+      stamina: to.stamina,
+      exhausted: to.exhausted,
     });
   }
 
@@ -106,8 +115,126 @@ function interpolateProjectiles(older: ProjectileState[], newer: ProjectileState
   return output;
 }
 
-function copyStructures(structures: BuildStructure[]) {
-  return structures.slice();
+// Enemies only exist while they are in both snapshots; one that appears or
+// dies is shown at the snapshot that has it.
+function interpolateEnemies(older: EnemyState[], newer: EnemyState[], alpha: number) {
+  const olderById = new Map(older.map((enemy) => [enemy.id, enemy]));
+  return newer.map((to) => {
+    const from = olderById.get(to.id);
+    if (!from) {
+      return to;
+    }
+    return {
+      ...to,
+      x: lerp(from.x, to.x, alpha),
+      y: lerp(from.y, to.y, alpha),
+      vx: lerp(from.vx, to.vx, alpha),
+      vy: lerp(from.vy, to.vy, alpha),
+    };
+  });
+}
+
+function applyEntityDelta<T>(
+  baseline: T[] | undefined,
+  delta: EntityDelta<T>,
+  entityId: (entity: T) => string,
+): T[] {
+  if (!baseline) {
+    return delta.upserts.slice();
+  }
+
+  const removed = new Set(delta.removes);
+  const upserted = new Map(delta.upserts.map((entity) => [entityId(entity), entity]));
+  const output: T[] = [];
+  for (const entity of baseline) {
+    const id = entityId(entity);
+    if (removed.has(id)) {
+      continue;
+    }
+    const replacement = upserted.get(id);
+    if (replacement) {
+      upserted.delete(id);
+      output.push(replacement);
+    } else {
+      output.push(entity);
+    }
+  }
+  for (const entity of upserted.values()) {
+    output.push(entity);
+  }
+  return output;
+}
+
+function byId(entity: { id: string }) {
+  return entity.id;
+}
+
+function byPlayerId(entity: { playerId: string }) {
+  return entity.playerId;
+}
+
+function reconstructSnapshot(
+  wire: WireRoomSnapshot,
+  baseline: RoomSnapshot | undefined,
+  previous: RoomSnapshot | undefined,
+): RoomSnapshot {
+  const { movement, build, projectile, enemy, health } = wire.features;
+  const base = baseline?.features;
+
+  return {
+    roomCode: wire.roomCode,
+    serverTick: wire.serverTick,
+    simRateHz: wire.simRateHz,
+    snapshotRateHz: wire.snapshotRateHz,
+    serverTime: wire.serverTime,
+    snapshotId: wire.snapshotId,
+    features: {
+      // Presence is not delta-encoded; it is only re-sent when it changes.
+      presence: wire.features.presence ?? previous?.features.presence,
+      movement: movement
+        ? {
+            ...movement,
+            players: applyEntityDelta(base?.movement?.players, movement.players, byId),
+          }
+        : base?.movement,
+      build: build
+        ? {
+            ...build,
+            structures: applyEntityDelta(base?.build?.structures, build.structures, byId),
+            previews: applyEntityDelta(base?.build?.previews, build.previews, byPlayerId),
+          }
+        : base?.build,
+      projectile: projectile
+        ? {
+            ...projectile,
+            projectiles: applyEntityDelta(
+              base?.projectile?.projectiles,
+              projectile.projectiles,
+              byId,
+            ),
+          }
+        : base?.projectile,
+      enemy: enemy
+        ? { ...enemy, enemies: applyEntityDelta(base?.enemy?.enemies, enemy.enemies, byId) }
+        : base?.enemy,
+      power: wire.features.power
+        ? { networks: applyEntityDelta(base?.power?.networks, wire.features.power.networks, byId) }
+        : base?.power,
+      health: health
+        ? {
+            players: applyEntityDelta(base?.health?.players, health.players, byId),
+            structures: applyEntityDelta(base?.health?.structures, health.structures, byId),
+          }
+        : base?.health,
+      belt: wire.features.belt
+        ? { chunks: applyEntityDelta(base?.belt?.chunks, wire.features.belt.chunks, byId) }
+        : base?.belt,
+      // Sent whole whenever it differs from the baseline.
+      inventory: wire.features.inventory ?? base?.inventory,
+      chest: wire.features.chest ?? base?.chest,
+      research: wire.features.research ?? base?.research,
+    },
+  };
 }
 
 function copyPreviews(previews: BuildPreview[]) {
@@ -118,9 +245,53 @@ function snapshotTime(snapshot: RoomSnapshot) {
   return snapshot.serverTime;
 }
 
+// The game client keeps its own copy of some entity sets, so only changes since
+// the last render frame are forwarded across the WASM bridge.
+class RenderedEntitySet<T extends { id: string }> {
+  private readonly rendered = new Map<string, T>();
+  private synced = false;
+
+  diff(entities: T[]) {
+    if (!this.synced) {
+      this.synced = true;
+      this.rendered.clear();
+      for (const entity of entities) {
+        this.rendered.set(entity.id, entity);
+      }
+      return { mode: 'full' as const, upserts: entities.slice(), removes: [] as string[] };
+    }
+
+    const upserts: T[] = [];
+    const seen = new Set<string>();
+    for (const entity of entities) {
+      seen.add(entity.id);
+      // Reconstruction reuses unchanged entity objects, so identity means "unchanged".
+      if (this.rendered.get(entity.id) !== entity) {
+        this.rendered.set(entity.id, entity);
+        upserts.push(entity);
+      }
+    }
+
+    const removes: string[] = [];
+    for (const id of this.rendered.keys()) {
+      if (!seen.has(id)) {
+        removes.push(id);
+      }
+    }
+    for (const id of removes) {
+      this.rendered.delete(id);
+    }
+
+    return { mode: 'delta' as const, upserts, removes };
+  }
+}
+
 export class ReplicationPipeline {
   private interpolationDelayMs: number;
   private readonly snapshots: RoomSnapshot[] = [];
+  private readonly baselines = new Map<number, RoomSnapshot>();
+  private readonly renderedStructures = new RenderedEntitySet<BuildStructure>();
+  private readonly renderedBeltChunks = new RenderedEntitySet<BeltChunk>();
   private clockOffsetMs = 0;
   private hasClockSync = false;
 
@@ -128,19 +299,34 @@ export class ReplicationPipeline {
     this.interpolationDelayMs = interpolationDelayMs;
   }
 
-  ingestSnapshot(snapshot: RoomSnapshot) {
+  /**
+   * Applies a wire snapshot on top of its acked baseline and buffers the result.
+   * Returns null when the baseline is no longer known; the caller should then
+   * request a full snapshot.
+   */
+  ingestSnapshot(wire: WireRoomSnapshot): RoomSnapshot | null {
+    if (wire.mode === 'full') {
+      this.baselines.clear();
+    }
+
+    const baseline =
+      wire.mode === 'delta' && wire.baselineId !== null
+        ? this.baselines.get(wire.baselineId)
+        : undefined;
+    if (wire.mode === 'delta' && !baseline) {
+      return null;
+    }
+
     const previous = this.snapshots[this.snapshots.length - 1];
-    const mergedSnapshot: RoomSnapshot = previous
-      ? {
-          ...snapshot,
-          features: {
-            presence: snapshot.features.presence ?? previous.features.presence,
-            movement: snapshot.features.movement ?? previous.features.movement,
-            build: snapshot.features.build ?? previous.features.build,
-            projectile: snapshot.features.projectile ?? previous.features.projectile,
-          },
-        }
-      : snapshot;
+    const snapshot = reconstructSnapshot(wire, baseline, previous);
+
+    this.baselines.set(snapshot.snapshotId, snapshot);
+    if (this.baselines.size > MAX_BASELINE_SNAPSHOTS) {
+      const oldest = this.baselines.keys().next().value;
+      if (oldest !== undefined) {
+        this.baselines.delete(oldest);
+      }
+    }
 
     const offsetSample = snapshot.serverTime - Date.now();
     if (!this.hasClockSync) {
@@ -150,13 +336,15 @@ export class ReplicationPipeline {
       this.clockOffsetMs = this.clockOffsetMs * 0.9 + offsetSample * 0.1;
     }
 
-    this.snapshots.push(mergedSnapshot);
+    this.snapshots.push(snapshot);
     this.snapshots.sort((a, b) => a.serverTick - b.serverTick);
 
     if (this.snapshots.length > MAX_BUFFERED_SNAPSHOTS) {
       const overflow = this.snapshots.length - MAX_BUFFERED_SNAPSHOTS;
       this.snapshots.splice(0, overflow);
     }
+
+    return snapshot;
   }
 
   setInterpolationDelayMs(delayMs: number) {
@@ -206,6 +394,8 @@ export class ReplicationPipeline {
     const newerMovement = newer.features.movement ?? latestMovement;
     const olderProjectiles = older.features.projectile?.projectiles ?? [];
     const newerProjectiles = newer.features.projectile?.projectiles ?? [];
+    const olderEnemies = older.features.enemy?.enemies ?? [];
+    const newerEnemies = newer.features.enemy?.enemies ?? [];
 
     const span = Math.max(1, snapshotTime(newer) - snapshotTime(older));
     const alphaRaw = (renderTargetTime - snapshotTime(older)) / span;
@@ -220,8 +410,13 @@ export class ReplicationPipeline {
     );
 
     const projectiles = interpolateProjectiles(olderProjectiles, newerProjectiles, alpha);
-    const structures = copyStructures(latest.features.build?.structures ?? []);
+    const enemies = interpolateEnemies(olderEnemies, newerEnemies, alpha);
+    const structureDelta = this.renderedStructures.diff(latest.features.build?.structures ?? []);
+    const beltDelta = this.renderedBeltChunks.diff(latest.features.belt?.chunks ?? []);
     const previews = copyPreviews(latest.features.build?.previews ?? []);
+    const playerHealth = (latest.features.health?.players ?? []).slice();
+    const structureHealth = (latest.features.health?.structures ?? []).slice();
+    const powerNetworks = (latest.features.power?.networks ?? []).slice();
 
     return {
       serverTick: latest.serverTick,
@@ -229,9 +424,19 @@ export class ReplicationPipeline {
       localAckSeq: latestMovement.inputAcks[localPlayerId] ?? 0,
       renderDelayMs: this.interpolationDelayMs,
       players,
-      structures,
+      buildMode: structureDelta.mode,
+      structures: structureDelta.upserts,
+      structureRemoves: structureDelta.removes,
+      beltMode: beltDelta.mode,
+      beltChunks: beltDelta.upserts,
+      beltChunkRemoves: beltDelta.removes,
       previews,
       projectiles,
+      enemies,
+      powerNetworks,
+      research: latest.features.research ?? null,
+      playerHealth,
+      structureHealth,
     };
   }
 }
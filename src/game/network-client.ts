@@ -1,27 +1,30 @@
+import { decodeServerEnvelope, encodeClientEnvelope } from './netcode/binary-protocol';
 import type {
   ClientCommandEnvelope,
+  Direction,
   InputCommand,
-  RoomSnapshot,
+  ProtocolVersion,
   ServerEnvelope,
   WelcomePayload,
+  WireRoomSnapshot,
 } from './types';
-import { PROTOCOL_VERSION } from './types';
+import { BINARY_PROTOCOL_VERSION, PROTOCOL_VERSION } from './types';
 
 type Handlers = {
   onWelcome: (payload: WelcomePayload) => void;
-  onSnapshot: (snapshot: RoomSnapshot) => void;
+  onSnapshot: (snapshot: WireRoomSnapshot) => void;
   onAck: (seq: number, feature: string, action: string) => void;
   onStatus: (status: string) => void;
   onEvent: (feature: string, action: string, payload: unknown) => void;
   onPong?: (latencyMs: number) => void;
 };
 
-function buildWebSocketUrl(roomCode: string, playerId: string) {
+function buildWebSocketUrl(roomCode: string, playerId: string, protocolVersion: ProtocolVersion) {
   const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
   const host = window.location.host;
   const encodedRoom = encodeURIComponent(roomCode);
   const encodedPlayer = encodeURIComponent(playerId);
-  return `${protocol}//${host}/api/rooms/${encodedRoom}/ws?playerId=${encodedPlayer}`;
+  return `${protocol}//${host}/api/rooms/${encodedRoom}/ws?playerId=${encodedPlayer}&protocol=${protocolVersion}`;
 }
 
 function appendResumeToken(url: string, resumeToken: string | null) {
@@ -104,10 +107,18 @@ function parseWelcomePayload(payload: unknown): WelcomePayload | null {
     simRateHz: payload.simRateHz,
     snapshotRateHz: payload.snapshotRateHz,
     resumeToken: typeof payload.resumeToken === 'string' ? payload.resumeToken : undefined,
+    protocol:
+      payload.protocol === PROTOCOL_VERSION || payload.protocol === BINARY_PROTOCOL_VERSION
+        ? payload.protocol
+        : undefined,
+    interestRadiusChunks:
+      typeof payload.interestRadiusChunks === 'number' ? payload.interestRadiusChunks : undefined,
+    chunkWorldSize: typeof payload.chunkWorldSize === 'number' ? payload.chunkWorldSize : undefined,
+    terrainSeed: typeof payload.terrainSeed === 'number' ? payload.terrainSeed : undefined,
   };
 }
 
-function parseRoomSnapshot(payload: unknown): RoomSnapshot | null {
+function parseRoomSnapshot(payload: unknown): WireRoomSnapshot | null {
   if (!isRecord(payload)) {
     return null;
   }
@@ -118,12 +129,14 @@ function parseRoomSnapshot(payload: unknown): RoomSnapshot | null {
     typeof payload.simRateHz !== 'number' ||
     typeof payload.snapshotRateHz !== 'number' ||
     typeof payload.serverTime !== 'number' ||
+    typeof payload.snapshotId !== 'number' ||
+    (payload.mode !== 'full' && payload.mode !== 'delta') ||
     !isRecord(payload.features)
   ) {
     return null;
   }
 
-  return payload as RoomSnapshot;
+  return payload as WireRoomSnapshot;
 }
 
 export class RoomSocket {
@@ -133,6 +146,7 @@ export class RoomSocket {
   private readonly handlers: Handlers;
   private readonly authToken: string | null;
   private readonly resumeToken: string | null;
+  private readonly protocol: ProtocolVersion;
   private seq = 1;
   private pingTimer: number | null = null;
   private pingSentAt = new Map<number, number>();
@@ -143,21 +157,24 @@ export class RoomSocket {
     handlers: Handlers,
     authToken: string | null = null,
     resumeToken: string | null = null,
+    protocol: ProtocolVersion = BINARY_PROTOCOL_VERSION,
   ) {
     this.roomCode = roomCode;
     this.playerId = playerId;
     this.handlers = handlers;
     this.authToken = authToken;
     this.resumeToken = resumeToken;
+    this.protocol = protocol;
   }
 
   async connect() {
-    const baseUrl = buildWebSocketUrl(this.roomCode, this.playerId);
+    const baseUrl = buildWebSocketUrl(this.roomCode, this.playerId, this.protocol);
     const withResume = appendResumeToken(baseUrl, this.resumeToken);
     const url = appendAuthToken(withResume, this.authToken);
     this.handlers.onStatus('Connecting...');
 
     this.socket = new WebSocket(url);
+    this.socket.binaryType = 'arraybuffer';
 
     this.socket.addEventListener('open', () => {
       this.handlers.onStatus('Connected');
@@ -165,11 +182,12 @@ export class RoomSocket {
     });
 
     this.socket.addEventListener('message', (event) => {
-      if (typeof event.data !== 'string') {
-        return;
-      }
-
-      const envelope = parseServerEnvelope(event.data);
+      const envelope =
+        typeof event.data === 'string'
+          ? parseServerEnvelope(event.data)
+          : event.data instanceof ArrayBuffer
+            ? decodeServerEnvelope(event.data)
+            : null;
       if (!envelope) {
         return;
       }
@@ -217,7 +235,9 @@ export class RoomSocket {
       }
 
       if (envelope.kind === 'error') {
-        this.handlers.onStatus(`Error: ${envelope.feature}.${envelope.action}`);
+        const payload = envelope.payload as { message?: unknown } | undefined;
+        const detail = typeof payload?.message === 'string' ? ` (${payload.message})` : '';
+        this.handlers.onStatus(`Error: ${envelope.feature}.${envelope.action}${detail}`);
       }
     });
 
@@ -232,11 +252,12 @@ export class RoomSocket {
     });
   }
 
-  sendBuildPlace(x: number, y: number, kind = 'beacon') {
+  sendBuildPlace(x: number, y: number, kind = 'beacon', direction?: Direction) {
     this.sendFeatureCommand('build', 'place', {
       x,
       y,
       kind,
+      direction,
       clientBuildId: `build_${crypto.randomUUID()}`,
     });
   }
@@ -253,6 +274,44 @@ export class RoomSocket {
     this.sendFeatureCommand('build', 'remove', { id });
   }
 
+  sendBuildSetRecipe(id: string, recipe: string | null) {
+    return this.sendFeatureCommand('build', 'set_recipe', { id, recipe });
+  }
+
+  sendBuildOpen(id: string) {
+    return this.sendFeatureCommand('build', 'open', { id });
+  }
+
+  sendBuildClose() {
+    return this.sendFeatureCommand('build', 'close');
+  }
+
+  // Without a count one full stack of `item` is moved.
+  sendBuildTransfer(id: string, item: string, to: 'chest' | 'player', count?: number) {
+    return this.sendFeatureCommand('build', 'transfer', { id, item, to, count });
+  }
+
+  // `null` stops research.
+  sendResearchSelect(techId: string | null) {
+    return this.sendFeatureCommand('research', 'select', { techId });
+  }
+
+  // Without `playerId` this joins the team ourselves: a new team, or one whose
+  // owner invited us. With it, a team owner invites that player and a room
+  // admin puts them on the team directly.
+  sendTeamJoin(teamId: string, playerId?: string) {
+    return this.sendFeatureCommand('team', 'join', { teamId, playerId });
+  }
+
+  sendTeamLeave() {
+    return this.sendFeatureCommand('team', 'leave');
+  }
+
+  // Acknowledging snapshot 0 asks the server to drop our baseline and resend full state.
+  sendSnapshotAck(snapshotId: number) {
+    return this.sendFeatureCommand('core', 'snapshot_ack', { snapshotId });
+  }
+
   sendCorePing() {
     return this.sendFeatureCommand('core', 'ping', null);
   }
@@ -300,7 +359,7 @@ export class RoomSocket {
 
     const currentSeq = this.seq;
     const envelope: ClientCommandEnvelope = {
-      v: PROTOCOL_VERSION,
+      v: this.protocol,
       kind: 'command',
       seq: currentSeq,
       feature: params.feature,
@@ -310,7 +369,11 @@ export class RoomSocket {
     };
 
     this.seq += 1;
-    this.socket.send(JSON.stringify(envelope));
+    this.socket.send(
+      this.protocol === BINARY_PROTOCOL_VERSION
+        ? encodeClientEnvelope(envelope)
+        : JSON.stringify(envelope),
+    );
     return currentSeq;
   }
 
@@ -1,4 +1,8 @@
 export const PROTOCOL_VERSION = 2 as const;
+export const BINARY_PROTOCOL_VERSION = 3 as const;
+
+// v2 is JSON text frames (handy for debugging), v3 is the compact binary framing.
+export type ProtocolVersion = typeof PROTOCOL_VERSION | typeof BINARY_PROTOCOL_VERSION;
 
 export type PlayerState = {
   id: string;
@@ -7,6 +11,71 @@ export type PlayerState = {
   vx: number;
   vy: number;
   connected: boolean;
+  weapon?: string;
+  // Only sent for the receiving player.
+  stamina?: number;
+  exhausted?: boolean;
+};
+
+export type MinerStatus = {
+  item: string | null;
+  output: number;
+  capacity: number;
+  working: boolean;
+};
+
+export type ItemCount = {
+  item: string;
+  count: number;
+};
+
+export type AssemblerStatus = {
+  recipe: string | null;
+  inputs: ItemCount[];
+  outputs: ItemCount[];
+  progress: number;
+  crafting: boolean;
+};
+
+export type Direction = 'north' | 'east' | 'south' | 'west';
+
+export type InserterPhase = 'idle' | 'extending' | 'retracting';
+
+export type InserterStatus = {
+  held: string | null;
+  phase: InserterPhase;
+};
+
+// Power network a pole, generator or consumer belongs to; `null` when no pole reaches it.
+export type PowerStatus = {
+  network: string | null;
+};
+
+export type PowerNetworkState = {
+  id: string;
+  supplyKw: number;
+  demandKw: number;
+  // Share of demand that is met, 0..1; consumers run at this speed.
+  satisfaction: number;
+};
+
+export type PowerSnapshot = {
+  networks: PowerNetworkState[];
+};
+
+export type LabStatus = {
+  packs: number;
+  capacity: number;
+  researching: boolean;
+};
+
+// Room-wide research. Technology names, costs and unlocks are compiled into the
+// game client from sim-core.
+export type ResearchSnapshot = {
+  current: string | null;
+  // Science packs spent per unfinished technology.
+  progress: Record<string, number>;
+  unlocked: string[];
 };
 
 export type BuildStructure = {
@@ -15,6 +84,13 @@ export type BuildStructure = {
   y: number;
   kind: string;
   ownerId: string;
+  // Rotation; belts also carry items this way and inserters drop this way.
+  direction: Direction;
+  miner?: MinerStatus;
+  assembler?: AssemblerStatus;
+  inserter?: InserterStatus;
+  power?: PowerStatus;
+  lab?: LabStatus;
 };
 
 export type BuildPreview = {
@@ -22,11 +98,52 @@ export type BuildPreview = {
   x: number;
   y: number;
   kind: string;
+  direction: Direction;
+};
+
+export type RoomRole = 'admin' | 'member';
+
+export type RoomMember = {
+  playerId: string;
+  role: RoomRole;
+  teamId: string | null;
 };
 
 export type PresenceSnapshot = {
   online: string[];
   onlineCount: number;
+  members?: RoomMember[];
+};
+
+export type BuildPlaceRejectCode =
+  | 'invalid_payload'
+  | 'invalid_kind'
+  | 'not_researched'
+  | 'blocked'
+  | 'needs_ore'
+  | 'id_taken'
+  | 'structure_limit'
+  | 'not_enough_materials'
+  | 'storage_failed';
+
+export type BuildPlaceRejectedPayload = {
+  code: BuildPlaceRejectCode;
+  message: string;
+};
+
+export type BuildRemoveRejectCode =
+  | 'invalid_payload'
+  | 'not_found'
+  | 'not_permitted'
+  | 'out_of_range'
+  | 'rate_limited'
+  | 'inventory_full'
+  | 'storage_failed';
+
+export type BuildRemoveRejectedPayload = {
+  code: BuildRemoveRejectCode;
+  message: string;
+  structureId: string | null;
 };
 
 export type InputAckMap = Record<string, number>;
@@ -54,22 +171,140 @@ export type ProjectileState = {
   clientProjectileId: string | null;
 };
 
+export type ProjectileHitEvent = {
+  projectileId: string;
+  ownerId: string;
+  clientProjectileId: string | null;
+  targetKind: 'player' | 'structure' | 'enemy';
+  targetId: string;
+  x: number;
+  y: number;
+};
+
 export type ProjectileSnapshot = {
   projectiles: ProjectileState[];
   projectileCount: number;
 };
 
+export type EnemyState = {
+  id: string;
+  x: number;
+  y: number;
+  vx: number;
+  vy: number;
+  hp: number;
+  maxHp: number;
+};
+
+export type EnemySnapshot = {
+  enemies: EnemyState[];
+  enemyCount: number;
+};
+
+export type PlayerHealth = {
+  id: string;
+  hp: number;
+  maxHp: number;
+  dead: boolean;
+  respawnInMs: number;
+  invulnerable: boolean;
+};
+
+export type StructureHealth = {
+  id: string;
+  hp: number;
+  maxHp: number;
+};
+
+export type HealthSnapshot = {
+  players: PlayerHealth[];
+  structures: StructureHealth[];
+};
+
+// Items on the belts of one build chunk. `items` is a flat list of
+// [cell, lane, position, paletteIndex] quadruples, where cell is
+// localY * 32 + localX and paletteIndex points into `palette`.
+export type BeltChunk = {
+  id: string;
+  chunkX: number;
+  chunkY: number;
+  palette: string[];
+  items: number[];
+};
+
+export type BeltSnapshot = {
+  chunks: BeltChunk[];
+};
+
+// The viewer's own inventory; never sent to other players.
+export type InventorySnapshot = {
+  slots: (ItemCount | null)[];
+};
+
+// Contents of the chest the viewer has open; only sent to players who opened it.
+export type OpenChest = InventorySnapshot & {
+  id: string;
+};
+
+export type ChestSnapshot = {
+  open: OpenChest | null;
+};
+
+export type SnapshotMode = 'full' | 'delta';
+
+// Reconstructed room state for one snapshot, after applying deltas to the acked baseline.
 export type RoomSnapshot = {
   roomCode: string;
   serverTick: number;
   simRateHz: number;
   snapshotRateHz: number;
   serverTime: number;
+  snapshotId: number;
   features: {
     presence?: PresenceSnapshot;
     movement?: MovementSnapshot;
     build?: BuildSnapshot;
     projectile?: ProjectileSnapshot;
+    enemy?: EnemySnapshot;
+    power?: PowerSnapshot;
+    health?: HealthSnapshot;
+    belt?: BeltSnapshot;
+    inventory?: InventorySnapshot;
+    chest?: ChestSnapshot;
+    research?: ResearchSnapshot;
+  };
+};
+
+export type EntityDelta<T> = {
+  upserts: T[];
+  removes: string[];
+};
+
+// Snapshot as sent by the server. In `full` mode `upserts` hold every entity and
+// `baselineId` is null; in `delta` mode they only hold changes against `baselineId`.
+export type WireRoomSnapshot = Omit<RoomSnapshot, 'features'> & {
+  mode: SnapshotMode;
+  baselineId: number | null;
+  features: {
+    presence?: PresenceSnapshot;
+    movement?: Omit<MovementSnapshot, 'players'> & { players: EntityDelta<PlayerState> };
+    build?: Omit<BuildSnapshot, 'structures' | 'previews'> & {
+      structures: EntityDelta<BuildStructure>;
+      previews: EntityDelta<BuildPreview>;
+    };
+    projectile?: Omit<ProjectileSnapshot, 'projectiles'> & {
+      projectiles: EntityDelta<ProjectileState>;
+    };
+    enemy?: Omit<EnemySnapshot, 'enemies'> & { enemies: EntityDelta<EnemyState> };
+    power?: { networks: EntityDelta<PowerNetworkState> };
+    health?: {
+      players: EntityDelta<PlayerHealth>;
+      structures: EntityDelta<StructureHealth>;
+    };
+    belt?: { chunks: EntityDelta<BeltChunk> };
+    inventory?: InventorySnapshot;
+    chest?: ChestSnapshot;
+    research?: ResearchSnapshot;
   };
 };
 
@@ -79,10 +314,27 @@ export type WelcomePayload = {
   simRateHz: number;
   snapshotRateHz: number;
   resumeToken?: string;
+  protocol?: ProtocolVersion;
+  interestRadiusChunks?: number;
+  chunkWorldSize?: number;
+  terrainSeed?: number;
+};
+
+export type InterestEntitySet = {
+  players: string[];
+  structures: string[];
+};
+
+export type InterestUpdateEvent = {
+  chunkX: number;
+  chunkY: number;
+  radius: number;
+  entered: InterestEntitySet;
+  left: InterestEntitySet;
 };
 
 export type ServerEnvelope = {
-  v: typeof PROTOCOL_VERSION;
+  v: ProtocolVersion;
   kind: 'welcome' | 'ack' | 'snapshot' | 'event' | 'error' | 'pong';
   tick: number;
   serverTime: number;
@@ -93,7 +345,7 @@ export type ServerEnvelope = {
 };
 
 export type ClientCommandEnvelope = {
-  v: typeof PROTOCOL_VERSION;
+  v: ProtocolVersion;
   kind: 'command';
   seq: number;
   feature: string;
@@ -107,6 +359,10 @@ export type InputState = {
   down: boolean;
   left: boolean;
   right: boolean;
+  // Quantized analog stick, -127..127 per axis; overrides the flags when non-zero.
+  moveX?: number;
+  moveY?: number;
+  sprint?: boolean;
 };
 
 export type InputCommand = InputState & {
@@ -125,7 +381,19 @@ export type RenderSnapshotPayload = {
   localAckSeq: number;
   renderDelayMs: number;
   players: PlayerState[];
+  // `full` replaces the client's structure set; `delta` applies upserts and removes.
+  buildMode: SnapshotMode;
   structures: BuildStructure[];
+  structureRemoves: string[];
+  // Same semantics as `buildMode`, for belt chunks.
+  beltMode: SnapshotMode;
+  beltChunks: BeltChunk[];
+  beltChunkRemoves: string[];
   previews: BuildPreview[];
   projectiles: ProjectileState[];
+  enemies: EnemyState[];
+  powerNetworks: PowerNetworkState[];
+  research: ResearchSnapshot | null;
+  playerHealth: PlayerHealth[];
+  structureHealth: StructureHealth[];
 };
@@ -5,13 +5,22 @@ import {
   bootGame,
   drainFeatureCommands,
   drainInputCommands,
+  pollGamepad,
   pushRenderSnapshot,
   resetSessionState,
   setPlayerId,
+  setTerrainSeed,
 } from '../game/bridge';
 import { RoomSocket } from '../game/network-client';
 import { ReplicationPipeline } from '../game/netcode/replication';
-import type { PlayerState, RoomSnapshot } from '../game/types';
+import type {
+  InventorySnapshot,
+  ItemCount,
+  PlayerState,
+  ProtocolVersion,
+  WireRoomSnapshot,
+} from '../game/types';
+import { BINARY_PROTOCOL_VERSION, PROTOCOL_VERSION } from '../game/types';
 
 const CANVAS_ID = 'bevy-game-canvas';
 const DEFAULT_INTERP_DELAY_MS = 110;
@@ -56,6 +65,12 @@ function getPersistentCanvas() {
   return persistentCanvas;
 }
 
+// `?protocol=2` keeps the readable JSON protocol for debugging; binary v3 otherwise.
+function preferredProtocolVersion(): ProtocolVersion {
+  const requested = new URLSearchParams(window.location.search).get('protocol');
+  return requested === String(PROTOCOL_VERSION) ? PROTOCOL_VERSION : BINARY_PROTOCOL_VERSION;
+}
+
 function resumeTokenStorageKey(roomCode: string, playerId: string) {
   return `ralph-resume:${roomCode}:${playerId}`;
 }
@@ -84,6 +99,42 @@ function localPlayerPosition(players: PlayerState[], playerId: string) {
   };
 }
 
+// Per-item totals across inventory slots, in first-seen slot order.
+function inventoryTotals(inventory: InventorySnapshot): ItemCount[] {
+  const totals = new Map<string, number>();
+  for (const slot of inventory.slots) {
+    if (slot) {
+      totals.set(slot.item, (totals.get(slot.item) ?? 0) + slot.count);
+    }
+  }
+
+  return Array.from(totals, ([item, count]) => ({ item, count }));
+}
+
+// Item pills; with `onPick` each one is a button.
+function ItemPills({
+  items,
+  onPick,
+}: {
+  items: ItemCount[];
+  onPick?: (item: string) => void;
+}) {
+  return (
+    <>
+      {items.map((entry) => {
+        const pill = <MetricPill label={entry.item.replace(/_/g, ' ')} value={entry.count} />;
+        return onPick ? (
+          <button key={entry.item} type="button" onClick={() => onPick(entry.item)}>
+            {pill}
+          </button>
+        ) : (
+          <span key={entry.item}>{pill}</span>
+        );
+      })}
+    </>
+  );
+}
+
 function MetricPill({ label, value }: { label: string; value: string | number }) {
   return (
     <span className="hud-pill hud-metric">
@@ -117,6 +168,9 @@ export function RoomRoute() {
   const [latencyMs, setLatencyMs] = useState(0);
   const [structureCount, setStructureCount] = useState(0);
   const [projectileCount, setProjectileCount] = useState(0);
+  const [localHp, setLocalHp] = useState<string>('-');
+  const [inventoryItems, setInventoryItems] = useState<ItemCount[]>([]);
+  const [openChest, setOpenChest] = useState<{ id: string; items: ItemCount[] } | null>(null);
   const [showDevConsole, setShowDevConsole] = useState(false);
   const [devInput, setDevInput] = useState('');
   const [devLog, setDevLog] = useState<string[]>([]);
@@ -187,6 +241,9 @@ export function RoomRoute() {
             setSnapshotRateHz(payload.snapshotRateHz);
             setConnectionStatus(`Connected to ${payload.roomCode}`);
             void setPlayerId(payload.playerId);
+            if (payload.terrainSeed !== undefined) {
+              void setTerrainSeed(payload.terrainSeed);
+            }
 
             if (payload.resumeToken) {
               window.localStorage.setItem(
@@ -195,8 +252,14 @@ export function RoomRoute() {
               );
             }
           },
-          onSnapshot: (snapshot: RoomSnapshot) => {
-            replicationRef.current.ingestSnapshot(snapshot);
+          onSnapshot: (wire: WireRoomSnapshot) => {
+            const snapshot = replicationRef.current.ingestSnapshot(wire);
+            if (!snapshot) {
+              // Baseline already evicted locally: ask for a full resync.
+              socketRef.current?.sendSnapshotAck(0);
+              return;
+            }
+            socketRef.current?.sendSnapshotAck(snapshot.snapshotId);
 
             setServerTick(snapshot.serverTick);
             setSimRateHz(snapshot.simRateHz);
@@ -210,6 +273,7 @@ export function RoomRoute() {
             const movement = snapshot.features.movement;
             const build = snapshot.features.build;
             const projectile = snapshot.features.projectile;
+            const health = snapshot.features.health;
 
             if (movement) {
               localPosRef.current = localPlayerPosition(movement.players, clientPlayerId);
@@ -222,6 +286,25 @@ export function RoomRoute() {
             if (projectile) {
               setProjectileCount(projectile.projectileCount);
             }
+
+            if (health) {
+              const local = health.players.find((entry) => entry.id === clientPlayerId);
+              if (local) {
+                setLocalHp(local.dead ? 'dead' : `${local.hp}/${local.maxHp}`);
+              }
+            }
+
+            const inventory = snapshot.features.inventory;
+            if (inventory) {
+              setInventoryItems(inventoryTotals(inventory));
+            }
+
+            const chest = snapshot.features.chest;
+            if (chest) {
+              setOpenChest(
+                chest.open ? { id: chest.open.id, items: inventoryTotals(chest.open) } : null,
+              );
+            }
           },
           onAck: (seq) => {
             setLastAckSeq((prev) => Math.max(prev, seq));
@@ -235,12 +318,14 @@ export function RoomRoute() {
         },
         clerkToken ?? null,
         storedResumeToken,
+        preferredProtocolVersion(),
       );
 
       await socket.connect();
       socketRef.current = socket;
 
       inputPump = window.setInterval(() => {
+        void pollGamepad();
         void drainInputCommands().then((commands) => {
           socket.sendInputCommands(commands);
         });
@@ -414,6 +499,7 @@ export function RoomRoute() {
           <MetricPill label="Online" value={activePlayers} />
           <MetricPill label="Structures" value={structureCount} />
           <MetricPill label="Projectiles" value={projectileCount} />
+          <MetricPill label="HP" value={localHp} />
           <span className="hidden rounded-md border border-white/15 bg-[#101b31] px-3 py-1.5 text-[#cfddf9] md:inline-flex">
             {playerLabel}
           </span>
@@ -426,6 +512,32 @@ export function RoomRoute() {
       <div className="min-h-0 p-2 md:p-3">
         <div className="relative h-full w-full overflow-hidden rounded-2xl border border-white/10 bg-[#060c16] shadow-[0_20px_80px_rgba(9,14,24,0.6)]">
           <div ref={canvasHostRef} className="absolute inset-0" />
+          {inventoryItems.length > 0 ? (
+            <div
+              className={`${openChest ? 'pointer-events-auto' : 'pointer-events-none'} absolute left-3 top-3 z-10 flex max-w-[60%] flex-wrap gap-2 text-xs`}
+            >
+              {/* With a chest open, clicking an item stores a stack of it. */}
+              <ItemPills
+                items={inventoryItems}
+                onPick={
+                  openChest
+                    ? (item) => socketRef.current?.sendBuildTransfer(openChest.id, item, 'chest')
+                    : undefined
+                }
+              />
+            </div>
+          ) : null}
+          {openChest ? (
+            <div className="absolute right-3 top-3 z-10 flex max-w-[35%] flex-wrap justify-end gap-2 text-xs">
+              <button type="button" onClick={() => socketRef.current?.sendBuildClose()}>
+                <MetricPill label="chest" value="close" />
+              </button>
+              <ItemPills
+                items={openChest.items}
+                onPick={(item) => socketRef.current?.sendBuildTransfer(openChest.id, item, 'player')}
+              />
+            </div>
+          ) : null}
           {showDevConsole ? (
             <div className="absolute inset-x-3 bottom-3 z-20 rounded-xl border border-[#6de7c0]/60 bg-[#071520]/88 p-3 backdrop-blur">
               <div className="mb-2 flex items-center justify-between text-[11px] uppercase tracking-[0.16em] text-[#b8ffe8]">
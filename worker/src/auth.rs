@@ -0,0 +1,459 @@
+//! Clerk session token verification.
+//!
+//! Tokens are RS256 JWTs checked locally against a JWKS. Where the keys come
+//! from is decided by `JwksSource::from_env`:
+//!
+//! - `CLERK_JWKS`: an inline JWKS document (a local fixture for tests/offline dev)
+//! - `CLERK_JWKS_URL` / `CLERK_SECRET_KEY`: fetched from Clerk and cached in
+//!   Durable Object storage, refreshed hourly or when an unknown `kid` shows up
+//!
+//! With none of these configured the room runs in permissive `playerId` mode.
+//! Once a key source is set, `CLERK_AUTHORIZED_PARTIES` must be too: a token
+//! is only accepted if its `azp` is listed there.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use worker::durable::Storage;
+use worker::*;
+
+use crate::{now_ms, now_seconds, parse_query_param, random_player_id, sanitize_player_id};
+
+const CLERK_JWKS_URL: &str = "https://api.clerk.com/v1/jwks";
+const JWKS_CACHE_KEY: &str = "auth:jwks";
+const JWKS_CACHE_TTL_MS: i64 = 60 * 60 * 1000;
+// Unknown `kid`s trigger a refetch, but never more often than this.
+const JWKS_REFETCH_MIN_INTERVAL_MS: i64 = 60 * 1000;
+const JWT_CLOCK_SKEW_SECONDS: i64 = 5;
+
+#[derive(Debug, Clone, Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct JwtClaims {
+    pub(crate) sub: String,
+    exp: Option<i64>,
+    nbf: Option<i64>,
+    azp: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Jwk {
+    kid: Option<String>,
+    kty: String,
+    alg: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Jwks {
+    keys: Vec<Jwk>,
+}
+
+impl Jwks {
+    fn find(&self, kid: &str) -> Option<&Jwk> {
+        self.keys.iter().find(|key| key.kid.as_deref() == Some(kid))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedJwks {
+    fetched_at: i64,
+    jwks: Jwks,
+}
+
+/// Where verification keys come from.
+pub(crate) enum JwksSource<'a> {
+    Static(Jwks),
+    Remote {
+        storage: &'a Storage,
+        url: String,
+        secret_key: Option<String>,
+    },
+}
+
+fn env_string(env: &Env, name: &str) -> Option<String> {
+    env.secret(name)
+        .or_else(|_| env.var(name))
+        .ok()
+        .map(|value| value.to_string())
+        .filter(|value| !value.trim().is_empty())
+}
+
+impl<'a> JwksSource<'a> {
+    pub(crate) fn from_env(env: &Env, storage: &'a Storage) -> Result<Option<Self>> {
+        if let Some(raw) = env_string(env, "CLERK_JWKS") {
+            let jwks = serde_json::from_str::<Jwks>(&raw)
+                .map_err(|_| Error::RustError("invalid CLERK_JWKS document".into()))?;
+            return Ok(Some(Self::Static(jwks)));
+        }
+
+        let secret_key = env_string(env, "CLERK_SECRET_KEY");
+        match env_string(env, "CLERK_JWKS_URL") {
+            Some(url) => Ok(Some(Self::Remote {
+                storage,
+                url,
+                secret_key: None,
+            })),
+            None => Ok(secret_key.map(|secret_key| Self::Remote {
+                storage,
+                url: CLERK_JWKS_URL.to_string(),
+                secret_key: Some(secret_key),
+            })),
+        }
+    }
+
+    async fn find_key(&self, kid: &str) -> Result<Jwk> {
+        let (storage, url, secret_key) = match self {
+            Self::Static(jwks) => {
+                return jwks
+                    .find(kid)
+                    .cloned()
+                    .ok_or_else(|| Error::RustError("unknown token key id".into()));
+            }
+            Self::Remote {
+                storage,
+                url,
+                secret_key,
+            } => (*storage, url, secret_key),
+        };
+
+        let now = now_ms();
+        let cached = storage.get::<CachedJwks>(JWKS_CACHE_KEY).await?;
+        if let Some(cached) = cached.as_ref() {
+            let fresh = now - cached.fetched_at < JWKS_CACHE_TTL_MS;
+            let recently_fetched = now - cached.fetched_at < JWKS_REFETCH_MIN_INTERVAL_MS;
+            match cached.jwks.find(kid) {
+                Some(key) if fresh => return Ok(key.clone()),
+                None if recently_fetched => {
+                    return Err(Error::RustError("unknown token key id".into()));
+                }
+                _ => {}
+            }
+        }
+
+        match fetch_jwks(url, secret_key.as_deref()).await {
+            Ok(jwks) => {
+                let key = jwks.find(kid).cloned();
+                storage
+                    .put(
+                        JWKS_CACHE_KEY,
+                        CachedJwks {
+                            fetched_at: now,
+                            jwks,
+                        },
+                    )
+                    .await?;
+                key.ok_or_else(|| Error::RustError("unknown token key id".into()))
+            }
+            // Clerk being unreachable should not lock out players whose key we already know.
+            Err(error) => cached
+                .and_then(|cached| cached.jwks.find(kid).cloned())
+                .ok_or(error),
+        }
+    }
+}
+
+async fn fetch_jwks(url: &str, secret_key: Option<&str>) -> Result<Jwks> {
+    let headers = Headers::new();
+    if let Some(secret_key) = secret_key {
+        headers.set("authorization", &format!("Bearer {secret_key}"))?;
+    }
+
+    let mut init = RequestInit::new();
+    init.with_method(Method::Get);
+    init.with_headers(headers);
+
+    let request = Request::new_with_init(url, &init)?;
+    let mut response = Fetch::Request(request).send().await?;
+    if response.status_code() != 200 {
+        return Err(Error::RustError("JWKS fetch failed".into()));
+    }
+
+    response.json().await
+}
+
+fn decode_segment(segment: &str, what: &str) -> Result<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(segment)
+        .map_err(|_| Error::RustError(format!("invalid JWT {what} encoding")))
+}
+
+fn rsa_public_key(jwk: &Jwk) -> Result<RsaPublicKey> {
+    if jwk.kty != "RSA" || jwk.alg.as_deref().is_some_and(|alg| alg != "RS256") {
+        return Err(Error::RustError("token key is not an RS256 key".into()));
+    }
+
+    let (Some(n), Some(e)) = (jwk.n.as_deref(), jwk.e.as_deref()) else {
+        return Err(Error::RustError("token key is missing its modulus".into()));
+    };
+    RsaPublicKey::new(
+        BigUint::from_bytes_be(&decode_segment(n, "key modulus")?),
+        BigUint::from_bytes_be(&decode_segment(e, "key exponent")?),
+    )
+    .map_err(|_| Error::RustError("invalid token key".into()))
+}
+
+fn validate_claims(claims: &JwtClaims, now: i64, authorized_parties: &[String]) -> Result<()> {
+    let exp = claims
+        .exp
+        .ok_or_else(|| Error::RustError("token missing exp claim".into()))?;
+    if exp + JWT_CLOCK_SKEW_SECONDS <= now {
+        return Err(Error::RustError("token expired".into()));
+    }
+
+    if claims
+        .nbf
+        .is_some_and(|nbf| nbf - JWT_CLOCK_SKEW_SECONDS > now)
+    {
+        return Err(Error::RustError("token not yet valid".into()));
+    }
+
+    if !claims
+        .azp
+        .as_ref()
+        .is_some_and(|azp| authorized_parties.contains(azp))
+    {
+        return Err(Error::RustError(
+            "token azp is not an authorized party".into(),
+        ));
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn verify_jwt(
+    token: &str,
+    source: &JwksSource<'_>,
+    authorized_parties: &[String],
+    now: i64,
+) -> Result<JwtClaims> {
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 {
+        return Err(Error::RustError("invalid JWT token format".into()));
+    }
+
+    let header: JwtHeader = serde_json::from_slice(&decode_segment(parts[0], "header")?)
+        .map_err(|_| Error::RustError("invalid JWT header".into()))?;
+    if header.alg != "RS256" {
+        return Err(Error::RustError("unsupported JWT algorithm".into()));
+    }
+    let kid = header
+        .kid
+        .ok_or_else(|| Error::RustError("token missing kid".into()))?;
+
+    let public_key = rsa_public_key(&source.find_key(&kid).await?)?;
+    let signed = &token[..parts[0].len() + 1 + parts[1].len()];
+    let digest = Sha256::digest(signed.as_bytes());
+    public_key
+        .verify(
+            Pkcs1v15Sign::new::<Sha256>(),
+            &digest,
+            &decode_segment(parts[2], "signature")?,
+        )
+        .map_err(|_| Error::RustError("invalid token signature".into()))?;
+
+    let claims: JwtClaims = serde_json::from_slice(&decode_segment(parts[1], "payload")?)
+        .map_err(|_| Error::RustError("invalid JWT payload".into()))?;
+    validate_claims(&claims, now, authorized_parties)?;
+
+    Ok(claims)
+}
+
+/// Comma-separated origins from `CLERK_AUTHORIZED_PARTIES`. A token's `azp` must be one of them.
+fn authorized_parties_from_env(env: &Env) -> Vec<String> {
+    env_string(env, "CLERK_AUTHORIZED_PARTIES")
+        .map(|raw| {
+            raw.split(',')
+                .map(|party| party.trim().trim_end_matches('/').to_string())
+                .filter(|party| !party.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) async fn authenticate_player(url: &Url, env: &Env, storage: &Storage) -> Result<String> {
+    let player_id_from_query = parse_query_param(url, "playerId")
+        .as_deref()
+        .and_then(sanitize_player_id);
+
+    let Some(source) = JwksSource::from_env(env, storage)? else {
+        return Ok(player_id_from_query.unwrap_or_else(random_player_id));
+    };
+
+    // Without it any Clerk app's token would be accepted, so refuse to verify at all.
+    let authorized_parties = authorized_parties_from_env(env);
+    if authorized_parties.is_empty() {
+        console_error!("CLERK_AUTHORIZED_PARTIES is not set; rejecting every Clerk token");
+        return Err(Error::RustError(
+            "token verification is misconfigured".into(),
+        ));
+    }
+
+    let token = parse_query_param(url, "token")
+        .ok_or_else(|| Error::RustError("missing Clerk token".into()))?;
+    let claims = verify_jwt(&token, &source, &authorized_parties, now_seconds()).await?;
+
+    let player_id = player_id_from_query.unwrap_or_else(|| claims.sub.clone());
+    let player_id = sanitize_player_id(&player_id)
+        .ok_or_else(|| Error::RustError("invalid player id".into()))?;
+
+    if claims.sub != player_id {
+        return Err(Error::RustError(
+            "token subject does not match player id".into(),
+        ));
+    }
+
+    Ok(player_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+    use rsa::traits::PublicKeyParts;
+    use rsa::RsaPrivateKey;
+
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+    const APP: &str = "http://localhost:5173";
+
+    // Throwaway key generated for these tests; nothing trusts it.
+    fn private_key() -> RsaPrivateKey {
+        RsaPrivateKey::from_pkcs1_pem(include_str!("../fixtures/test-rsa-key.pem")).unwrap()
+    }
+
+    fn source(key: &RsaPrivateKey) -> JwksSource<'static> {
+        JwksSource::Static(Jwks {
+            keys: vec![Jwk {
+                kid: Some("test-key".into()),
+                kty: "RSA".into(),
+                alg: Some("RS256".into()),
+                n: Some(URL_SAFE_NO_PAD.encode(key.n().to_bytes_be())),
+                e: Some(URL_SAFE_NO_PAD.encode(key.e().to_bytes_be())),
+            }],
+        })
+    }
+
+    fn sign(key: &RsaPrivateKey, kid: &str, claims: serde_json::Value) -> String {
+        let header = serde_json::json!({ "alg": "RS256", "kid": kid });
+        let signed = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature = key
+            .sign(
+                Pkcs1v15Sign::new::<Sha256>(),
+                &Sha256::digest(signed.as_bytes()),
+            )
+            .unwrap();
+        format!("{signed}.{}", URL_SAFE_NO_PAD.encode(signature))
+    }
+
+    // A static key source never has to wait, so one poll settles it.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("static JWKS lookups do not suspend"),
+        }
+    }
+
+    fn verify(token: &str, source: &JwksSource<'_>, parties: &[&str]) -> Result<JwtClaims> {
+        let parties: Vec<String> = parties.iter().map(|party| party.to_string()).collect();
+        block_on(verify_jwt(token, source, &parties, NOW))
+    }
+
+    fn error(result: Result<JwtClaims>) -> String {
+        match result {
+            Ok(claims) => panic!("accepted token for {}", claims.sub),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn exp_and_nbf_allow_five_seconds_of_skew() {
+        let key = private_key();
+        let source = source(&key);
+        let claims = |exp: i64, nbf: i64| serde_json::json!({ "sub": "user_1", "exp": exp, "nbf": nbf, "azp": APP });
+
+        let token = sign(&key, "test-key", claims(NOW - 4, NOW + 5));
+        assert_eq!(verify(&token, &source, &[APP]).unwrap().sub, "user_1");
+
+        let expired = sign(&key, "test-key", claims(NOW - 5, NOW));
+        assert!(error(verify(&expired, &source, &[APP])).contains("expired"));
+
+        let early = sign(&key, "test-key", claims(NOW + 60, NOW + 6));
+        assert!(error(verify(&early, &source, &[APP])).contains("not yet valid"));
+
+        let no_exp = sign(
+            &key,
+            "test-key",
+            serde_json::json!({ "sub": "user_1", "azp": APP }),
+        );
+        assert!(error(verify(&no_exp, &source, &[APP])).contains("missing exp"));
+    }
+
+    #[test]
+    fn azp_must_be_an_authorized_party() {
+        let key = private_key();
+        let source = source(&key);
+        let token = |azp: Option<&str>| {
+            let mut claims = serde_json::json!({ "sub": "user_1", "exp": NOW + 60 });
+            if let Some(azp) = azp {
+                claims["azp"] = azp.into();
+            }
+            sign(&key, "test-key", claims)
+        };
+
+        assert!(verify(&token(Some(APP)), &source, &["https://other.example", APP]).is_ok());
+        for (azp, parties) in [
+            (Some("https://evil.example"), &[APP][..]),
+            (None, &[APP][..]),
+            (Some(APP), &[][..]),
+        ] {
+            assert!(error(verify(&token(azp), &source, parties)).contains("authorized party"));
+        }
+    }
+
+    #[test]
+    fn wrong_kid_and_bad_signatures_are_rejected() {
+        let key = private_key();
+        let source = source(&key);
+        let claims = serde_json::json!({ "sub": "user_1", "exp": NOW + 60, "azp": APP });
+
+        let unknown_kid = sign(&key, "rotated-key", claims.clone());
+        assert!(error(verify(&unknown_kid, &source, &[APP])).contains("unknown token key id"));
+
+        // Same header and signature, but the payload now claims another user.
+        let token = sign(&key, "test-key", claims);
+        let mut parts: Vec<String> = token.split('.').map(str::to_string).collect();
+        parts[1] = URL_SAFE_NO_PAD.encode(
+            serde_json::json!({ "sub": "user_2", "exp": NOW + 60, "azp": APP }).to_string(),
+        );
+        assert!(
+            error(verify(&parts.join("."), &source, &[APP])).contains("invalid token signature")
+        );
+
+        let mut signature = URL_SAFE_NO_PAD
+            .decode(token.rsplit('.').next().unwrap())
+            .unwrap();
+        signature[0] ^= 1;
+        parts[1] = token.split('.').nth(1).unwrap().to_string();
+        parts[2] = URL_SAFE_NO_PAD.encode(signature);
+        assert!(
+            error(verify(&parts.join("."), &source, &[APP])).contains("invalid token signature")
+        );
+    }
+}
@@ -0,0 +1,863 @@
+//! Protocol v3: compact binary framing for websocket envelopes.
+//!
+//! Layout (all integers are unsigned LEB128 varints unless noted):
+//!
+//! - client command: `u8 version`, `u8 kind`, `seq`, `f64 clientTime` (LE), route, `u8 flags`, payload
+//! - server envelope: `u8 version`, `u8 kind`, `tick`, `serverTime`, route, `u8 flags`, `[seq]`, `[payload]`
+//!
+//! A route is a one-byte index into `KNOWN_ROUTES`, or `ROUTE_INLINE` followed by
+//! the feature and action strings. Payloads are tagged values (see `TAG_*`) with
+//! object keys interned through `KNOWN_KEYS`. `movement.input_batch` has a
+//! dedicated bit-packed payload because it is sent every frame.
+//!
+//! The tables below are part of the wire format and must stay in sync with
+//! `src/game/netcode/binary-protocol.ts`. Only ever append to them; both sides'
+//! tests check them against `worker/fixtures/binary-protocol.json`.
+
+use serde_json::{Map as JsonMap, Number, Value};
+use worker::{Error, Result};
+
+pub(crate) const BINARY_PROTOCOL_VERSION: u32 = 3;
+
+const KNOWN_ROUTES: &[(&str, &str)] = &[
+    ("core", "connected"),
+    ("core", "command"),
+    ("core", "duplicate"),
+    ("core", "state"),
+    ("core", "pong"),
+    ("core", "ping"),
+    ("core", "snapshot_ack"),
+    ("core", "invalid_message"),
+    ("core", "command_rejected"),
+    ("movement", "input_batch"),
+    ("build", "place"),
+    ("build", "remove"),
+    ("build", "preview"),
+    ("projectile", "fire"),
+    ("projectile", "hit"),
+    ("health", "death"),
+    ("interest", "update"),
+    ("build", "remove_rejected"),
+    ("team", "join"),
+    ("team", "leave"),
+    ("build", "set_recipe"),
+    ("projectile", "equip"),
+    ("build", "open"),
+    ("build", "close"),
+    ("build", "transfer"),
+    ("research", "select"),
+    ("build", "place_rejected"),
+];
+
+const KNOWN_KEYS: &[&str] = &[
+    "id",
+    "x",
+    "y",
+    "vx",
+    "vy",
+    "connected",
+    "players",
+    "structures",
+    "previews",
+    "projectiles",
+    "inputAcks",
+    "speed",
+    "ownerId",
+    "kind",
+    "chunkX",
+    "chunkY",
+    "playerId",
+    "clientProjectileId",
+    "hp",
+    "maxHp",
+    "dead",
+    "respawnInMs",
+    "invulnerable",
+    "upserts",
+    "removes",
+    "online",
+    "onlineCount",
+    "structureCount",
+    "previewCount",
+    "projectileCount",
+    "presence",
+    "movement",
+    "build",
+    "projectile",
+    "health",
+    "roomCode",
+    "serverTick",
+    "simRateHz",
+    "snapshotRateHz",
+    "serverTime",
+    "mode",
+    "snapshotId",
+    "baselineId",
+    "features",
+    "inputs",
+    "seq",
+    "up",
+    "down",
+    "left",
+    "right",
+    "clientTime",
+    "clientBuildId",
+    "ok",
+    "resumeToken",
+    "interestRadiusChunks",
+    "chunkWorldSize",
+    "message",
+    "projectileId",
+    "targetKind",
+    "targetId",
+    "killerId",
+    "radius",
+    "entered",
+    "members",
+    "role",
+    "teamId",
+    "code",
+    "structureId",
+    "terrainSeed",
+    "miner",
+    "item",
+    "output",
+    "capacity",
+    "working",
+    "assembler",
+    "recipe",
+    "outputs",
+    "count",
+    "progress",
+    "crafting",
+    "inventory",
+    "slots",
+    "direction",
+    "belt",
+    "chunks",
+    "palette",
+    "items",
+    "inserter",
+    "held",
+    "phase",
+    "moveX",
+    "moveY",
+    "sprint",
+    "stamina",
+    "exhausted",
+    "weapon",
+    "weaponId",
+    "enemy",
+    "enemies",
+    "enemyCount",
+    "power",
+    "networks",
+    "network",
+    "supplyKw",
+    "demandKw",
+    "satisfaction",
+    "chest",
+    "open",
+    "to",
+    "research",
+    "current",
+    "unlocked",
+    "techId",
+    "lab",
+    "packs",
+    "researching",
+];
+
+const SERVER_KINDS: &[&str] = &["welcome", "ack", "snapshot", "event", "error", "pong"];
+const CLIENT_KIND_COMMAND: u8 = 0;
+const ROUTE_INLINE: u8 = 0xff;
+
+const FLAG_SEQ: u8 = 1 << 0;
+const FLAG_PAYLOAD: u8 = 1 << 1;
+const FLAG_PACKED_INPUTS: u8 = 1 << 2;
+
+const TAG_NULL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_UINT: u8 = 3;
+const TAG_NEG_INT: u8 = 4;
+const TAG_F32: u8 = 5;
+const TAG_F64: u8 = 6;
+const TAG_STRING: u8 = 7;
+const TAG_ARRAY: u8 = 8;
+const TAG_OBJECT: u8 = 9;
+
+const INPUT_UP: u8 = 1 << 0;
+const INPUT_DOWN: u8 = 1 << 1;
+const INPUT_LEFT: u8 = 1 << 2;
+const INPUT_RIGHT: u8 = 1 << 3;
+const INPUT_SPRINT: u8 = 1 << 4;
+/// Followed by two signed bytes: `moveX`, `moveY`.
+const INPUT_ANALOG: u8 = 1 << 5;
+
+const MAX_DECODE_DEPTH: usize = 16;
+
+/// Decoded form of a binary client command, before envelope validation.
+pub(crate) struct BinaryCommand {
+    pub(crate) seq: u32,
+    pub(crate) feature: String,
+    pub(crate) action: String,
+    pub(crate) client_time: f64,
+    pub(crate) payload: Option<Value>,
+}
+
+fn protocol_error(message: &str) -> Error {
+    Error::RustError(format!("binary envelope: {message}"))
+}
+
+pub(crate) fn encode_server_envelope(
+    kind: &str,
+    tick: u64,
+    server_time: i64,
+    feature: &str,
+    action: &str,
+    seq: Option<u32>,
+    payload: Option<&Value>,
+) -> Vec<u8> {
+    let mut out = Vec::with_capacity(64);
+    out.push(BINARY_PROTOCOL_VERSION as u8);
+    out.push(
+        SERVER_KINDS
+            .iter()
+            .position(|known| *known == kind)
+            .unwrap_or(SERVER_KINDS.len() - 1) as u8,
+    );
+    write_varint(&mut out, tick);
+    write_varint(&mut out, server_time.max(0) as u64);
+    write_route(&mut out, feature, action);
+
+    let mut flags = 0;
+    if seq.is_some() {
+        flags |= FLAG_SEQ;
+    }
+    if payload.is_some() {
+        flags |= FLAG_PAYLOAD;
+    }
+    out.push(flags);
+    if let Some(seq) = seq {
+        write_varint(&mut out, seq as u64);
+    }
+    if let Some(payload) = payload {
+        write_value(&mut out, payload);
+    }
+    out
+}
+
+pub(crate) fn decode_client_envelope(bytes: &[u8]) -> Result<BinaryCommand> {
+    let mut reader = Reader { bytes, offset: 0 };
+    if reader.byte()? as u32 != BINARY_PROTOCOL_VERSION {
+        return Err(protocol_error("unsupported version"));
+    }
+    if reader.byte()? != CLIENT_KIND_COMMAND {
+        return Err(protocol_error("unsupported kind"));
+    }
+
+    let seq = u32::try_from(reader.varint()?).map_err(|_| protocol_error("seq overflow"))?;
+    let client_time = reader.f64()?;
+    let (feature, action) = reader.route()?;
+    let flags = reader.byte()?;
+    let payload = if flags & FLAG_PACKED_INPUTS != 0 {
+        Some(reader.packed_inputs()?)
+    } else if flags & FLAG_PAYLOAD != 0 {
+        Some(reader.value(0)?)
+    } else {
+        None
+    };
+
+    if reader.offset != bytes.len() {
+        return Err(protocol_error("trailing bytes"));
+    }
+
+    Ok(BinaryCommand {
+        seq,
+        feature,
+        action,
+        client_time,
+        payload,
+    })
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_str(out: &mut Vec<u8>, value: &str) {
+    write_varint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn write_route(out: &mut Vec<u8>, feature: &str, action: &str) {
+    match KNOWN_ROUTES
+        .iter()
+        .position(|(known_feature, known_action)| {
+            *known_feature == feature && *known_action == action
+        }) {
+        Some(index) => out.push(index as u8),
+        None => {
+            out.push(ROUTE_INLINE);
+            write_str(out, feature);
+            write_str(out, action);
+        }
+    }
+}
+
+fn write_key(out: &mut Vec<u8>, key: &str) {
+    match KNOWN_KEYS.iter().position(|known| *known == key) {
+        Some(index) => write_varint(out, index as u64 + 1),
+        None => {
+            out.push(0);
+            write_str(out, key);
+        }
+    }
+}
+
+fn write_value(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.push(TAG_NULL),
+        Value::Bool(false) => out.push(TAG_FALSE),
+        Value::Bool(true) => out.push(TAG_TRUE),
+        Value::Number(number) => {
+            if let Some(unsigned) = number.as_u64() {
+                out.push(TAG_UINT);
+                write_varint(out, unsigned);
+            } else if let Some(signed) = number.as_i64() {
+                out.push(TAG_NEG_INT);
+                write_varint(out, (-(signed + 1)) as u64);
+            } else {
+                let float = number.as_f64().unwrap_or(0.0);
+                // Runtime state is f32, so most floats survive the narrower encoding.
+                if (float as f32) as f64 == float {
+                    out.push(TAG_F32);
+                    out.extend_from_slice(&(float as f32).to_le_bytes());
+                } else {
+                    out.push(TAG_F64);
+                    out.extend_from_slice(&float.to_le_bytes());
+                }
+            }
+        }
+        Value::String(text) => {
+            out.push(TAG_STRING);
+            write_str(out, text);
+        }
+        Value::Array(items) => {
+            out.push(TAG_ARRAY);
+            write_varint(out, items.len() as u64);
+            for item in items {
+                write_value(out, item);
+            }
+        }
+        Value::Object(fields) => {
+            out.push(TAG_OBJECT);
+            write_varint(out, fields.len() as u64);
+            for (key, field) in fields {
+                write_key(out, key);
+                write_value(out, field);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        let end = self
+            .offset
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| protocol_error("truncated"))?;
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(protocol_error("varint overflow"))
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        let len = self.varint()? as usize;
+        // Every element takes at least one byte, which bounds allocations.
+        if len > self.bytes.len() - self.offset {
+            return Err(protocol_error("length exceeds message"));
+        }
+        Ok(len)
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        let mut raw = [0u8; 8];
+        raw.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(raw))
+    }
+
+    fn f32(&mut self) -> Result<f32> {
+        let mut raw = [0u8; 4];
+        raw.copy_from_slice(self.take(4)?);
+        Ok(f32::from_le_bytes(raw))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.len()?;
+        let raw = self.take(len)?;
+        String::from_utf8(raw.to_vec()).map_err(|_| protocol_error("invalid utf-8"))
+    }
+
+    fn route(&mut self) -> Result<(String, String)> {
+        let index = self.byte()?;
+        if index == ROUTE_INLINE {
+            return Ok((self.string()?, self.string()?));
+        }
+        KNOWN_ROUTES
+            .get(index as usize)
+            .map(|(feature, action)| (feature.to_string(), action.to_string()))
+            .ok_or_else(|| protocol_error("unknown route"))
+    }
+
+    fn key(&mut self) -> Result<String> {
+        match self.varint()? {
+            0 => self.string(),
+            index => KNOWN_KEYS
+                .get(index as usize - 1)
+                .map(|key| key.to_string())
+                .ok_or_else(|| protocol_error("unknown key")),
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > MAX_DECODE_DEPTH {
+            return Err(protocol_error("nesting too deep"));
+        }
+
+        match self.byte()? {
+            TAG_NULL => Ok(Value::Null),
+            TAG_FALSE => Ok(Value::Bool(false)),
+            TAG_TRUE => Ok(Value::Bool(true)),
+            TAG_UINT => Ok(Value::from(self.varint()?)),
+            TAG_NEG_INT => {
+                let magnitude =
+                    i64::try_from(self.varint()?).map_err(|_| protocol_error("int overflow"))?;
+                Ok(Value::from(-magnitude - 1))
+            }
+            TAG_F32 => float_value(self.f32()? as f64),
+            TAG_F64 => float_value(self.f64()?),
+            TAG_STRING => Ok(Value::String(self.string()?)),
+            TAG_ARRAY => {
+                let len = self.len()?;
+                let mut items = Vec::with_capacity(len);
+                for _ in 0..len {
+                    items.push(self.value(depth + 1)?);
+                }
+                Ok(Value::Array(items))
+            }
+            TAG_OBJECT => {
+                let len = self.len()?;
+                let mut fields = JsonMap::new();
+                for _ in 0..len {
+                    let key = self.key()?;
+                    fields.insert(key, self.value(depth + 1)?);
+                }
+                Ok(Value::Object(fields))
+            }
+            _ => Err(protocol_error("unknown value tag")),
+        }
+    }
+
+    /// `count`, then per input: seq delta from the previous input (the first is
+    /// absolute), one byte of input bits and, with `INPUT_ANALOG`, the stick
+    /// axes as two signed bytes.
+    fn packed_inputs(&mut self) -> Result<Value> {
+        let count = self.len()?;
+        let mut inputs = Vec::with_capacity(count);
+        let mut seq = 0u64;
+        for _ in 0..count {
+            seq = seq
+                .checked_add(self.varint()?)
+                .ok_or_else(|| protocol_error("seq overflow"))?;
+            let bits = self.byte()?;
+            let (move_x, move_y) = if bits & INPUT_ANALOG != 0 {
+                (self.byte()? as i8, self.byte()? as i8)
+            } else {
+                (0, 0)
+            };
+            inputs.push(serde_json::json!({
+                "seq": seq,
+                "up": bits & INPUT_UP != 0,
+                "down": bits & INPUT_DOWN != 0,
+                "left": bits & INPUT_LEFT != 0,
+                "right": bits & INPUT_RIGHT != 0,
+                "moveX": move_x,
+                "moveY": move_y,
+                "sprint": bits & INPUT_SPRINT != 0,
+            }));
+        }
+        Ok(serde_json::json!({ "inputs": inputs }))
+    }
+}
+
+fn float_value(value: f64) -> Result<Value> {
+    Number::from_f64(value)
+        .map(Value::Number)
+        .ok_or_else(|| protocol_error("non-finite number"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Shared with `src/game/netcode/binary-protocol.test.ts`: client hex comes
+    /// from the TypeScript encoder, server hex from `encode_server_envelope`.
+    const FIXTURE: &str = include_str!("../fixtures/binary-protocol.json");
+
+    #[derive(Debug, PartialEq)]
+    struct ServerFrame {
+        kind: String,
+        tick: u64,
+        server_time: u64,
+        feature: String,
+        action: String,
+        seq: Option<u32>,
+        payload: Option<Value>,
+    }
+
+    fn decode_server(bytes: &[u8]) -> Result<ServerFrame> {
+        let mut reader = Reader { bytes, offset: 0 };
+        if reader.byte()? as u32 != BINARY_PROTOCOL_VERSION {
+            return Err(protocol_error("unsupported version"));
+        }
+        let kind = SERVER_KINDS
+            .get(reader.byte()? as usize)
+            .ok_or_else(|| protocol_error("unsupported kind"))?;
+        let tick = reader.varint()?;
+        let server_time = reader.varint()?;
+        let (feature, action) = reader.route()?;
+        let flags = reader.byte()?;
+        let seq = if flags & FLAG_SEQ != 0 {
+            Some(reader.varint()? as u32)
+        } else {
+            None
+        };
+        let payload = if flags & FLAG_PAYLOAD != 0 {
+            Some(reader.value(0)?)
+        } else {
+            None
+        };
+        if reader.offset != bytes.len() {
+            return Err(protocol_error("trailing bytes"));
+        }
+        Ok(ServerFrame {
+            kind: kind.to_string(),
+            tick,
+            server_time,
+            feature,
+            action,
+            seq,
+            payload,
+        })
+    }
+
+    /// The TypeScript client's layout for commands with a tagged payload.
+    fn encode_client(
+        seq: u32,
+        client_time: f64,
+        feature: &str,
+        action: &str,
+        payload: Option<&Value>,
+    ) -> Vec<u8> {
+        let mut out = vec![BINARY_PROTOCOL_VERSION as u8, CLIENT_KIND_COMMAND];
+        write_varint(&mut out, seq as u64);
+        out.extend_from_slice(&client_time.to_le_bytes());
+        write_route(&mut out, feature, action);
+        match payload {
+            Some(payload) => {
+                out.push(FLAG_PAYLOAD);
+                write_value(&mut out, payload);
+            }
+            None => out.push(0),
+        }
+        out
+    }
+
+    fn sample_payload() -> Value {
+        json!({
+            "id": "p1",
+            "x": 12.5,
+            "vx": 0.1,
+            "count": 300,
+            "hp": -40000,
+            "min": i64::MIN,
+            "max": u64::MAX,
+            "ok": true,
+            "dead": false,
+            "teamId": null,
+            "message": "héllo ✓",
+            "items": [1, [2, []], {}],
+            "customKey": { "nested": [{ "kind": "miner" }] },
+        })
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    fn unhex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|index| u8::from_str_radix(&text[index..index + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn inline_and_known_routes() -> impl Iterator<Item = (&'static str, &'static str)> {
+        KNOWN_ROUTES
+            .iter()
+            .copied()
+            .chain([("trade", "offer"), ("", "")])
+    }
+
+    #[test]
+    fn server_envelopes_round_trip_for_every_kind_and_route() {
+        let payload = sample_payload();
+        for kind in SERVER_KINDS {
+            for (feature, action) in inline_and_known_routes() {
+                for (seq, payload) in [(None, None), (Some(u32::MAX), Some(&payload))] {
+                    let bytes = encode_server_envelope(
+                        kind,
+                        u64::MAX,
+                        1_700_000_000_123,
+                        feature,
+                        action,
+                        seq,
+                        payload,
+                    );
+                    let frame = decode_server(&bytes).unwrap();
+                    assert_eq!(
+                        frame,
+                        ServerFrame {
+                            kind: kind.to_string(),
+                            tick: u64::MAX,
+                            server_time: 1_700_000_000_123,
+                            feature: feature.to_string(),
+                            action: action.to_string(),
+                            seq,
+                            payload: payload.cloned(),
+                        }
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn client_commands_round_trip_for_every_route() {
+        let payload = sample_payload();
+        for (feature, action) in inline_and_known_routes() {
+            for payload in [None, Some(&payload)] {
+                let bytes = encode_client(u32::MAX, -2.75, feature, action, payload);
+                let command = decode_client_envelope(&bytes).unwrap();
+                assert_eq!(command.seq, u32::MAX);
+                assert_eq!(command.client_time, -2.75);
+                assert_eq!(
+                    (command.feature.as_str(), command.action.as_str()),
+                    (feature, action)
+                );
+                assert_eq!(command.payload.as_ref(), payload);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_input_is_rejected() {
+        let fixture: Value = serde_json::from_str(FIXTURE).unwrap();
+        let mut messages: Vec<Vec<u8>> = fixture["client"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| unhex(entry["hex"].as_str().unwrap()))
+            .collect();
+        messages.push(encode_client(
+            7,
+            1.0,
+            "trade",
+            "offer",
+            Some(&sample_payload()),
+        ));
+        for bytes in messages {
+            for len in 0..bytes.len() {
+                assert!(
+                    decode_client_envelope(&bytes[..len]).is_err(),
+                    "{} accepted at {len} bytes",
+                    hex(&bytes)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn oversized_and_malformed_input_is_rejected() {
+        let header = |route: u8, flags: u8| {
+            let mut out = vec![BINARY_PROTOCOL_VERSION as u8, CLIENT_KIND_COMMAND, 1];
+            out.extend_from_slice(&0f64.to_le_bytes());
+            out.extend_from_slice(&[route, flags]);
+            out
+        };
+        let with = |mut bytes: Vec<u8>, tail: &[u8]| {
+            bytes.extend_from_slice(tail);
+            bytes
+        };
+        let payload = |tail: &[u8]| with(header(5, FLAG_PAYLOAD), tail);
+        let huge = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        let deep = {
+            let mut tail = [TAG_ARRAY, 1].repeat(MAX_DECODE_DEPTH + 2);
+            tail.push(TAG_NULL);
+            tail
+        };
+
+        let cases: Vec<(&str, Vec<u8>)> = vec![
+            ("string length", payload(&with(vec![TAG_STRING], &huge))),
+            ("array length", payload(&with(vec![TAG_ARRAY], &huge))),
+            ("object length", payload(&[TAG_OBJECT, 0x7f])),
+            (
+                "inline key length",
+                payload(&[TAG_OBJECT, 1, 0, 0x40, b'k']),
+            ),
+            (
+                "varint overflow",
+                payload(&with(vec![TAG_UINT], &[0x80; 11])),
+            ),
+            (
+                "negative overflow",
+                payload(&with(vec![TAG_NEG_INT], &huge)),
+            ),
+            ("nesting", payload(&deep)),
+            ("trailing bytes", payload(&[TAG_NULL, TAG_NULL])),
+            ("unknown tag", payload(&[0x2a])),
+            ("unknown key", payload(&[TAG_OBJECT, 1, 0x7f, TAG_NULL])),
+            ("invalid utf-8", payload(&[TAG_STRING, 2, 0xc3, 0x28])),
+            (
+                "non-finite float",
+                payload(&with(vec![TAG_F64], &f64::NAN.to_le_bytes())),
+            ),
+            ("unknown route", header(KNOWN_ROUTES.len() as u8, 0)),
+            (
+                "inline route length",
+                with(header(ROUTE_INLINE, 0), &[0x7f]),
+            ),
+            (
+                "packed input count",
+                with(header(9, FLAG_PACKED_INPUTS), &huge),
+            ),
+            (
+                "packed seq overflow",
+                with(
+                    header(9, FLAG_PACKED_INPUTS),
+                    &with(with(vec![2], &huge), &[0, 1, 0]),
+                ),
+            ),
+            (
+                "version",
+                with(vec![BINARY_PROTOCOL_VERSION as u8 + 1], &header(5, 0)[1..]),
+            ),
+            (
+                "kind",
+                with(vec![BINARY_PROTOCOL_VERSION as u8, 1], &header(5, 0)[2..]),
+            ),
+            ("seq overflow", {
+                let mut bytes = vec![BINARY_PROTOCOL_VERSION as u8, CLIENT_KIND_COMMAND];
+                write_varint(&mut bytes, u32::MAX as u64 + 1);
+                bytes.extend_from_slice(&header(5, 0)[3..]);
+                bytes
+            }),
+        ];
+        assert!(decode_client_envelope(&payload(&[TAG_NULL])).is_ok());
+        for (name, bytes) in cases {
+            assert!(decode_client_envelope(&bytes).is_err(), "{name} accepted");
+        }
+    }
+
+    #[test]
+    fn golden_fixture_matches_the_typescript_codec() {
+        let fixture: Value = serde_json::from_str(FIXTURE).unwrap();
+
+        for entry in fixture["server"].as_array().unwrap() {
+            let bytes = encode_server_envelope(
+                entry["kind"].as_str().unwrap(),
+                entry["tick"].as_u64().unwrap(),
+                entry["serverTime"].as_i64().unwrap(),
+                entry["feature"].as_str().unwrap(),
+                entry["action"].as_str().unwrap(),
+                entry
+                    .get("seq")
+                    .and_then(Value::as_u64)
+                    .map(|seq| seq as u32),
+                entry.get("payload"),
+            );
+            assert_eq!(hex(&bytes), entry["hex"], "{}", entry["name"]);
+        }
+
+        for entry in fixture["client"].as_array().unwrap() {
+            let command = decode_client_envelope(&unhex(entry["hex"].as_str().unwrap())).unwrap();
+            assert_eq!(Value::from(command.seq), entry["seq"], "{}", entry["name"]);
+            assert_eq!(command.client_time, entry["clientTime"].as_f64().unwrap());
+            assert_eq!(command.feature, entry["feature"]);
+            assert_eq!(command.action, entry["action"]);
+            assert_eq!(command.payload.as_ref(), entry.get("payload"));
+        }
+
+        // The fixture lists both tables in full, so appending to one side
+        // without the other (or without regenerating the fixture) fails here
+        // or in the TypeScript test.
+        let routes = fixture["routes"].as_array().unwrap();
+        assert_eq!(routes.len(), KNOWN_ROUTES.len());
+        for (entry, (feature, action)) in routes.iter().zip(KNOWN_ROUTES) {
+            let command = decode_client_envelope(&unhex(entry["hex"].as_str().unwrap())).unwrap();
+            assert_eq!(
+                (command.feature.as_str(), command.action.as_str()),
+                (*feature, *action)
+            );
+            assert_eq!(
+                (&entry["feature"], &entry["action"]),
+                (&json!(feature), &json!(action))
+            );
+        }
+        let every_key = fixture["server"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|entry| entry["name"] == "every known key")
+            .unwrap();
+        let keys: Vec<(&str, u64)> = every_key["payload"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(key, index)| (key.as_str(), index.as_u64().unwrap()))
+            .collect();
+        assert_eq!(keys.len(), KNOWN_KEYS.len());
+        for (key, index) in keys {
+            assert_eq!(KNOWN_KEYS[index as usize], key);
+        }
+    }
+}
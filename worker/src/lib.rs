@@ -1,16 +1,35 @@
-use base64::engine::general_purpose::URL_SAFE_NO_PAD;
-use base64::Engine;
+mod auth;
+mod binary_protocol;
+
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map as JsonMap, Value};
 use sim_core::{
-    movement_step_with_obstacles, projectile_step, InputState as CoreInputState, StructureObstacle,
-    PLAYER_COLLIDER_RADIUS, STRUCTURE_COLLIDER_HALF_EXTENT,
+    enemy_step, enemy_touches_player, enemy_touches_structure, enemy_waypoint,
+    movement_step_with_terrain_fixed, player_can_take_damage, power_role,
+    projectile_step_with_hits_fixed, recipe_by_id, respawn_position, step_reach,
+    structure_has_behavior, structure_is_solid, structure_max_hp, structure_prototype, tile_center,
+    weapon_by_id, AssemblerState, BeltGrid, BeltItem, BeltState, DamageOutcome, Direction,
+    FlowField, Footprint, Health, InputState as CoreInputState, InserterGrid, InserterPhase,
+    InserterState, InserterWorld, Inventory, ItemKind, ItemStack, LabState, MinerState,
+    NetworkBalance, ObstacleIndex, PlayerCollider, PositionHistory, PowerGrid, ProjectileHit,
+    ResearchState, Stamina, StructureBehavior, StructureObstacle, Terrain, Weapon, WeaponCooldowns,
+    ANALOG_AXIS_MAX, CHEST_SLOTS, ENEMY_ATTACK_INTERVAL_MS, ENEMY_COLLIDER_RADIUS,
+    ENEMY_CONTACT_DAMAGE, ENEMY_LEASH_RADIUS, ENEMY_MAX_HP, ENEMY_SPEED, INVENTORY_SLOTS,
+    LAB_PACK_CAPACITY, MINER_OUTPUT_CAPACITY, NEST_ACTIVATION_RADIUS, NEST_MAX_ENEMIES,
+    NEST_SPAWN_INTERVAL_MS, PLAYER_COLLIDER_RADIUS, PLAYER_MAX_HP, PROJECTILE_COLLIDER_RADIUS,
+    RESPAWN_DELAY_MS, RESPAWN_INVULNERABILITY_MS, SPRINT_SPEED_MULTIPLIER,
 };
 use std::cell::{Cell, RefCell};
-use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
 use worker::durable::{DurableObject, State, WebSocketIncomingMessage};
 use worker::*;
 
+use auth::authenticate_player;
+use binary_protocol::{decode_client_envelope, encode_server_envelope, BINARY_PROTOCOL_VERSION};
+
 const PROTOCOL_VERSION: u32 = 2;
 const PLAYER_ID_RE_MIN: usize = 3;
 const PLAYER_ID_RE_MAX: usize = 120;
@@ -22,12 +41,11 @@ const SIM_DT_SECONDS: f32 = 1.0 / SIM_RATE_HZ as f32;
 const SIM_DT_MS: f64 = 1000.0 / SIM_RATE_HZ as f64;
 const SNAPSHOT_INTERVAL_TICKS: u64 = (SIM_RATE_HZ / SNAPSHOT_RATE_HZ) as u64;
 const MAX_CATCHUP_STEPS: usize = 8;
+const TICK_ALARM_MIN_DELAY_MS: u64 = 1;
 
 const MOVE_SPEED: f32 = 220.0;
 const MOVEMENT_MAP_LIMIT: f32 = 5000.0;
 const PROJECTILE_MAP_LIMIT: f32 = 5500.0;
-const PROJECTILE_TTL_MS: i64 = 1800;
-const PROJECTILE_MAX_SPEED: f64 = 900.0;
 
 const BUILD_GRID_SIZE: f64 = 32.0;
 const BUILD_CHUNK_CELLS: i64 = 32;
@@ -37,16 +55,54 @@ const RESUME_TOKEN_TTL_MS: i64 = 86_400_000;
 
 const PREVIEW_COMMAND_MIN_INTERVAL_MS: i64 = 40;
 const PLACE_COMMAND_MIN_INTERVAL_MS: i64 = 120;
-const PROJECTILE_FIRE_MIN_INTERVAL_MS: i64 = 33;
+const REMOVE_COMMAND_MIN_INTERVAL_MS: i64 = 250;
+/// How far a projectile may spawn from the shooter's rewound path as-is.
+const PROJECTILE_ORIGIN_TOLERANCE: f32 = 48.0;
+/// Origins off by more than the tolerance but within this distance are moved
+/// onto the shooter; anything further is rejected.
+const PROJECTILE_REANCHOR_DISTANCE: f32 = 160.0;
+/// Rewind added on top of the measured round trip for tick and input jitter.
+const PROJECTILE_REWIND_SLACK_MS: i64 = 100;
+const PROJECTILE_MAX_REWIND_MS: i64 = 400;
+
+const DEFAULT_BUILD_INTERACTION_DISTANCE: f32 = 320.0;
+const MIN_BUILD_INTERACTION_DISTANCE: f32 = 32.0;
+const MAX_BUILD_INTERACTION_DISTANCE: f32 = 4096.0;
+const MAX_TEAM_ID_LEN: usize = 32;
+
+const DEFAULT_INTEREST_RADIUS_CHUNKS: i64 = 2;
+const MAX_INTEREST_RADIUS_CHUNKS: i64 = 8;
 
 const MAX_STRUCTURES: usize = 1024;
 const MAX_PROJECTILES: usize = 4096;
+const MAX_ENEMIES: usize = 256;
 const MAX_PREVIEWS: usize = 256;
+const MAX_PENDING_BASELINES: usize = 32;
+// Replicated assembler progress is rounded down to 1/ASSEMBLER_PROGRESS_STEPS.
+const ASSEMBLER_PROGRESS_STEPS: f32 = 20.0;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct SocketAttachment {
     player_id: String,
     last_seq: u32,
+    #[serde(default)]
+    socket_id: String,
+    #[serde(default = "default_protocol_version")]
+    protocol: u32,
+}
+
+fn default_protocol_version() -> u32 {
+    PROTOCOL_VERSION
+}
+
+impl SocketAttachment {
+    fn view_key(&self) -> &str {
+        if self.socket_id.is_empty() {
+            &self.player_id
+        } else {
+            &self.socket_id
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -76,21 +132,32 @@ struct ServerEnvelope {
     payload: Option<Value>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 struct InputState {
     up: bool,
     down: bool,
     left: bool,
     right: bool,
+    move_x: i8,
+    move_y: i8,
+    sprint: bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct InputCommand {
     seq: u32,
     up: bool,
     down: bool,
     left: bool,
     right: bool,
+    /// Analog stick axes; out-of-range values are clamped on receipt.
+    #[serde(default)]
+    move_x: i32,
+    #[serde(default)]
+    move_y: i32,
+    #[serde(default)]
+    sprint: bool,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -105,6 +172,8 @@ struct BuildPlacePayload {
     y: f64,
     kind: String,
     client_build_id: Option<String>,
+    #[serde(default)]
+    direction: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -112,6 +181,54 @@ struct BuildRemovePayload {
     id: String,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+struct BuildSetRecipePayload {
+    id: String,
+    recipe: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BuildOpenPayload {
+    id: String,
+}
+
+/// `to` is `"chest"` or `"player"`; `count` defaults to one stack.
+#[derive(Debug, Clone, Deserialize)]
+struct BuildTransferPayload {
+    id: String,
+    item: String,
+    count: Option<u32>,
+    to: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TeamJoinPayload {
+    team_id: String,
+    /// Someone else to put on the team; defaults to the sender.
+    player_id: Option<String>,
+}
+
+/// `techId = null` stops research.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResearchSelectPayload {
+    tech_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RoomTeamRow {
+    team_id: String,
+    owner_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RoomMemberRow {
+    player_id: String,
+    role: String,
+    team_id: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct BuildPreviewPayload {
@@ -119,6 +236,8 @@ struct BuildPreviewPayload {
     x: Option<f64>,
     y: Option<f64>,
     kind: Option<String>,
+    #[serde(default)]
+    direction: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -129,19 +248,21 @@ struct ProjectileFirePayload {
     vx: f64,
     vy: f64,
     client_projectile_id: Option<String>,
+    /// The weapon the client believes is equipped; a mismatch is rejected.
+    #[serde(default)]
+    weapon_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
-struct JwtClaims {
-    sub: String,
-    sid: Option<String>,
-    exp: Option<i64>,
+#[serde(rename_all = "camelCase")]
+struct ProjectileEquipPayload {
+    weapon_id: String,
 }
 
 #[derive(Debug, Clone, Deserialize)]
-struct ClerkSessionResponse {
-    user_id: String,
-    status: Option<String>,
+#[serde(rename_all = "camelCase")]
+struct SnapshotAckPayload {
+    snapshot_id: u32,
 }
 
 #[derive(Debug, Deserialize)]
@@ -154,10 +275,223 @@ struct BuildRow {
     grid_x: Option<i64>,
     grid_y: Option<i64>,
     created_at: Option<i64>,
+    hp: Option<i64>,
+    machine_state: Option<String>,
+    direction: Option<String>,
+}
+
+/// JSON stored in `build_structures.machine_state` for structures that run machines.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MachineStateRecord {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    miner: Option<MinerRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    assembler: Option<AssemblerRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    belt: Option<BeltRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inserter: Option<InserterRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chest: Option<Vec<Option<ItemStackRecord>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lab: Option<LabRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LabRecord {
+    packs: u32,
+    progress: f32,
+    researching: bool,
+}
+
+impl LabRecord {
+    fn from_state(lab: &LabState) -> Self {
+        Self {
+            packs: lab.packs,
+            progress: lab.progress,
+            researching: lab.researching,
+        }
+    }
+
+    fn restore(&self) -> LabState {
+        LabState {
+            packs: self.packs.min(LAB_PACK_CAPACITY),
+            progress: if self.researching {
+                self.progress.clamp(0.0, 1.0)
+            } else {
+                0.0
+            },
+            researching: self.researching,
+        }
+    }
+}
+
+/// Room-wide research, kept in `room_meta` under `research`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ResearchRecord {
+    #[serde(default)]
+    unlocked: Vec<String>,
+    #[serde(default)]
+    current: Option<String>,
+    #[serde(default)]
+    progress: BTreeMap<String, u32>,
+}
+
+impl ResearchRecord {
+    fn from_state(research: &ResearchState) -> Self {
+        Self {
+            unlocked: research.unlocked().map(str::to_string).collect(),
+            current: research
+                .current()
+                .map(|technology| technology.id.to_string()),
+            progress: research
+                .progress_entries()
+                .map(|(id, packs)| (id.to_string(), packs))
+                .collect(),
+        }
+    }
+
+    fn restore(&self) -> ResearchState {
+        ResearchState::restore(
+            self.unlocked.iter().map(String::as_str),
+            self.current.as_deref(),
+            self.progress
+                .iter()
+                .map(|(id, packs)| (id.as_str(), *packs)),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MinerRecord {
+    output_count: u32,
+    progress: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BeltRecord {
+    direction: String,
+    lanes: [Vec<BeltItemRecord>; 2],
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct BeltItemRecord {
+    item: String,
+    position: u16,
+}
+
+impl BeltRecord {
+    fn from_state(belt: &BeltState) -> Self {
+        Self {
+            direction: belt.direction.as_str().to_string(),
+            lanes: belt.lanes.clone().map(|lane| {
+                lane.into_iter()
+                    .map(|item| BeltItemRecord {
+                        item: item.item.as_str().to_string(),
+                        position: item.position,
+                    })
+                    .collect()
+            }),
+        }
+    }
+
+    fn restore(&self, direction: Direction) -> BeltState {
+        let lanes = self.lanes.clone().map(|lane| {
+            lane.into_iter()
+                .filter_map(|record| {
+                    Some(BeltItem {
+                        item: ItemKind::parse(&record.item)?,
+                        position: record.position,
+                    })
+                })
+                .collect()
+        });
+        BeltState::restore(direction, lanes)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct InserterRecord {
+    direction: String,
+    held: Option<String>,
+    phase: String,
+    ticks: u16,
+}
+
+impl InserterRecord {
+    fn from_state(inserter: &InserterState) -> Self {
+        Self {
+            direction: inserter.direction.as_str().to_string(),
+            held: inserter.held.map(|item| item.as_str().to_string()),
+            phase: inserter.phase.as_str().to_string(),
+            ticks: inserter.ticks,
+        }
+    }
+
+    fn restore(&self, direction: Direction) -> InserterState {
+        InserterState::restore(
+            direction,
+            self.held.as_deref().and_then(ItemKind::parse),
+            InserterPhase::parse(&self.phase).unwrap_or_default(),
+            self.ticks,
+        )
+    }
+}
+
+/// Slot counts are stored in recipe order; they are dropped if the recipe no
+/// longer matches on load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AssemblerRecord {
+    recipe: Option<String>,
+    inputs: Vec<u32>,
+    outputs: Vec<u32>,
+    progress: f32,
+    crafting: bool,
+}
+
+impl AssemblerRecord {
+    fn from_state(assembler: &AssemblerState) -> Self {
+        Self {
+            recipe: assembler.recipe.map(|recipe| recipe.id.to_string()),
+            inputs: assembler.inputs.iter().map(|slot| slot.count).collect(),
+            outputs: assembler.outputs.iter().map(|slot| slot.count).collect(),
+            progress: assembler.progress,
+            crafting: assembler.crafting,
+        }
+    }
+
+    fn restore(&self) -> AssemblerState {
+        let mut assembler =
+            AssemblerState::with_recipe(self.recipe.as_deref().and_then(recipe_by_id));
+        if assembler.inputs.len() != self.inputs.len()
+            || assembler.outputs.len() != self.outputs.len()
+        {
+            return assembler;
+        }
+
+        for (index, count) in self.inputs.iter().enumerate() {
+            let capacity = assembler.input_capacity(assembler.inputs[index].item);
+            assembler.inputs[index].count = (*count).min(capacity);
+        }
+        for (index, count) in self.outputs.iter().enumerate() {
+            let capacity = assembler.output_capacity(assembler.outputs[index].item);
+            assembler.outputs[index].count = (*count).min(capacity);
+        }
+        assembler.crafting = self.crafting;
+        assembler.progress = if self.crafting {
+            self.progress.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        assembler
+    }
 }
 
 #[derive(Debug, Deserialize)]
-struct RoomCodeRow {
+struct RoomMetaRow {
     value: String,
 }
 
@@ -179,9 +513,18 @@ struct RuntimeHydratedPlayerRow {
     down: i64,
     left: i64,
     right: i64,
+    move_x: i64,
+    move_y: i64,
+    sprint: i64,
     last_input_seq: i64,
     connected: i64,
     last_seen: i64,
+    hp: Option<i64>,
+    dead_until: Option<i64>,
+    invulnerable_until: Option<i64>,
+    respawn_count: Option<i64>,
+    weapon: Option<String>,
+    inventory: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -191,12 +534,139 @@ struct RuntimePlayerState {
     vx: f32,
     vy: f32,
     input: InputState,
+    stamina: Stamina,
+    /// Recent positions for lag-compensated checks, filled every tick.
+    position_history: PositionHistory,
+    /// Smoothed snapshot round trip of the player's latest acknowledging socket.
+    rtt_ms: i64,
     last_input_seq: u32,
     connected: bool,
     last_seen: i64,
+    health: Health,
+    dead_until: i64,
+    invulnerable_until: i64,
+    respawn_count: u32,
     last_preview_cmd_at: i64,
     last_place_cmd_at: i64,
-    last_projectile_fire_at: i64,
+    last_remove_cmd_at: i64,
+    weapon_cooldowns: WeaponCooldowns,
+    weapon: &'static Weapon,
+    inventory: Inventory,
+    /// Chest whose contents this player is sent; not persisted.
+    open_chest: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RoomRole {
+    Admin,
+    Member,
+}
+
+impl RoomRole {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::Member => "member",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        if value == "admin" {
+            Self::Admin
+        } else {
+            Self::Member
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RoomMemberState {
+    role: RoomRole,
+    team_id: Option<String>,
+}
+
+/// Why a `build.place` was refused. Sent back as a `build.place_rejected`
+/// error envelope, like removals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BuildPlaceError {
+    InvalidPayload,
+    InvalidKind,
+    NotResearched,
+    Blocked,
+    NeedsOre,
+    IdTaken,
+    StructureLimit,
+    NotEnoughMaterials,
+    StorageFailed,
+}
+
+impl BuildPlaceError {
+    fn code(self) -> &'static str {
+        match self {
+            Self::InvalidPayload => "invalid_payload",
+            Self::InvalidKind => "invalid_kind",
+            Self::NotResearched => "not_researched",
+            Self::Blocked => "blocked",
+            Self::NeedsOre => "needs_ore",
+            Self::IdTaken => "id_taken",
+            Self::StructureLimit => "structure_limit",
+            Self::NotEnoughMaterials => "not_enough_materials",
+            Self::StorageFailed => "storage_failed",
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Self::InvalidPayload => "invalid build payload",
+            Self::InvalidKind => "invalid structure kind",
+            Self::NotResearched => "structure is not researched yet",
+            Self::Blocked => "build cell is blocked",
+            Self::NeedsOre => "miners must be placed on ore",
+            Self::IdTaken => "structure id is already taken",
+            Self::StructureLimit => "this room has reached its structure limit",
+            Self::NotEnoughMaterials => "not enough materials to build this",
+            Self::StorageFailed => "could not save the structure, try again",
+        }
+    }
+}
+
+/// Why a `build.remove` was refused. Sent back as a `build.remove_rejected`
+/// error envelope so clients can tell griefing protection from bugs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BuildRemoveError {
+    InvalidPayload,
+    NotFound,
+    NotPermitted,
+    OutOfRange,
+    RateLimited,
+    InventoryFull,
+    StorageFailed,
+}
+
+impl BuildRemoveError {
+    fn code(self) -> &'static str {
+        match self {
+            Self::InvalidPayload => "invalid_payload",
+            Self::NotFound => "not_found",
+            Self::NotPermitted => "not_permitted",
+            Self::OutOfRange => "out_of_range",
+            Self::RateLimited => "rate_limited",
+            Self::InventoryFull => "inventory_full",
+            Self::StorageFailed => "storage_failed",
+        }
+    }
+
+    fn message(self) -> &'static str {
+        match self {
+            Self::InvalidPayload => "invalid build payload",
+            Self::NotFound => "structure not found",
+            Self::NotPermitted => "only the owner, their team, or a room admin can remove this",
+            Self::OutOfRange => "structure is out of reach",
+            Self::RateLimited => "removing too fast",
+            Self::InventoryFull => "not enough inventory space",
+            Self::StorageFailed => "could not save the removal, try again",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -211,12 +681,114 @@ struct RuntimeStructureState {
     chunk_x: i64,
     chunk_y: i64,
     created_at: i64,
+    health: Health,
+    miner: Option<MinerState>,
+    assembler: Option<AssemblerState>,
+    chest: Option<Inventory>,
+    lab: Option<LabState>,
+    /// Rotation; belts also move items this way and inserters drop this way.
+    direction: Direction,
+}
+
+impl RuntimeStructureState {
+    /// The anchor cell the structure was placed on.
+    fn cell(&self) -> (i32, i32) {
+        (self.grid_x as i32, self.grid_y as i32)
+    }
+
+    fn footprint(&self) -> Footprint {
+        Footprint::for_structure(self.kind.as_str(), self.direction)
+    }
+
+    fn has_behavior(&self, behavior: StructureBehavior) -> bool {
+        structure_has_behavior(self.kind.as_str(), behavior)
+    }
+
+    fn obstacle(&self) -> StructureObstacle {
+        self.footprint().obstacle(self.x, self.y)
+    }
+
+    /// Belts and inserters live in the room's grids, so the room is passed in.
+    fn machine_state_json(&self, runtime: &RoomRuntimeState) -> Option<String> {
+        let belt = runtime
+            .belts
+            .get(self.cell())
+            .filter(|_| self.has_behavior(StructureBehavior::Belt));
+        let inserter = runtime
+            .inserters
+            .get(self.cell())
+            .filter(|_| self.has_behavior(StructureBehavior::Inserter));
+        if self.miner.is_none()
+            && self.assembler.is_none()
+            && self.chest.is_none()
+            && self.lab.is_none()
+            && belt.is_none()
+            && inserter.is_none()
+        {
+            return None;
+        }
+        let record = MachineStateRecord {
+            miner: self.miner.map(|miner| MinerRecord {
+                output_count: miner.output_count,
+                progress: miner.progress,
+            }),
+            assembler: self.assembler.as_ref().map(AssemblerRecord::from_state),
+            belt: belt.map(BeltRecord::from_state),
+            inserter: inserter.map(InserterRecord::from_state),
+            chest: self.chest.as_ref().map(inventory_records),
+            lab: self.lab.as_ref().map(LabRecord::from_state),
+        };
+        serde_json::to_string(&record).ok()
+    }
+
+    /// What removing the structure gives back: its build cost plus anything
+    /// buffered inside it or carried on it.
+    fn refund_items(&self, runtime: &RoomRuntimeState) -> Vec<ItemStack> {
+        let mut items: Vec<ItemStack> = structure_prototype(&self.kind)
+            .map(|prototype| prototype.cost.clone())
+            .unwrap_or_default();
+        if let Some(mut miner) = self.miner {
+            items.extend(
+                miner
+                    .take_output(u32::MAX)
+                    .map(|(item, count)| ItemStack { item, count }),
+            );
+        }
+        if let Some(mut assembler) = self.assembler.clone() {
+            items.extend(assembler.set_recipe(None));
+        }
+        if let Some(chest) = self.chest.as_ref() {
+            items.extend(chest.slots().iter().flatten().copied());
+        }
+        if let Some(lab) = self.lab.filter(|lab| lab.packs > 0) {
+            items.push(ItemStack {
+                item: ItemKind::SciencePack,
+                count: lab.packs,
+            });
+        }
+        if let Some(belt) = runtime
+            .belts
+            .get(self.cell())
+            .filter(|_| self.has_behavior(StructureBehavior::Belt))
+        {
+            items.extend(belt.items());
+        }
+        if let Some(inserter) = runtime
+            .inserters
+            .get(self.cell())
+            .filter(|_| self.has_behavior(StructureBehavior::Inserter))
+        {
+            items.extend(inserter.held.map(|item| ItemStack { item, count: 1 }));
+        }
+        items
+    }
 }
 
 #[derive(Debug, Clone)]
 struct RuntimePreviewState {
     player_id: String,
     kind: String,
+    direction: Direction,
     x: f32,
     y: f32,
     updated_at: i64,
@@ -232,170 +804,570 @@ struct RuntimeProjectileState {
     vy: f32,
     expires_at: i64,
     client_projectile_id: Option<String>,
+    damage: i32,
     updated_at: i64,
 }
 
-#[derive(Debug, Default)]
-struct RoomRuntimeState {
-    players: HashMap<String, RuntimePlayerState>,
-    structures: HashMap<String, RuntimeStructureState>,
-    previews: HashMap<String, RuntimePreviewState>,
-    projectiles: HashMap<String, RuntimeProjectileState>,
+/// Enemies only live in memory; a room that goes idle loses them and its
+/// nests start over.
+#[derive(Debug, Clone)]
+struct RuntimeEnemyState {
+    enemy_id: String,
+    nest: (i32, i32),
+    x: f32,
+    y: f32,
+    vx: f32,
+    vy: f32,
+    health: Health,
+    next_attack_at: i64,
 }
 
-fn now_ms() -> i64 {
-    Date::now().as_millis() as i64
-}
+/// Damage to apply once a tick's movement is done: target kind, target id,
+/// attacker id and amount.
+type PendingDamage = (&'static str, String, String, i32);
 
-fn now_seconds() -> i64 {
-    now_ms() / 1000
+#[derive(Debug, Clone, Copy)]
+struct InterestArea {
+    chunk_x: i64,
+    chunk_y: i64,
+    radius: i64,
 }
 
-fn sanitize_room_code(input: &str) -> Option<String> {
-    let candidate = input.trim().to_ascii_uppercase();
-    if candidate.is_empty() || candidate.len() > ROOM_RE_MAX {
-        return None;
-    }
-
-    if candidate
-        .chars()
-        .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-')
-    {
-        Some(candidate)
-    } else {
-        None
+impl InterestArea {
+    fn around(x: f32, y: f32, radius: i64) -> Self {
+        Self {
+            chunk_x: chunk_coord_for_world(x),
+            chunk_y: chunk_coord_for_world(y),
+            radius,
+        }
     }
-}
 
-fn sanitize_player_id(input: &str) -> Option<String> {
-    let candidate = input.trim();
-    if candidate.len() < PLAYER_ID_RE_MIN || candidate.len() > PLAYER_ID_RE_MAX {
-        return None;
+    fn contains_chunk(&self, chunk_x: i64, chunk_y: i64) -> bool {
+        (chunk_x - self.chunk_x).abs() <= self.radius
+            && (chunk_y - self.chunk_y).abs() <= self.radius
     }
 
-    if candidate
-        .chars()
-        .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-')
-    {
-        Some(candidate.to_string())
-    } else {
-        None
+    fn contains_point(&self, x: f32, y: f32) -> bool {
+        self.contains_chunk(chunk_coord_for_world(x), chunk_coord_for_world(y))
     }
 }
 
-fn random_player_id() -> String {
-    let time = now_ms() as u64;
-    let random = (js_sys::Math::random() * 1_000_000_000.0) as u64;
-    format!("anon_{:x}", time ^ random)
-}
-
-fn parse_room_code_from_path(path: &str) -> Option<String> {
-    let parts: Vec<&str> = path.split('/').collect();
-    if parts.len() != 5 {
-        return None;
-    }
-
-    if parts[1] != "api" || parts[2] != "rooms" || parts[4] != "ws" {
-        return None;
-    }
+/// Per-entity fingerprints of one sent snapshot, keyed by channel and then entity id.
+type ReplicatedState = HashMap<&'static str, HashMap<String, u64>>;
 
-    sanitize_room_code(parts[3])
+/// Replication bookkeeping for one socket: the entities it saw in its previous
+/// snapshot (for enter/leave events), the last snapshot it acknowledged (the
+/// delta baseline) and the snapshots still awaiting acknowledgement along with
+/// when they were sent.
+#[derive(Debug, Default)]
+struct SocketView {
+    players: HashSet<String>,
+    structures: HashSet<String>,
+    next_snapshot_id: u32,
+    baseline: Option<(u32, ReplicatedState)>,
+    pending: VecDeque<(u32, i64, ReplicatedState)>,
 }
 
-fn parse_query_param(url: &Url, key: &str) -> Option<String> {
-    url.query_pairs()
-        .find(|(name, _)| name == key)
-        .map(|(_, value)| value.into_owned())
+/// Viewer-independent parts of a snapshot, built once per broadcast and then
+/// filtered and diffed per socket.
+struct SharedSnapshot {
+    connected_players: Vec<String>,
+    /// Presence entries for every connected player.
+    members: Vec<Value>,
+    structures: HashMap<String, Value>,
+    /// Belt chunk entities with their chunk coordinates.
+    belt_chunks: Vec<((i64, i64), (String, Value))>,
+    balances: BTreeMap<u32, NetworkBalance>,
+    research: Vec<(String, Value)>,
 }
 
-fn parse_jwt_claims_unverified(token: &str) -> Result<JwtClaims> {
-    let parts: Vec<&str> = token.split('.').collect();
-    if parts.len() != 3 {
-        return Err(Error::RustError("invalid JWT token format".into()));
-    }
-
-    let payload = URL_SAFE_NO_PAD
-        .decode(parts[1])
-        .map_err(|_| Error::RustError("invalid JWT payload encoding".into()))?;
-
-    serde_json::from_slice::<JwtClaims>(&payload)
-        .map_err(|_| Error::RustError("invalid JWT payload".into()))
+struct InterestChange {
+    entered_players: Vec<String>,
+    left_players: Vec<String>,
+    entered_structures: Vec<String>,
+    left_structures: Vec<String>,
 }
 
-async fn verify_clerk_session(secret_key: &str, session_id: &str, player_id: &str) -> Result<()> {
-    let url = format!("https://api.clerk.com/v1/sessions/{session_id}");
-
-    let headers = Headers::new();
-    headers.set("authorization", &format!("Bearer {secret_key}"))?;
-
-    let mut init = RequestInit::new();
-    init.with_method(Method::Get);
-    init.with_headers(headers);
+/// What a removal took out of the room, kept until the removal is saved.
+struct RemovedStructure {
+    structure: RuntimeStructureState,
+    belt: Option<BeltState>,
+    inserter: Option<InserterState>,
+    /// The remover's inventory before the refund.
+    inventory: Inventory,
+}
 
-    let request = Request::new_with_init(&url, &init)?;
-    let mut response = Fetch::Request(request).send().await?;
+#[derive(Debug, Default)]
+struct RoomRuntimeState {
+    players: HashMap<String, RuntimePlayerState>,
+    structures: HashMap<String, RuntimeStructureState>,
+    previews: HashMap<String, RuntimePreviewState>,
+    projectiles: HashMap<String, RuntimeProjectileState>,
+    members: HashMap<String, RoomMemberState>,
+    // Owner of every team that has members.
+    team_owners: HashMap<String, String>,
+    // Open `(player, team)` invites from team owners; not persisted.
+    team_invites: HashSet<(String, String)>,
+    // Structures whose machine state changed since the last checkpoint.
+    dirty_machines: HashSet<String>,
+    // Structures whose hp changed since the last checkpoint.
+    dirty_structure_health: HashSet<String>,
+    // Structure id by every grid cell it covers; used for placement checks and
+    // by machines that interact with their neighbours.
+    structure_cells: HashMap<(i32, i32), String>,
+    belts: BeltGrid,
+    // Belt cells whose contents changed since the last checkpoint.
+    dirty_belts: HashSet<(i32, i32)>,
+    inserters: InserterGrid,
+    // Colliders of solid structures by id, kept in step with `structures`.
+    obstacles: ObstacleIndex<String>,
+    enemies: HashMap<String, RuntimeEnemyState>,
+    // When each nest near a player last spawned (or was first seen).
+    nest_spawned_at: HashMap<(i32, i32), i64>,
+    next_enemy_id: u64,
+    // Paths towards solid structures; dropped whenever one is built or removed.
+    flow_field: Option<FlowField>,
+    power: PowerGrid<String>,
+    research: ResearchState,
+}
 
-    if response.status_code() != 200 {
-        return Err(Error::RustError("Clerk session verification failed".into()));
+impl RoomRuntimeState {
+    /// Belt and inserter state goes into the grids separately.
+    fn insert_structure(&mut self, structure: RuntimeStructureState) {
+        if structure_is_solid(&structure.kind) {
+            self.obstacles
+                .insert(structure.structure_id.clone(), structure.obstacle());
+            self.flow_field = None;
+        }
+        for cell in structure.footprint().cells(structure.cell()) {
+            self.structure_cells
+                .insert(cell, structure.structure_id.clone());
+        }
+        self.power.insert(
+            structure.structure_id.clone(),
+            &structure.kind,
+            structure.cell(),
+            structure.footprint(),
+        );
+        self.structures
+            .insert(structure.structure_id.clone(), structure);
     }
 
-    let clerk_session: ClerkSessionResponse = response.json().await?;
-    if clerk_session.user_id != player_id {
-        return Err(Error::RustError("session user mismatch".into()));
+    /// Removes a structure along with its belt or inserter state.
+    fn remove_structure(&mut self, structure_id: &str) -> Option<RuntimeStructureState> {
+        let structure = self.structures.remove(structure_id)?;
+        if self.obstacles.remove(&structure.structure_id).is_some() {
+            self.flow_field = None;
+        }
+        self.power.remove(&structure.structure_id);
+        for cell in structure.footprint().cells(structure.cell()) {
+            if self.structure_cells.get(&cell) == Some(&structure.structure_id) {
+                self.structure_cells.remove(&cell);
+            }
+        }
+        let cell = structure.cell();
+        if structure.has_behavior(StructureBehavior::Belt) {
+            self.belts.remove(cell);
+            self.dirty_belts.remove(&cell);
+        }
+        if structure.has_behavior(StructureBehavior::Inserter) {
+            self.inserters.remove(cell);
+        }
+        Some(structure)
     }
 
-    if let Some(status) = clerk_session.status {
-        if status != "active" {
-            return Err(Error::RustError("session not active".into()));
+    /// Removes a structure and refunds it into `player_id`'s inventory, or
+    /// does neither if the refund does not fit.
+    fn remove_structure_with_refund(
+        &mut self,
+        structure_id: &str,
+        player_id: &str,
+    ) -> Option<RemovedStructure> {
+        let structure = self.structures.get(structure_id)?;
+        let refund = structure.refund_items(self);
+        let cell = structure.cell();
+        let belt = self
+            .belts
+            .get(cell)
+            .filter(|_| structure.has_behavior(StructureBehavior::Belt))
+            .cloned();
+        let inserter = self
+            .inserters
+            .get(cell)
+            .filter(|_| structure.has_behavior(StructureBehavior::Inserter))
+            .copied();
+        let player = self.players.get_mut(player_id)?;
+        let inventory = player.inventory.clone();
+        if !player.inventory.insert_all(&refund) {
+            return None;
         }
+        Some(RemovedStructure {
+            structure: self.remove_structure(structure_id)?,
+            belt,
+            inserter,
+            inventory,
+        })
     }
 
-    Ok(())
-}
+    /// Undoes [`RoomRuntimeState::remove_structure_with_refund`].
+    fn restore_removed_structure(&mut self, removed: RemovedStructure, player_id: &str) {
+        if let Some(player) = self.players.get_mut(player_id) {
+            player.inventory = removed.inventory;
+        }
+        let cell = removed.structure.cell();
+        if let Some(belt) = removed.belt {
+            self.belts.insert(cell, belt);
+        }
+        if let Some(inserter) = removed.inserter {
+            self.inserters.insert(cell, inserter);
+        }
+        self.insert_structure(removed.structure);
+    }
 
-async fn authenticate_player(url: &Url, env: &Env) -> Result<String> {
-    let player_id_from_query = parse_query_param(url, "playerId")
-        .as_deref()
-        .and_then(sanitize_player_id);
+    /// Rebuilds structures from their saved rows, oldest first. Returns the
+    /// structures dropped for overlapping an older one, with their owners,
+    /// who got the refund where they have a saved inventory.
+    fn hydrate_structures(
+        &mut self,
+        rows: Vec<BuildRow>,
+        terrain: &Terrain,
+        now: i64,
+    ) -> Vec<(String, String)> {
+        let mut dropped = Vec::new();
+        for row in rows {
+            let grid_x = row.grid_x.unwrap_or_else(|| snap_axis_to_grid(row.x));
+            let grid_y = row.grid_y.unwrap_or_else(|| snap_axis_to_grid(row.y));
+            let max_hp = structure_max_hp(row.kind.as_str());
+            let hp = row
+                .hp
+                .map_or(max_hp, |hp| hp.clamp(1, max_hp as i64) as i32);
+            let record: MachineStateRecord = row
+                .machine_state
+                .as_deref()
+                .and_then(|raw| serde_json::from_str(raw).ok())
+                .unwrap_or_default();
+            // Belts and inserters saved before rotation was stored keep their
+            // direction in the machine state.
+            let saved_direction = row.direction.as_deref().or_else(|| {
+                record
+                    .belt
+                    .as_ref()
+                    .map(|belt| belt.direction.as_str())
+                    .or(record
+                        .inserter
+                        .as_ref()
+                        .map(|inserter| inserter.direction.as_str()))
+            });
+            let direction = saved_direction
+                .and_then(Direction::parse)
+                .unwrap_or_default();
+            let cell = (grid_x as i32, grid_y as i32);
+            let footprint = Footprint::for_structure(row.kind.as_str(), direction);
+            let (x, y) = footprint.center(cell);
+            let miner = miner_for_structure(row.kind.as_str(), terrain, footprint, cell).map(
+                |mut miner| {
+                    if let Some(saved) = record.miner {
+                        miner.output_count = saved.output_count.min(MINER_OUTPUT_CAPACITY);
+                        miner.progress = saved.progress.clamp(0.0, 1.0);
+                    }
+                    miner
+                },
+            );
+            let assembler = assembler_for_structure(row.kind.as_str()).map(|assembler| {
+                record
+                    .assembler
+                    .as_ref()
+                    .map_or(assembler, AssemblerRecord::restore)
+            });
+            let chest = chest_for_structure(row.kind.as_str()).map(|chest| {
+                record
+                    .chest
+                    .as_deref()
+                    .map_or(chest, |slots| inventory_from_records(CHEST_SLOTS, slots))
+            });
+            let lab = lab_for_structure(row.kind.as_str())
+                .map(|lab| record.lab.as_ref().map_or(lab, LabRecord::restore));
+            let belt =
+                structure_has_behavior(row.kind.as_str(), StructureBehavior::Belt).then(|| {
+                    record
+                        .belt
+                        .as_ref()
+                        .map_or_else(|| BeltState::new(direction), |belt| belt.restore(direction))
+                });
+            let inserter = structure_has_behavior(row.kind.as_str(), StructureBehavior::Inserter)
+                .then(|| {
+                    record.inserter.as_ref().map_or_else(
+                        || InserterState::new(direction),
+                        |inserter| inserter.restore(direction),
+                    )
+                });
+            let structure = RuntimeStructureState {
+                structure_id: row.structure_id,
+                owner_id: row.owner_id,
+                kind: row.kind,
+                x,
+                y,
+                grid_x,
+                grid_y,
+                chunk_x: chunk_coord_for_grid(grid_x),
+                chunk_y: chunk_coord_for_grid(grid_y),
+                created_at: row.created_at.unwrap_or(now),
+                health: Health {
+                    current: hp,
+                    max: max_hp,
+                },
+                miner,
+                assembler,
+                chest,
+                lab,
+                direction,
+            };
 
-    let token = parse_query_param(url, "token");
-    let secret_key = env
-        .secret("CLERK_SECRET_KEY")
-        .ok()
-        .map(|secret| secret.to_string());
+            // Rows saved as single cells can overlap once they grow to their
+            // real footprint; the newer one is dropped and refunded.
+            if footprint
+                .cells(cell)
+                .any(|cell| self.structure_cells.contains_key(&cell))
+            {
+                let mut held = RoomRuntimeState::default();
+                if let Some(belt) = belt {
+                    held.belts.insert(cell, belt);
+                }
+                if let Some(inserter) = inserter {
+                    held.inserters.insert(cell, inserter);
+                }
+                if let Some(owner) = self.players.get_mut(&structure.owner_id) {
+                    for stack in structure.refund_items(&held) {
+                        owner.inventory.insert(stack.item, stack.count);
+                    }
+                }
+                dropped.push((structure.structure_id, structure.owner_id));
+                continue;
+            }
+
+            if let Some(belt) = belt {
+                self.belts.insert(cell, belt);
+            }
+            if let Some(inserter) = inserter {
+                self.inserters.insert(cell, inserter);
+            }
+            self.insert_structure(structure);
+        }
+        dropped
+    }
+}
 
-    if let Some(secret_key) = secret_key {
-        let token = token.ok_or_else(|| Error::RustError("missing Clerk token".into()))?;
-        let claims = parse_jwt_claims_unverified(&token)?;
+/// What inserters see of the room: miners, assemblers, chests and labs by
+/// cell, plus belts.
+/// Everything it touches is marked for the next checkpoint.
+struct RuntimeInserterWorld<'a> {
+    structures: &'a mut HashMap<String, RuntimeStructureState>,
+    structure_cells: &'a HashMap<(i32, i32), String>,
+    belts: &'a mut BeltGrid,
+    dirty_machines: &'a mut HashSet<String>,
+    dirty_belts: &'a mut HashSet<(i32, i32)>,
+}
 
-        let player_id = player_id_from_query
-            .clone()
-            .unwrap_or_else(|| claims.sub.clone());
-        let player_id = sanitize_player_id(&player_id)
-            .ok_or_else(|| Error::RustError("invalid player id".into()))?;
+impl RuntimeInserterWorld<'_> {
+    fn structure(&self, cell: (i32, i32)) -> Option<&RuntimeStructureState> {
+        self.structures.get(self.structure_cells.get(&cell)?)
+    }
 
-        if claims.sub != player_id {
-            return Err(Error::RustError(
-                "token subject does not match player id".into(),
-            ));
+    fn structure_mut(&mut self, cell: (i32, i32)) -> Option<&mut RuntimeStructureState> {
+        let structure = self.structures.get_mut(self.structure_cells.get(&cell)?)?;
+        self.dirty_machines.insert(structure.structure_id.clone());
+        Some(structure)
+    }
+}
+
+impl InserterWorld for RuntimeInserterWorld<'_> {
+    fn pickable_items(&self, cell: (i32, i32)) -> Vec<ItemKind> {
+        let Some(structure) = self.structure(cell) else {
+            return Vec::new();
+        };
+        if let Some(miner) = structure.miner {
+            return miner
+                .output_item
+                .filter(|_| miner.output_count > 0)
+                .into_iter()
+                .collect();
+        }
+        if let Some(assembler) = structure.assembler.as_ref() {
+            return assembler
+                .outputs
+                .iter()
+                .filter(|slot| slot.count > 0)
+                .map(|slot| slot.item)
+                .collect();
+        }
+        if let Some(chest) = structure.chest.as_ref() {
+            let items: BTreeSet<ItemKind> = chest
+                .slots()
+                .iter()
+                .flatten()
+                .map(|stack| stack.item)
+                .collect();
+            return items.into_iter().collect();
+        }
+        self.belts.pickable_items(cell)
+    }
+
+    fn take_item(&mut self, cell: (i32, i32), item: ItemKind) -> bool {
+        if self.belts.take_item(cell, item) {
+            self.dirty_belts.insert(cell);
+            return true;
+        }
+        let Some(structure) = self.structure_mut(cell) else {
+            return false;
+        };
+        if let Some(miner) = structure.miner.as_mut() {
+            return miner.take_output(1).is_some();
+        }
+        if let Some(chest) = structure.chest.as_mut() {
+            return chest.remove(item, 1);
+        }
+        structure
+            .assembler
+            .as_mut()
+            .is_some_and(|assembler| assembler.take_item(item))
+    }
+
+    fn can_accept(&self, cell: (i32, i32), item: ItemKind, direction: Direction) -> bool {
+        if let Some(structure) = self.structure(cell) {
+            if let Some(assembler) = structure.assembler.as_ref() {
+                return assembler.input_room(item) > 0;
+            }
+            if let Some(chest) = structure.chest.as_ref() {
+                return chest.room_for(item) > 0;
+            }
+            if let Some(lab) = structure.lab.as_ref() {
+                return lab.input_room(item) > 0;
+            }
         }
+        self.belts.can_drop(cell, direction)
+    }
 
-        let exp = claims
-            .exp
-            .ok_or_else(|| Error::RustError("token missing exp claim".into()))?;
-        if exp <= now_seconds() {
-            return Err(Error::RustError("token expired".into()));
+    fn drop_item(&mut self, cell: (i32, i32), item: ItemKind, direction: Direction) -> bool {
+        if self.belts.drop_item(cell, item, direction) {
+            self.dirty_belts.insert(cell);
+            return true;
         }
+        let Some(structure) = self.structure_mut(cell) else {
+            return false;
+        };
+        if let Some(chest) = structure.chest.as_mut() {
+            return chest.insert(item, 1) == 1;
+        }
+        if let Some(lab) = structure.lab.as_mut() {
+            return lab.insert(item, 1) == 1;
+        }
+        structure
+            .assembler
+            .as_mut()
+            .is_some_and(|assembler| assembler.insert_input(item, 1) == 1)
+    }
+}
+
+fn now_ms() -> i64 {
+    Date::now().as_millis() as i64
+}
+
+fn now_seconds() -> i64 {
+    now_ms() / 1000
+}
+
+/// Whether advancing the sim from `from_tick` to `to_tick` passed a snapshot
+/// boundary.
+fn crosses_snapshot_interval(from_tick: u64, to_tick: u64) -> bool {
+    to_tick / SNAPSHOT_INTERVAL_TICKS > from_tick / SNAPSHOT_INTERVAL_TICKS
+}
+
+/// Time until the next `SIM_DT_MS` boundary, given what is already banked.
+fn tick_delay_ms(accumulator_ms: f64) -> u64 {
+    let remaining = (SIM_DT_MS - accumulator_ms).ceil();
+    (remaining.max(0.0) as u64).max(TICK_ALARM_MIN_DELAY_MS)
+}
+
+/// When the tick alarm should fire again, or `None` once nobody is connected
+/// and the loop should stop.
+fn next_alarm_delay_ms(open_sockets: usize, accumulator_ms: f64) -> Option<u64> {
+    (open_sockets > 0).then(|| tick_delay_ms(accumulator_ms))
+}
+
+fn sanitize_room_code(input: &str) -> Option<String> {
+    let candidate = input.trim().to_ascii_uppercase();
+    if candidate.is_empty() || candidate.len() > ROOM_RE_MAX {
+        return None;
+    }
+
+    if candidate
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-')
+    {
+        Some(candidate)
+    } else {
+        None
+    }
+}
+
+fn sanitize_player_id(input: &str) -> Option<String> {
+    let candidate = input.trim();
+    if candidate.len() < PLAYER_ID_RE_MIN || candidate.len() > PLAYER_ID_RE_MAX {
+        return None;
+    }
+
+    if candidate
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-')
+    {
+        Some(candidate.to_string())
+    } else {
+        None
+    }
+}
+
+fn random_player_id() -> String {
+    let time = now_ms() as u64;
+    let random = (js_sys::Math::random() * 1_000_000_000.0) as u64;
+    format!("anon_{:x}", time ^ random)
+}
+
+fn parse_room_code_from_path(path: &str) -> Option<String> {
+    let parts: Vec<&str> = path.split('/').collect();
+    if parts.len() != 5 {
+        return None;
+    }
+
+    if parts[1] != "api" || parts[2] != "rooms" || parts[4] != "ws" {
+        return None;
+    }
+
+    sanitize_room_code(parts[3])
+}
 
-        let session_id = claims
-            .sid
-            .ok_or_else(|| Error::RustError("token missing sid claim".into()))?;
-        verify_clerk_session(&secret_key, &session_id, &player_id).await?;
+fn parse_query_param(url: &Url, key: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(name, _)| name == key)
+        .map(|(_, value)| value.into_owned())
+}
 
-        return Ok(player_id);
+/// Wire protocol requested by the client: `protocol=3` selects binary
+/// envelopes, anything else keeps JSON v2.
+fn parse_protocol_version(url: &Url) -> u32 {
+    match parse_query_param(url, "protocol").as_deref() {
+        Some("3") => BINARY_PROTOCOL_VERSION,
+        _ => PROTOCOL_VERSION,
     }
+}
 
-    Ok(player_id_from_query.unwrap_or_else(random_player_id))
+fn add_column_if_missing(sql: &SqlStorage, statement: &str) -> Result<()> {
+    if let Err(error) = sql.exec(statement, None) {
+        let message = format!("{error}");
+        if !message.contains("duplicate column") {
+            return Err(error);
+        }
+    }
+    Ok(())
 }
 
 fn json_response(payload: Value, status: u16) -> Result<Response> {
@@ -408,15 +1380,261 @@ fn map_input_to_core(input: &InputState) -> CoreInputState {
         down: input.down,
         left: input.left,
         right: input.right,
+        move_x: input.move_x,
+        move_y: input.move_y,
+        sprint: input.sprint,
+    }
+}
+
+/// Clamps each analog axis into range and the stick vector to unit length.
+fn input_from_command(command: &InputCommand) -> InputState {
+    let axis_max = ANALOG_AXIS_MAX as i32;
+    let axis = |value: i32| value.clamp(-axis_max, axis_max) as i8;
+    let clamped = CoreInputState {
+        up: command.up,
+        down: command.down,
+        left: command.left,
+        right: command.right,
+        move_x: axis(command.move_x),
+        move_y: axis(command.move_y),
+        sprint: command.sprint,
+    }
+    .clamped();
+    InputState {
+        up: clamped.up,
+        down: clamped.down,
+        left: clamped.left,
+        right: clamped.right,
+        move_x: clamped.move_x,
+        move_y: clamped.move_y,
+        sprint: clamped.sprint,
+    }
+}
+
+/// Fresh miner state for a miner standing on `(grid_x, grid_y)`; `None` for other kinds.
+/// Miners mine the first ore tile under their footprint.
+fn miner_for_structure(
+    kind: &str,
+    terrain: &Terrain,
+    footprint: Footprint,
+    cell: (i32, i32),
+) -> Option<MinerState> {
+    structure_has_behavior(kind, StructureBehavior::Miner).then(|| {
+        let tile = footprint
+            .cells(cell)
+            .map(|(x, y)| terrain.tile_at(x, y))
+            .find(|tile| tile.ore().is_some())
+            .unwrap_or_else(|| terrain.tile_at(cell.0, cell.1));
+        MinerState::on_tile(tile)
+    })
+}
+
+/// Fresh (recipe-less) assembler state for assemblers; `None` for other kinds.
+fn assembler_for_structure(kind: &str) -> Option<AssemblerState> {
+    structure_has_behavior(kind, StructureBehavior::Assembler).then(AssemblerState::default)
+}
+
+/// Empty storage for chests; `None` for other kinds.
+fn chest_for_structure(kind: &str) -> Option<Inventory> {
+    structure_has_behavior(kind, StructureBehavior::Chest).then(|| Inventory::empty(CHEST_SLOTS))
+}
+
+/// Fresh (empty) lab state for labs; `None` for other kinds.
+fn lab_for_structure(kind: &str) -> Option<LabState> {
+    structure_has_behavior(kind, StructureBehavior::Lab).then(LabState::default)
+}
+
+/// Replicated whole in `features.research`; the technology list itself is
+/// compiled into clients.
+fn research_json(research: &ResearchState) -> Value {
+    json!({
+        "current": research.current().map(|technology| technology.id),
+        "progress": research.progress_entries().collect::<BTreeMap<_, _>>(),
+        "unlocked": research.unlocked().collect::<Vec<_>>(),
+    })
+}
+
+fn item_slots_json(slots: &[ItemStack]) -> Value {
+    Value::Array(
+        slots
+            .iter()
+            .map(|slot| json!({ "item": slot.item.as_str(), "count": slot.count }))
+            .collect(),
+    )
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ItemStackRecord {
+    item: String,
+    count: u32,
+}
+
+/// Inventory slots as stored in `player_inventories.slots` and replicated in
+/// `features.inventory`; empty slots are `null`.
+fn inventory_json(inventory: &Inventory) -> Value {
+    Value::Array(
+        inventory
+            .slots()
+            .iter()
+            .map(|slot| match slot {
+                Some(stack) => json!({ "item": stack.item.as_str(), "count": stack.count }),
+                None => Value::Null,
+            })
+            .collect(),
+    )
+}
+
+fn inventory_records(inventory: &Inventory) -> Vec<Option<ItemStackRecord>> {
+    inventory
+        .slots()
+        .iter()
+        .map(|slot| {
+            slot.map(|stack| ItemStackRecord {
+                item: stack.item.as_str().to_string(),
+                count: stack.count,
+            })
+        })
+        .collect()
+}
+
+/// Saved slots with unknown items dropped, sized to `slot_count`.
+fn inventory_from_records(slot_count: usize, slots: &[Option<ItemStackRecord>]) -> Inventory {
+    Inventory::restore(
+        slot_count,
+        slots.iter().map(|slot| {
+            let record = slot.as_ref()?;
+            Some(ItemStack {
+                item: ItemKind::parse(&record.item)?,
+                count: record.count,
+            })
+        }),
+    )
+}
+
+fn inventory_from_json(raw: &str) -> Option<Inventory> {
+    let slots: Vec<Option<ItemStackRecord>> = serde_json::from_str(raw).ok()?;
+    Some(inventory_from_records(INVENTORY_SLOTS, &slots))
+}
+
+fn structure_json(
+    structure: &RuntimeStructureState,
+    inserters: &InserterGrid,
+    power: &PowerGrid<String>,
+) -> Value {
+    let mut value = json!({
+        "id": structure.structure_id,
+        "ownerId": structure.owner_id,
+        "kind": structure.kind,
+        "x": structure.x,
+        "y": structure.y,
+        "chunkX": structure.chunk_x,
+        "chunkY": structure.chunk_y,
+        "direction": structure.direction.as_str(),
+    });
+    // Swing ticks stay server-side; clients animate a swing from the phase change.
+    if let Some(inserter) = inserters
+        .get(structure.cell())
+        .filter(|_| structure.has_behavior(StructureBehavior::Inserter))
+    {
+        value["inserter"] = json!({
+            "held": inserter.held.map(ItemKind::as_str),
+            "phase": inserter.phase.as_str(),
+        });
+    }
+    // Satisfaction is per network, in the `power` channel.
+    if power_role(&structure.kind).is_some() {
+        value["power"] = json!({
+            "network": power
+                .network_of(&structure.structure_id)
+                .map(|network| network.to_string()),
+        });
     }
+    // Progress is left out on purpose: it changes every tick and would defeat the delta.
+    if let Some(miner) = structure.miner {
+        value["miner"] = json!({
+            "item": miner.output_item.map(ItemKind::as_str),
+            "output": miner.output_count,
+            "capacity": MINER_OUTPUT_CAPACITY,
+            "working": miner.is_working(),
+        });
+    }
+    // Crafts are short enough that clients need progress to animate them; it is
+    // quantized so a running assembler changes its fingerprint a few times per craft.
+    if let Some(assembler) = structure.assembler.as_ref() {
+        value["assembler"] = json!({
+            "recipe": assembler.recipe.map(|recipe| recipe.id),
+            "inputs": item_slots_json(&assembler.inputs),
+            "outputs": item_slots_json(&assembler.outputs),
+            "progress": (assembler.progress * ASSEMBLER_PROGRESS_STEPS).floor() / ASSEMBLER_PROGRESS_STEPS,
+            "crafting": assembler.is_working(),
+        });
+    }
+    if let Some(lab) = structure.lab {
+        value["lab"] = json!({
+            "packs": lab.packs,
+            "capacity": LAB_PACK_CAPACITY,
+            "researching": lab.researching,
+        });
+    }
+    value
+}
+
+/// Belt contents are replicated one entity per build chunk rather than one per
+/// item. `items` is a flat list of `[cell, lane, position, paletteIndex]`
+/// quadruples, where `cell` is `localY * BUILD_CHUNK_CELLS + localX` and
+/// `paletteIndex` points into the chunk's `palette` of item names.
+fn belt_chunks_json(belts: &BeltGrid) -> Vec<((i64, i64), (String, Value))> {
+    let mut chunks: BTreeMap<(i64, i64), (Vec<ItemKind>, Vec<u32>)> = BTreeMap::new();
+    for (&(grid_x, grid_y), belt) in belts.iter() {
+        if belt.is_empty() {
+            continue;
+        }
+        let (grid_x, grid_y) = (grid_x as i64, grid_y as i64);
+        let (chunk_x, chunk_y) = (chunk_coord_for_grid(grid_x), chunk_coord_for_grid(grid_y));
+        let cell = (grid_y - chunk_y * BUILD_CHUNK_CELLS) * BUILD_CHUNK_CELLS
+            + (grid_x - chunk_x * BUILD_CHUNK_CELLS);
+        let (palette, items) = chunks.entry((chunk_x, chunk_y)).or_default();
+        for (lane, lane_items) in belt.lanes.iter().enumerate() {
+            for item in lane_items {
+                let index = palette
+                    .iter()
+                    .position(|kind| *kind == item.item)
+                    .unwrap_or_else(|| {
+                        palette.push(item.item);
+                        palette.len() - 1
+                    });
+                items.extend([cell as u32, lane as u32, item.position as u32, index as u32]);
+            }
+        }
+    }
+
+    chunks
+        .into_iter()
+        .map(|((chunk_x, chunk_y), (palette, items))| {
+            let id = format!("{chunk_x}:{chunk_y}");
+            let value = json!({
+                "id": id,
+                "chunkX": chunk_x,
+                "chunkY": chunk_y,
+                "palette": palette.into_iter().map(ItemKind::as_str).collect::<Vec<_>>(),
+                "items": items,
+            });
+            ((chunk_x, chunk_y), (id, value))
+        })
+        .collect()
 }
 
+/// Every kind with a prototype can be built; whether the room may build it
+/// yet is up to research.
 fn is_valid_structure_kind(kind: &str) -> bool {
-    matches!(kind, "beacon" | "miner" | "assembler")
+    structure_prototype(kind).is_some()
 }
 
-fn structure_half_extent(_kind: &str) -> f32 {
-    STRUCTURE_COLLIDER_HALF_EXTENT
+/// Placement direction; omitted means east.
+fn parse_direction(value: Option<&str>) -> Result<Direction> {
+    value.map_or(Ok(Direction::default()), |value| {
+        Direction::parse(value).ok_or_else(|| Error::RustError("invalid direction".into()))
+    })
 }
 
 fn snap_axis_to_grid(value: f64) -> i64 {
@@ -424,10 +1642,6 @@ fn snap_axis_to_grid(value: f64) -> i64 {
     (clamped / BUILD_GRID_SIZE).round() as i64
 }
 
-fn grid_cell_center(grid_axis: i64) -> f64 {
-    grid_axis as f64 * BUILD_GRID_SIZE
-}
-
 fn chunk_coord_for_grid(grid_axis: i64) -> i64 {
     if grid_axis >= 0 {
         grid_axis / BUILD_CHUNK_CELLS
@@ -436,6 +1650,217 @@ fn chunk_coord_for_grid(grid_axis: i64) -> i64 {
     }
 }
 
+fn chunk_coord_for_world(value: f32) -> i64 {
+    chunk_coord_for_grid(snap_axis_to_grid(value as f64))
+}
+
+fn entity_fingerprint(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.to_string().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Diffs one entity channel against the client's baseline fingerprints.
+/// Returns the `{ upserts, removes }` payload, whether it carries any change,
+/// and the fingerprints to remember for this snapshot.
+fn diff_entity_channel(
+    entities: &[(String, Value)],
+    baseline: Option<&HashMap<String, u64>>,
+) -> (Value, bool, HashMap<String, u64>) {
+    let mut fingerprints = HashMap::with_capacity(entities.len());
+    let mut upserts = Vec::new();
+    for (entity_id, value) in entities {
+        let fingerprint = entity_fingerprint(value);
+        if baseline.and_then(|state| state.get(entity_id)) != Some(&fingerprint) {
+            upserts.push(value.clone());
+        }
+        fingerprints.insert(entity_id.clone(), fingerprint);
+    }
+
+    let removes: Vec<&String> = baseline
+        .map(|state| {
+            state
+                .keys()
+                .filter(|entity_id| !fingerprints.contains_key(*entity_id))
+                .collect()
+        })
+        .unwrap_or_default();
+    let changed = !upserts.is_empty() || !removes.is_empty();
+
+    (
+        json!({
+            "upserts": upserts,
+            "removes": removes,
+        }),
+        changed,
+        fingerprints,
+    )
+}
+
+fn sanitize_team_id(input: &str) -> Option<String> {
+    let candidate = input.trim();
+    if candidate.is_empty() || candidate.len() > MAX_TEAM_ID_LEN {
+        return None;
+    }
+
+    if candidate
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-')
+    {
+        Some(candidate.to_string())
+    } else {
+        None
+    }
+}
+
+fn is_room_admin(
+    player_id: &str,
+    members: &HashMap<String, RoomMemberState>,
+    global_admins: &HashSet<String>,
+) -> bool {
+    global_admins.contains(player_id)
+        || members
+            .get(player_id)
+            .is_some_and(|member| member.role == RoomRole::Admin)
+}
+
+/// Only the player who created the room starts out as its admin; joining
+/// first grants nothing.
+fn initial_room_role(player_id: &str, creator_id: Option<&str>) -> RoomRole {
+    if creator_id == Some(player_id) {
+        RoomRole::Admin
+    } else {
+        RoomRole::Member
+    }
+}
+
+/// What a permitted `team.join` does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TeamJoin {
+    /// The target is put on the team.
+    Join,
+    /// The target is invited and joins once they send `team.join` themselves.
+    Invite,
+}
+
+/// Teammates share structures, so nobody ends up on a team without asking:
+/// anyone may start a team nobody is on yet, but joining an existing one
+/// takes an invite from its owner. Only room admins put other players on a
+/// team directly.
+fn check_team_join(
+    actor_id: &str,
+    target_id: &str,
+    team_id: &str,
+    members: &HashMap<String, RoomMemberState>,
+    team_owners: &HashMap<String, String>,
+    team_invites: &HashSet<(String, String)>,
+    global_admins: &HashSet<String>,
+) -> Result<TeamJoin> {
+    if !members.contains_key(target_id) {
+        return Err(Error::RustError("player is not a room member".into()));
+    }
+    if is_room_admin(actor_id, members, global_admins) {
+        return Ok(TeamJoin::Join);
+    }
+
+    let owner_id = team_owners.get(team_id);
+    if actor_id != target_id {
+        return if owner_id.is_some_and(|owner_id| owner_id == actor_id) {
+            Ok(TeamJoin::Invite)
+        } else {
+            Err(Error::RustError(
+                "only the team owner can invite players".into(),
+            ))
+        };
+    }
+    if owner_id.is_none() || team_invites.contains(&(target_id.to_string(), team_id.to_string())) {
+        Ok(TeamJoin::Join)
+    } else {
+        Err(Error::RustError(
+            "joining this team takes an invite from its owner".into(),
+        ))
+    }
+}
+
+/// Who takes over a team when its owner leaves: the remaining member with
+/// the lowest id, or nobody once the team is empty.
+fn next_team_owner(team_id: &str, members: &HashMap<String, RoomMemberState>) -> Option<String> {
+    members
+        .iter()
+        .filter(|(_, member)| member.team_id.as_deref() == Some(team_id))
+        .map(|(player_id, _)| player_id)
+        .min()
+        .cloned()
+}
+
+/// Owners, members of the owner's team, and room admins may remove or
+/// reconfigure a structure.
+fn can_modify_structure(
+    actor_id: &str,
+    owner_id: &str,
+    members: &HashMap<String, RoomMemberState>,
+    global_admins: &HashSet<String>,
+) -> bool {
+    actor_id == owner_id
+        || is_room_admin(actor_id, members, global_admins)
+        || same_team(actor_id, owner_id, members)
+}
+
+/// Structures take damage from enemies and from players outside the owner's
+/// team, so nobody can shoot down their own or a teammate's base.
+fn structure_damaged_by(
+    attacker_id: &str,
+    owner_id: &str,
+    members: &HashMap<String, RoomMemberState>,
+) -> bool {
+    attacker_id != owner_id && !same_team(attacker_id, owner_id, members)
+}
+
+fn same_team(a: &str, b: &str, members: &HashMap<String, RoomMemberState>) -> bool {
+    match (
+        members.get(a).and_then(|member| member.team_id.as_deref()),
+        members.get(b).and_then(|member| member.team_id.as_deref()),
+    ) {
+        (Some(a_team), Some(b_team)) => a_team == b_team,
+        _ => false,
+    }
+}
+
+fn build_interaction_distance_from_env(env: &Env) -> f32 {
+    env.var("BUILD_INTERACTION_DISTANCE")
+        .ok()
+        .and_then(|value| value.to_string().trim().parse::<f32>().ok())
+        .filter(|distance| distance.is_finite())
+        .map(|distance| {
+            distance.clamp(
+                MIN_BUILD_INTERACTION_DISTANCE,
+                MAX_BUILD_INTERACTION_DISTANCE,
+            )
+        })
+        .unwrap_or(DEFAULT_BUILD_INTERACTION_DISTANCE)
+}
+
+/// Player ids from the comma-separated `ROOM_ADMIN_IDS` var; admins in every room.
+fn room_admin_ids_from_env(env: &Env) -> HashSet<String> {
+    env.var("ROOM_ADMIN_IDS")
+        .map(|value| {
+            value
+                .to_string()
+                .split(',')
+                .filter_map(sanitize_player_id)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn interest_radius_from_env(env: &Env) -> i64 {
+    env.var("INTEREST_RADIUS_CHUNKS")
+        .ok()
+        .and_then(|value| value.to_string().trim().parse::<i64>().ok())
+        .map(|radius| radius.clamp(1, MAX_INTEREST_RADIUS_CHUNKS))
+        .unwrap_or(DEFAULT_INTEREST_RADIUS_CHUNKS)
+}
+
 #[event(fetch)]
 pub async fn fetch(req: Request, env: Env, _ctx: Context) -> Result<Response> {
     let url = req.url()?;
@@ -513,14 +1938,22 @@ pub struct RoomDurableObject {
     state: State,
     env: Env,
     room_code: RefCell<String>,
+    room_creator_id: RefCell<Option<String>>,
     tick: Cell<u64>,
     last_loop_ms: Cell<f64>,
     accumulator_ms: Cell<f64>,
+    tick_alarm_armed: Cell<bool>,
     last_checkpoint_ms: Cell<i64>,
     snapshot_dirty: Cell<bool>,
     dirty_presence: Cell<bool>,
     dirty_build: Cell<bool>,
     dirty_projectiles: Cell<bool>,
+    dirty_health: Cell<bool>,
+    interest_radius_chunks: Cell<i64>,
+    terrain: Cell<Terrain>,
+    build_interaction_distance: f32,
+    room_admin_ids: HashSet<String>,
+    socket_views: RefCell<HashMap<String, SocketView>>,
     runtime: RefCell<RoomRuntimeState>,
 }
 
@@ -535,18 +1968,24 @@ impl RoomDurableObject {
             y: 0.0,
             vx: 0.0,
             vy: 0.0,
-            input: InputState {
-                up: false,
-                down: false,
-                left: false,
-                right: false,
-            },
+            input: InputState::default(),
+            stamina: Stamina::full(),
+            position_history: PositionHistory::default(),
+            rtt_ms: 0,
             last_input_seq: 0,
             connected: false,
             last_seen: now,
+            health: Health::full(PLAYER_MAX_HP),
+            dead_until: 0,
+            invulnerable_until: 0,
+            respawn_count: 0,
             last_preview_cmd_at: 0,
             last_place_cmd_at: 0,
-            last_projectile_fire_at: 0,
+            last_remove_cmd_at: 0,
+            weapon_cooldowns: WeaponCooldowns::default(),
+            weapon: Weapon::default_weapon(),
+            inventory: Inventory::starter(),
+            open_chest: None,
         }
     }
 
@@ -558,16 +1997,22 @@ impl RoomDurableObject {
             .exec(
                 "
                 SELECT s.player_id, s.x, s.y, s.vx, s.vy,
+                       s.hp, s.dead_until, s.invulnerable_until, s.respawn_count, s.weapon,
                        COALESCE(i.up, 0) AS up,
                        COALESCE(i.down, 0) AS down,
                        COALESCE(i.left, 0) AS left,
                        COALESCE(i.right, 0) AS right,
+                       COALESCE(i.move_x, 0) AS move_x,
+                       COALESCE(i.move_y, 0) AS move_y,
+                       COALESCE(i.sprint, 0) AS sprint,
                        COALESCE(i.last_input_seq, 0) AS last_input_seq,
                        COALESCE(p.connected, 0) AS connected,
-                       COALESCE(p.last_seen, 0) AS last_seen
+                       COALESCE(p.last_seen, 0) AS last_seen,
+                       v.slots AS inventory
                 FROM movement_state s
                 LEFT JOIN movement_input_state i ON i.player_id = s.player_id
                 LEFT JOIN presence_players p ON p.player_id = s.player_id
+                LEFT JOIN player_inventories v ON v.player_id = s.player_id
                 ORDER BY s.player_id ASC
                 ",
                 None,
@@ -577,7 +2022,7 @@ impl RoomDurableObject {
         let structure_rows: Vec<BuildRow> = sql
             .exec(
                 "
-                SELECT structure_id, owner_id, kind, x, y, grid_x, grid_y, created_at
+                SELECT structure_id, owner_id, kind, x, y, grid_x, grid_y, created_at, hp, machine_state, direction
                 FROM build_structures
                 ORDER BY created_at ASC
                 LIMIT ?
@@ -586,11 +2031,60 @@ impl RoomDurableObject {
             )?
             .to_array()?;
 
+        let member_rows: Vec<RoomMemberRow> = sql
+            .exec(
+                "SELECT player_id, role, team_id FROM room_members ORDER BY joined_at ASC",
+                None,
+            )?
+            .to_array()?;
+        let team_rows: Vec<RoomTeamRow> = sql
+            .exec("SELECT team_id, owner_id FROM room_teams", None)?
+            .to_array()?;
+
+        let research = self.load_research_from_db()?;
+
         let mut runtime = self.runtime.borrow_mut();
+        runtime.research = research;
         runtime.players.clear();
         runtime.structures.clear();
         runtime.previews.clear();
         runtime.projectiles.clear();
+        runtime.members.clear();
+        runtime.team_owners.clear();
+        runtime.team_invites.clear();
+        runtime.structure_cells.clear();
+        runtime.obstacles.clear();
+        runtime.power.clear();
+        runtime.belts = BeltGrid::default();
+        runtime.dirty_belts.clear();
+        runtime.inserters = InserterGrid::default();
+
+        for row in team_rows {
+            runtime.team_owners.insert(row.team_id, row.owner_id);
+        }
+        for row in member_rows {
+            // Teams formed before owners were recorded go to their earliest member.
+            if let Some(team_id) = row
+                .team_id
+                .as_ref()
+                .filter(|team_id| !runtime.team_owners.contains_key(*team_id))
+            {
+                runtime
+                    .team_owners
+                    .insert(team_id.clone(), row.player_id.clone());
+                sql.exec(
+                    "INSERT INTO room_teams (team_id, owner_id) VALUES (?, ?)",
+                    Some(vec![team_id.clone().into(), row.player_id.clone().into()]),
+                )?;
+            }
+            runtime.members.insert(
+                row.player_id,
+                RoomMemberState {
+                    role: RoomRole::parse(&row.role),
+                    team_id: row.team_id,
+                },
+            );
+        }
 
         for row in player_rows {
             runtime.players.insert(
@@ -605,39 +2099,54 @@ impl RoomDurableObject {
                         down: row.down != 0,
                         left: row.left != 0,
                         right: row.right != 0,
+                        move_x: row.move_x.clamp(i8::MIN as i64, i8::MAX as i64) as i8,
+                        move_y: row.move_y.clamp(i8::MIN as i64, i8::MAX as i64) as i8,
+                        sprint: row.sprint != 0,
                     },
+                    stamina: Stamina::full(),
+                    position_history: PositionHistory::default(),
+                    rtt_ms: 0,
                     last_input_seq: row.last_input_seq.max(0) as u32,
                     connected: row.connected != 0,
                     last_seen: row.last_seen.max(0),
+                    health: Health {
+                        current: row
+                            .hp
+                            .map_or(PLAYER_MAX_HP, |hp| hp.clamp(0, PLAYER_MAX_HP as i64) as i32),
+                        max: PLAYER_MAX_HP,
+                    },
+                    dead_until: row.dead_until.unwrap_or(0),
+                    invulnerable_until: row.invulnerable_until.unwrap_or(0),
+                    respawn_count: row.respawn_count.unwrap_or(0).max(0) as u32,
                     last_preview_cmd_at: 0,
                     last_place_cmd_at: 0,
-                    last_projectile_fire_at: 0,
-                },
-            );
-        }
-
-        for row in structure_rows {
-            let grid_x = row.grid_x.unwrap_or_else(|| snap_axis_to_grid(row.x));
-            let grid_y = row.grid_y.unwrap_or_else(|| snap_axis_to_grid(row.y));
-            runtime.structures.insert(
-                row.structure_id.clone(),
-                RuntimeStructureState {
-                    structure_id: row.structure_id,
-                    owner_id: row.owner_id,
-                    kind: row.kind,
-                    x: grid_cell_center(grid_x) as f32,
-                    y: grid_cell_center(grid_y) as f32,
-                    grid_x,
-                    grid_y,
-                    chunk_x: chunk_coord_for_grid(grid_x),
-                    chunk_y: chunk_coord_for_grid(grid_y),
-                    created_at: row.created_at.unwrap_or(now),
+                    last_remove_cmd_at: 0,
+                    weapon_cooldowns: WeaponCooldowns::default(),
+                    weapon: row
+                        .weapon
+                        .as_deref()
+                        .and_then(weapon_by_id)
+                        .unwrap_or_else(Weapon::default_weapon),
+                    inventory: row
+                        .inventory
+                        .as_deref()
+                        .and_then(inventory_from_json)
+                        .unwrap_or_else(Inventory::starter),
+                    open_chest: None,
                 },
             );
         }
 
+        let terrain = self.terrain.get();
+        let dropped = runtime.hydrate_structures(structure_rows, &terrain, now);
         drop(runtime);
 
+        for (structure_id, owner_id) in dropped {
+            console_warn!("dropped overlapping structure {structure_id} on load");
+            self.persist_structure_delete(&structure_id)?;
+            self.persist_player_inventory(&owner_id)?;
+        }
+
         // Preview/projectile state is ephemeral and should not survive process hibernation.
         sql.exec("DELETE FROM build_previews", None)?;
         sql.exec("DELETE FROM projectile_state", None)?;
@@ -651,13 +2160,18 @@ impl RoomDurableObject {
         for (player_id, player) in runtime.players.iter() {
             sql.exec(
                 "
-                INSERT INTO movement_state (player_id, x, y, vx, vy, updated_at)
-                VALUES (?, ?, ?, ?, ?, ?)
+                INSERT INTO movement_state (player_id, x, y, vx, vy, hp, dead_until, invulnerable_until, respawn_count, weapon, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
                 ON CONFLICT(player_id) DO UPDATE SET
                   x = excluded.x,
                   y = excluded.y,
                   vx = excluded.vx,
                   vy = excluded.vy,
+                  hp = excluded.hp,
+                  dead_until = excluded.dead_until,
+                  invulnerable_until = excluded.invulnerable_until,
+                  respawn_count = excluded.respawn_count,
+                  weapon = excluded.weapon,
                   updated_at = excluded.updated_at
                 ",
                 Some(vec![
@@ -666,19 +2180,27 @@ impl RoomDurableObject {
                     (player.y as f64).into(),
                     (player.vx as f64).into(),
                     (player.vy as f64).into(),
+                    (player.health.current as i64).into(),
+                    player.dead_until.into(),
+                    player.invulnerable_until.into(),
+                    (player.respawn_count as i64).into(),
+                    player.weapon.id.into(),
                     player.last_seen.into(),
                 ]),
             )?;
 
             sql.exec(
                 "
-                INSERT INTO movement_input_state (player_id, up, down, left, right, last_input_seq, updated_at)
-                VALUES (?, ?, ?, ?, ?, ?, ?)
+                INSERT INTO movement_input_state (player_id, up, down, left, right, move_x, move_y, sprint, last_input_seq, updated_at)
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
                 ON CONFLICT(player_id) DO UPDATE SET
                   up = excluded.up,
                   down = excluded.down,
                   left = excluded.left,
                   right = excluded.right,
+                  move_x = excluded.move_x,
+                  move_y = excluded.move_y,
+                  sprint = excluded.sprint,
                   last_input_seq = excluded.last_input_seq,
                   updated_at = excluded.updated_at
                 ",
@@ -688,6 +2210,9 @@ impl RoomDurableObject {
                     (player.input.down as i64).into(),
                     (player.input.left as i64).into(),
                     (player.input.right as i64).into(),
+                    (player.input.move_x as i64).into(),
+                    (player.input.move_y as i64).into(),
+                    (player.input.sprint as i64).into(),
                     (player.last_input_seq as i64).into(),
                     player.last_seen.into(),
                 ]),
@@ -719,22 +2244,27 @@ impl RoomDurableObject {
         }
 
         self.checkpoint_runtime_players_to_db()?;
+        self.checkpoint_machines_to_db()?;
         self.last_checkpoint_ms.set(now);
         Ok(())
     }
 
     fn persist_structure_insert(&self, structure: &RuntimeStructureState) -> Result<()> {
+        let machine_state = structure.machine_state_json(&self.runtime.borrow());
         self.sql().exec(
             "
-            INSERT INTO build_structures (structure_id, owner_id, kind, x, y, grid_x, grid_y, created_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            INSERT INTO build_structures (structure_id, owner_id, kind, x, y, grid_x, grid_y, created_at, hp, machine_state, direction)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
             ON CONFLICT(structure_id) DO UPDATE SET
               owner_id = excluded.owner_id,
               kind = excluded.kind,
               x = excluded.x,
               y = excluded.y,
               grid_x = excluded.grid_x,
-              grid_y = excluded.grid_y
+              grid_y = excluded.grid_y,
+              hp = excluded.hp,
+              machine_state = excluded.machine_state,
+              direction = excluded.direction
             ",
             Some(vec![
                 structure.structure_id.as_str().into(),
@@ -745,11 +2275,108 @@ impl RoomDurableObject {
                 structure.grid_x.into(),
                 structure.grid_y.into(),
                 structure.created_at.into(),
+                (structure.health.current as i64).into(),
+                machine_state.into(),
+                structure.direction.as_str().into(),
             ]),
         )?;
         Ok(())
     }
 
+    fn checkpoint_machines_to_db(&self) -> Result<()> {
+        let sql = self.sql();
+        let mut runtime = self.runtime.borrow_mut();
+        let dirty: Vec<String> = runtime.dirty_machines.drain().collect();
+
+        for structure_id in dirty {
+            let Some(structure) = runtime.structures.get(&structure_id) else {
+                continue;
+            };
+            sql.exec(
+                "UPDATE build_structures SET machine_state = ? WHERE structure_id = ?",
+                Some(vec![
+                    structure.machine_state_json(&runtime).into(),
+                    structure_id.into(),
+                ]),
+            )?;
+        }
+
+        let dirty_health: Vec<String> = runtime.dirty_structure_health.drain().collect();
+        for structure_id in dirty_health {
+            let Some(structure) = runtime.structures.get(&structure_id) else {
+                continue;
+            };
+            sql.exec(
+                "UPDATE build_structures SET hp = ? WHERE structure_id = ?",
+                Some(vec![
+                    (structure.health.current as i64).into(),
+                    structure_id.into(),
+                ]),
+            )?;
+        }
+
+        let dirty_belts: Vec<(i32, i32)> = runtime.dirty_belts.drain().collect();
+        for cell in dirty_belts {
+            let Some(belt) = runtime.belts.get(cell) else {
+                continue;
+            };
+            let record = MachineStateRecord {
+                belt: Some(BeltRecord::from_state(belt)),
+                ..Default::default()
+            };
+            sql.exec(
+                "UPDATE build_structures SET machine_state = ? WHERE kind = 'belt' AND grid_x = ? AND grid_y = ?",
+                Some(vec![
+                    serde_json::to_string(&record).ok().into(),
+                    (cell.0 as i64).into(),
+                    (cell.1 as i64).into(),
+                ]),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn persist_player_inventory(&self, player_id: &str) -> Result<()> {
+        let slots = {
+            let runtime = self.runtime.borrow();
+            let Some(player) = runtime.players.get(player_id) else {
+                return Ok(());
+            };
+            inventory_json(&player.inventory).to_string()
+        };
+        self.sql().exec(
+            "
+            INSERT INTO player_inventories (player_id, slots, updated_at)
+            VALUES (?, ?, ?)
+            ON CONFLICT(player_id) DO UPDATE SET
+              slots = excluded.slots,
+              updated_at = excluded.updated_at
+            ",
+            Some(vec![player_id.into(), slots.into(), now_ms().into()]),
+        )?;
+        Ok(())
+    }
+
+    /// `None` disbands the team.
+    fn persist_team_owner(&self, team_id: &str, owner_id: Option<&str>) -> Result<()> {
+        match owner_id {
+            Some(owner_id) => self.sql().exec(
+                "
+                INSERT INTO room_teams (team_id, owner_id)
+                VALUES (?, ?)
+                ON CONFLICT(team_id) DO UPDATE SET owner_id = excluded.owner_id
+                ",
+                Some(vec![team_id.into(), owner_id.into()]),
+            )?,
+            None => self.sql().exec(
+                "DELETE FROM room_teams WHERE team_id = ?",
+                Some(vec![team_id.into()]),
+            )?,
+        };
+        Ok(())
+    }
+
     fn persist_structure_delete(&self, structure_id: &str) -> Result<()> {
         self.sql().exec(
             "DELETE FROM build_structures WHERE structure_id = ?",
@@ -794,6 +2421,28 @@ impl RoomDurableObject {
             None,
         )?;
 
+        sql.exec(
+            "
+            CREATE TABLE IF NOT EXISTS room_members (
+              player_id TEXT PRIMARY KEY,
+              role TEXT NOT NULL DEFAULT 'member',
+              team_id TEXT,
+              joined_at INTEGER NOT NULL
+            )
+            ",
+            None,
+        )?;
+
+        sql.exec(
+            "
+            CREATE TABLE IF NOT EXISTS room_teams (
+              team_id TEXT PRIMARY KEY,
+              owner_id TEXT NOT NULL
+            )
+            ",
+            None,
+        )?;
+
         sql.exec(
             "
             CREATE TABLE IF NOT EXISTS movement_state (
@@ -802,6 +2451,36 @@ impl RoomDurableObject {
               y REAL NOT NULL DEFAULT 0,
               vx REAL NOT NULL DEFAULT 0,
               vy REAL NOT NULL DEFAULT 0,
+              hp INTEGER,
+              dead_until INTEGER,
+              invulnerable_until INTEGER,
+              respawn_count INTEGER,
+              updated_at INTEGER NOT NULL
+            )
+            ",
+            None,
+        )?;
+
+        add_column_if_missing(&sql, "ALTER TABLE movement_state ADD COLUMN hp INTEGER")?;
+        add_column_if_missing(
+            &sql,
+            "ALTER TABLE movement_state ADD COLUMN dead_until INTEGER",
+        )?;
+        add_column_if_missing(
+            &sql,
+            "ALTER TABLE movement_state ADD COLUMN invulnerable_until INTEGER",
+        )?;
+        add_column_if_missing(
+            &sql,
+            "ALTER TABLE movement_state ADD COLUMN respawn_count INTEGER",
+        )?;
+        add_column_if_missing(&sql, "ALTER TABLE movement_state ADD COLUMN weapon TEXT")?;
+
+        sql.exec(
+            "
+            CREATE TABLE IF NOT EXISTS player_inventories (
+              player_id TEXT PRIMARY KEY,
+              slots TEXT NOT NULL,
               updated_at INTEGER NOT NULL
             )
             ",
@@ -823,6 +2502,19 @@ impl RoomDurableObject {
             None,
         )?;
 
+        add_column_if_missing(
+            &sql,
+            "ALTER TABLE movement_input_state ADD COLUMN move_x INTEGER NOT NULL DEFAULT 0",
+        )?;
+        add_column_if_missing(
+            &sql,
+            "ALTER TABLE movement_input_state ADD COLUMN move_y INTEGER NOT NULL DEFAULT 0",
+        )?;
+        add_column_if_missing(
+            &sql,
+            "ALTER TABLE movement_input_state ADD COLUMN sprint INTEGER NOT NULL DEFAULT 0",
+        )?;
+
         sql.exec(
             "
             CREATE TABLE IF NOT EXISTS build_structures (
@@ -833,31 +2525,32 @@ impl RoomDurableObject {
               y REAL NOT NULL,
               grid_x INTEGER,
               grid_y INTEGER,
-              created_at INTEGER NOT NULL
+              created_at INTEGER NOT NULL,
+              hp INTEGER,
+              machine_state TEXT
             )
             ",
             None,
         )?;
 
-        if let Err(error) = sql.exec(
+        add_column_if_missing(
+            &sql,
             "ALTER TABLE build_structures ADD COLUMN grid_x INTEGER",
-            None,
-        ) {
-            let message = format!("{error}");
-            if !message.contains("duplicate column") {
-                return Err(error);
-            }
-        }
+        )?;
 
-        if let Err(error) = sql.exec(
+        add_column_if_missing(
+            &sql,
             "ALTER TABLE build_structures ADD COLUMN grid_y INTEGER",
-            None,
-        ) {
-            let message = format!("{error}");
-            if !message.contains("duplicate column") {
-                return Err(error);
-            }
-        }
+        )?;
+        add_column_if_missing(&sql, "ALTER TABLE build_structures ADD COLUMN hp INTEGER")?;
+        add_column_if_missing(
+            &sql,
+            "ALTER TABLE build_structures ADD COLUMN machine_state TEXT",
+        )?;
+        add_column_if_missing(
+            &sql,
+            "ALTER TABLE build_structures ADD COLUMN direction TEXT",
+        )?;
 
         sql.exec(
             "UPDATE build_structures SET grid_x = CAST(ROUND(x / ?) AS INTEGER), grid_y = CAST(ROUND(y / ?) AS INTEGER) WHERE grid_x IS NULL OR grid_y IS NULL",
@@ -920,15 +2613,10 @@ impl RoomDurableObject {
             None,
         )?;
 
-        if let Err(error) = sql.exec(
+        add_column_if_missing(
+            &sql,
             "ALTER TABLE projectile_state ADD COLUMN client_projectile_id TEXT",
-            None,
-        ) {
-            let message = format!("{error}");
-            if !message.contains("duplicate column") {
-                return Err(error);
-            }
-        }
+        )?;
 
         sql.exec(
             "DELETE FROM build_previews WHERE updated_at < ?",
@@ -945,7 +2633,7 @@ impl RoomDurableObject {
 
     fn load_room_code_from_db(&self) -> Result<Option<String>> {
         let sql = self.sql();
-        let rows: Vec<RoomCodeRow> = sql
+        let rows: Vec<RoomMetaRow> = sql
             .exec(
                 "SELECT value FROM room_meta WHERE key = 'room_code' LIMIT 1",
                 None,
@@ -964,6 +2652,78 @@ impl RoomDurableObject {
         Ok(())
     }
 
+    fn load_room_creator_from_db(&self) -> Result<Option<String>> {
+        let sql = self.sql();
+        let rows: Vec<RoomMetaRow> = sql
+            .exec(
+                "SELECT value FROM room_meta WHERE key = 'creator_id' LIMIT 1",
+                None,
+            )?
+            .to_array()?;
+
+        Ok(rows.first().map(|row| row.value.clone()))
+    }
+
+    /// Records who opened the room first. Never overwritten, so the creator
+    /// stays the room's admin even after leaving and rejoining.
+    fn persist_room_creator(&self, player_id: &str) -> Result<()> {
+        let sql = self.sql();
+        sql.exec(
+            "INSERT INTO room_meta (key, value) VALUES ('creator_id', ?) ON CONFLICT(key) DO NOTHING",
+            Some(vec![player_id.into()]),
+        )?;
+        Ok(())
+    }
+
+    /// The terrain seed is picked once per room and kept in `room_meta`.
+    fn load_or_create_terrain_seed(&self) -> Result<u32> {
+        let sql = self.sql();
+        let rows: Vec<RoomMetaRow> = sql
+            .exec(
+                "SELECT value FROM room_meta WHERE key = 'terrain_seed' LIMIT 1",
+                None,
+            )?
+            .to_array()?;
+
+        if let Some(seed) = rows.first().and_then(|row| row.value.parse::<u32>().ok()) {
+            return Ok(seed);
+        }
+
+        let seed = (js_sys::Math::random() * u32::MAX as f64) as u32;
+        sql.exec(
+            "INSERT INTO room_meta (key, value) VALUES ('terrain_seed', ?) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            Some(vec![seed.to_string().into()]),
+        )?;
+        Ok(seed)
+    }
+
+    fn load_research_from_db(&self) -> Result<ResearchState> {
+        let rows: Vec<RoomMetaRow> = self
+            .sql()
+            .exec(
+                "SELECT value FROM room_meta WHERE key = 'research' LIMIT 1",
+                None,
+            )?
+            .to_array()?;
+
+        Ok(rows
+            .first()
+            .and_then(|row| serde_json::from_str::<ResearchRecord>(&row.value).ok())
+            .map(|record| record.restore())
+            .unwrap_or_default())
+    }
+
+    fn persist_research(&self) -> Result<()> {
+        let record = ResearchRecord::from_state(&self.runtime.borrow().research);
+        let value =
+            serde_json::to_string(&record).map_err(|error| Error::RustError(error.to_string()))?;
+        self.sql().exec(
+            "INSERT INTO room_meta (key, value) VALUES ('research', ?) ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+            Some(vec![value.into()]),
+        )?;
+        Ok(())
+    }
+
     fn issue_resume_token(&self, player_id: &str, resume_token: Option<&str>) -> Result<String> {
         let now = now_ms();
         let expires_at = now + RESUME_TOKEN_TTL_MS;
@@ -1070,6 +2830,23 @@ impl RoomDurableObject {
         seq: Option<u32>,
         payload: Option<Value>,
     ) {
+        let protocol = self
+            .read_socket_attachment(socket)
+            .map_or(PROTOCOL_VERSION, |attachment| attachment.protocol);
+        if protocol == BINARY_PROTOCOL_VERSION {
+            let bytes = encode_server_envelope(
+                kind,
+                self.tick.get(),
+                now_ms(),
+                feature,
+                action,
+                seq,
+                payload.as_ref(),
+            );
+            let _ = socket.send_with_bytes(bytes);
+            return;
+        }
+
         let envelope = ServerEnvelope {
             v: PROTOCOL_VERSION,
             kind,
@@ -1150,18 +2927,27 @@ impl RoomDurableObject {
             // Input sequence numbers are connection-scoped. Reset on join so
             // reconnecting clients that start from seq=1 are accepted immediately.
             player.last_input_seq = 0;
-            player.input = InputState {
-                up: false,
-                down: false,
-                left: false,
-                right: false,
-            };
+            player.input = InputState::default();
             player.vx = 0.0;
             player.vy = 0.0;
+            player.position_history.clear();
+            player.rtt_ms = 0;
             player.connected = true;
             player.last_seen = now;
+
+            // Checkpoints from before the room had terrain can sit in water.
+            if self
+                .terrain
+                .get()
+                .blocks_area(player.x, player.y, PLAYER_COLLIDER_RADIUS)
+            {
+                let (x, y) = respawn_position(player_id, player.respawn_count);
+                player.x = x;
+                player.y = y;
+            }
         }
 
+        self.ensure_room_member(player_id, now)?;
         self.checkpoint_runtime_players_to_db()?;
         self.last_checkpoint_ms.set(now);
 
@@ -1170,6 +2956,103 @@ impl RoomDurableObject {
         Ok(())
     }
 
+    /// Registers first-time joiners. The first player in a room without an
+    /// admin becomes its admin.
+    fn ensure_room_member(&self, player_id: &str, now: i64) -> Result<()> {
+        let role = {
+            let mut runtime = self.runtime.borrow_mut();
+            if runtime.members.contains_key(player_id) {
+                return Ok(());
+            }
+
+            let role = initial_room_role(player_id, self.room_creator_id.borrow().as_deref());
+            runtime.members.insert(
+                player_id.to_string(),
+                RoomMemberState {
+                    role,
+                    team_id: None,
+                },
+            );
+            role
+        };
+
+        self.sql().exec(
+            "
+            INSERT INTO room_members (player_id, role, team_id, joined_at)
+            VALUES (?, ?, NULL, ?)
+            ON CONFLICT(player_id) DO NOTHING
+            ",
+            Some(vec![player_id.into(), role.as_str().into(), now.into()]),
+        )?;
+        Ok(())
+    }
+
+    /// The first player on a team becomes its owner.
+    fn set_member_team(&self, player_id: &str, team_id: Option<String>) -> Result<bool> {
+        let (previous_team, founded) = {
+            let mut guard = self.runtime.borrow_mut();
+            let runtime = &mut *guard;
+            let Some(member) = runtime.members.get_mut(player_id) else {
+                return Ok(false);
+            };
+            if member.team_id == team_id {
+                return Ok(false);
+            }
+            let previous_team = std::mem::replace(&mut member.team_id, team_id.clone());
+            let founded = team_id
+                .as_ref()
+                .filter(|team_id| !runtime.team_owners.contains_key(*team_id))
+                .cloned();
+            if let Some(team_id) = &founded {
+                runtime
+                    .team_owners
+                    .insert(team_id.clone(), player_id.to_string());
+            }
+            (previous_team, founded)
+        };
+
+        self.sql().exec(
+            "UPDATE room_members SET team_id = ? WHERE player_id = ?",
+            Some(vec![team_id.into(), player_id.into()]),
+        )?;
+        if let Some(team_id) = founded {
+            self.persist_team_owner(&team_id, Some(player_id))?;
+        }
+        if let Some(previous_team) = previous_team {
+            let next_owner = {
+                let mut runtime = self.runtime.borrow_mut();
+                if runtime
+                    .team_owners
+                    .get(&previous_team)
+                    .is_some_and(|owner_id| owner_id != player_id)
+                {
+                    None
+                } else {
+                    let next_owner = next_team_owner(&previous_team, &runtime.members);
+                    match &next_owner {
+                        Some(owner_id) => runtime
+                            .team_owners
+                            .insert(previous_team.clone(), owner_id.clone()),
+                        None => {
+                            runtime
+                                .team_invites
+                                .retain(|(_, team_id)| *team_id != previous_team);
+                            runtime.team_owners.remove(&previous_team)
+                        }
+                    };
+                    Some(next_owner)
+                }
+            };
+            if let Some(next_owner) = next_owner {
+                self.persist_team_owner(&previous_team, next_owner.as_deref())?;
+            }
+        }
+
+        self.snapshot_dirty.set(true);
+        self.dirty_presence.set(true);
+        Ok(true)
+    }
+
     fn on_disconnect_player(&self, player_id: &str) -> Result<()> {
         let now = now_ms();
         let sql = self.sql();
@@ -1227,12 +3110,7 @@ impl RoomDurableObject {
 
             last_seq = command.seq;
             accepted = true;
-            latest_state = InputState {
-                up: command.up,
-                down: command.down,
-                left: command.left,
-                right: command.right,
-            };
+            latest_state = input_from_command(&command);
         }
 
         if accepted {
@@ -1253,31 +3131,34 @@ impl RoomDurableObject {
         Ok(())
     }
 
-    fn can_place_structure_at_cell(
+    /// Every cell of the footprint must be buildable and free, and solid
+    /// structures must not land on a connected player.
+    fn can_place_structure(
         &self,
-        grid_x: i64,
-        grid_y: i64,
-        center_x: f64,
-        center_y: f64,
+        kind: &str,
+        footprint: Footprint,
+        cell: (i32, i32),
+        center: (f32, f32),
     ) -> Result<bool> {
+        let terrain = self.terrain.get();
         let runtime = self.runtime.borrow();
-        if runtime
-            .structures
-            .values()
-            .any(|structure| structure.grid_x == grid_x && structure.grid_y == grid_y)
-        {
+        let free = footprint.cells(cell).all(|(x, y)| {
+            terrain.tile_at(x, y).is_buildable() && !runtime.structure_cells.contains_key(&(x, y))
+        });
+        if !free {
             return Ok(false);
         }
+        if !structure_is_solid(kind) {
+            return Ok(true);
+        }
 
-        let blocked = STRUCTURE_COLLIDER_HALF_EXTENT + PLAYER_COLLIDER_RADIUS;
+        let obstacle = footprint.obstacle(center.0, center.1);
         for player in runtime.players.values() {
             if !player.connected {
                 continue;
             }
 
-            if (player.x as f64 - center_x).abs() < blocked as f64
-                && (player.y as f64 - center_y).abs() < blocked as f64
-            {
+            if obstacle.blocks(player.x, player.y, PLAYER_COLLIDER_RADIUS) {
                 return Ok(false);
             }
         }
@@ -1328,17 +3209,19 @@ impl RoomDurableObject {
         if !is_valid_structure_kind(kind) {
             return Err(Error::RustError("invalid structure kind".into()));
         }
+        let direction = parse_direction(preview.direction.as_deref())?;
 
-        let center_x = grid_cell_center(snap_axis_to_grid(x));
-        let center_y = grid_cell_center(snap_axis_to_grid(y));
+        let cell = (snap_axis_to_grid(x) as i32, snap_axis_to_grid(y) as i32);
+        let (center_x, center_y) = Footprint::for_structure(kind, direction).center(cell);
 
         self.runtime.borrow_mut().previews.insert(
             player_id.to_string(),
             RuntimePreviewState {
                 player_id: player_id.to_string(),
                 kind: kind.to_string(),
-                x: center_x as f32,
-                y: center_y as f32,
+                direction,
+                x: center_x,
+                y: center_y,
                 updated_at: now,
             },
         );
@@ -1356,112 +3239,35 @@ impl RoomDurableObject {
         payload: Option<Value>,
     ) -> Result<bool> {
         match action {
-            "place" => {
-                let payload =
-                    payload.ok_or_else(|| Error::RustError("missing build payload".into()))?;
-                let place: BuildPlacePayload = serde_json::from_value(payload)
-                    .map_err(|_| Error::RustError("invalid build payload".into()))?;
-                let now = now_ms();
-
-                {
-                    let mut runtime = self.runtime.borrow_mut();
-                    let player = runtime
-                        .players
-                        .entry(player_id.to_string())
-                        .or_insert_with(|| Self::default_runtime_player(now));
-
-                    if now - player.last_place_cmd_at < PLACE_COMMAND_MIN_INTERVAL_MS {
-                        return Ok(false);
-                    }
-                    player.last_place_cmd_at = now;
-                    player.last_seen = now;
-                }
-
-                if !is_valid_structure_kind(place.kind.as_str()) {
-                    return Err(Error::RustError("invalid structure kind".into()));
-                }
-
-                let grid_x = snap_axis_to_grid(place.x);
-                let grid_y = snap_axis_to_grid(place.y);
-                let snapped_x = grid_cell_center(grid_x);
-                let snapped_y = grid_cell_center(grid_y);
-
-                if !self.can_place_structure_at_cell(grid_x, grid_y, snapped_x, snapped_y)? {
-                    return Err(Error::RustError("build cell is blocked".into()));
-                }
-
-                let structure_id = place
-                    .client_build_id
-                    .filter(|value| !value.is_empty())
-                    .unwrap_or_else(|| format!("build_{}_{}", now_ms(), js_sys::Math::random()));
-
-                let structure = RuntimeStructureState {
-                    structure_id: structure_id.clone(),
-                    owner_id: player_id.to_string(),
-                    kind: place.kind.clone(),
-                    x: snapped_x as f32,
-                    y: snapped_y as f32,
-                    grid_x,
-                    grid_y,
-                    chunk_x: chunk_coord_for_grid(grid_x),
-                    chunk_y: chunk_coord_for_grid(grid_y),
-                    created_at: now,
-                };
-
-                self.runtime
+            "preview" => self.handle_build_preview(player_id, payload),
+            "set_recipe" => self.handle_build_set_recipe(player_id, payload),
+            "open" => self.handle_build_open(player_id, payload),
+            "close" => {
+                let closed = self
+                    .runtime
                     .borrow_mut()
-                    .structures
-                    .insert(structure_id.clone(), structure.clone());
-                self.persist_structure_insert(&structure)?;
-
-                let overflow_structure_id = {
-                    let runtime = self.runtime.borrow();
-                    if runtime.structures.len() > MAX_STRUCTURES {
-                        runtime
-                            .structures
-                            .values()
-                            .min_by_key(|value| value.created_at)
-                            .map(|value| value.structure_id.clone())
-                    } else {
-                        None
-                    }
-                };
-
-                if let Some(overflow_structure_id) = overflow_structure_id {
-                    self.runtime
-                        .borrow_mut()
-                        .structures
-                        .remove(&overflow_structure_id);
-                    self.persist_structure_delete(&overflow_structure_id)?;
+                    .players
+                    .get_mut(player_id)
+                    .and_then(|player| player.open_chest.take())
+                    .is_some();
+                if closed {
+                    self.snapshot_dirty.set(true);
                 }
-
-                self.snapshot_dirty.set(true);
-                self.dirty_build.set(true);
-                Ok(true)
-            }
-            "preview" => self.handle_build_preview(player_id, payload),
-            "remove" => {
-                let payload =
-                    payload.ok_or_else(|| Error::RustError("missing build payload".into()))?;
-                let remove: BuildRemovePayload = serde_json::from_value(payload)
-                    .map_err(|_| Error::RustError("invalid build payload".into()))?;
-
-                self.runtime.borrow_mut().structures.remove(&remove.id);
-                self.persist_structure_delete(&remove.id)?;
-
-                self.snapshot_dirty.set(true);
-                self.dirty_build.set(true);
-                Ok(true)
+                Ok(closed)
             }
+            "transfer" => self.handle_build_transfer(player_id, payload),
             _ => Err(Error::RustError("invalid build action".into())),
         }
     }
 
-    fn handle_projectile_fire(&self, player_id: &str, payload: Option<Value>) -> Result<bool> {
-        let payload =
-            payload.ok_or_else(|| Error::RustError("missing projectile payload".into()))?;
-        let mut fire: ProjectileFirePayload = serde_json::from_value(payload)
-            .map_err(|_| Error::RustError("invalid projectile payload".into()))?;
+    fn handle_build_place(
+        &self,
+        player_id: &str,
+        payload: Option<Value>,
+    ) -> std::result::Result<bool, BuildPlaceError> {
+        let place: BuildPlacePayload = payload
+            .and_then(|payload| serde_json::from_value(payload).ok())
+            .ok_or(BuildPlaceError::InvalidPayload)?;
         let now = now_ms();
 
         {
@@ -1471,47 +3277,480 @@ impl RoomDurableObject {
                 .entry(player_id.to_string())
                 .or_insert_with(|| Self::default_runtime_player(now));
 
-            if now - player.last_projectile_fire_at < PROJECTILE_FIRE_MIN_INTERVAL_MS {
+            if now - player.last_place_cmd_at < PLACE_COMMAND_MIN_INTERVAL_MS {
                 return Ok(false);
             }
-            player.last_projectile_fire_at = now;
+            player.last_place_cmd_at = now;
             player.last_seen = now;
         }
 
-        let speed = (fire.vx * fire.vx + fire.vy * fire.vy).sqrt();
-        if speed > PROJECTILE_MAX_SPEED && speed > 0.0 {
-            let scale = PROJECTILE_MAX_SPEED / speed;
-            fire.vx *= scale;
-            fire.vy *= scale;
+        if !is_valid_structure_kind(place.kind.as_str()) {
+            return Err(BuildPlaceError::InvalidKind);
+        }
+        if !self
+            .runtime
+            .borrow()
+            .research
+            .structure_unlocked(place.kind.as_str())
+        {
+            return Err(BuildPlaceError::NotResearched);
+        }
+        let cost = structure_prototype(place.kind.as_str())
+            .map(|prototype| prototype.cost.as_slice())
+            .unwrap_or_default();
+
+        let direction = parse_direction(place.direction.as_deref())
+            .map_err(|_| BuildPlaceError::InvalidPayload)?;
+        let grid_x = snap_axis_to_grid(place.x);
+        let grid_y = snap_axis_to_grid(place.y);
+        let cell = (grid_x as i32, grid_y as i32);
+        let footprint = Footprint::for_structure(place.kind.as_str(), direction);
+        let center = footprint.center(cell);
+
+        if !self
+            .can_place_structure(place.kind.as_str(), footprint, cell, center)
+            .unwrap_or(false)
+        {
+            return Err(BuildPlaceError::Blocked);
         }
 
-        let projectile_id = format!("proj_{}_{}", now, js_sys::Math::random());
-        let expires_at = now + PROJECTILE_TTL_MS;
-        let updated_at = now;
-
-        self.runtime.borrow_mut().projectiles.insert(
-            projectile_id.clone(),
-            RuntimeProjectileState {
-                projectile_id,
-                owner_id: player_id.to_string(),
-                x: fire.x as f32,
-                y: fire.y as f32,
-                vx: fire.vx as f32,
-                vy: fire.vy as f32,
-                expires_at,
-                client_projectile_id: fire.client_projectile_id,
-                updated_at,
-            },
-        );
+        let miner = miner_for_structure(place.kind.as_str(), &self.terrain.get(), footprint, cell);
+        if miner.is_some_and(|miner| miner.output_item.is_none()) {
+            return Err(BuildPlaceError::NeedsOre);
+        }
 
-        let overflow_projectile_id = {
+        let structure_id = place
+            .client_build_id
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| format!("build_{}_{}", now_ms(), js_sys::Math::random()));
+        {
             let runtime = self.runtime.borrow();
-            if runtime.projectiles.len() > MAX_PROJECTILES {
-                runtime
-                    .projectiles
-                    .values()
-                    .min_by_key(|projectile| projectile.updated_at)
-                    .map(|projectile| projectile.projectile_id.clone())
+            // The insert upserts by id, so a reused id would take the structure over.
+            if runtime.structures.contains_key(&structure_id) {
+                return Err(BuildPlaceError::IdTaken);
+            }
+            // Full rooms refuse new structures rather than evicting anyone's.
+            if runtime.structures.len() >= MAX_STRUCTURES {
+                return Err(BuildPlaceError::StructureLimit);
+            }
+        }
+
+        let inventory = {
+            let mut runtime = self.runtime.borrow_mut();
+            let player = runtime
+                .players
+                .get_mut(player_id)
+                .ok_or(BuildPlaceError::NotEnoughMaterials)?;
+            let inventory = player.inventory.clone();
+            if !player.inventory.remove_all(cost) {
+                return Err(BuildPlaceError::NotEnoughMaterials);
+            }
+            inventory
+        };
+
+        let structure = RuntimeStructureState {
+            structure_id: structure_id.clone(),
+            owner_id: player_id.to_string(),
+            kind: place.kind.clone(),
+            x: center.0,
+            y: center.1,
+            grid_x,
+            grid_y,
+            chunk_x: chunk_coord_for_grid(grid_x),
+            chunk_y: chunk_coord_for_grid(grid_y),
+            created_at: now,
+            health: Health::full(structure_max_hp(place.kind.as_str())),
+            miner,
+            assembler: assembler_for_structure(place.kind.as_str()),
+            chest: chest_for_structure(place.kind.as_str()),
+            lab: lab_for_structure(place.kind.as_str()),
+            direction,
+        };
+
+        {
+            let mut runtime = self.runtime.borrow_mut();
+            if structure.has_behavior(StructureBehavior::Belt) {
+                runtime
+                    .belts
+                    .insert(structure.cell(), BeltState::new(direction));
+            }
+            if structure.has_behavior(StructureBehavior::Inserter) {
+                runtime
+                    .inserters
+                    .insert(structure.cell(), InserterState::new(direction));
+            }
+            runtime.insert_structure(structure.clone());
+        }
+
+        // Like removal: the spent materials and the new structure are saved
+        // together, or the placement is undone.
+        let saved = self.state.storage().transaction_sync(|| {
+            self.persist_player_inventory(player_id)?;
+            self.persist_structure_insert(&structure)
+        });
+        if let Err(error) = saved {
+            console_error!("failed to persist structure placement: {error}");
+            let mut runtime = self.runtime.borrow_mut();
+            runtime.remove_structure(&structure_id);
+            if let Some(player) = runtime.players.get_mut(player_id) {
+                player.inventory = inventory;
+            }
+            return Err(BuildPlaceError::StorageFailed);
+        }
+
+        self.snapshot_dirty.set(true);
+        self.dirty_build.set(true);
+        Ok(true)
+    }
+
+    fn handle_build_set_recipe(&self, player_id: &str, payload: Option<Value>) -> Result<bool> {
+        let set_recipe: BuildSetRecipePayload = payload
+            .and_then(|payload| serde_json::from_value(payload).ok())
+            .ok_or_else(|| Error::RustError("invalid set_recipe payload".into()))?;
+        let recipe = match set_recipe.recipe.as_deref() {
+            Some(id) => {
+                Some(recipe_by_id(id).ok_or_else(|| Error::RustError("unknown recipe".into()))?)
+            }
+            None => None,
+        };
+        let now = now_ms();
+
+        let mut guard = self.runtime.borrow_mut();
+        let runtime = &mut *guard;
+        if recipe.is_some_and(|recipe| !runtime.research.recipe_unlocked(recipe.id)) {
+            return Err(Error::RustError("recipe is not researched yet".into()));
+        }
+        let (player_x, player_y) = {
+            let player = runtime
+                .players
+                .entry(player_id.to_string())
+                .or_insert_with(|| Self::default_runtime_player(now));
+            player.last_seen = now;
+            (player.x, player.y)
+        };
+        let is_admin = is_room_admin(player_id, &runtime.members, &self.room_admin_ids);
+
+        let structure = runtime
+            .structures
+            .get_mut(&set_recipe.id)
+            .ok_or_else(|| Error::RustError("structure not found".into()))?;
+        if !can_modify_structure(
+            player_id,
+            &structure.owner_id,
+            &runtime.members,
+            &self.room_admin_ids,
+        ) {
+            return Err(Error::RustError(
+                "not permitted to configure this structure".into(),
+            ));
+        }
+        let distance = (structure.x - player_x).hypot(structure.y - player_y);
+        if !is_admin && distance > self.build_interaction_distance {
+            return Err(Error::RustError("structure is out of range".into()));
+        }
+        let assembler = structure
+            .assembler
+            .as_mut()
+            .ok_or_else(|| Error::RustError("structure has no recipe".into()))?;
+        if assembler.recipe.map(|current| current.id) == recipe.map(|next| next.id) {
+            return Ok(false);
+        }
+
+        // Whatever the old recipe held goes back to the player, so the switch
+        // is refused if it does not fit.
+        let mut switched = assembler.clone();
+        let returned = switched.set_recipe(recipe);
+        if !runtime
+            .players
+            .get_mut(player_id)
+            .is_some_and(|player| player.inventory.insert_all(&returned))
+        {
+            return Err(Error::RustError("not enough inventory space".into()));
+        }
+        *assembler = switched;
+        runtime.dirty_machines.insert(set_recipe.id);
+        drop(guard);
+        self.persist_player_inventory(player_id)?;
+
+        self.snapshot_dirty.set(true);
+        self.dirty_build.set(true);
+        Ok(true)
+    }
+
+    /// Checks that `player_id` may reach into chest `chest_id`: same rules as
+    /// configuring a structure, and in range unless the player is an admin.
+    fn check_chest_access(
+        &self,
+        runtime: &mut RoomRuntimeState,
+        player_id: &str,
+        chest_id: &str,
+        now: i64,
+    ) -> Result<()> {
+        let (player_x, player_y) = {
+            let player = runtime
+                .players
+                .entry(player_id.to_string())
+                .or_insert_with(|| Self::default_runtime_player(now));
+            player.last_seen = now;
+            (player.x, player.y)
+        };
+        let structure = runtime
+            .structures
+            .get(chest_id)
+            .filter(|structure| structure.chest.is_some())
+            .ok_or_else(|| Error::RustError("chest not found".into()))?;
+        if !can_modify_structure(
+            player_id,
+            &structure.owner_id,
+            &runtime.members,
+            &self.room_admin_ids,
+        ) {
+            return Err(Error::RustError("not permitted to open this chest".into()));
+        }
+        let distance = (structure.x - player_x).hypot(structure.y - player_y);
+        if !is_room_admin(player_id, &runtime.members, &self.room_admin_ids)
+            && distance > self.build_interaction_distance
+        {
+            return Err(Error::RustError("structure is out of range".into()));
+        }
+        Ok(())
+    }
+
+    fn handle_build_open(&self, player_id: &str, payload: Option<Value>) -> Result<bool> {
+        let open: BuildOpenPayload = payload
+            .and_then(|payload| serde_json::from_value(payload).ok())
+            .ok_or_else(|| Error::RustError("invalid open payload".into()))?;
+
+        let mut runtime = self.runtime.borrow_mut();
+        self.check_chest_access(&mut runtime, player_id, &open.id, now_ms())?;
+        let Some(player) = runtime.players.get_mut(player_id) else {
+            return Ok(false);
+        };
+        if player.open_chest.as_ref() == Some(&open.id) {
+            return Ok(false);
+        }
+        player.open_chest = Some(open.id);
+        drop(runtime);
+
+        self.snapshot_dirty.set(true);
+        Ok(true)
+    }
+
+    fn handle_build_transfer(&self, player_id: &str, payload: Option<Value>) -> Result<bool> {
+        let transfer: BuildTransferPayload = payload
+            .and_then(|payload| serde_json::from_value(payload).ok())
+            .ok_or_else(|| Error::RustError("invalid transfer payload".into()))?;
+        let item = ItemKind::parse(&transfer.item)
+            .ok_or_else(|| Error::RustError("unknown item".into()))?;
+        let count = transfer.count.unwrap_or_else(|| item.stack_size());
+
+        let mut guard = self.runtime.borrow_mut();
+        let runtime = &mut *guard;
+        self.check_chest_access(runtime, player_id, &transfer.id, now_ms())?;
+        let (Some(player), Some(chest)) = (
+            runtime.players.get_mut(player_id),
+            runtime
+                .structures
+                .get_mut(&transfer.id)
+                .and_then(|structure| structure.chest.as_mut()),
+        ) else {
+            return Ok(false);
+        };
+        let moved = match transfer.to.as_str() {
+            "chest" => player.inventory.move_to(chest, item, count),
+            "player" => chest.move_to(&mut player.inventory, item, count),
+            _ => return Err(Error::RustError("invalid transfer target".into())),
+        };
+        if moved == 0 {
+            return Ok(false);
+        }
+        runtime.dirty_machines.insert(transfer.id);
+        drop(guard);
+
+        // Both sides are written right away so a restart can neither lose nor
+        // duplicate the moved items.
+        self.persist_player_inventory(player_id)?;
+        self.checkpoint_machines_to_db()?;
+
+        self.snapshot_dirty.set(true);
+        Ok(true)
+    }
+
+    fn handle_build_remove(
+        &self,
+        player_id: &str,
+        payload: Option<Value>,
+    ) -> std::result::Result<(), BuildRemoveError> {
+        let remove: BuildRemovePayload = payload
+            .and_then(|payload| serde_json::from_value(payload).ok())
+            .ok_or(BuildRemoveError::InvalidPayload)?;
+        let now = now_ms();
+
+        let removed = {
+            let mut guard = self.runtime.borrow_mut();
+            let runtime = &mut *guard;
+            let player = runtime
+                .players
+                .entry(player_id.to_string())
+                .or_insert_with(|| Self::default_runtime_player(now));
+
+            if now - player.last_remove_cmd_at < REMOVE_COMMAND_MIN_INTERVAL_MS {
+                return Err(BuildRemoveError::RateLimited);
+            }
+            player.last_remove_cmd_at = now;
+            player.last_seen = now;
+            let (player_x, player_y) = (player.x, player.y);
+
+            let structure = runtime
+                .structures
+                .get(&remove.id)
+                .ok_or(BuildRemoveError::NotFound)?;
+            if !can_modify_structure(
+                player_id,
+                &structure.owner_id,
+                &runtime.members,
+                &self.room_admin_ids,
+            ) {
+                return Err(BuildRemoveError::NotPermitted);
+            }
+
+            let is_admin = is_room_admin(player_id, &runtime.members, &self.room_admin_ids);
+            let distance = (structure.x - player_x).hypot(structure.y - player_y);
+            if !is_admin && distance > self.build_interaction_distance {
+                return Err(BuildRemoveError::OutOfRange);
+            }
+
+            runtime
+                .remove_structure_with_refund(&remove.id, player_id)
+                .ok_or(BuildRemoveError::InventoryFull)?
+        };
+
+        // Saving only one side would either bring the structure back next to
+        // its refund or lose both, so the two writes land together or not at all.
+        let saved = self.state.storage().transaction_sync(|| {
+            self.persist_structure_delete(&remove.id)?;
+            self.persist_player_inventory(player_id)
+        });
+        if let Err(error) = saved {
+            console_error!("failed to persist structure removal: {error}");
+            self.runtime
+                .borrow_mut()
+                .restore_removed_structure(removed, player_id);
+            return Err(BuildRemoveError::StorageFailed);
+        }
+
+        self.snapshot_dirty.set(true);
+        self.dirty_build.set(true);
+        Ok(())
+    }
+
+    fn handle_projectile_fire(&self, player_id: &str, payload: Option<Value>) -> Result<bool> {
+        let payload =
+            payload.ok_or_else(|| Error::RustError("missing projectile payload".into()))?;
+        let mut fire: ProjectileFirePayload = serde_json::from_value(payload)
+            .map_err(|_| Error::RustError("invalid projectile payload".into()))?;
+        if !fire.x.is_finite() || !fire.y.is_finite() {
+            return Err(Error::RustError("invalid projectile origin".into()));
+        }
+        let now = now_ms();
+
+        let weapon = {
+            let mut runtime = self.runtime.borrow_mut();
+            let player = runtime
+                .players
+                .entry(player_id.to_string())
+                .or_insert_with(|| Self::default_runtime_player(now));
+
+            if player.health.is_dead() {
+                return Err(Error::RustError("cannot fire while dead".into()));
+            }
+            let weapon = player.weapon;
+            if fire
+                .weapon_id
+                .as_deref()
+                .is_some_and(|weapon_id| weapon_id != weapon.id)
+            {
+                return Err(Error::RustError("weapon not equipped".into()));
+            }
+            if !player.weapon_cooldowns.ready(weapon, now) {
+                return Ok(false);
+            }
+            let (vx, vy) = weapon
+                .shot_velocity(
+                    fire.vx as f32,
+                    fire.vy as f32,
+                    js_sys::Math::random() as f32 * 2.0 - 1.0,
+                )
+                .ok_or_else(|| Error::RustError("invalid projectile direction".into()))?;
+            // The client fires from where it saw itself, which can trail the
+            // server by up to a round trip. Compare against the path the
+            // shooter actually took over that window.
+            let rewind = (player.rtt_ms + PROJECTILE_REWIND_SLACK_MS).min(PROJECTILE_MAX_REWIND_MS);
+            let (x, y) = (fire.x as f32, fire.y as f32);
+            let distance = player
+                .position_history
+                .distance_since(now - rewind, x, y)
+                .unwrap_or_else(|| ((x - player.x).powi(2) + (y - player.y).powi(2)).sqrt());
+            if distance > PROJECTILE_REANCHOR_DISTANCE {
+                return Err(Error::RustError(
+                    "projectile origin too far from shooter".into(),
+                ));
+            }
+            if distance > PROJECTILE_ORIGIN_TOLERANCE {
+                let (anchor_x, anchor_y) = player
+                    .position_history
+                    .position_at(now - player.rtt_ms / 2)
+                    .unwrap_or((player.x, player.y));
+                fire.x = anchor_x as f64;
+                fire.y = anchor_y as f64;
+            }
+
+            if let Some(ammo) = weapon.ammo {
+                if !player.inventory.remove(ammo, 1) {
+                    return Err(Error::RustError(format!(
+                        "no {} left in inventory",
+                        ammo.as_str()
+                    )));
+                }
+            }
+            fire.vx = vx as f64;
+            fire.vy = vy as f64;
+            player.weapon_cooldowns.fired(weapon, now);
+            player.last_seen = now;
+            weapon
+        };
+        if weapon.ammo.is_some() {
+            self.persist_player_inventory(player_id)?;
+        }
+
+        let projectile_id = format!("proj_{}_{}", now, js_sys::Math::random());
+        let expires_at = now + weapon.projectile_ttl_ms;
+        let updated_at = now;
+
+        self.runtime.borrow_mut().projectiles.insert(
+            projectile_id.clone(),
+            RuntimeProjectileState {
+                projectile_id,
+                owner_id: player_id.to_string(),
+                x: fire.x as f32,
+                y: fire.y as f32,
+                vx: fire.vx as f32,
+                vy: fire.vy as f32,
+                expires_at,
+                client_projectile_id: fire.client_projectile_id,
+                damage: weapon.damage,
+                updated_at,
+            },
+        );
+
+        let overflow_projectile_id = {
+            let runtime = self.runtime.borrow();
+            if runtime.projectiles.len() > MAX_PROJECTILES {
+                runtime
+                    .projectiles
+                    .values()
+                    .min_by_key(|projectile| projectile.updated_at)
+                    .map(|projectile| projectile.projectile_id.clone())
             } else {
                 None
             }
@@ -1529,6 +3768,64 @@ impl RoomDurableObject {
         Ok(true)
     }
 
+    fn handle_projectile_equip(&self, player_id: &str, payload: Option<Value>) -> Result<bool> {
+        let equip: ProjectileEquipPayload = payload
+            .and_then(|payload| serde_json::from_value(payload).ok())
+            .ok_or_else(|| Error::RustError("invalid equip payload".into()))?;
+        let weapon = weapon_by_id(&equip.weapon_id)
+            .ok_or_else(|| Error::RustError("unknown weapon".into()))?;
+
+        let now = now_ms();
+        let mut runtime = self.runtime.borrow_mut();
+        let player = runtime
+            .players
+            .entry(player_id.to_string())
+            .or_insert_with(|| Self::default_runtime_player(now));
+        if player.weapon.id == weapon.id {
+            return Ok(false);
+        }
+        player.weapon = weapon;
+        player.last_seen = now;
+        drop(runtime);
+
+        self.snapshot_dirty.set(true);
+        Ok(true)
+    }
+
+    /// Any player may pick what the room researches next.
+    fn handle_research_select(&self, player_id: &str, payload: Option<Value>) -> Result<bool> {
+        let select: ResearchSelectPayload = payload
+            .and_then(|payload| serde_json::from_value(payload).ok())
+            .ok_or_else(|| Error::RustError("invalid research payload".into()))?;
+
+        let now = now_ms();
+        let mut runtime = self.runtime.borrow_mut();
+        runtime
+            .players
+            .entry(player_id.to_string())
+            .or_insert_with(|| Self::default_runtime_player(now))
+            .last_seen = now;
+        let current = runtime.research.current().map(|technology| technology.id);
+        if current == select.tech_id.as_deref() {
+            return Ok(false);
+        }
+        match select.tech_id.as_deref() {
+            Some(tech_id) => {
+                runtime.research.select(tech_id).ok_or_else(|| {
+                    Error::RustError("technology is unknown or not available".into())
+                })?;
+            }
+            None => {
+                runtime.research.cancel();
+            }
+        }
+        drop(runtime);
+        self.persist_research()?;
+
+        self.snapshot_dirty.set(true);
+        Ok(true)
+    }
+
     fn run_simulation_until_now(&self) -> Result<()> {
         let now = now_ms() as f64;
         let elapsed = (now - self.last_loop_ms.get()).clamp(0.0, 250.0);
@@ -1536,29 +3833,27 @@ impl RoomDurableObject {
 
         let mut accumulator = self.accumulator_ms.get() + elapsed;
         let mut steps = 0usize;
+        let first_tick = self.tick.get();
 
         while accumulator >= SIM_DT_MS && steps < MAX_CATCHUP_STEPS {
             self.tick.set(self.tick.get().saturating_add(1));
 
             let connected_players = self.connected_player_ids();
+            self.tick_respawns(&connected_players);
             let movement_changed = self.tick_movement(&connected_players)?;
             let projectile_changed = self.tick_projectiles()?;
+            let enemy_changed = self.tick_enemies(&connected_players)?;
+            self.tick_machines()?;
+            self.tick_belts();
+            self.tick_inserters();
 
-            if movement_changed || projectile_changed {
+            if movement_changed || projectile_changed || enemy_changed {
                 self.snapshot_dirty.set(true);
                 if projectile_changed {
                     self.dirty_projectiles.set(true);
                 }
             }
 
-            if self.tick.get() % SNAPSHOT_INTERVAL_TICKS == 0 || self.snapshot_dirty.get() {
-                self.broadcast_snapshot(false);
-                self.snapshot_dirty.set(false);
-                self.dirty_presence.set(false);
-                self.dirty_build.set(false);
-                self.dirty_projectiles.set(false);
-            }
-
             self.checkpoint_runtime_if_due()?;
 
             accumulator -= SIM_DT_MS;
@@ -1570,30 +3865,175 @@ impl RoomDurableObject {
         }
 
         self.accumulator_ms.set(accumulator);
+
+        // Dirty flags pile up between snapshots; a call that catches up over
+        // several boundaries still sends one.
+        if crosses_snapshot_interval(first_tick, self.tick.get()) {
+            self.broadcast_snapshot(false);
+            self.snapshot_dirty.set(false);
+            self.dirty_presence.set(false);
+            self.dirty_build.set(false);
+            self.dirty_projectiles.set(false);
+            self.dirty_health.set(false);
+        }
         Ok(())
     }
 
+    fn next_tick_delay_ms(&self) -> u64 {
+        tick_delay_ms(self.accumulator_ms.get())
+    }
+
+    async fn ensure_tick_alarm(&self) -> Result<()> {
+        if self.tick_alarm_armed.get() {
+            return Ok(());
+        }
+
+        let storage = self.state.storage();
+        if storage.get_alarm().await?.is_none() {
+            // Waking from idle: drop whatever time passed while nobody was
+            // connected instead of replaying it as catch-up steps.
+            self.last_loop_ms.set(now_ms() as f64);
+            self.accumulator_ms.set(0.0);
+            storage
+                .set_alarm(Duration::from_millis(self.next_tick_delay_ms()))
+                .await?;
+        }
+
+        self.tick_alarm_armed.set(true);
+        Ok(())
+    }
+
+    fn tick_respawns(&self, connected_players: &[String]) {
+        let now = now_ms();
+        let mut respawned = false;
+        let mut runtime = self.runtime.borrow_mut();
+
+        for player_id in connected_players {
+            let Some(player) = runtime.players.get_mut(player_id) else {
+                continue;
+            };
+            if player.invulnerable_until != 0 && now >= player.invulnerable_until {
+                // Clearing the window here lets the health channel flip the
+                // replicated flag exactly once.
+                player.invulnerable_until = 0;
+                respawned = true;
+            }
+            if !player.health.is_dead() || now < player.dead_until {
+                continue;
+            }
+
+            let (x, y) = respawn_position(player_id, player.respawn_count);
+            player.x = x;
+            player.y = y;
+            player.vx = 0.0;
+            player.vy = 0.0;
+            player.health = Health::full(PLAYER_MAX_HP);
+            player.stamina = Stamina::full();
+            player.position_history.clear();
+            player.dead_until = 0;
+            player.invulnerable_until = now + RESPAWN_INVULNERABILITY_MS;
+            player.respawn_count = player.respawn_count.saturating_add(1);
+            respawned = true;
+        }
+
+        if respawned {
+            self.snapshot_dirty.set(true);
+            self.dirty_health.set(true);
+        }
+    }
+
+    /// Output changes reach clients through the regular build snapshot diff.
+    /// Machines run at their power network's satisfaction; unpowered ones stand still.
+    fn tick_machines(&self) -> Result<()> {
+        let mut guard = self.runtime.borrow_mut();
+        let runtime = &mut *guard;
+        let balances = runtime.power.balances();
+        let research_selected = runtime.research.current().is_some();
+        let mut finished_packs = 0;
+        for structure in runtime.structures.values_mut() {
+            let dt = SIM_DT_SECONDS
+                * runtime
+                    .power
+                    .satisfaction_of(&structure.structure_id, &balances);
+            if dt <= 0.0 {
+                continue;
+            }
+            let mut changed = false;
+            if let Some(miner) = structure.miner.as_mut().filter(|miner| miner.is_working()) {
+                miner.step(dt);
+                changed = true;
+            }
+            if let Some(assembler) = structure.assembler.as_mut() {
+                let was_crafting = assembler.is_working();
+                changed |= assembler.step(dt) > 0 || was_crafting || assembler.is_working();
+            }
+            if let Some(lab) = structure.lab.as_mut() {
+                let was_researching = lab.researching;
+                finished_packs += u32::from(lab.step(dt, research_selected));
+                changed |= was_researching || lab.researching;
+            }
+            if changed {
+                runtime
+                    .dirty_machines
+                    .insert(structure.structure_id.clone());
+            }
+        }
+        if finished_packs == 0 {
+            return Ok(());
+        }
+        runtime.research.add_progress(finished_packs);
+        drop(guard);
+        self.persist_research()
+    }
+
+    /// Belt contents reach clients through the per-chunk `belt` snapshot channel.
+    fn tick_belts(&self) {
+        let mut guard = self.runtime.borrow_mut();
+        let runtime = &mut *guard;
+        if runtime.belts.is_empty() {
+            return;
+        }
+        let changed = runtime.belts.step();
+        runtime.dirty_belts.extend(changed);
+    }
+
+    /// Runs after belts so inserters see this tick's belt contents.
+    fn tick_inserters(&self) {
+        let mut guard = self.runtime.borrow_mut();
+        let runtime = &mut *guard;
+        if runtime.inserters.is_empty() {
+            return;
+        }
+        let mut world = RuntimeInserterWorld {
+            structures: &mut runtime.structures,
+            structure_cells: &runtime.structure_cells,
+            belts: &mut runtime.belts,
+            dirty_machines: &mut runtime.dirty_machines,
+            dirty_belts: &mut runtime.dirty_belts,
+        };
+        let changed = runtime.inserters.step(&mut world);
+        for cell in changed {
+            if let Some(structure_id) = runtime.structure_cells.get(&cell) {
+                runtime.dirty_machines.insert(structure_id.clone());
+            }
+        }
+    }
+
     fn tick_movement(&self, connected_players: &[String]) -> Result<bool> {
         if connected_players.is_empty() {
             return Ok(false);
         }
 
         let now = now_ms();
-        let structure_obstacles: Vec<StructureObstacle> = {
-            let runtime = self.runtime.borrow();
-            runtime
-                .structures
-                .values()
-                .map(|structure| StructureObstacle {
-                    x: structure.x,
-                    y: structure.y,
-                    half_extent: structure_half_extent(structure.kind.as_str()),
-                })
-                .collect()
-        };
-
+        let reach = step_reach(
+            MOVE_SPEED * SPRINT_SPEED_MULTIPLIER,
+            SIM_DT_SECONDS,
+            PLAYER_COLLIDER_RADIUS,
+        );
+        let terrain = self.terrain.get();
         let mut changed = false;
-        let mut runtime = self.runtime.borrow_mut();
+        let mut guard = self.runtime.borrow_mut();
+        let runtime = &mut *guard;
 
         for player_id in connected_players {
             let player = runtime
@@ -1601,21 +4041,34 @@ impl RoomDurableObject {
                 .entry(player_id.clone())
                 .or_insert_with(|| Self::default_runtime_player(now));
 
-            let step = movement_step_with_obstacles(
+            if player.health.is_dead() {
+                player.vx = 0.0;
+                player.vy = 0.0;
+                player.last_seen = now;
+                continue;
+            }
+
+            let input = map_input_to_core(&player.input);
+            let stamina_before = player.stamina;
+            let speed = player.stamina.step(input, MOVE_SPEED, SIM_DT_SECONDS);
+            let structure_obstacles = runtime.obstacles.obstacles_near(player.x, player.y, reach);
+            let step = movement_step_with_terrain_fixed(
                 player.x,
                 player.y,
-                map_input_to_core(&player.input),
+                input,
                 SIM_DT_SECONDS,
-                MOVE_SPEED,
+                speed,
                 MOVEMENT_MAP_LIMIT,
                 &structure_obstacles,
                 PLAYER_COLLIDER_RADIUS,
+                &terrain,
             );
 
             if (step.x - player.x).abs() > f32::EPSILON
                 || (step.y - player.y).abs() > f32::EPSILON
                 || (step.vx - player.vx).abs() > f32::EPSILON
                 || (step.vy - player.vy).abs() > f32::EPSILON
+                || player.stamina != stamina_before
             {
                 changed = true;
             }
@@ -1624,6 +4077,7 @@ impl RoomDurableObject {
             player.y = step.y;
             player.vx = step.vx;
             player.vy = step.vy;
+            player.position_history.record(now, step.x, step.y);
             player.connected = true;
             player.last_seen = now;
         }
@@ -1631,89 +4085,487 @@ impl RoomDurableObject {
         Ok(changed)
     }
 
-    fn tick_projectiles(&self) -> Result<bool> {
-        let now = now_ms();
-        let mut runtime = self.runtime.borrow_mut();
-        if runtime.projectiles.is_empty() {
-            return Ok(false);
+    fn tick_projectiles(&self) -> Result<bool> {
+        let now = now_ms();
+        let mut runtime = self.runtime.borrow_mut();
+        if runtime.projectiles.is_empty() {
+            return Ok(false);
+        }
+
+        let mut player_ids = Vec::new();
+        let mut player_colliders = Vec::new();
+        for (player_id, player) in runtime.players.iter() {
+            if !player.connected || player.health.is_dead() {
+                continue;
+            }
+            player_ids.push(player_id.clone());
+            player_colliders.push(PlayerCollider {
+                x: player.x,
+                y: player.y,
+                radius: PLAYER_COLLIDER_RADIUS,
+            });
+        }
+
+        let (enemy_ids, enemy_colliders): (Vec<String>, Vec<PlayerCollider>) = runtime
+            .enemies
+            .values()
+            .map(|enemy| {
+                (
+                    enemy.enemy_id.clone(),
+                    PlayerCollider {
+                        x: enemy.x,
+                        y: enemy.y,
+                        radius: ENEMY_COLLIDER_RADIUS,
+                    },
+                )
+            })
+            .unzip();
+
+        let mut changed = false;
+        let mut hit_events = Vec::new();
+        let mut pending_damage: Vec<PendingDamage> = Vec::new();
+
+        let projectile_ids: Vec<String> = runtime.projectiles.keys().cloned().collect();
+        for projectile_id in projectile_ids {
+            let Some(projectile) = runtime.projectiles.get(&projectile_id) else {
+                continue;
+            };
+            if projectile.expires_at <= now {
+                runtime.projectiles.remove(&projectile_id);
+                changed = true;
+                continue;
+            }
+
+            let speed = projectile.vx.hypot(projectile.vy);
+            let (structure_ids, structure_obstacles): (Vec<String>, Vec<StructureObstacle>) =
+                runtime
+                    .obstacles
+                    .near(
+                        projectile.x,
+                        projectile.y,
+                        step_reach(speed, SIM_DT_SECONDS, PROJECTILE_COLLIDER_RADIUS),
+                    )
+                    .into_iter()
+                    .map(|(id, obstacle)| (id.clone(), obstacle))
+                    .unzip();
+            let Some(projectile) = runtime.projectiles.get_mut(&projectile_id) else {
+                continue;
+            };
+
+            // Shooters never collide with their own projectiles.
+            let (targets, target_ids): (Vec<PlayerCollider>, Vec<(&'static str, &String)>) =
+                player_colliders
+                    .iter()
+                    .zip(player_ids.iter())
+                    .filter(|(_, player_id)| **player_id != projectile.owner_id)
+                    .map(|(collider, player_id)| (*collider, ("player", player_id)))
+                    .chain(
+                        enemy_colliders
+                            .iter()
+                            .zip(enemy_ids.iter())
+                            .map(|(collider, enemy_id)| (*collider, ("enemy", enemy_id))),
+                    )
+                    .unzip();
+
+            let step = projectile_step_with_hits_fixed(
+                projectile.x,
+                projectile.y,
+                projectile.vx,
+                projectile.vy,
+                SIM_DT_SECONDS,
+                PROJECTILE_MAP_LIMIT,
+                &structure_obstacles,
+                &targets,
+                PROJECTILE_COLLIDER_RADIUS,
+            );
+            projectile.x = step.x;
+            projectile.y = step.y;
+            projectile.updated_at = now;
+            changed = true;
+
+            let Some(hit) = step.hit else {
+                continue;
+            };
+
+            let (target_kind, target_id) = match hit {
+                ProjectileHit::Structure(index) => ("structure", structure_ids[index].clone()),
+                ProjectileHit::Player(index) => (target_ids[index].0, target_ids[index].1.clone()),
+            };
+            hit_events.push(json!({
+                "projectileId": projectile.projectile_id,
+                "ownerId": projectile.owner_id,
+                "clientProjectileId": projectile.client_projectile_id,
+                "targetKind": target_kind,
+                "targetId": target_id,
+                "x": step.x,
+                "y": step.y,
+            }));
+            pending_damage.push((
+                target_kind,
+                target_id,
+                projectile.owner_id.clone(),
+                projectile.damage,
+            ));
+            runtime.projectiles.remove(&projectile_id);
+        }
+        drop(runtime);
+
+        for hit_event in hit_events {
+            self.broadcast_envelope("event", "projectile", "hit", Some(hit_event));
+        }
+        self.apply_pending_damage(pending_damage, now)?;
+
+        Ok(changed)
+    }
+
+    /// Spawns enemies from nests near players, walks them towards players or
+    /// structures and lands their contact attacks.
+    fn tick_enemies(&self, connected_players: &[String]) -> Result<bool> {
+        let now = now_ms();
+        let terrain = self.terrain.get();
+        let mut guard = self.runtime.borrow_mut();
+        let runtime = &mut *guard;
+
+        // Dead players still keep nests awake and enemies around; only living
+        // ones are chased and hit.
+        let anchors: Vec<(f32, f32)> = connected_players
+            .iter()
+            .filter_map(|player_id| runtime.players.get(player_id))
+            .map(|player| (player.x, player.y))
+            .collect();
+        let (player_ids, player_colliders): (Vec<String>, Vec<PlayerCollider>) = connected_players
+            .iter()
+            .filter_map(|player_id| Some((player_id, runtime.players.get(player_id)?)))
+            .filter(|(_, player)| !player.health.is_dead())
+            .map(|(player_id, player)| {
+                (
+                    player_id.clone(),
+                    PlayerCollider {
+                        x: player.x,
+                        y: player.y,
+                        radius: PLAYER_COLLIDER_RADIUS,
+                    },
+                )
+            })
+            .unzip();
+
+        let enemy_count = runtime.enemies.len();
+        runtime.enemies.retain(|_, enemy| {
+            anchors
+                .iter()
+                .any(|(x, y)| (x - enemy.x).hypot(y - enemy.y) <= ENEMY_LEASH_RADIUS)
+        });
+        let mut changed = runtime.enemies.len() != enemy_count;
+
+        let nests: BTreeSet<(i32, i32)> = anchors
+            .iter()
+            .flat_map(|(x, y)| terrain.nests_near(*x, *y, NEST_ACTIVATION_RADIUS))
+            .collect();
+        for nest in nests {
+            let spawned_at = *runtime.nest_spawned_at.entry(nest).or_insert(now);
+            if now - spawned_at < NEST_SPAWN_INTERVAL_MS || runtime.enemies.len() >= MAX_ENEMIES {
+                continue;
+            }
+            // A full nest restarts its timer so a replacement does not pop out
+            // the moment one of its enemies dies.
+            runtime.nest_spawned_at.insert(nest, now);
+            let alive = runtime
+                .enemies
+                .values()
+                .filter(|enemy| enemy.nest == nest)
+                .count();
+            if alive >= NEST_MAX_ENEMIES {
+                continue;
+            }
+            runtime.next_enemy_id += 1;
+            let enemy_id = format!("enemy_{}", runtime.next_enemy_id);
+            runtime.enemies.insert(
+                enemy_id.clone(),
+                RuntimeEnemyState {
+                    enemy_id,
+                    nest,
+                    x: tile_center(nest.0),
+                    y: tile_center(nest.1),
+                    vx: 0.0,
+                    vy: 0.0,
+                    health: Health::full(ENEMY_MAX_HP),
+                    next_attack_at: now,
+                },
+            );
+            changed = true;
+        }
+
+        if runtime.enemies.is_empty() {
+            return Ok(changed);
+        }
+
+        let flow_field = &*runtime.flow_field.get_or_insert_with(|| {
+            // Solid structures are the targets. Their cells are never entered,
+            // so they act as obstacles for everything routed around them.
+            let mut targets: Vec<(i32, i32)> = runtime
+                .structure_cells
+                .iter()
+                .filter(|(_, structure_id)| {
+                    runtime
+                        .structures
+                        .get(*structure_id)
+                        .is_some_and(|structure| structure_is_solid(&structure.kind))
+                })
+                .map(|(cell, _)| *cell)
+                .collect();
+            targets.sort_unstable();
+            FlowField::build(&targets, |x, y| terrain.tile_at(x, y).is_walkable())
+        });
+
+        let reach = step_reach(ENEMY_SPEED, SIM_DT_SECONDS, ENEMY_COLLIDER_RADIUS);
+        let mut pending_damage: Vec<PendingDamage> = Vec::new();
+        for enemy in runtime.enemies.values_mut() {
+            let nearby = runtime.obstacles.near(enemy.x, enemy.y, reach);
+            let waypoint = enemy_waypoint(enemy.x, enemy.y, &player_colliders, flow_field);
+            let step = enemy_step(
+                enemy.x,
+                enemy.y,
+                waypoint,
+                SIM_DT_SECONDS,
+                MOVEMENT_MAP_LIMIT,
+                |x, y| {
+                    terrain.blocks_area(x, y, ENEMY_COLLIDER_RADIUS)
+                        || nearby
+                            .iter()
+                            .any(|(_, obstacle)| obstacle.blocks(x, y, ENEMY_COLLIDER_RADIUS))
+                },
+            );
+            if step.x != enemy.x || step.y != enemy.y || step.vx != enemy.vx || step.vy != enemy.vy
+            {
+                changed = true;
+            }
+            enemy.x = step.x;
+            enemy.y = step.y;
+            enemy.vx = step.vx;
+            enemy.vy = step.vy;
+
+            if now < enemy.next_attack_at {
+                continue;
+            }
+            let target = player_colliders
+                .iter()
+                .zip(player_ids.iter())
+                .find(|(player, _)| enemy_touches_player(enemy.x, enemy.y, player))
+                .map(|(_, player_id)| ("player", player_id.clone()))
+                .or_else(|| {
+                    nearby
+                        .iter()
+                        .find(|(_, obstacle)| enemy_touches_structure(enemy.x, enemy.y, obstacle))
+                        .map(|(structure_id, _)| ("structure", (*structure_id).clone()))
+                });
+            if let Some((target_kind, target_id)) = target {
+                pending_damage.push((
+                    target_kind,
+                    target_id,
+                    enemy.enemy_id.clone(),
+                    ENEMY_CONTACT_DAMAGE,
+                ));
+                enemy.next_attack_at = now + ENEMY_ATTACK_INTERVAL_MS;
+            }
+        }
+        drop(guard);
+
+        if !pending_damage.is_empty() {
+            self.apply_pending_damage(pending_damage, now)?;
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    /// Applies damage gathered during a tick, persists structure damage and
+    /// announces deaths.
+    fn apply_pending_damage(&self, pending_damage: Vec<PendingDamage>, now: i64) -> Result<()> {
+        if pending_damage.is_empty() {
+            return Ok(());
+        }
+        let mut runtime = self.runtime.borrow_mut();
+        let mut death_events = Vec::new();
+        let mut damaged_structures = Vec::new();
+        let mut destroyed_structures = Vec::new();
+        for (target_kind, target_id, attacker_id, damage) in pending_damage {
+            let outcome = if target_kind == "player" {
+                let Some(player) = runtime.players.get_mut(&target_id) else {
+                    continue;
+                };
+                if !player_can_take_damage(player.health, player.invulnerable_until, now) {
+                    continue;
+                }
+                let outcome = player.health.apply_damage(damage);
+                if outcome == DamageOutcome::Killed {
+                    player.dead_until = now + RESPAWN_DELAY_MS;
+                    player.vx = 0.0;
+                    player.vy = 0.0;
+                }
+                outcome
+            } else if target_kind == "enemy" {
+                let Some(enemy) = runtime.enemies.get_mut(&target_id) else {
+                    continue;
+                };
+                let outcome = enemy.health.apply_damage(damage);
+                if outcome == DamageOutcome::Killed {
+                    runtime.enemies.remove(&target_id);
+                }
+                outcome
+            } else {
+                let state = &mut *runtime;
+                let Some(structure) = state.structures.get_mut(&target_id) else {
+                    continue;
+                };
+                if !structure_damaged_by(&attacker_id, &structure.owner_id, &state.members) {
+                    continue;
+                }
+                let outcome = structure.health.apply_damage(damage);
+                match outcome {
+                    DamageOutcome::Killed => destroyed_structures.push(target_id.clone()),
+                    DamageOutcome::Damaged => damaged_structures.push(target_id.clone()),
+                    DamageOutcome::Ignored => {}
+                }
+                outcome
+            };
+
+            if outcome == DamageOutcome::Killed {
+                death_events.push(json!({
+                    "targetKind": target_kind,
+                    "targetId": target_id,
+                    "killerId": attacker_id,
+                }));
+            }
+            if outcome != DamageOutcome::Ignored {
+                self.dirty_health.set(true);
+            }
         }
 
-        let mut changed = false;
-
-        let projectile_ids: Vec<String> = runtime.projectiles.keys().cloned().collect();
-        for projectile_id in projectile_ids {
-            let Some(projectile) = runtime.projectiles.get_mut(&projectile_id) else {
-                continue;
-            };
+        // Hp is written at the next checkpoint; destroyed structures are
+        // deleted right away.
+        runtime.dirty_structure_health.extend(damaged_structures);
+        for structure_id in destroyed_structures.iter() {
+            runtime.remove_structure(structure_id);
+        }
+        drop(runtime);
 
-            if projectile.expires_at <= now {
-                runtime.projectiles.remove(&projectile_id);
-                changed = true;
-                continue;
+        if !destroyed_structures.is_empty() {
+            for structure_id in destroyed_structures.iter() {
+                self.persist_structure_delete(structure_id)?;
             }
+            self.dirty_build.set(true);
+        }
 
-            let (next_x, next_y) = projectile_step(
-                projectile.x,
-                projectile.y,
-                projectile.vx,
-                projectile.vy,
-                SIM_DT_SECONDS,
-                PROJECTILE_MAP_LIMIT,
-            );
-            projectile.x = next_x;
-            projectile.y = next_y;
-            projectile.updated_at = now;
-            changed = true;
+        for death_event in death_events {
+            self.broadcast_envelope("event", "health", "death", Some(death_event));
         }
 
-        Ok(changed)
+        Ok(())
     }
 
-    fn snapshot_payload(&self, full: bool) -> Result<Value> {
+    fn shared_snapshot(&self) -> Result<SharedSnapshot> {
+        self.prune_stale_build_previews()?;
         let connected_players = self.connected_player_ids();
+        let runtime = self.runtime.borrow();
+        let members = connected_players
+            .iter()
+            .map(|player_id| {
+                let role = if is_room_admin(player_id, &runtime.members, &self.room_admin_ids) {
+                    RoomRole::Admin
+                } else {
+                    RoomRole::Member
+                };
+                json!({
+                    "playerId": player_id,
+                    "role": role.as_str(),
+                    "teamId": runtime
+                        .members
+                        .get(player_id)
+                        .and_then(|member| member.team_id.clone()),
+                })
+            })
+            .collect();
+        let structures = runtime
+            .structures
+            .values()
+            .map(|row| {
+                (
+                    row.structure_id.clone(),
+                    structure_json(row, &runtime.inserters, &runtime.power),
+                )
+            })
+            .collect();
+        Ok(SharedSnapshot {
+            connected_players,
+            members,
+            structures,
+            belt_chunks: belt_chunks_json(&runtime.belts),
+            balances: runtime.power.balances(),
+            research: vec![("room".to_string(), research_json(&runtime.research))],
+        })
+    }
+
+    fn snapshot_payload(
+        &self,
+        viewer: &SocketAttachment,
+        full: bool,
+        shared: &SharedSnapshot,
+    ) -> Result<(Value, Option<Value>)> {
+        let connected_players = &shared.connected_players;
         let connected_set: HashSet<&str> = connected_players.iter().map(String::as_str).collect();
-        let online = connected_players.clone();
         let now = now_ms();
 
-        self.prune_stale_build_previews()?;
         let runtime = self.runtime.borrow();
 
-        let mut movement_players = Vec::new();
-        for player_id in connected_players.iter() {
-            if let Some(player) = runtime.players.get(player_id) {
-                movement_players.push(json!({
-                    "id": player_id,
-                    "x": player.x,
-                    "y": player.y,
-                    "vx": player.vx,
-                    "vy": player.vy,
-                    "connected": true,
-                }));
-            }
-        }
+        let area = runtime
+            .players
+            .get(&viewer.player_id)
+            .map(|player| {
+                InterestArea::around(player.x, player.y, self.interest_radius_chunks.get())
+            })
+            .unwrap_or_else(|| InterestArea::around(0.0, 0.0, self.interest_radius_chunks.get()));
+
+        // The viewer always sees itself so prediction/reconciliation never loses its anchor.
+        let visible_players: Vec<(&String, &RuntimePlayerState)> = connected_players
+            .iter()
+            .filter_map(|player_id| runtime.players.get(player_id).map(|row| (player_id, row)))
+            .filter(|(player_id, row)| {
+                **player_id == viewer.player_id || area.contains_point(row.x, row.y)
+            })
+            .collect();
 
+        let mut movement_players = Vec::new();
         let mut input_acks = JsonMap::new();
-        for player_id in connected_players.iter() {
-            if let Some(player) = runtime.players.get(player_id) {
-                input_acks.insert(player_id.clone(), Value::from(player.last_input_seq));
+        for (player_id, player) in visible_players.iter() {
+            let mut player_json = json!({
+                "id": player_id,
+                "x": player.x,
+                "y": player.y,
+                "vx": player.vx,
+                "vy": player.vy,
+                "connected": true,
+                "weapon": player.weapon.id,
+            });
+            // Only the viewer predicts its own sprint budget.
+            if **player_id == viewer.player_id {
+                player_json["stamina"] = json!(player.stamina.seconds);
+                player_json["exhausted"] = json!(player.stamina.exhausted);
             }
+            movement_players.push(((*player_id).clone(), player_json));
+            input_acks.insert((*player_id).clone(), Value::from(player.last_input_seq));
         }
 
-        let mut structure_rows: Vec<&RuntimeStructureState> = runtime.structures.values().collect();
+        let mut structure_rows: Vec<&RuntimeStructureState> = runtime
+            .structures
+            .values()
+            .filter(|row| area.contains_chunk(row.chunk_x, row.chunk_y))
+            .collect();
         structure_rows.sort_by_key(|row| std::cmp::Reverse(row.created_at));
-        let structures: Vec<Value> = structure_rows
+        structure_rows.truncate(MAX_STRUCTURES);
+        let structures: Vec<(String, Value)> = structure_rows
             .iter()
-            .take(MAX_STRUCTURES)
-            .map(|row| {
-                json!({
-                    "id": row.structure_id,
-                    "ownerId": row.owner_id,
-                    "kind": row.kind,
-                    "x": row.x,
-                    "y": row.y,
-                    "chunkX": row.chunk_x,
-                    "chunkY": row.chunk_y,
-                })
+            .filter_map(|row| {
+                let value = shared.structures.get(&row.structure_id)?;
+                Some((row.structure_id.clone(), value.clone()))
             })
             .collect();
 
@@ -1723,19 +4575,24 @@ impl RoomDurableObject {
             .map(|(_, row)| row)
             .filter(|row| connected_set.contains(row.player_id.as_str()))
             .filter(|row| row.updated_at > now - BUILD_PREVIEW_STALE_MS)
+            .filter(|row| area.contains_point(row.x, row.y))
             .collect();
         preview_rows.sort_by_key(|row| std::cmp::Reverse(row.updated_at));
 
-        let previews: Vec<Value> = preview_rows
+        let previews: Vec<(String, Value)> = preview_rows
             .iter()
             .take(MAX_PREVIEWS)
             .map(|row| {
-                json!({
-                    "playerId": row.player_id,
-                    "kind": row.kind,
-                    "x": row.x,
-                    "y": row.y,
-                })
+                (
+                    row.player_id.clone(),
+                    json!({
+                        "playerId": row.player_id,
+                        "kind": row.kind,
+                        "direction": row.direction.as_str(),
+                        "x": row.x,
+                        "y": row.y,
+                    }),
+                )
             })
             .collect();
 
@@ -1744,36 +4601,231 @@ impl RoomDurableObject {
             .iter()
             .map(|(_, row)| row)
             .filter(|row| row.expires_at > now)
+            .filter(|row| area.contains_point(row.x, row.y))
             .collect();
         projectile_rows.sort_by_key(|row| std::cmp::Reverse(row.updated_at));
 
-        let projectiles: Vec<Value> = projectile_rows
+        let projectiles: Vec<(String, Value)> = projectile_rows
             .iter()
             .take(MAX_PROJECTILES)
             .map(|row| {
-                json!({
-                    "id": row.projectile_id,
-                    "ownerId": row.owner_id,
-                    "x": row.x,
-                    "y": row.y,
-                    "vx": row.vx,
-                    "vy": row.vy,
-                    "clientProjectileId": row.client_projectile_id,
+                (
+                    row.projectile_id.clone(),
+                    json!({
+                        "id": row.projectile_id,
+                        "ownerId": row.owner_id,
+                        "x": row.x,
+                        "y": row.y,
+                        "vx": row.vx,
+                        "vy": row.vy,
+                        "clientProjectileId": row.client_projectile_id,
+                    }),
+                )
+            })
+            .collect();
+
+        // Only networks the viewer can see a member of.
+        let balances = &shared.balances;
+        let visible_networks: BTreeSet<u32> = structure_rows
+            .iter()
+            .filter_map(|row| runtime.power.network_of(&row.structure_id))
+            .collect();
+        let power_networks: Vec<(String, Value)> = visible_networks
+            .into_iter()
+            .filter_map(|network| Some((network, balances.get(&network)?)))
+            .map(|(network, balance)| {
+                (
+                    network.to_string(),
+                    json!({
+                        "id": network.to_string(),
+                        "supplyKw": balance.supply_kw,
+                        "demandKw": balance.demand_kw,
+                        "satisfaction": balance.satisfaction(),
+                    }),
+                )
+            })
+            .collect();
+
+        let mut enemy_rows: Vec<&RuntimeEnemyState> = runtime
+            .enemies
+            .values()
+            .filter(|row| area.contains_point(row.x, row.y))
+            .collect();
+        enemy_rows.sort_by(|a, b| a.enemy_id.cmp(&b.enemy_id));
+
+        let enemies: Vec<(String, Value)> = enemy_rows
+            .iter()
+            .take(MAX_ENEMIES)
+            .map(|row| {
+                (
+                    row.enemy_id.clone(),
+                    json!({
+                        "id": row.enemy_id,
+                        "x": row.x,
+                        "y": row.y,
+                        "vx": row.vx,
+                        "vy": row.vy,
+                        "hp": row.health.current,
+                        "maxHp": row.health.max,
+                    }),
+                )
+            })
+            .collect();
+
+        let health_players: Vec<(String, Value)> = visible_players
+            .iter()
+            .map(|(player_id, row)| {
+                (
+                    (*player_id).clone(),
+                    json!({
+                        "id": player_id,
+                        "hp": row.health.current,
+                        "maxHp": row.health.max,
+                        "dead": row.health.is_dead(),
+                        "respawnInMs": if row.health.is_dead() { (row.dead_until - now).max(0) } else { 0 },
+                        "invulnerable": row.invulnerable_until > now,
+                    }),
+                )
+            })
+            .collect();
+
+        // Structures at full health are implied by their presence in the build channel.
+        let health_structures: Vec<(String, Value)> = structure_rows
+            .iter()
+            .filter(|row| row.health.current < row.health.max)
+            .map(|row| {
+                (
+                    row.structure_id.clone(),
+                    json!({
+                        "id": row.structure_id,
+                        "hp": row.health.current,
+                        "maxHp": row.health.max,
+                    }),
+                )
+            })
+            .collect();
+
+        let mut views = self.socket_views.borrow_mut();
+        let view = views.entry(viewer.view_key().to_string()).or_default();
+
+        let interest_change = {
+            let next_players: HashSet<String> = visible_players
+                .iter()
+                .map(|(player_id, _)| (*player_id).clone())
+                .collect();
+            let next_structures: HashSet<String> = structure_rows
+                .iter()
+                .map(|row| row.structure_id.clone())
+                .collect();
+            let change = InterestChange {
+                entered_players: next_players.difference(&view.players).cloned().collect(),
+                left_players: view.players.difference(&next_players).cloned().collect(),
+                entered_structures: next_structures
+                    .difference(&view.structures)
+                    .cloned()
+                    .collect(),
+                left_structures: view
+                    .structures
+                    .difference(&next_structures)
+                    .cloned()
+                    .collect(),
+            };
+            view.players = next_players;
+            view.structures = next_structures;
+            change
+        };
+
+        // Without an acknowledged baseline the client has nothing to apply a
+        // delta to, so the snapshot goes out full.
+        let baseline = if full { None } else { view.baseline.as_ref() };
+        let full = baseline.is_none();
+        let baseline_id = baseline.map(|(snapshot_id, _)| *snapshot_id);
+        let baseline_state = baseline.map(|(_, state)| state);
+
+        let mut sent_state = ReplicatedState::new();
+        let mut diff = |channel: &'static str, entities: &[(String, Value)]| {
+            let (delta, changed, fingerprints) = diff_entity_channel(
+                entities,
+                baseline_state.and_then(|state| state.get(channel)),
+            );
+            sent_state.insert(channel, fingerprints);
+            (delta, changed)
+        };
+        let (players_delta, _) = diff("movement.players", &movement_players);
+        let (structures_delta, structures_changed) = diff("build.structures", &structures);
+        let (previews_delta, previews_changed) = diff("build.previews", &previews);
+        let (projectiles_delta, projectiles_changed) = diff("projectile.projectiles", &projectiles);
+        let (enemies_delta, enemies_changed) = diff("enemy.enemies", &enemies);
+        let (power_networks_delta, power_networks_changed) =
+            diff("power.networks", &power_networks);
+        let (health_players_delta, health_players_changed) =
+            diff("health.players", &health_players);
+        let (health_structures_delta, health_structures_changed) =
+            diff("health.structures", &health_structures);
+        // Only the viewer's own inventory is ever put in its snapshot.
+        let inventory: Vec<(String, Value)> = runtime
+            .players
+            .get(&viewer.player_id)
+            .map(|player| {
+                (
+                    viewer.player_id.clone(),
+                    json!({ "slots": inventory_json(&player.inventory) }),
+                )
+            })
+            .into_iter()
+            .collect();
+        let (_, inventory_changed) = diff("inventory.self", &inventory);
+        // A chest's contents only go to players who have it open, may use it
+        // and are still in reach.
+        let open_chest: Vec<(String, Value)> = runtime
+            .players
+            .get(&viewer.player_id)
+            .and_then(|player| {
+                let structure = runtime.structures.get(player.open_chest.as_ref()?)?;
+                let chest = structure.chest.as_ref()?;
+                let permitted = can_modify_structure(
+                    &viewer.player_id,
+                    &structure.owner_id,
+                    &runtime.members,
+                    &self.room_admin_ids,
+                );
+                let in_reach =
+                    is_room_admin(&viewer.player_id, &runtime.members, &self.room_admin_ids)
+                        || (structure.x - player.x).hypot(structure.y - player.y)
+                            <= self.build_interaction_distance;
+                (permitted && in_reach).then(|| {
+                    (
+                        structure.structure_id.clone(),
+                        json!({ "id": structure.structure_id, "slots": inventory_json(chest) }),
+                    )
                 })
             })
+            .into_iter()
+            .collect();
+        let (_, open_chest_changed) = diff("chest.open", &open_chest);
+        let (_, research_changed) = diff("research.state", &shared.research);
+        let belt_chunks: Vec<(String, Value)> = shared
+            .belt_chunks
+            .iter()
+            .filter(|((chunk_x, chunk_y), _)| area.contains_chunk(*chunk_x, *chunk_y))
+            .map(|(_, chunk)| chunk.clone())
             .collect();
+        let (belt_chunks_delta, belt_chunks_changed) = diff("belt.chunks", &belt_chunks);
 
         let include_presence = full || self.dirty_presence.get();
-        let include_build = full || self.dirty_build.get();
-        let include_projectiles = full || self.dirty_projectiles.get();
+        let include_build =
+            full || self.dirty_build.get() || structures_changed || previews_changed;
+        let include_projectiles = full || projectiles_changed;
+        let include_health = full || health_players_changed || health_structures_changed;
         let mut features = JsonMap::new();
 
         if include_presence {
             features.insert(
                 "presence".to_string(),
                 json!({
-                    "online": online,
+                    "online": connected_players,
                     "onlineCount": connected_players.len(),
+                    "members": shared.members,
                 }),
             );
         }
@@ -1782,7 +4834,7 @@ impl RoomDurableObject {
         features.insert(
             "movement".to_string(),
             json!({
-                "players": movement_players,
+                "players": players_delta,
                 "inputAcks": input_acks,
                 "speed": MOVE_SPEED,
             }),
@@ -1792,9 +4844,9 @@ impl RoomDurableObject {
             features.insert(
                 "build".to_string(),
                 json!({
-                    "structures": structures,
+                    "structures": structures_delta,
                     "structureCount": runtime.structures.len(),
-                    "previews": previews,
+                    "previews": previews_delta,
                     "previewCount": preview_rows.len().min(MAX_PREVIEWS),
                 }),
             );
@@ -1804,32 +4856,175 @@ impl RoomDurableObject {
             features.insert(
                 "projectile".to_string(),
                 json!({
-                    "projectiles": projectiles,
+                    "projectiles": projectiles_delta,
                     "projectileCount": projectile_rows.len().min(MAX_PROJECTILES),
                 }),
             );
         }
 
-        Ok(json!({
+        if full || power_networks_changed {
+            features.insert(
+                "power".to_string(),
+                json!({ "networks": power_networks_delta }),
+            );
+        }
+
+        if full || enemies_changed {
+            features.insert(
+                "enemy".to_string(),
+                json!({
+                    "enemies": enemies_delta,
+                    "enemyCount": enemy_rows.len().min(MAX_ENEMIES),
+                }),
+            );
+        }
+
+        if include_health {
+            features.insert(
+                "health".to_string(),
+                json!({
+                    "players": health_players_delta,
+                    "structures": health_structures_delta,
+                }),
+            );
+        }
+
+        if full || belt_chunks_changed {
+            features.insert("belt".to_string(), json!({ "chunks": belt_chunks_delta }));
+        }
+
+        if full || inventory_changed {
+            if let Some((_, value)) = inventory.into_iter().next() {
+                features.insert("inventory".to_string(), value);
+            }
+        }
+
+        if full || research_changed {
+            if let Some((_, value)) = shared.research.first() {
+                features.insert("research".to_string(), value.clone());
+            }
+        }
+
+        if full || open_chest_changed {
+            features.insert(
+                "chest".to_string(),
+                json!({ "open": open_chest.into_iter().next().map(|(_, value)| value) }),
+            );
+        }
+
+        view.next_snapshot_id = view.next_snapshot_id.wrapping_add(1).max(1);
+        let snapshot_id = view.next_snapshot_id;
+        view.pending.push_back((snapshot_id, now_ms(), sent_state));
+        while view.pending.len() > MAX_PENDING_BASELINES {
+            view.pending.pop_front();
+        }
+
+        let snapshot = json!({
             "roomCode": self.room_code.borrow().clone(),
             "serverTick": self.tick.get(),
             "simRateHz": SIM_RATE_HZ,
             "snapshotRateHz": SNAPSHOT_RATE_HZ,
             "serverTime": now_ms(),
             "mode": if full { "full" } else { "delta" },
+            "snapshotId": snapshot_id,
+            "baselineId": baseline_id,
             "features": features,
-        }))
+        });
+
+        let interest_changed = !interest_change.entered_players.is_empty()
+            || !interest_change.left_players.is_empty()
+            || !interest_change.entered_structures.is_empty()
+            || !interest_change.left_structures.is_empty();
+        let event = interest_changed.then(|| {
+            json!({
+                "chunkX": area.chunk_x,
+                "chunkY": area.chunk_y,
+                "radius": area.radius,
+                "entered": {
+                    "players": interest_change.entered_players,
+                    "structures": interest_change.entered_structures,
+                },
+                "left": {
+                    "players": interest_change.left_players,
+                    "structures": interest_change.left_structures,
+                },
+            })
+        });
+
+        Ok((snapshot, event))
+    }
+
+    fn handle_snapshot_ack(
+        &self,
+        viewer: &SocketAttachment,
+        payload: Option<Value>,
+    ) -> Result<bool> {
+        let payload =
+            payload.ok_or_else(|| Error::RustError("missing snapshot ack payload".into()))?;
+        let ack: SnapshotAckPayload = serde_json::from_value(payload)
+            .map_err(|_| Error::RustError("invalid snapshot ack payload".into()))?;
+
+        let now = now_ms();
+        let mut views = self.socket_views.borrow_mut();
+        let view = views.entry(viewer.view_key().to_string()).or_default();
+        if let Some(index) = view
+            .pending
+            .iter()
+            .position(|(snapshot_id, _, _)| *snapshot_id == ack.snapshot_id)
+        {
+            if let Some((snapshot_id, sent_at, state)) = view.pending.drain(..=index).next_back() {
+                view.baseline = Some((snapshot_id, state));
+                if let Some(player) = self.runtime.borrow_mut().players.get_mut(&viewer.player_id) {
+                    let sample = (now - sent_at).max(0);
+                    player.rtt_ms = if player.rtt_ms == 0 {
+                        sample
+                    } else {
+                        (player.rtt_ms * 7 + sample) / 8
+                    };
+                }
+            }
+        } else if view
+            .baseline
+            .as_ref()
+            .is_none_or(|(snapshot_id, _)| *snapshot_id != ack.snapshot_id)
+        {
+            // Unknown or expired id (including the explicit resync id 0):
+            // forget the baseline so the next snapshot is sent full.
+            view.baseline = None;
+            view.pending.clear();
+        }
+
+        Ok(false)
     }
 
-    fn send_snapshot_to(&self, socket: &WebSocket, full: bool) {
-        if let Ok(payload) = self.snapshot_payload(full) {
+    fn send_snapshot_to(&self, socket: &WebSocket, full: bool, shared: &SharedSnapshot) {
+        let Some(attachment) = self.read_socket_attachment(socket) else {
+            return;
+        };
+
+        if let Ok((payload, interest_event)) = self.snapshot_payload(&attachment, full, shared) {
+            if let Some(interest_event) = interest_event {
+                self.send_envelope(
+                    socket,
+                    "event",
+                    "interest",
+                    "update",
+                    None,
+                    Some(interest_event),
+                );
+            }
             self.send_envelope(socket, "snapshot", "core", "state", None, Some(payload));
         }
     }
 
+    /// Previews are pruned and the shared parts built once, then each socket
+    /// gets its own filtered delta.
     fn broadcast_snapshot(&self, full: bool) {
-        if let Ok(payload) = self.snapshot_payload(full) {
-            self.broadcast_envelope("snapshot", "core", "state", Some(payload));
+        let Ok(shared) = self.shared_snapshot() else {
+            return;
+        };
+        for socket in self.state.get_websockets() {
+            self.send_snapshot_to(&socket, full, &shared);
         }
     }
 
@@ -1837,23 +5032,36 @@ impl RoomDurableObject {
         &self,
         message: WebSocketIncomingMessage,
     ) -> Result<ClientCommandEnvelope> {
-        let raw = match message {
-            WebSocketIncomingMessage::String(text) => text,
-            WebSocketIncomingMessage::Binary(_) => {
-                return Err(Error::RustError(
-                    "binary websocket payloads are not supported".into(),
-                ));
-            }
-        };
+        let (envelope, expected_version) = match message {
+            WebSocketIncomingMessage::String(raw) => {
+                if raw.len() > 32 * 1024 {
+                    return Err(Error::RustError("protocol envelope too large".into()));
+                }
 
-        if raw.len() > 32 * 1024 {
-            return Err(Error::RustError("protocol envelope too large".into()));
-        }
+                let envelope: ClientCommandEnvelope = serde_json::from_str(&raw)
+                    .map_err(|_| Error::RustError("malformed protocol envelope".into()))?;
+                (envelope, PROTOCOL_VERSION)
+            }
+            WebSocketIncomingMessage::Binary(bytes) => {
+                if bytes.len() > 32 * 1024 {
+                    return Err(Error::RustError("protocol envelope too large".into()));
+                }
 
-        let envelope: ClientCommandEnvelope = serde_json::from_str(&raw)
-            .map_err(|_| Error::RustError("malformed protocol envelope".into()))?;
+                let command = decode_client_envelope(&bytes)?;
+                let envelope = ClientCommandEnvelope {
+                    v: BINARY_PROTOCOL_VERSION,
+                    kind: "command".to_string(),
+                    seq: command.seq,
+                    feature: command.feature,
+                    action: command.action,
+                    client_time: command.client_time,
+                    payload: command.payload,
+                };
+                (envelope, BINARY_PROTOCOL_VERSION)
+            }
+        };
 
-        if envelope.v != PROTOCOL_VERSION
+        if envelope.v != expected_version
             || envelope.kind != "command"
             || envelope.seq < 1
             || envelope.feature.is_empty()
@@ -1888,15 +5096,105 @@ impl RoomDurableObject {
                 );
                 Ok(false)
             }
+            ("core", "snapshot_ack") => {
+                let Some(attachment) = self.read_socket_attachment(socket) else {
+                    return Ok(false);
+                };
+                self.handle_snapshot_ack(&attachment, envelope.payload.clone())
+            }
             ("movement", "input_batch") => {
                 self.handle_movement_input_batch(player_id, envelope.payload.clone())
             }
+            ("build", "remove") => {
+                match self.handle_build_remove(player_id, envelope.payload.clone()) {
+                    Ok(()) => Ok(true),
+                    Err(error) => {
+                        let structure_id = envelope
+                            .payload
+                            .as_ref()
+                            .and_then(|payload| payload.get("id"))
+                            .cloned()
+                            .unwrap_or(Value::Null);
+                        self.send_envelope(
+                            socket,
+                            "error",
+                            "build",
+                            "remove_rejected",
+                            Some(envelope.seq),
+                            Some(json!({
+                                "code": error.code(),
+                                "message": error.message(),
+                                "structureId": structure_id,
+                            })),
+                        );
+                        Ok(false)
+                    }
+                }
+            }
+            ("build", "place") => {
+                match self.handle_build_place(player_id, envelope.payload.clone()) {
+                    Ok(changed) => Ok(changed),
+                    Err(error) => {
+                        self.send_envelope(
+                            socket,
+                            "error",
+                            "build",
+                            "place_rejected",
+                            Some(envelope.seq),
+                            Some(json!({
+                                "code": error.code(),
+                                "message": error.message(),
+                            })),
+                        );
+                        Ok(false)
+                    }
+                }
+            }
             ("build", action) => {
                 self.handle_build_command(player_id, action, envelope.payload.clone())
             }
+            ("team", "join") => {
+                let team: TeamJoinPayload = envelope
+                    .payload
+                    .clone()
+                    .and_then(|payload| serde_json::from_value(payload).ok())
+                    .ok_or_else(|| Error::RustError("invalid team payload".into()))?;
+                let team_id = sanitize_team_id(&team.team_id)
+                    .ok_or_else(|| Error::RustError("invalid team id".into()))?;
+                let target_id = match team.player_id.as_deref() {
+                    Some(target_id) => sanitize_player_id(target_id)
+                        .ok_or_else(|| Error::RustError("invalid player id".into()))?,
+                    None => player_id.to_string(),
+                };
+                let mut runtime = self.runtime.borrow_mut();
+                let join = check_team_join(
+                    player_id,
+                    &target_id,
+                    &team_id,
+                    &runtime.members,
+                    &runtime.team_owners,
+                    &runtime.team_invites,
+                    &self.room_admin_ids,
+                )?;
+                let invite = (target_id, team_id);
+                if join == TeamJoin::Invite {
+                    return Ok(runtime.team_invites.insert(invite));
+                }
+                runtime.team_invites.remove(&invite);
+                drop(runtime);
+                let (target_id, team_id) = invite;
+                self.set_member_team(&target_id, Some(team_id))
+            }
+            ("team", "leave") => self.set_member_team(player_id, None),
             ("projectile", "fire") => {
                 self.handle_projectile_fire(player_id, envelope.payload.clone())
             }
+            ("projectile", "equip") => {
+                self.handle_projectile_equip(player_id, envelope.payload.clone())
+            }
+            ("research", "select") => {
+                self.handle_research_select(player_id, envelope.payload.clone())
+            }
             _ => Err(Error::RustError("unknown feature/action".into())),
         }
     }
@@ -1937,18 +5235,29 @@ impl RoomDurableObject {
 
 impl DurableObject for RoomDurableObject {
     fn new(state: State, env: Env) -> Self {
+        let interest_radius_chunks = interest_radius_from_env(&env);
+        let build_interaction_distance = build_interaction_distance_from_env(&env);
+        let room_admin_ids = room_admin_ids_from_env(&env);
         let room = Self {
             state,
             env,
             room_code: RefCell::new("UNKNOWN".to_string()),
+            room_creator_id: RefCell::new(None),
             tick: Cell::new(0),
             last_loop_ms: Cell::new(now_ms() as f64),
             accumulator_ms: Cell::new(0.0),
+            tick_alarm_armed: Cell::new(false),
             last_checkpoint_ms: Cell::new(now_ms()),
             snapshot_dirty: Cell::new(false),
             dirty_presence: Cell::new(false),
             dirty_build: Cell::new(false),
             dirty_projectiles: Cell::new(false),
+            dirty_health: Cell::new(false),
+            interest_radius_chunks: Cell::new(interest_radius_chunks),
+            terrain: Cell::new(Terrain::new(0)),
+            build_interaction_distance,
+            room_admin_ids,
+            socket_views: RefCell::new(HashMap::new()),
             runtime: RefCell::new(RoomRuntimeState::default()),
         };
 
@@ -1959,6 +5268,14 @@ impl DurableObject for RoomDurableObject {
         if let Ok(Some(room_code)) = room.load_room_code_from_db() {
             room.room_code.replace(room_code);
         }
+        if let Ok(creator_id) = room.load_room_creator_from_db() {
+            room.room_creator_id.replace(creator_id);
+        }
+
+        match room.load_or_create_terrain_seed() {
+            Ok(seed) => room.terrain.set(Terrain::new(seed)),
+            Err(error) => console_error!("failed to load terrain seed: {error}"),
+        }
 
         if let Err(error) = room.hydrate_runtime_from_db() {
             console_error!("failed to hydrate runtime state: {error}");
@@ -1976,9 +5293,6 @@ impl DurableObject for RoomDurableObject {
         let room_code = parse_room_code_from_path(url.path())
             .ok_or_else(|| Error::RustError("invalid room endpoint".into()))?;
 
-        self.room_code.replace(room_code.clone());
-        self.persist_room_code(&room_code)?;
-
         let upgrade = req
             .headers()
             .get("Upgrade")?
@@ -1991,7 +5305,18 @@ impl DurableObject for RoomDurableObject {
 
         let resume_token_hint =
             parse_query_param(&url, "resumeToken").or_else(|| parse_query_param(&url, "resume"));
-        let player_id = authenticate_player(&url, &self.env).await?;
+        let player_id = authenticate_player(&url, &self.env, &self.state.storage()).await?;
+
+        // The room code is stored by the first connection that gets this far;
+        // that player created the room.
+        if self.load_room_code_from_db()?.is_none() {
+            self.persist_room_creator(&player_id)?;
+            self.room_creator_id
+                .replace(self.load_room_creator_from_db()?);
+        }
+        self.room_code.replace(room_code.clone());
+        self.persist_room_code(&room_code)?;
+        let protocol = parse_protocol_version(&url);
         let resume_token = self.issue_resume_token(&player_id, resume_token_hint.as_deref())?;
 
         let pair = WebSocketPair::new()?;
@@ -2004,6 +5329,12 @@ impl DurableObject for RoomDurableObject {
         server.serialize_attachment(SocketAttachment {
             player_id: player_id.clone(),
             last_seq: 0,
+            socket_id: format!(
+                "sock_{:x}_{:x}",
+                now_ms() as u64,
+                (js_sys::Math::random() * 1e12) as u64
+            ),
+            protocol,
         })?;
 
         self.on_connect_player(&player_id)?;
@@ -2020,21 +5351,51 @@ impl DurableObject for RoomDurableObject {
                 "simRateHz": SIM_RATE_HZ,
                 "snapshotRateHz": SNAPSHOT_RATE_HZ,
                 "resumeToken": resume_token,
+                "protocol": protocol,
+                "interestRadiusChunks": self.interest_radius_chunks.get(),
+                "chunkWorldSize": BUILD_GRID_SIZE * BUILD_CHUNK_CELLS as f64,
+                "terrainSeed": self.terrain.get().seed(),
             })),
         );
 
-        self.send_snapshot_to(&server, true);
+        if let Ok(shared) = self.shared_snapshot() {
+            self.send_snapshot_to(&server, true, &shared);
+        }
         self.broadcast_snapshot(false);
+        self.ensure_tick_alarm().await?;
 
         Response::from_websocket(client)
     }
 
+    async fn alarm(&self) -> Result<Response> {
+        self.tick_alarm_armed.set(false);
+        self.run_simulation_until_now()?;
+
+        let open_sockets = self.state.get_websockets().len();
+        let Some(delay_ms) = next_alarm_delay_ms(open_sockets, self.accumulator_ms.get()) else {
+            // Room went idle: persist the final player state and let the
+            // object hibernate until the next connection restarts the loop.
+            self.checkpoint_runtime_players_to_db()?;
+            self.checkpoint_machines_to_db()?;
+            self.last_checkpoint_ms.set(now_ms());
+            return Response::empty();
+        };
+
+        self.state
+            .storage()
+            .set_alarm(Duration::from_millis(delay_ms))
+            .await?;
+        self.tick_alarm_armed.set(true);
+        Response::empty()
+    }
+
     async fn websocket_message(
         &self,
         ws: WebSocket,
         message: WebSocketIncomingMessage,
     ) -> Result<()> {
         self.run_simulation_until_now()?;
+        self.ensure_tick_alarm().await?;
 
         let mut attachment = match self.read_socket_attachment(&ws) {
             Some(attachment) => attachment,
@@ -2057,9 +5418,13 @@ impl DurableObject for RoomDurableObject {
         attachment.last_seq = envelope.seq;
         ws.serialize_attachment(attachment.clone())?;
 
+        // Snapshot acks arrive at snapshot rate; acking them back would only add traffic.
+        let wants_ack = !(envelope.feature == "core" && envelope.action == "snapshot_ack");
         match self.apply_command(&ws, &attachment.player_id, &envelope) {
             Ok(state_changed) => {
-                self.send_ack(&ws, "core", "command", envelope.seq);
+                if wants_ack {
+                    self.send_ack(&ws, "core", "command", envelope.seq);
+                }
                 if state_changed {
                     self.snapshot_dirty.set(true);
                     self.broadcast_snapshot(false);
@@ -2067,6 +5432,7 @@ impl DurableObject for RoomDurableObject {
                     self.dirty_presence.set(false);
                     self.dirty_build.set(false);
                     self.dirty_projectiles.set(false);
+                    self.dirty_health.set(false);
                 }
             }
             Err(error) => {
@@ -2090,6 +5456,7 @@ impl DurableObject for RoomDurableObject {
                 self.on_disconnect_player(&attachment.player_id)?;
                 self.broadcast_snapshot(false);
             }
+            self.socket_views.borrow_mut().remove(attachment.view_key());
         }
 
         Ok(())
@@ -2101,8 +5468,253 @@ impl DurableObject for RoomDurableObject {
                 self.on_disconnect_player(&attachment.player_id)?;
                 self.broadcast_snapshot(false);
             }
+            self.socket_views.borrow_mut().remove(attachment.view_key());
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(team_id: Option<&str>) -> RoomMemberState {
+        RoomMemberState {
+            role: RoomRole::Member,
+            team_id: team_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn joining_a_team_takes_an_invite_the_player_accepts() {
+        let admins = HashSet::from(["admin".to_string()]);
+        let mut members = HashMap::from([
+            ("owner".to_string(), member(Some("red"))),
+            ("victim".to_string(), member(None)),
+            ("admin".to_string(), member(None)),
+        ]);
+        let team_owners = HashMap::from([("red".to_string(), "owner".to_string())]);
+        let mut invites = HashSet::new();
+        let join = |actor: &str, target: &str, team: &str, invites: &HashSet<_>| {
+            check_team_join(
+                actor,
+                target,
+                team,
+                &members,
+                &team_owners,
+                invites,
+                &admins,
+            )
+            .ok()
+        };
+
+        // Neither side gets on the team alone, so neither reaches the other's
+        // structures without both agreeing.
+        assert_eq!(join("victim", "victim", "red", &invites), None);
+        assert_eq!(
+            join("owner", "victim", "red", &invites),
+            Some(TeamJoin::Invite)
+        );
+        assert!(!can_modify_structure("owner", "victim", &members, &admins));
+        assert!(!can_modify_structure("victim", "owner", &members, &admins));
+
+        invites.insert(("victim".to_string(), "red".to_string()));
+        assert_eq!(
+            join("victim", "victim", "red", &invites),
+            Some(TeamJoin::Join)
+        );
+        assert_eq!(
+            join("victim", "victim", "blue", &invites),
+            Some(TeamJoin::Join)
+        );
+        assert_eq!(join("victim", "owner", "blue", &invites), None);
+        assert_eq!(join("owner", "stranger", "red", &invites), None);
+        assert_eq!(
+            join("admin", "victim", "red", &HashSet::new()),
+            Some(TeamJoin::Join)
+        );
+
+        members.get_mut("victim").unwrap().team_id = Some("red".to_string());
+        assert!(can_modify_structure("victim", "owner", &members, &admins));
+        assert_eq!(next_team_owner("red", &members).as_deref(), Some("owner"));
+        assert_eq!(next_team_owner("blue", &members), None);
+    }
+
+    #[test]
+    fn removal_is_refused_when_the_refund_does_not_fit() {
+        let mut runtime = RoomRuntimeState::default();
+        let mut player = RoomDurableObject::default_runtime_player(0);
+        player.inventory = Inventory::default();
+        while player.inventory.insert(ItemKind::Coal, u32::MAX) > 0 {}
+        runtime.players.insert("owner".to_string(), player);
+
+        let mut chest = chest_for_structure("chest");
+        chest.as_mut().unwrap().insert(ItemKind::IronPlate, 7);
+        runtime.insert_structure(RuntimeStructureState {
+            structure_id: "chest_1".to_string(),
+            owner_id: "owner".to_string(),
+            kind: "chest".to_string(),
+            x: 16.0,
+            y: 16.0,
+            grid_x: 0,
+            grid_y: 0,
+            chunk_x: 0,
+            chunk_y: 0,
+            created_at: 0,
+            health: Health::full(structure_max_hp("chest")),
+            miner: None,
+            assembler: None,
+            chest,
+            lab: None,
+            direction: Direction::East,
+        });
+
+        assert!(runtime
+            .remove_structure_with_refund("chest_1", "owner")
+            .is_none());
+        assert!(runtime.structures.contains_key("chest_1"));
+        assert!(runtime.structure_cells.contains_key(&(0, 0)));
+
+        let inventory = &mut runtime.players.get_mut("owner").unwrap().inventory;
+        inventory.remove(ItemKind::Coal, 2 * ItemKind::Coal.stack_size());
+        let removed = runtime
+            .remove_structure_with_refund("chest_1", "owner")
+            .unwrap();
+        assert!(!runtime.structures.contains_key("chest_1"));
+        let inventory = &runtime.players["owner"].inventory;
+        assert_eq!(inventory.count(ItemKind::Chest), 1);
+        assert_eq!(inventory.count(ItemKind::IronPlate), 7);
+
+        // A removal that could not be saved is put back, refund and all.
+        runtime.restore_removed_structure(removed, "owner");
+        assert_eq!(
+            runtime.structures["chest_1"]
+                .chest
+                .as_ref()
+                .unwrap()
+                .count(ItemKind::IronPlate),
+            7
+        );
+        assert_eq!(
+            runtime.structure_cells.get(&(0, 0)).map(String::as_str),
+            Some("chest_1")
+        );
+        let inventory = &runtime.players["owner"].inventory;
+        assert_eq!(inventory.count(ItemKind::Chest), 0);
+        assert_eq!(inventory.count(ItemKind::IronPlate), 0);
+    }
+
+    #[test]
+    fn snapshots_go_out_once_per_interval() {
+        assert_eq!(SNAPSHOT_INTERVAL_TICKS, 3);
+        assert!(!crosses_snapshot_interval(0, 1));
+        assert!(!crosses_snapshot_interval(1, 2));
+        assert!(crosses_snapshot_interval(2, 3));
+        assert!(!crosses_snapshot_interval(3, 5));
+        // A catch-up run over several boundaries is still one snapshot.
+        assert!(crosses_snapshot_interval(5, 13));
+        assert!(!crosses_snapshot_interval(7, 7));
+    }
+
+    #[test]
+    fn the_alarm_waits_for_the_next_tick_boundary() {
+        assert_eq!(tick_delay_ms(0.0), 34);
+        assert_eq!(tick_delay_ms(20.0), 14);
+        assert_eq!(tick_delay_ms(SIM_DT_MS - 0.5), 1);
+        // Already past the boundary (catch-up was capped): fire as soon as allowed.
+        assert_eq!(tick_delay_ms(SIM_DT_MS * 3.0), TICK_ALARM_MIN_DELAY_MS);
+    }
+
+    #[test]
+    fn the_alarm_stops_once_the_room_is_idle() {
+        assert_eq!(next_alarm_delay_ms(0, 0.0), None);
+        assert_eq!(next_alarm_delay_ms(0, 20.0), None);
+        assert_eq!(next_alarm_delay_ms(1, 0.0), Some(34));
+        assert_eq!(next_alarm_delay_ms(3, 20.0), Some(14));
+    }
+
+    #[test]
+    fn structures_ignore_damage_from_their_owner_and_team() {
+        let members = HashMap::from([
+            ("owner".to_string(), member(Some("red"))),
+            ("mate".to_string(), member(Some("red"))),
+            ("rival".to_string(), member(Some("blue"))),
+            ("loner".to_string(), member(None)),
+        ]);
+
+        assert!(!structure_damaged_by("owner", "owner", &members));
+        assert!(!structure_damaged_by("mate", "owner", &members));
+        assert!(structure_damaged_by("rival", "owner", &members));
+        assert!(structure_damaged_by("loner", "owner", &members));
+        assert!(structure_damaged_by("enemy_7", "owner", &members));
+        // Without a team only the owner's own shots are ignored.
+        assert!(!structure_damaged_by("loner", "loner", &members));
+        assert!(structure_damaged_by("owner", "loner", &members));
+    }
+
+    #[test]
+    fn only_the_room_creator_starts_as_admin() {
+        assert_eq!(initial_room_role("alice", Some("alice")), RoomRole::Admin);
+        assert_eq!(initial_room_role("bob", Some("alice")), RoomRole::Member);
+        // Rooms created before the creator was recorded have no admin but
+        // `ROOM_ADMIN_IDS`, however empty they are when someone joins.
+        assert_eq!(initial_room_role("bob", None), RoomRole::Member);
+    }
+
+    #[test]
+    fn legacy_rows_that_grow_into_each_other_drop_the_newer_one() {
+        let mut runtime = RoomRuntimeState::default();
+        let mut owner = RoomDurableObject::default_runtime_player(0);
+        owner.inventory = Inventory::default();
+        runtime.players.insert("owner".to_string(), owner);
+
+        // Saved as single cells before footprints: no grid for the oldest
+        // row, no direction for any of them.
+        let row = |id: &str, kind: &str, x: f64, grid: Option<i64>, created_at: i64| {
+            serde_json::from_value::<BuildRow>(json!({
+                "structure_id": id,
+                "owner_id": "owner",
+                "kind": kind,
+                "x": x,
+                "y": 0.0,
+                "grid_x": grid,
+                "grid_y": grid.map(|_| 0),
+                "created_at": created_at,
+                "hp": null,
+                "machine_state": null,
+                "direction": null,
+            }))
+            .unwrap()
+        };
+        let dropped = runtime.hydrate_structures(
+            vec![
+                row("belt_1", "belt", 32.0, None, 1),
+                row("assembler_1", "assembler", 0.0, Some(0), 2),
+                row("chest_1", "chest", 160.0, Some(5), 3),
+            ],
+            &Terrain::new(0),
+            0,
+        );
+
+        assert_eq!(
+            dropped,
+            vec![("assembler_1".to_string(), "owner".to_string())]
+        );
+        let mut kept: Vec<&str> = runtime.structures.keys().map(String::as_str).collect();
+        kept.sort();
+        assert_eq!(kept, ["belt_1", "chest_1"]);
+        assert_eq!(
+            runtime.structure_cells.get(&(1, 0)).map(String::as_str),
+            Some("belt_1")
+        );
+        assert!(!runtime.structure_cells.contains_key(&(0, 0)));
+        assert!(runtime.belts.get((1, 0)).is_some());
+        assert_eq!(
+            runtime.players["owner"]
+                .inventory
+                .count(ItemKind::Assembler),
+            1
+        );
+    }
+}